@@ -0,0 +1,9 @@
+// Rebuild when a migration is added, since `sqlx::migrate!` embeds the directory at compile time.
+// The gRPC code is generated from `proto/` with a vendored `protoc`, so none needs installing.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=proto");
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_prost_build::compile_protos("proto/usdcwatch.proto")?;
+    Ok(())
+}
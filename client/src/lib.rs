@@ -0,0 +1,226 @@
+//! A typed client of the HTTP API `ethereum-erc20-indexer serve` exposes. It depends only on
+//! `reqwest` and `serde`, so services can talk to the indexer without building it.
+
+use anyhow::Result;
+use futures::{stream, Stream};
+use serde::de::DeserializeOwned;
+use std::{collections::VecDeque, time::Duration};
+
+mod types;
+
+pub use types::*;
+
+/// Typed access to a `serve` instance.
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use usdcwatch_client::{ApiClient, TransferParams};
+///
+/// let client = ApiClient::new("http://127.0.0.1:8080").with_token("uwk_...");
+/// let params = TransferParams {
+///     min_value: Some("1000000".to_string()),
+///     ..TransferParams::default()
+/// };
+/// for transfer in client.transfers(&params).await?.transfers {
+///     println!("{} -> {}: {}", transfer.from_address, transfer.to_address, transfer.value);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl ApiClient {
+    /// `base_url` is the scheme, host and port `serve` listens on, like `http://127.0.0.1:8080`.
+    pub fn new(base_url: &str) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Uses a preconfigured client, e.g. one with timeouts or default headers.
+    pub fn with_http_client(http: reqwest::Client, base_url: &str) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: None,
+        }
+    }
+
+    /// Sends `token` as the bearer token of every request, for servers with API keys.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    pub async fn info(&self) -> Result<ApiInfo> {
+        self.get("/api/info", &()).await
+    }
+
+    pub async fn stats(&self) -> Result<StatsReport> {
+        self.get("/api/stats", &()).await
+    }
+
+    /// One page of transfers, newest first; pass `next_cursor` back as `cursor` for the next.
+    pub async fn transfers(&self, params: &TransferParams) -> Result<TransferPage> {
+        self.get("/api/transfers", params).await
+    }
+
+    /// `None` when the rollups have no activity for `address`.
+    pub async fn address(&self, address: &str) -> Result<Option<AddressStatsReport>> {
+        let response = self.request(reqwest::Method::GET, &format!("/api/addresses/{}", address)).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(decode(response).await?))
+    }
+
+    pub async fn volume(&self, params: &VolumeParams) -> Result<Vec<PeriodActivity>> {
+        self.get("/api/volume", params).await
+    }
+
+    /// `None` until the rollups have run.
+    pub async fn holders(&self) -> Result<Option<HolderSnapshot>> {
+        self.get("/api/holders", &()).await
+    }
+
+    pub async fn volume_series(&self, params: &SeriesParams) -> Result<Series> {
+        self.get("/api/series/volume", params).await
+    }
+
+    pub async fn supply_series(&self, params: &SeriesParams) -> Result<Series> {
+        self.get("/api/series/supply", params).await
+    }
+
+    /// Needs an `admin` token, as do the other key methods.
+    pub async fn api_keys(&self) -> Result<Vec<ApiKey>> {
+        self.get("/api/keys", &()).await
+    }
+
+    pub async fn create_api_key(&self, name: &str, scope: Scope) -> Result<CreatedApiKey> {
+        let key = NewApiKey {
+            name: name.to_string(),
+            scope,
+        };
+        decode(self.request(reqwest::Method::POST, "/api/keys").json(&key).send().await?).await
+    }
+
+    /// `false` when no key has that name.
+    pub async fn revoke_api_key(&self, name: &str) -> Result<bool> {
+        let response = self.request(reqwest::Method::DELETE, &format!("/api/keys/{}", name)).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        decode_empty(response).await?;
+        Ok(true)
+    }
+
+    /// Transfers matching `params` committed after the call, oldest first, found by polling every
+    /// `poll`. A failed poll yields its error and the next one picks up where it left off.
+    pub fn subscribe_transfers(&self, params: TransferParams, poll: Duration) -> impl Stream<Item = Result<TransferEvent>> {
+        struct Subscription {
+            client: ApiClient,
+            params: TransferParams,
+            /// Newest block already seen; `None` until the first poll sets the starting point.
+            last_block: Option<i64>,
+            pending: VecDeque<TransferEvent>,
+        }
+
+        let subscription = Subscription {
+            client: self.clone(),
+            params,
+            last_block: None,
+            pending: VecDeque::new(),
+        };
+        stream::unfold(subscription, move |mut sub| async move {
+            loop {
+                if let Some(transfer) = sub.pending.pop_front() {
+                    return Some((Ok(transfer), sub));
+                }
+                let Some(last_block) = sub.last_block else {
+                    match sub.client.latest_block(&sub.params).await {
+                        Ok(block) => sub.last_block = Some(block),
+                        Err(e) => return Some((Err(e), sub)),
+                    }
+                    continue;
+                };
+                tokio::time::sleep(poll).await;
+                match sub.client.transfers_after(&sub.params, last_block).await {
+                    Ok(transfers) => {
+                        if let Some(newest) = transfers.last() {
+                            sub.last_block = Some(newest.block_number);
+                        }
+                        sub.pending.extend(transfers);
+                    }
+                    Err(e) => return Some((Err(e), sub)),
+                }
+            }
+        })
+    }
+
+    /// The block of the newest transfer matching `params`, or the block before `from_block`.
+    async fn latest_block(&self, params: &TransferParams) -> Result<i64> {
+        let params = TransferParams {
+            limit: Some(1),
+            cursor: None,
+            ..params.clone()
+        };
+        let page = self.transfers(&params).await?;
+        let floor = params.from_block.map_or(0, |block| block as i64 - 1);
+        Ok(page.transfers.first().map_or(floor, |t| t.block_number.max(floor)))
+    }
+
+    /// Every transfer matching `params` in blocks after `last_block`, oldest first. Ranges are
+    /// committed whole, so nothing more can appear in a block once one of its transfers is seen.
+    async fn transfers_after(&self, params: &TransferParams, last_block: i64) -> Result<Vec<TransferEvent>> {
+        let mut params = TransferParams {
+            from_block: Some(params.from_block.unwrap_or(0).max((last_block + 1).max(0) as u64)),
+            limit: Some(1_000),
+            cursor: None,
+            ..params.clone()
+        };
+        let mut transfers = Vec::new();
+        loop {
+            let page = self.transfers(&params).await?;
+            transfers.extend(page.transfers);
+            match page.next_cursor {
+                Some(cursor) => params.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        transfers.reverse();
+        Ok(transfers)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &impl serde::Serialize) -> Result<T> {
+        decode(self.request(reqwest::Method::GET, path).query(query).send().await?).await
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.base_url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+/// The body of a successful response, or the server's `error` message.
+async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    Ok(decode_empty(response).await?.json().await?)
+}
+
+/// `response` if it succeeded, or the server's `error` message.
+async fn decode_empty(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if !status.is_success() {
+        let message = match response.json::<ErrorBody>().await {
+            Ok(body) => body.error,
+            Err(_) => "no error message".to_string(),
+        };
+        return Err(anyhow::anyhow!("API request failed with {}: {}", status, message));
+    }
+    Ok(response)
+}
@@ -0,0 +1,282 @@
+//! The request and response bodies of the `serve` API, as they appear on the wire. The server's
+//! own types encode to the same JSON; these only carry what a client needs to decode them.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Token details a client needs to scale raw amounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiInfo {
+    pub contract_address: String,
+    pub token_symbol: String,
+    pub token_decimals: u8,
+    pub last_processed_block: Option<i64>,
+}
+
+/// Body of every failed request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// What a token may do; `admin` includes everything `read` can.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Every `GET` endpoint.
+    #[default]
+    Read,
+    /// Also creating, listing and revoking API keys.
+    Admin,
+}
+
+/// Body of `POST /api/keys`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewApiKey {
+    pub name: String,
+    pub scope: Scope,
+}
+
+/// A key just created over the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedApiKey {
+    pub name: String,
+    pub scope: Scope,
+    /// The bearer token; it is not stored and cannot be shown again.
+    pub token: String,
+}
+
+/// A stored API key, without its token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub scope: Scope,
+    pub created_at: DateTime<Utc>,
+    /// Updated at most once a minute.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Which side of a transfer an address filter applies to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+    #[default]
+    Both,
+}
+
+/// Time buckets of `GET /api/volume`, all in UTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowInterval {
+    Hour,
+    #[default]
+    Day,
+    /// Weeks starting on Monday.
+    Week,
+    Month,
+}
+
+/// How a series is reduced to the requested number of points.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Downsample {
+    /// Largest-Triangle-Three-Buckets: keeps the points that preserve the visual shape.
+    #[default]
+    Lttb,
+    /// Largest value per bucket.
+    Max,
+    /// Mean value per bucket.
+    Avg,
+    /// Total per bucket; only meaningful for volume.
+    Sum,
+}
+
+/// Filters of `GET /api/transfers`, named like the `query` flags. Amounts are in token units, or
+/// raw with a `raw` suffix; times are RFC3339 or relative ages like `24h`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransferParams {
+    pub address: Option<String>,
+    /// Which side `address` is matched on; defaults to both.
+    pub direction: Option<Direction>,
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub min_value: Option<String>,
+    pub max_value: Option<String>,
+    pub from_time: Option<String>,
+    pub to_time: Option<String>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// Defaults to 100, at most 1000.
+    pub limit: Option<i64>,
+    /// Also search transfers moved to the archive.
+    #[serde(default)]
+    pub archived: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VolumeParams {
+    pub interval: Option<FlowInterval>,
+    /// Defaults to 30 days ago.
+    pub from_time: Option<String>,
+    pub to_time: Option<String>,
+}
+
+/// Range and downsampling of `GET /api/series/volume` and `/api/series/supply`. `address`,
+/// `direction` and `archived` only apply to volume.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SeriesParams {
+    pub address: Option<String>,
+    pub direction: Option<Direction>,
+    /// Defaults to 30 days ago.
+    pub from_time: Option<String>,
+    pub to_time: Option<String>,
+    /// Defaults to 500, at most 5000.
+    pub points: Option<usize>,
+    /// Defaults to `lttb`; `sum` is rejected for supply.
+    pub method: Option<Downsample>,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferEvent {
+    pub id: String,
+    pub transaction_hash: String,
+    pub log_index: i64,
+    pub block_number: i64,
+    pub block_hash: String,
+    pub from_address: String,
+    pub to_address: String,
+    /// Raw token units.
+    pub value: String,
+    pub timestamp: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub from_label: Option<String>,
+    #[serde(default)]
+    pub to_label: Option<String>,
+    #[serde(default)]
+    pub from_ens: Option<String>,
+    #[serde(default)]
+    pub to_ens: Option<String>,
+    #[serde(default)]
+    pub tx_sender: Option<String>,
+    #[serde(default)]
+    pub tx_to: Option<String>,
+    /// Fee of the originating transaction, in wei.
+    #[serde(default)]
+    pub tx_fee: Option<String>,
+    /// Dollar value at the transfer's block, when it was priced.
+    #[serde(default)]
+    pub usd_value: Option<String>,
+    #[serde(default)]
+    pub sanctioned: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferPage {
+    pub transfers: Vec<TransferEvent>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseStats {
+    pub total_transfers: i64,
+    pub unique_addresses: i64,
+    pub latest_block: Option<i64>,
+    pub earliest_block: Option<i64>,
+}
+
+/// Database totals, recent throughput, supply changes and indexing lag.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsReport {
+    #[serde(flatten)]
+    pub database: DatabaseStats,
+    /// Raw token units moved by every stored transfer.
+    pub total_volume: String,
+    pub transfers_last_7d: i64,
+    pub transfers_per_day_7d: f64,
+    pub transfers_last_30d: i64,
+    pub transfers_per_day_30d: f64,
+    /// Raw token units sent from the zero address.
+    pub minted: String,
+    /// Raw token units sent to the zero address.
+    pub burned: String,
+    pub database_bytes: u64,
+    pub last_processed_block: Option<i64>,
+    /// `None` when the server could not reach its node.
+    pub chain_head: Option<u64>,
+    pub lag_blocks: Option<u64>,
+    /// Seconds between the last processed block's timestamp and now.
+    pub lag_secs: Option<i64>,
+}
+
+/// When an address first and last sent or received a stored transfer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRange {
+    pub first_block: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_block: i64,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Counts, totals, first and last activity, and balance rank of one address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressStatsReport {
+    pub address: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    pub transfers: i64,
+    /// Raw token units.
+    pub total_in: String,
+    pub total_out: String,
+    /// `total_in - total_out`, with a leading `-` when negative.
+    pub balance: String,
+    #[serde(flatten)]
+    pub activity: Option<ActivityRange>,
+    /// 1 for the largest balance; `None` when the address holds nothing.
+    pub holder_rank: Option<usize>,
+    pub holders: usize,
+}
+
+/// Token-wide activity in one time bucket, with velocity against the tracked supply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodActivity {
+    pub period: String,
+    pub transfers: i64,
+    /// Raw token units moved.
+    pub volume: String,
+    /// Distinct senders and recipients, the zero address aside.
+    pub active_addresses: i64,
+    /// Raw token units; `None` when no supply is tracked.
+    pub supply: Option<String>,
+    /// `volume / supply`.
+    pub velocity: Option<f64>,
+}
+
+/// How many addresses held a non-zero balance as of a block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderSnapshot {
+    pub block_number: i64,
+    pub timestamp: DateTime<Utc>,
+    pub holders: i64,
+    /// Raw token units minted minus burned.
+    pub supply: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SeriesPoint {
+    pub timestamp: DateTime<Utc>,
+    /// Token units, as a float for charting.
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Series {
+    pub method: Downsample,
+    /// Width of the slots volume was summed over before downsampling; `None` for supply.
+    pub resolution_secs: Option<i64>,
+    pub points: Vec<SeriesPoint>,
+}
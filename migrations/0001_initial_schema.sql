@@ -0,0 +1,123 @@
+-- Create transfer_events table
+CREATE TABLE IF NOT EXISTS transfer_events (
+    id TEXT PRIMARY KEY,
+    transaction_hash TEXT NOT NULL,
+    log_index INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    block_hash TEXT NOT NULL,
+    from_address TEXT NOT NULL,
+    to_address TEXT NOT NULL,
+    value TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    UNIQUE(transaction_hash, log_index)
+);
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_block_number ON transfer_events(block_number);
+CREATE INDEX IF NOT EXISTS idx_from_address ON transfer_events(from_address);
+CREATE INDEX IF NOT EXISTS idx_to_address ON transfer_events(to_address);
+CREATE INDEX IF NOT EXISTS idx_timestamp ON transfer_events(timestamp);
+
+-- Create processed_blocks table
+CREATE TABLE IF NOT EXISTS processed_blocks (
+    block_number INTEGER PRIMARY KEY,
+    block_hash TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    processed_at TEXT NOT NULL
+);
+
+-- Create pending_transfers table (unfinalized blocks, see PENDING_BLOCKS)
+CREATE TABLE IF NOT EXISTS pending_transfers (
+    id TEXT PRIMARY KEY,
+    transaction_hash TEXT NOT NULL,
+    log_index INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    block_hash TEXT NOT NULL,
+    from_address TEXT NOT NULL,
+    to_address TEXT NOT NULL,
+    value TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    confirmed INTEGER NOT NULL DEFAULT 0,
+    UNIQUE(transaction_hash, log_index)
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_block_number ON pending_transfers(block_number);
+
+-- Create pending_tx table (mempool watcher)
+CREATE TABLE IF NOT EXISTS pending_tx (
+    tx_hash TEXT PRIMARY KEY,
+    sender TEXT NOT NULL,
+    method TEXT NOT NULL,
+    from_address TEXT NOT NULL,
+    to_address TEXT NOT NULL,
+    value TEXT NOT NULL,
+    first_seen TEXT NOT NULL
+);
+
+-- Create meta table (database identity: chain_id, contract_address, provider_fingerprint)
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+-- Create event_sources table (non-RPC origin of transfer rows)
+CREATE TABLE IF NOT EXISTS event_sources (
+    event_id TEXT PRIMARY KEY,
+    source TEXT NOT NULL,
+    ingested_at TEXT NOT NULL
+);
+
+-- Create ingest_conflicts table (payload mismatches between sources)
+CREATE TABLE IF NOT EXISTS ingest_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id TEXT NOT NULL,
+    existing_source TEXT NOT NULL,
+    incoming_source TEXT NOT NULL,
+    fields TEXT NOT NULL,
+    resolution TEXT NOT NULL,
+    detected_at TEXT NOT NULL
+);
+
+-- Create reorg_events table (observed reorg depths)
+CREATE TABLE IF NOT EXISTS reorg_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    fork_block INTEGER NOT NULL,
+    chain_head INTEGER NOT NULL,
+    depth INTEGER NOT NULL,
+    finality_blocks INTEGER NOT NULL,
+    detected_at TEXT NOT NULL
+);
+
+-- Create archived_transfers table (zstd-compressed JSON batches of old transfers)
+CREATE TABLE IF NOT EXISTS archived_transfers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_block INTEGER NOT NULL,
+    end_block INTEGER NOT NULL,
+    row_count INTEGER NOT NULL,
+    min_timestamp TEXT NOT NULL,
+    max_timestamp TEXT NOT NULL,
+    data BLOB NOT NULL,
+    archived_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_archived_block_range ON archived_transfers(start_block, end_block);
+
+-- Create address_stats table (per-address rollups maintained at ingest)
+CREATE TABLE IF NOT EXISTS address_stats (
+    address TEXT PRIMARY KEY,
+    total_in TEXT NOT NULL,
+    total_out TEXT NOT NULL,
+    tx_count INTEGER NOT NULL,
+    last_block INTEGER NOT NULL
+);
+
+-- Create address_bloom table (single-row bloom filter over address_stats addresses)
+CREATE TABLE IF NOT EXISTS address_bloom (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    capacity INTEGER NOT NULL,
+    num_hashes INTEGER NOT NULL,
+    items INTEGER NOT NULL,
+    bits BLOB NOT NULL
+);
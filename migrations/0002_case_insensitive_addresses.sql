@@ -0,0 +1,11 @@
+-- Addresses are now stored EIP-55 checksummed while older rows are lowercase, so address
+-- lookups compare case-insensitively through NOCASE indexes.
+DROP INDEX IF EXISTS idx_from_address;
+DROP INDEX IF EXISTS idx_to_address;
+CREATE INDEX IF NOT EXISTS idx_from_address_nocase ON transfer_events(from_address COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS idx_to_address_nocase ON transfer_events(to_address COLLATE NOCASE);
+
+-- Rollups were keyed by lowercase address; recompute them under checksummed keys.
+DELETE FROM address_stats;
+DELETE FROM address_bloom;
+DELETE FROM meta WHERE key = 'address_stats_block';
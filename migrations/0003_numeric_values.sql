@@ -0,0 +1,8 @@
+-- `value` stays the canonical decimal string. These virtual columns split it into the digits above
+-- and the low nine digits, so SQL can SUM, compare and index amounts without overflowing i64.
+ALTER TABLE transfer_events ADD COLUMN value_high INTEGER GENERATED ALWAYS AS (CAST(SUBSTR(value, 1, LENGTH(value) - 9) AS INTEGER)) VIRTUAL;
+ALTER TABLE transfer_events ADD COLUMN value_low INTEGER GENERATED ALWAYS AS (CAST(SUBSTR(value, -9) AS INTEGER)) VIRTUAL;
+CREATE INDEX IF NOT EXISTS idx_value ON transfer_events(value_high, value_low);
+
+ALTER TABLE pending_transfers ADD COLUMN value_high INTEGER GENERATED ALWAYS AS (CAST(SUBSTR(value, 1, LENGTH(value) - 9) AS INTEGER)) VIRTUAL;
+ALTER TABLE pending_transfers ADD COLUMN value_low INTEGER GENERATED ALWAYS AS (CAST(SUBSTR(value, -9) AS INTEGER)) VIRTUAL;
@@ -0,0 +1,8 @@
+-- ERC-20 metadata read from the contract, keyed by checksummed contract address.
+CREATE TABLE IF NOT EXISTS tokens (
+    address TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    decimals INTEGER NOT NULL,
+    fetched_at TEXT NOT NULL
+);
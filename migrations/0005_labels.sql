@@ -0,0 +1,6 @@
+-- Human names for addresses, keyed by checksummed address.
+CREATE TABLE IF NOT EXISTS labels (
+    address TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Cached ENS lookups. A NULL result is cached too, so addresses without a primary name and names
+-- that do not resolve are not looked up again until the entry expires.
+CREATE TABLE IF NOT EXISTS ens_names (
+    address TEXT PRIMARY KEY,
+    name TEXT,
+    resolved_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ens_addresses (
+    name TEXT PRIMARY KEY,
+    address TEXT,
+    resolved_at TEXT NOT NULL
+);
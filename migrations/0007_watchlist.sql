@@ -0,0 +1,5 @@
+-- Addresses indexed when WATCHLIST_ONLY is on, keyed by checksummed address.
+CREATE TABLE IF NOT EXISTS watchlist (
+    address TEXT PRIMARY KEY,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,15 @@
+-- User-defined alert rules evaluated against newly indexed transfers. NULL conditions are not
+-- checked; `channels` is a comma-separated list of sink names, NULL meaning every configured sink.
+CREATE TABLE IF NOT EXISTS alert_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    address TEXT,
+    direction TEXT NOT NULL DEFAULT 'both',
+    min_value TEXT,
+    max_value TEXT,
+    counterparty_label TEXT,
+    min_count INTEGER,
+    window_secs INTEGER,
+    channels TEXT,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Alerts held for the daily email digest until the day they fired on is over and sent.
+CREATE TABLE IF NOT EXISTS email_digest (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    at TEXT NOT NULL,
+    message TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_digest_at ON email_digest(at);
@@ -0,0 +1,17 @@
+-- Undecoded Transfer logs as returned by the node, kept when RAW_LOGS is on so `replay` can
+-- rebuild transfer_events without the RPC. `topics` is a comma-separated list of 0x-prefixed words.
+CREATE TABLE IF NOT EXISTS raw_logs (
+    block_number INTEGER NOT NULL,
+    log_index INTEGER NOT NULL,
+    block_hash TEXT NOT NULL,
+    block_timestamp TEXT NOT NULL,
+    transaction_hash TEXT NOT NULL,
+    transaction_index INTEGER,
+    address TEXT NOT NULL,
+    topics TEXT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (block_number, log_index)
+);
+
+-- Blocks indexed with RAW_LOGS on; only these can be replayed.
+ALTER TABLE processed_blocks ADD COLUMN raw_logs BOOLEAN NOT NULL DEFAULT 0;
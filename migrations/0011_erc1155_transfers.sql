@@ -0,0 +1,24 @@
+-- ERC-1155 TransferSingle events and TransferBatch events unrolled into one row per token id,
+-- for the contracts listed in ERC1155_CONTRACTS.
+CREATE TABLE IF NOT EXISTS erc1155_transfers (
+    id TEXT PRIMARY KEY,
+    contract_address TEXT NOT NULL,
+    transaction_hash TEXT NOT NULL,
+    log_index INTEGER NOT NULL,
+    batch_index INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    block_hash TEXT NOT NULL,
+    operator TEXT NOT NULL,
+    from_address TEXT NOT NULL,
+    to_address TEXT NOT NULL,
+    token_id TEXT NOT NULL,
+    value TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    UNIQUE(transaction_hash, log_index, batch_index)
+);
+
+CREATE INDEX IF NOT EXISTS idx_erc1155_block ON erc1155_transfers(block_number);
+CREATE INDEX IF NOT EXISTS idx_erc1155_from ON erc1155_transfers(from_address COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS idx_erc1155_to ON erc1155_transfers(to_address COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS idx_erc1155_token ON erc1155_transfers(contract_address COLLATE NOCASE, token_id);
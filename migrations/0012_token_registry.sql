@@ -0,0 +1,13 @@
+-- Indexing progress per chain and token contract, so a token added to the configuration later is
+-- backfilled on its own while the others keep following the chain.
+ALTER TABLE tokens ADD COLUMN standard TEXT NOT NULL DEFAULT 'erc20';
+
+CREATE TABLE IF NOT EXISTS token_checkpoints (
+    chain_id INTEGER NOT NULL,
+    token_address TEXT NOT NULL COLLATE NOCASE,
+    standard TEXT NOT NULL,
+    start_block INTEGER NOT NULL,
+    next_block INTEGER NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (chain_id, token_address)
+);
@@ -0,0 +1,15 @@
+-- Sender, gas and fee of transactions that moved tokens, fetched from their receipts when
+-- TX_ENRICHMENT is on. Amounts are decimal strings in wei.
+CREATE TABLE IF NOT EXISTS transactions (
+    transaction_hash TEXT PRIMARY KEY,
+    block_number INTEGER NOT NULL,
+    sender TEXT NOT NULL,
+    recipient TEXT,
+    gas_used TEXT NOT NULL,
+    effective_gas_price TEXT NOT NULL,
+    fee TEXT NOT NULL,
+    fetched_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_transactions_block ON transactions(block_number);
+CREATE INDEX IF NOT EXISTS idx_transactions_sender ON transactions(sender COLLATE NOCASE);
@@ -0,0 +1,6 @@
+-- Header fields of each processed block, for correlating transfer activity with congestion.
+-- NULL for blocks processed before this migration; base_fee_per_gas is also NULL before London.
+ALTER TABLE processed_blocks ADD COLUMN base_fee_per_gas TEXT;
+ALTER TABLE processed_blocks ADD COLUMN gas_used INTEGER;
+ALTER TABLE processed_blocks ADD COLUMN gas_limit INTEGER;
+ALTER TABLE processed_blocks ADD COLUMN fee_recipient TEXT;
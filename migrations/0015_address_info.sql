@@ -0,0 +1,9 @@
+-- Whether each address seen in a transfer has contract code, checked once with eth_getCode when
+-- ADDRESS_CLASSIFICATION is on.
+CREATE TABLE IF NOT EXISTS address_info (
+    address TEXT PRIMARY KEY COLLATE NOCASE,
+    is_contract BOOLEAN NOT NULL,
+    checked_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_address_info_contract ON address_info(is_contract, address COLLATE NOCASE);
@@ -0,0 +1,5 @@
+-- Labels can carry a category such as exchange or bridge, so volume can be grouped by the kind of
+-- entity on the other side.
+ALTER TABLE labels ADD COLUMN category TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_labels_category ON labels(category);
@@ -0,0 +1,18 @@
+-- Chainlink answers read once per PRICE_BLOCK_INTERVAL blocks, and the dollar value of each
+-- transfer at its block, when PRICE_FEED_ADDRESS is set.
+CREATE TABLE IF NOT EXISTS block_prices (
+    feed TEXT NOT NULL COLLATE NOCASE,
+    block_number INTEGER NOT NULL,
+    answer TEXT NOT NULL,
+    decimals INTEGER NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (feed, block_number)
+);
+
+CREATE TABLE IF NOT EXISTS transfer_usd_values (
+    transfer_id TEXT PRIMARY KEY,
+    block_number INTEGER NOT NULL,
+    usd_value TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_transfer_usd_values_block ON transfer_usd_values(block_number);
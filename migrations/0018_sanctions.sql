@@ -0,0 +1,6 @@
+-- Addresses from the sanctions list named by SANCTIONS_LIST, replaced wholesale on each refresh.
+CREATE TABLE IF NOT EXISTS sanctioned_addresses (
+    address TEXT PRIMARY KEY COLLATE NOCASE,
+    source TEXT NOT NULL,
+    loaded_at TEXT NOT NULL
+);
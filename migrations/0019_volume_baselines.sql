@@ -0,0 +1,8 @@
+-- Rolling per-window volume averages used by anomaly detection. The address '*' holds the
+-- token-wide baseline.
+CREATE TABLE IF NOT EXISTS volume_baselines (
+    address TEXT PRIMARY KEY COLLATE NOCASE,
+    average REAL NOT NULL,
+    windows INTEGER NOT NULL,
+    last_window INTEGER NOT NULL
+);
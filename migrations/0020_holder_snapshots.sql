@@ -0,0 +1,10 @@
+-- Holder count and tracked supply over time, at most one row per HOLDER_SNAPSHOT_SECS period,
+-- for `holders --history`.
+CREATE TABLE IF NOT EXISTS holder_snapshots (
+    block_number INTEGER PRIMARY KEY,
+    timestamp TEXT NOT NULL,
+    holders INTEGER NOT NULL,
+    supply TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_holder_snapshots_timestamp ON holder_snapshots(timestamp);
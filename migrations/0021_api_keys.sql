@@ -0,0 +1,8 @@
+-- Bearer tokens for `serve`, managed with `api-key`. Only a hash of each token is kept.
+CREATE TABLE IF NOT EXISTS api_keys (
+    name TEXT PRIMARY KEY,
+    key_hash TEXT NOT NULL UNIQUE,
+    scope TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT
+);
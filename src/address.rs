@@ -0,0 +1,79 @@
+use anyhow::Result;
+use ethers_core::{types::H160, utils::to_checksum};
+use std::str::FromStr;
+
+/// Sender of mints and recipient of burns.
+pub const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// EIP-55 form of `address`, the way addresses are stored and printed.
+pub fn checksum(address: &H160) -> String {
+    to_checksum(address, None)
+}
+
+/// Parses an address in any case and returns its checksummed form.
+///
+/// All-lowercase and all-uppercase input is accepted as-is; mixed-case input must carry a valid
+/// EIP-55 checksum, since a wrong one usually means a typo.
+pub fn normalize_address(input: &str) -> Result<String> {
+    let trimmed = input.trim();
+    let address = H160::from_str(trimmed).map_err(|_| anyhow::anyhow!("Invalid address: {}", input))?;
+    let checksummed = checksum(&address);
+
+    let hex = trimmed.strip_prefix("0x").unwrap_or(trimmed);
+    let mixed_case = hex.chars().any(|c| c.is_ascii_lowercase()) && hex.chars().any(|c| c.is_ascii_uppercase());
+    if mixed_case && hex != &checksummed[2..] {
+        return Err(anyhow::anyhow!("Invalid EIP-55 checksum: {}", input));
+    }
+
+    Ok(checksummed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference vectors from the EIP-55 specification.
+    const EIP55: &[&str] = &[
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+
+    #[test]
+    fn checksummed_input_is_unchanged() {
+        for address in EIP55 {
+            assert_eq!(normalize_address(address).unwrap(), *address);
+        }
+    }
+
+    #[test]
+    fn single_case_input_is_checksummed() {
+        for address in EIP55 {
+            let hex = &address[2..];
+            assert_eq!(normalize_address(&format!("0x{}", hex.to_lowercase())).unwrap(), *address);
+            assert_eq!(normalize_address(&format!("0x{}", hex.to_uppercase())).unwrap(), *address);
+            assert_eq!(normalize_address(&hex.to_lowercase()).unwrap(), *address);
+        }
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_ignored() {
+        assert_eq!(normalize_address("  0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed\n").unwrap(), EIP55[0]);
+    }
+
+    #[test]
+    fn mixed_case_with_wrong_checksum_is_rejected() {
+        // EIP55[0] with the case of its first letter flipped.
+        let err = normalize_address("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{}", err);
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for input in ["", "0x", "0x1234", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAe", "0xzzaeb6053f3e94c9b9a09f33669435e7ef1beaed", "alice.eth"] {
+            let err = normalize_address(input).unwrap_err();
+            assert!(err.to_string().starts_with("Invalid address"), "{}: {}", input, err);
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use ethers_core::types::U256;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    config::Config,
+    database::Database,
+    latency::LatencyReport,
+    models::{AlertRule, ReorgRecord, TransferEvent},
+    output::AmountFormat,
+    query::TransferFilter,
+};
+
+/// Trailing window the freshness SLO is evaluated over.
+pub const SLO_WINDOW_HOURS: i64 = 1;
+
+/// Something an operator should be told about.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Alert {
+    FreshnessSloViolated {
+        within_slo_percent: f64,
+        slo_secs: u64,
+        slo_percent: f64,
+        p95_secs: i64,
+    },
+    FreshnessSloRecovered,
+    /// The indexer stayed more than `LAG_ALERT_BLOCKS` behind the chain head for `LAG_ALERT_MINUTES`.
+    HeadLagExceeded {
+        lag_blocks: u64,
+        threshold_blocks: u64,
+        behind_secs: i64,
+    },
+    HeadLagRecovered {
+        lag_blocks: u64,
+    },
+    ReorgBeyondFinality {
+        fork_block: i64,
+        depth: i64,
+        finality_blocks: i64,
+    },
+    /// A transfer that satisfied a stored alert rule.
+    RuleMatched {
+        rule_id: i64,
+        rule: String,
+        transaction_hash: String,
+        block_number: i64,
+        from_address: String,
+        to_address: String,
+        value: String,
+        amount: String,
+        tx_url: String,
+        /// Matching transfers in the rule's window, for frequency rules.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        count: Option<i64>,
+    },
+    /// A transfer at or above `ALERT_MIN_VALUE`.
+    LargeTransfer {
+        transaction_hash: String,
+        block_number: i64,
+        from_address: String,
+        to_address: String,
+        value: String,
+        /// `value` in token units.
+        amount: String,
+        tx_url: String,
+    },
+    /// A transfer to or from an address on the sanctions list.
+    SanctionedTransfer {
+        transaction_hash: String,
+        block_number: i64,
+        from_address: String,
+        to_address: String,
+        /// The listed side, or both joined with a comma.
+        sanctioned_address: String,
+        value: String,
+        amount: String,
+        tx_url: String,
+    },
+    /// A window in which an address, or the whole token, moved far more than its baseline.
+    VolumeAnomaly {
+        /// `None` for token-wide volume.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        address: Option<String>,
+        window_start: DateTime<Utc>,
+        window_secs: u64,
+        /// Raw volume in the window.
+        volume: String,
+        amount: String,
+        baseline_amount: String,
+        /// `volume` as a multiple of the baseline.
+        ratio: f64,
+    },
+}
+
+impl Alert {
+    /// An observed reorg only alerts when it was deeper than finality allowed for.
+    pub fn for_reorg(reorg: &ReorgRecord) -> Option<Self> {
+        (reorg.depth > reorg.finality_blocks).then_some(Alert::ReorgBeyondFinality {
+            fork_block: reorg.fork_block,
+            depth: reorg.depth,
+            finality_blocks: reorg.finality_blocks,
+        })
+    }
+
+    /// Alerts for a transfer whose value reaches `ALERT_MIN_VALUE`.
+    pub fn for_transfer(transfer: &TransferEvent, config: &Config) -> Option<Self> {
+        let threshold = config.alert_min_value?;
+        let value = U256::from_dec_str(&transfer.value).ok()?;
+        (value >= threshold).then(|| Alert::LargeTransfer {
+            transaction_hash: transfer.transaction_hash.clone(),
+            block_number: transfer.block_number,
+            from_address: transfer.from_address.clone(),
+            to_address: transfer.to_address.clone(),
+            value: transfer.value.clone(),
+            amount: AmountFormat::new(config, false).format(&transfer.value),
+            tx_url: format!("{}{}", config.explorer_tx_url, transfer.transaction_hash),
+        })
+    }
+
+    /// Alerts for a transfer with a side in `sanctioned` (lowercased addresses).
+    pub fn for_sanctioned_transfer(transfer: &TransferEvent, sanctioned: &HashSet<String>, config: &Config) -> Option<Self> {
+        let listed: Vec<&str> = [&transfer.from_address, &transfer.to_address]
+            .into_iter()
+            .filter(|address| sanctioned.contains(&address.to_lowercase()))
+            .map(String::as_str)
+            .collect();
+        (!listed.is_empty()).then(|| Alert::SanctionedTransfer {
+            transaction_hash: transfer.transaction_hash.clone(),
+            block_number: transfer.block_number,
+            from_address: transfer.from_address.clone(),
+            to_address: transfer.to_address.clone(),
+            sanctioned_address: listed.join(","),
+            value: transfer.value.clone(),
+            amount: AmountFormat::new(config, false).format(&transfer.value),
+            tx_url: format!("{}{}", config.explorer_tx_url, transfer.transaction_hash),
+        })
+    }
+
+    /// `summary` followed by the transaction link, if any.
+    pub fn message(&self) -> String {
+        match self.tx_url() {
+            Some(url) => format!("{}: {}", self.summary(), url),
+            None => self.summary(),
+        }
+    }
+
+    /// Explorer link for alerts about a single transfer.
+    pub fn tx_url(&self) -> Option<&str> {
+        match self {
+            Alert::LargeTransfer { tx_url, .. }
+            | Alert::RuleMatched { tx_url, .. }
+            | Alert::SanctionedTransfer { tx_url, .. } => Some(tx_url),
+            _ => None,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        match self {
+            Alert::FreshnessSloViolated {
+                within_slo_percent,
+                slo_secs,
+                slo_percent,
+                p95_secs,
+            } => format!(
+                "Freshness SLO violated: {:.1}% of blocks available within {}s of finality (target {}%), p95 latency {}s",
+                within_slo_percent, slo_secs, slo_percent, p95_secs
+            ),
+            Alert::FreshnessSloRecovered => "Freshness SLO recovered".to_string(),
+            Alert::HeadLagExceeded {
+                lag_blocks,
+                threshold_blocks,
+                behind_secs,
+            } => format!(
+                "Indexer is {} blocks behind the chain head, more than LAG_ALERT_BLOCKS={} for {}s",
+                lag_blocks, threshold_blocks, behind_secs
+            ),
+            Alert::HeadLagRecovered { lag_blocks } => {
+                format!("Indexer caught up: {} blocks behind the chain head", lag_blocks)
+            }
+            Alert::ReorgBeyondFinality {
+                fork_block,
+                depth,
+                finality_blocks,
+            } => format!(
+                "Observed reorg of depth {} at block {} exceeds FINALITY_BLOCKS={}",
+                depth, fork_block, finality_blocks
+            ),
+            Alert::LargeTransfer {
+                from_address,
+                to_address,
+                amount,
+                ..
+            } => format!("Large transfer of {} from {} to {}", amount, from_address, to_address),
+            Alert::SanctionedTransfer {
+                from_address,
+                to_address,
+                sanctioned_address,
+                amount,
+                ..
+            } => format!(
+                "Transfer of {} from {} to {} touches sanctioned address {}",
+                amount, from_address, to_address, sanctioned_address
+            ),
+            Alert::VolumeAnomaly {
+                address,
+                window_start,
+                window_secs,
+                amount,
+                baseline_amount,
+                ratio,
+                ..
+            } => format!(
+                "Unusual volume: {} moved {} in the {}s from {}, {:.1}x its usual {}",
+                address.as_deref().unwrap_or("the token"),
+                amount,
+                window_secs,
+                window_start.to_rfc3339(),
+                ratio,
+                baseline_amount
+            ),
+            Alert::RuleMatched {
+                rule,
+                from_address,
+                to_address,
+                amount,
+                count,
+                ..
+            } => {
+                let frequency = count.map_or(String::new(), |count| format!(" ({} matching transfers in window)", count));
+                format!("Rule {} matched: {} from {} to {}{}", rule, amount, from_address, to_address, frequency)
+            }
+        }
+    }
+}
+
+impl AlertRule {
+    /// Address, direction and value conditions as a transfer filter.
+    pub fn filter(&self) -> TransferFilter {
+        TransferFilter::new()
+            .address(self.address.clone())
+            .direction(self.direction)
+            .value_range(self.min_value.clone(), self.max_value.clone())
+    }
+
+    /// The parties whose label `counterparty_label` is checked against: the side opposite the
+    /// rule's address, or both sides when the rule has none.
+    fn counterparties<'a>(&self, transfer: &'a TransferEvent) -> Vec<&'a str> {
+        match &self.address {
+            None => vec![&transfer.from_address, &transfer.to_address],
+            Some(address) => {
+                let mut parties = Vec::new();
+                if transfer.from_address.eq_ignore_ascii_case(address) {
+                    parties.push(transfer.to_address.as_str());
+                }
+                if transfer.to_address.eq_ignore_ascii_case(address) {
+                    parties.push(transfer.from_address.as_str());
+                }
+                parties
+            }
+        }
+    }
+
+    /// Checks every condition except frequency. `labels` must cover the transfer's addresses.
+    pub fn matches(&self, transfer: &TransferEvent, labels: &HashMap<String, String>) -> bool {
+        if !self.filter().matches(transfer) {
+            return false;
+        }
+        match &self.counterparty_label {
+            None => true,
+            Some(pattern) => {
+                let pattern = pattern.to_lowercase();
+                self.counterparties(transfer)
+                    .into_iter()
+                    .filter_map(|address| labels.get(address))
+                    .any(|label| label.to_lowercase().contains(&pattern))
+            }
+        }
+    }
+}
+
+/// Remembers when each frequency rule last fired, so one burst alerts once instead of again
+/// every time the sliding window climbs back to `min_count`.
+#[derive(Debug, Default)]
+pub struct RuleMonitor {
+    last_fired: Mutex<HashMap<i64, DateTime<Utc>>>,
+}
+
+impl RuleMonitor {
+    /// Evaluates `rules` against `transfers`, oldest first, returning each rule that fired with
+    /// its alert, timed at the transfer. Frequency rules count stored transfers, so `transfers`
+    /// must already be committed. A frequency rule fires when at least `min_count` matches fall
+    /// in its window, then stays quiet for one window.
+    pub async fn evaluate<'a>(
+        &self,
+        database: &Database,
+        config: &Config,
+        rules: &'a [AlertRule],
+        transfers: &[TransferEvent],
+    ) -> Result<Vec<(&'a AlertRule, FiredAlert)>> {
+        let addresses: Vec<String> = transfers
+            .iter()
+            .flat_map(|t| [t.from_address.clone(), t.to_address.clone()])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let labels = database.get_labels(&addresses).await?;
+        let amounts = AmountFormat::new(config, false);
+
+        let mut matches = Vec::new();
+        for rule in rules {
+            let matched: Vec<(usize, &TransferEvent)> =
+                transfers.iter().enumerate().filter(|(_, t)| rule.matches(t, &labels)).collect();
+            if matched.is_empty() {
+                continue;
+            }
+            let counts = match (rule.min_count, rule.window_secs) {
+                (Some(_), Some(window_secs)) => {
+                    let matched: Vec<&TransferEvent> = matched.iter().map(|(_, t)| *t).collect();
+                    Some(window_counts(database, rule, &matched, window_secs).await?)
+                }
+                _ => None,
+            };
+            for (k, (i, _)) in matched.into_iter().enumerate() {
+                matches.push((i, rule, counts.as_ref().map(|counts| counts[k])));
+            }
+        }
+        matches.sort_by_key(|(i, _, _)| *i);
+
+        let mut last_fired = self.last_fired.lock().unwrap();
+        let mut fired = Vec::new();
+        for (i, rule, count) in matches {
+            let transfer = &transfers[i];
+            if let (Some(count), Some(min_count), Some(window_secs)) = (count, rule.min_count, rule.window_secs) {
+                let quiet = last_fired
+                    .get(&rule.id)
+                    .is_some_and(|at| transfer.timestamp < *at + Duration::seconds(window_secs));
+                if count < min_count || quiet {
+                    continue;
+                }
+                last_fired.insert(rule.id, transfer.timestamp);
+            }
+
+            let alert = Alert::RuleMatched {
+                rule_id: rule.id,
+                rule: rule.name.clone(),
+                transaction_hash: transfer.transaction_hash.clone(),
+                block_number: transfer.block_number,
+                from_address: transfer.from_address.clone(),
+                to_address: transfer.to_address.clone(),
+                value: transfer.value.clone(),
+                amount: amounts.format(&transfer.value),
+                tx_url: format!("{}{}", config.explorer_tx_url, transfer.transaction_hash),
+                count,
+            };
+            fired.push((
+                rule,
+                FiredAlert {
+                    at: transfer.timestamp,
+                    alert,
+                },
+            ));
+        }
+        Ok(fired)
+    }
+}
+
+/// For each of `matched` (oldest first), the stored transfers matching `rule` in the
+/// `window_secs` up to and including it. The whole span is loaded with one query.
+async fn window_counts(database: &Database, rule: &AlertRule, matched: &[&TransferEvent], window_secs: i64) -> Result<Vec<i64>> {
+    let window = Duration::seconds(window_secs);
+    let (first, last) = (matched[0], matched[matched.len() - 1]);
+    let filter = rule.filter().time_range(Some(first.timestamp - window), Some(last.timestamp));
+
+    let mut candidates = Vec::new();
+    database
+        .for_each_transfer(&filter, |t| {
+            candidates.push(t);
+            Ok(())
+        })
+        .await?;
+
+    if rule.counterparty_label.is_some() {
+        let addresses: Vec<String> = candidates
+            .iter()
+            .flat_map(|t| rule.counterparties(t))
+            .map(str::to_string)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let labels = database.get_labels(&addresses).await?;
+        candidates.retain(|t| rule.matches(t, &labels));
+    }
+
+    // In chain order timestamps never decrease, so both window edges can be binary searched.
+    candidates.sort_by_key(|t| (t.block_number, t.log_index));
+    Ok(matched
+        .iter()
+        .map(|t| {
+            let end = candidates.partition_point(|c| (c.block_number, c.log_index) <= (t.block_number, t.log_index));
+            let start = candidates[..end].partition_point(|c| c.timestamp < t.timestamp - window);
+            (end - start) as i64
+        })
+        .collect())
+}
+
+/// Tracks whether the freshness SLO is currently violated so only transitions alert.
+#[derive(Debug, Default)]
+pub struct FreshnessMonitor {
+    violated: AtomicBool,
+}
+
+impl FreshnessMonitor {
+    /// Evaluates the SLO over the window ending at `now`.
+    pub async fn check(&self, database: &Database, config: &Config, now: DateTime<Utc>) -> Result<Option<Alert>> {
+        let since = now - Duration::hours(SLO_WINDOW_HOURS);
+        let samples = database.get_latency_samples(since, now).await?;
+        let report = LatencyReport::new(
+            SLO_WINDOW_HOURS,
+            &samples,
+            config.finality_blocks,
+            config.freshness_slo_secs,
+            config.freshness_slo_percent,
+        );
+
+        let was_violated = self.violated.swap(!report.slo_met, Ordering::Relaxed);
+        Ok(if !report.slo_met && !was_violated {
+            Some(Alert::FreshnessSloViolated {
+                within_slo_percent: report.within_slo_percent.unwrap_or(0.0),
+                slo_secs: report.slo_secs,
+                slo_percent: report.slo_percent,
+                p95_secs: report.p95_secs.unwrap_or(0),
+            })
+        } else if report.slo_met && was_violated {
+            Some(Alert::FreshnessSloRecovered)
+        } else {
+            None
+        })
+    }
+}
+
+/// Tracks how long the indexer has been more than `LAG_ALERT_BLOCKS` behind the chain head, so
+/// lag alerts once it has lasted `LAG_ALERT_MINUTES` and again when it recovers.
+#[derive(Debug, Default)]
+pub struct LagMonitor {
+    /// When the lag went above the threshold; `None` while within it.
+    behind_since: Mutex<Option<DateTime<Utc>>>,
+    alerted: AtomicBool,
+}
+
+impl LagMonitor {
+    /// Records `lag_blocks` as observed at `now`. Never alerts when `LAG_ALERT_BLOCKS` is 0.
+    pub fn observe(&self, lag_blocks: u64, config: &Config, now: DateTime<Utc>) -> Option<Alert> {
+        if config.lag_alert_blocks == 0 {
+            return None;
+        }
+        let mut behind_since = self.behind_since.lock().unwrap();
+        if lag_blocks <= config.lag_alert_blocks {
+            *behind_since = None;
+            return self
+                .alerted
+                .swap(false, Ordering::Relaxed)
+                .then_some(Alert::HeadLagRecovered { lag_blocks });
+        }
+
+        let behind = now - *behind_since.get_or_insert(now);
+        (behind >= Duration::minutes(config.lag_alert_minutes as i64) && !self.alerted.swap(true, Ordering::Relaxed))
+            .then_some(Alert::HeadLagExceeded {
+                lag_blocks,
+                threshold_blocks: config.lag_alert_blocks,
+                behind_secs: behind.num_seconds(),
+            })
+    }
+}
+
+/// An alert that would have fired at `at`.
+#[derive(Debug, Serialize)]
+pub struct FiredAlert {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub alert: Alert,
+}
+
+/// Replays alert rules over `[from, to)` in `step` increments against stored history.
+pub async fn backtest(
+    database: &Database,
+    config: &Config,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    step: Duration,
+) -> Result<Vec<FiredAlert>> {
+    let monitor = FreshnessMonitor::default();
+    let rule_monitor = RuleMonitor::default();
+    let rules = database.list_alert_rules().await?;
+    let mut fired = Vec::new();
+
+    let mut now = from;
+    while now < to {
+        let next = std::cmp::min(now + step, to);
+
+        for reorg in database.get_reorg_events(now, next).await? {
+            if let Some(alert) = Alert::for_reorg(&reorg) {
+                fired.push(FiredAlert {
+                    at: reorg.detected_at,
+                    alert,
+                });
+            }
+        }
+
+        if config.alert_min_value.is_some() || !rules.is_empty() {
+            let mut filter = TransferFilter::new().time_range(Some(now), Some(next)).include_archived(true);
+            if rules.is_empty() {
+                filter = filter.value_range(config.alert_min_value.map(|v| v.to_string()), None);
+            }
+
+            let mut transfers = Vec::new();
+            database
+                .for_each_transfer(&filter, |transfer| {
+                    // `to_time` is inclusive; transfers at `next` belong to the following step.
+                    if transfer.timestamp < next {
+                        transfers.push(transfer);
+                    }
+                    Ok(())
+                })
+                .await?;
+
+            for transfer in &transfers {
+                if let Some(alert) = Alert::for_transfer(transfer, config) {
+                    fired.push(FiredAlert {
+                        at: transfer.timestamp,
+                        alert,
+                    });
+                }
+            }
+            fired.extend(rule_monitor.evaluate(database, config, &rules, &transfers).await?.into_iter().map(|(_, f)| f));
+        }
+
+        if let Some(alert) = monitor.check(database, config, next).await? {
+            fired.push(FiredAlert { at: next, alert });
+        }
+
+        now = next;
+    }
+
+    fired.sort_by_key(|f| f.at);
+    Ok(fired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lag_alerts_once_sustained_and_once_recovered() {
+        let config = Config {
+            lag_alert_blocks: 100,
+            lag_alert_minutes: 10,
+            ..Config::from_env().unwrap()
+        };
+        let monitor = LagMonitor::default();
+        let start = Utc::now();
+        let at = |mins| start + Duration::minutes(mins);
+
+        assert!(monitor.observe(150, &config, at(0)).is_none());
+        assert!(monitor.observe(50, &config, at(5)).is_none());
+        assert!(monitor.observe(150, &config, at(6)).is_none());
+        assert!(monitor.observe(150, &config, at(15)).is_none());
+        assert!(matches!(
+            monitor.observe(180, &config, at(16)),
+            Some(Alert::HeadLagExceeded { lag_blocks: 180, threshold_blocks: 100, behind_secs: 600 })
+        ));
+        assert!(monitor.observe(200, &config, at(30)).is_none());
+        assert!(matches!(monitor.observe(20, &config, at(31)), Some(Alert::HeadLagRecovered { lag_blocks: 20 })));
+        assert!(monitor.observe(20, &config, at(32)).is_none());
+
+        let disabled = Config { lag_alert_blocks: 0, ..config };
+        assert!(LagMonitor::default().observe(u64::MAX, &disabled, at(0)).is_none());
+    }
+}
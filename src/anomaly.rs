@@ -0,0 +1,218 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use ethers_core::types::U256;
+use std::collections::BTreeMap;
+
+use crate::{
+    alerts::Alert, config::Config, database::Database, models::VolumeBaseline, output::AmountFormat,
+    query::TransferFilter,
+};
+
+/// Key of the token-wide baseline in `volume_baselines`.
+pub const GLOBAL_BASELINE: &str = "*";
+
+/// Windows a baseline must have folded in before it is trusted to alert against.
+const MIN_BASELINE_WINDOWS: i64 = 5;
+
+/// Windows analyzed per call, so catching up after downtime does not stall indexing.
+const MAX_WINDOWS_PER_RUN: i64 = 168;
+
+/// How volume is cut into windows and how far from its baseline a window must be to alert.
+#[derive(Debug, Clone)]
+pub struct AnomalySettings {
+    pub window_secs: u64,
+    pub baseline_windows: u64,
+    pub sensitivity: f64,
+    /// In token units.
+    pub min_volume: f64,
+    pub amounts: AmountFormat,
+}
+
+impl AnomalySettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            window_secs: config.anomaly_window_secs.max(1),
+            baseline_windows: config.anomaly_baseline_windows.max(1),
+            sensitivity: config.anomaly_sensitivity,
+            min_volume: config.anomaly_min_volume,
+            amounts: AmountFormat::new(config, false),
+        }
+    }
+
+    /// Weight of the newest window in the moving averages.
+    fn alpha(&self) -> f64 {
+        2.0 / (self.baseline_windows as f64 + 1.0)
+    }
+}
+
+/// Folds every window that ended before `through` into the stored baselines and returns an
+/// alert for each address, and for the token, whose volume in a window reached `sensitivity`
+/// times its baseline. The first run learns from the `baseline_windows` before `through`
+/// without alerting.
+pub async fn detect_anomalies(database: &Database, settings: &AnomalySettings, through: DateTime<Utc>) -> Result<Vec<Alert>> {
+    let current = through.timestamp().div_euclid(settings.window_secs as i64);
+    let (mut window, warm_up) = match database.get_volume_baselines_window().await? {
+        Some((window, window_secs)) if window_secs == settings.window_secs => (window + 1, false),
+        stored => {
+            if stored.is_some() {
+                database.clear_volume_baselines().await?;
+            }
+            (current - settings.baseline_windows as i64, true)
+        }
+    };
+
+    let end = if warm_up { current } else { current.min(window + MAX_WINDOWS_PER_RUN) };
+    let mut alerts = Vec::new();
+    while window < end {
+        let found = analyze_window(database, settings, window).await?;
+        if !warm_up {
+            alerts.extend(found);
+        }
+        window += 1;
+    }
+    Ok(alerts)
+}
+
+/// Compares `window`'s volumes with the baselines, then folds them in.
+async fn analyze_window(database: &Database, settings: &AnomalySettings, window: i64) -> Result<Vec<Alert>> {
+    let start = Utc.timestamp_opt(window * settings.window_secs as i64, 0).unwrap();
+    let filter = TransferFilter::new().time_range(Some(start), Some(start + Duration::seconds(settings.window_secs as i64 - 1)));
+
+    let mut total = U256::zero();
+    let mut volumes: BTreeMap<String, U256> = BTreeMap::new();
+    database
+        .for_each_transfer(&filter, |transfer| {
+            let value = U256::from_dec_str(&transfer.value)?;
+            total = total.saturating_add(value);
+            let volume = volumes.entry(transfer.from_address.clone()).or_default();
+            *volume = volume.saturating_add(value);
+            if transfer.to_address != transfer.from_address {
+                let volume = volumes.entry(transfer.to_address).or_default();
+                *volume = volume.saturating_add(value);
+            }
+            Ok(())
+        })
+        .await?;
+
+    let mut keys = vec![GLOBAL_BASELINE.to_string()];
+    keys.extend(volumes.keys().cloned());
+    let baselines = database.get_volume_baselines(&keys).await?;
+
+    let mut alerts = Vec::new();
+    let mut updated = Vec::new();
+    // The token is active in every window, quiet ones included; addresses only when they move.
+    for (address, volume) in std::iter::once((GLOBAL_BASELINE.to_string(), total)).chain(volumes) {
+        let baseline = baselines.get(&address.to_lowercase());
+        let volume = to_f64(volume);
+        if let Some(alert) = check(settings, &address, start, volume, baseline) {
+            alerts.push(alert);
+        }
+        updated.push(fold(settings, address, window, volume, baseline));
+    }
+
+    database.save_volume_baselines(&updated, window, settings.window_secs).await?;
+    Ok(alerts)
+}
+
+fn check(settings: &AnomalySettings, address: &str, window_start: DateTime<Utc>, volume: f64, baseline: Option<&VolumeBaseline>) -> Option<Alert> {
+    let baseline = baseline.filter(|b| b.windows >= MIN_BASELINE_WINDOWS && b.average > 0.0)?;
+    let units = volume / 10f64.powi(settings.amounts.decimals as i32);
+    let ratio = volume / baseline.average;
+    (units >= settings.min_volume && ratio >= settings.sensitivity).then(|| Alert::VolumeAnomaly {
+        address: (address != GLOBAL_BASELINE).then(|| address.to_string()),
+        window_start,
+        window_secs: settings.window_secs,
+        volume: format!("{:.0}", volume),
+        amount: settings.amounts.format(&format!("{:.0}", volume)),
+        baseline_amount: settings.amounts.format(&format!("{:.0}", baseline.average)),
+        ratio,
+    })
+}
+
+fn fold(settings: &AnomalySettings, address: String, window: i64, volume: f64, baseline: Option<&VolumeBaseline>) -> VolumeBaseline {
+    match baseline {
+        Some(baseline) => VolumeBaseline {
+            address,
+            average: baseline.average + settings.alpha() * (volume - baseline.average),
+            windows: baseline.windows + 1,
+            last_window: window,
+        },
+        None => VolumeBaseline {
+            address,
+            average: volume,
+            windows: 1,
+            last_window: window,
+        },
+    }
+}
+
+fn to_f64(value: U256) -> f64 {
+    value.to_string().parse().unwrap_or(f64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::{database, TransferBuilder};
+
+    const WHALE: &str = "0x28C6c06298d514Db089934071355E5743bf21d60";
+    const SHOP: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const NEWCOMER: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    fn hour(n: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_767_225_600 + n * 3600, 0).unwrap()
+    }
+
+    async fn insert(database: &Database, n: i64, from: &str, to: &str, value: &str) {
+        let transfer = TransferBuilder::new(n)
+            .from(from)
+            .to(to)
+            .value(value)
+            .at(hour(n / 10) + Duration::minutes(n % 10))
+            .build();
+        database.insert_transfer_event(&transfer).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn alerts_on_windows_far_above_the_baseline() {
+        let database = database().await;
+        let settings = AnomalySettings {
+            window_secs: 3600,
+            baseline_windows: 8,
+            sensitivity: 10.0,
+            min_volume: 100.0,
+            amounts: AmountFormat {
+                decimals: 6,
+                symbol: "USDC".to_string(),
+                raw: false,
+            },
+        };
+
+        // 50 USDC an hour for eight hours.
+        for h in 0..8 {
+            insert(&database, h * 10, WHALE, SHOP, "50000000").await;
+        }
+        // The first run only learns the history.
+        assert!(detect_anomalies(&database, &settings, hour(8)).await.unwrap().is_empty());
+
+        // A whale move, and a large first transfer from an address with no history.
+        insert(&database, 80, WHALE, SHOP, "900000000").await;
+        insert(&database, 81, NEWCOMER, SHOP, "1000000").await;
+        let alerts = detect_anomalies(&database, &settings, hour(9)).await.unwrap();
+        let flagged: Vec<_> = alerts
+            .iter()
+            .map(|alert| match alert {
+                Alert::VolumeAnomaly { address, ratio, .. } => (address.clone(), ratio.round()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            flagged,
+            [(None, 18.0), (Some(WHALE.to_string()), 18.0), (Some(SHOP.to_string()), 18.0)]
+        );
+
+        // Back to normal, after the spike raised the baselines.
+        insert(&database, 90, WHALE, SHOP, "50000000").await;
+        assert!(detect_anomalies(&database, &settings, hour(10)).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,816 @@
+use anyhow::Result;
+use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        ConnectInfo, Path, Query, Request, State,
+    },
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::net::TcpListener;
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    IntoParams, Modify, OpenApi, ToSchema,
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::{
+    address::normalize_address,
+    auth::{create_api_key, key_name, Authenticator, Caller, Rejection, Scope},
+    database::{is_interrupted, Database, QueryBudget},
+    models::{ApiKey, HolderSnapshot, PeriodActivity, TransferPage},
+    query::{parse_time, Cursor, Direction, FlowInterval, TransferFilter},
+    rate_limit::RateLimiter,
+    series::{supply_series, volume_series, Downsample, Series},
+    stats::{address_stats_report, collect_stats, AddressStatsReport, StatsReport},
+    units::parse_amount,
+    velocity::velocity,
+};
+
+/// Page size when a request gives no `limit`.
+pub(crate) const DEFAULT_LIMIT: i64 = 100;
+
+/// Largest page a single request can ask for.
+pub(crate) const MAX_LIMIT: i64 = 1_000;
+
+/// Chart points when a request gives no `points`, and the most it can ask for.
+const DEFAULT_POINTS: usize = 500;
+const MAX_POINTS: usize = 5_000;
+
+/// The single page web dashboard, served at `/` by `serve --dashboard`.
+const DASHBOARD_HTML: &str = include_str!("../static/dashboard.html");
+
+/// The OpenAPI document of every `/api` route, served at `/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "USDCWatch API",
+        description = "Access to indexed ERC-20 transfers, plus API key management under `/api/keys` \
+                       for admin-scoped tokens; a read-only server answers `POST /api/keys` and \
+                       `DELETE /api/keys/{name}` with a 403. Amounts are raw integer strings unless \
+                       noted; scale them by `token_decimals` from `/api/info`. Any request may also \
+                       fail with a 500, or a 503 when its queries run past the server's time limit. \
+                       Once any API token exists, every request needs one as a bearer token: a 401 \
+                       means it was missing or unknown, a 403 that its scope is too narrow. A 429 \
+                       means the caller is over the server's rate limit; retry after `Retry-After` seconds."
+    ),
+    paths(
+        info, stats, transfers, address, volume, holders, volume_chart, supply_chart,
+        api_keys, create_key, revoke_key
+    ),
+    // Only referenced from query parameters, which do not register their schemas.
+    components(schemas(Direction, FlowInterval)),
+    modifiers(&BearerAuth),
+    security(("bearer" = []))
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+/// `ApiDoc` without the empty license utoipa fills in from Cargo.toml, which has none.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    let mut openapi = ApiDoc::openapi();
+    openapi.info.license = None;
+    openapi
+}
+
+/// What every handler needs; cloned per request.
+#[derive(Clone)]
+pub struct ApiState {
+    pub database: Database,
+    pub contract_address: String,
+    pub token_symbol: String,
+    pub token_decimals: u8,
+    pub auth: Authenticator,
+    /// Shared by every request; `None` for no limit.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// How long one request's queries may run before SQLite interrupts them; `None` for no limit.
+    /// Takes effect only on a database opened with `PoolSettings::interruptible`.
+    pub query_timeout: Option<Duration>,
+}
+
+/// Token details a client needs to scale raw amounts.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiInfo {
+    pub contract_address: String,
+    pub token_symbol: String,
+    pub token_decimals: u8,
+    pub last_processed_block: Option<i64>,
+}
+
+/// Filters of `GET /api/transfers`, named like the `query` flags. Amounts are in token units, or
+/// raw with a `raw` suffix; times are RFC3339 or relative ages like `24h`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransferParams {
+    pub address: Option<String>,
+    /// Which side `address` is matched on; defaults to both.
+    pub direction: Option<Direction>,
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub min_value: Option<String>,
+    pub max_value: Option<String>,
+    pub from_time: Option<String>,
+    pub to_time: Option<String>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// Defaults to 100, at most 1000.
+    pub limit: Option<i64>,
+    /// Also search transfers moved to the archive.
+    #[serde(default)]
+    pub archived: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VolumeParams {
+    pub interval: Option<FlowInterval>,
+    /// Defaults to 30 days ago.
+    pub from_time: Option<String>,
+    pub to_time: Option<String>,
+}
+
+/// Range and downsampling of `GET /api/series/volume` and `/api/series/supply`. `address`,
+/// `direction` and `archived` only apply to volume.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SeriesParams {
+    pub address: Option<String>,
+    pub direction: Option<Direction>,
+    /// Defaults to 30 days ago.
+    pub from_time: Option<String>,
+    pub to_time: Option<String>,
+    /// Defaults to 500, at most 5000.
+    pub points: Option<usize>,
+    /// Defaults to `lttb`; `sum` is rejected for supply.
+    pub method: Option<Downsample>,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// Body of every failed request.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// Body of `POST /api/keys`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NewApiKey {
+    pub name: String,
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// A key just created over the API.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreatedApiKey {
+    pub name: String,
+    pub scope: Scope,
+    /// The bearer token; it is not stored and cannot be shown again.
+    pub token: String,
+}
+
+/// A failed request, answered as an `ErrorBody`.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(error: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: error.to_string(),
+        }
+    }
+
+    fn not_found(error: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: error.to_string(),
+        }
+    }
+
+    fn unauthorized(error: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: error.to_string(),
+        }
+    }
+
+    fn forbidden(error: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: error.to_string(),
+        }
+    }
+
+    fn conflict(error: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        if is_interrupted(&error) {
+            return Self {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                message: "Query ran past the server's time limit; narrow the filters".to_string(),
+            };
+        }
+        tracing::error!(error = %format!("{:#}", error), "API request failed");
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(ErrorBody { error: self.message });
+        if self.status == StatusCode::UNAUTHORIZED {
+            return (self.status, [(header::WWW_AUTHENTICATE, "Bearer")], body).into_response();
+        }
+        (self.status, body).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<Json<T>, ApiError>;
+
+/// The JSON API with its OpenAPI document at `/openapi.json` and Swagger UI at `/docs`, plus the
+/// web dashboard at `/` when `dashboard` is set. Only the `/api` routes check tokens.
+pub fn router(state: ApiState, dashboard: bool) -> Router {
+    let admin = Router::new()
+        .route("/api/keys", get(api_keys).post(create_key))
+        .route("/api/keys/{name}", delete(revoke_key))
+        .route_layer(middleware::from_fn_with_state((state.clone(), Scope::Admin), authorize));
+    let mut router = Router::new()
+        .route("/api/info", get(info))
+        .route("/api/stats", get(stats))
+        .route("/api/transfers", get(transfers))
+        .route("/api/addresses/{address}", get(address))
+        .route("/api/volume", get(volume))
+        .route("/api/holders", get(holders))
+        .route("/api/series/volume", get(volume_chart))
+        .route("/api/series/supply", get(supply_chart))
+        .route_layer(middleware::from_fn_with_state((state.clone(), Scope::Read), authorize))
+        .merge(admin)
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", openapi()));
+    if dashboard {
+        router = router.route("/", get(|| async { Html(DASHBOARD_HTML) }));
+    }
+    router
+        .layer(middleware::from_fn_with_state(state.clone(), with_query_budget))
+        .with_state(state)
+}
+
+/// Binds the request's queries to a fresh `QueryBudget`, which is cancelled if axum drops the
+/// request because its client went away.
+async fn with_query_budget(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    QueryBudget::new(state.query_timeout).run(next.run(request)).await
+}
+
+/// Lets the request through, with its `Caller` in the extensions, when its bearer token grants
+/// `scope` and its caller is within the rate limit.
+async fn authorize(State((state, scope)): State<(ApiState, Scope)>, mut request: Request, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim().to_string());
+    let client = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+    match state.auth.admit(token.as_deref(), client, scope, state.rate_limiter.as_deref()).await {
+        Ok(caller) => {
+            request.extensions_mut().insert::<Caller>(caller);
+            next.run(request).await
+        }
+        Err(Rejection::Unauthorized(message)) => ApiError::unauthorized(message).into_response(),
+        Err(Rejection::Forbidden(scope)) => {
+            ApiError::forbidden(format!("Needs a token with {} scope", scope.as_str())).into_response()
+        }
+        Err(Rejection::RateLimited(wait)) => too_many_requests(wait),
+        Err(Rejection::Failed(e)) => ApiError::from(e).into_response(),
+    }
+}
+
+/// A 429 telling the client to wait `wait`, rounded up to whole seconds.
+fn too_many_requests(wait: Duration) -> Response {
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    let body = Json(ErrorBody {
+        error: format!("Rate limit exceeded; retry in {}s", secs),
+    });
+    (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, secs.to_string())], body).into_response()
+}
+
+/// Serves `router` on `listener` until Ctrl-C, with client addresses for the rate limiter.
+pub async fn serve(listener: TcpListener, router: Router) -> Result<()> {
+    axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
+
+#[utoipa::path(get, path = "/api/info", responses((status = 200, body = ApiInfo)))]
+async fn info(State(state): State<ApiState>) -> ApiResult<ApiInfo> {
+    let last_block = state.database.query_block_activity(None, None, 1).await?.into_iter().next();
+    Ok(Json(ApiInfo {
+        contract_address: state.contract_address,
+        token_symbol: state.token_symbol,
+        token_decimals: state.token_decimals,
+        last_processed_block: last_block.map(|block| block.block_number),
+    }))
+}
+
+/// `stats` with the chain head `index` last saw rather than the node's, which would put an RPC
+/// call on every request.
+#[utoipa::path(get, path = "/api/stats", responses((status = 200, body = StatsReport)))]
+async fn stats(State(state): State<ApiState>) -> ApiResult<StatsReport> {
+    let chain_head = state.database.get_meta("chain_head").await?.and_then(|head| head.parse().ok());
+    Ok(Json(collect_stats(&state.database, chain_head).await?))
+}
+
+/// Newest first, with labels, one page at a time.
+#[utoipa::path(
+    get,
+    path = "/api/transfers",
+    params(TransferParams),
+    responses((status = 200, body = TransferPage), (status = 400, body = ErrorBody))
+)]
+async fn transfers(
+    State(state): State<ApiState>,
+    params: Result<Query<TransferParams>, QueryRejection>,
+) -> ApiResult<TransferPage> {
+    let Query(params) = params.map_err(ApiError::bad_request)?;
+    let filter = transfer_filter(&params, state.token_decimals, state.database.now()).map_err(ApiError::bad_request)?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    Ok(Json(labeled_page(&state.database, &filter, limit).await?))
+}
+
+/// A page of transfers matching `filter` with their addresses' labels filled in.
+pub(crate) async fn labeled_page(database: &Database, filter: &TransferFilter, limit: i64) -> Result<TransferPage> {
+    let mut page = database.query_transfers_page(filter, limit).await?;
+    let addresses: Vec<String> = page
+        .transfers
+        .iter()
+        .flat_map(|t| [t.from_address.clone(), t.to_address.clone()])
+        .collect();
+    let labels = database.get_labels(&addresses).await?;
+    for transfer in &mut page.transfers {
+        transfer.from_label = labels.get(&transfer.from_address).cloned();
+        transfer.to_label = labels.get(&transfer.to_address).cloned();
+    }
+    Ok(page)
+}
+
+/// `stats --address`, from the rollups.
+#[utoipa::path(
+    get,
+    path = "/api/addresses/{address}",
+    params(("address" = String, Path)),
+    responses(
+        (status = 200, body = AddressStatsReport),
+        (status = 400, body = ErrorBody),
+        (status = 404, description = "No activity recorded for the address", body = ErrorBody)
+    )
+)]
+async fn address(State(state): State<ApiState>, Path(address): Path<String>) -> ApiResult<AddressStatsReport> {
+    let address = normalize_address(&address).map_err(ApiError::bad_request)?;
+    if state.database.get_address_stats(&address).await?.is_none() {
+        return Err(ApiError::not_found(format!("No activity recorded for {}", address)));
+    }
+    Ok(Json(address_stats_report(&state.database, &address).await?))
+}
+
+/// `analytics velocity`: volume, velocity and active addresses per period.
+#[utoipa::path(
+    get,
+    path = "/api/volume",
+    params(VolumeParams),
+    responses((status = 200, body = Vec<PeriodActivity>), (status = 400, body = ErrorBody))
+)]
+async fn volume(
+    State(state): State<ApiState>,
+    params: Result<Query<VolumeParams>, QueryRejection>,
+) -> ApiResult<Vec<PeriodActivity>> {
+    let Query(params) = params.map_err(ApiError::bad_request)?;
+    let now = state.database.now();
+    let from_time = parse_time(params.from_time.as_deref().unwrap_or("30d"), now).map_err(ApiError::bad_request)?;
+    let to_time = params
+        .to_time
+        .as_deref()
+        .map(|input| parse_time(input, now))
+        .transpose()
+        .map_err(ApiError::bad_request)?;
+    let interval = params.interval.unwrap_or_default();
+    Ok(Json(velocity(&state.database, interval, Some(from_time), to_time).await?))
+}
+
+/// The current holder count and supply; `null` until the rollups have run.
+#[utoipa::path(get, path = "/api/holders", responses((status = 200, body = Option<HolderSnapshot>)))]
+async fn holders(State(state): State<ApiState>) -> ApiResult<Option<HolderSnapshot>> {
+    Ok(Json(state.database.holder_snapshot().await?))
+}
+
+/// Volume per time slot over a long range, downsampled to `points` for charting.
+#[utoipa::path(
+    get,
+    path = "/api/series/volume",
+    params(SeriesParams),
+    responses((status = 200, body = Series), (status = 400, body = ErrorBody))
+)]
+async fn volume_chart(
+    State(state): State<ApiState>,
+    params: Result<Query<SeriesParams>, QueryRejection>,
+) -> ApiResult<Series> {
+    let Query(params) = params.map_err(ApiError::bad_request)?;
+    let (from, to) = series_range(&params, state.database.now()).map_err(ApiError::bad_request)?;
+    let filter = TransferFilter::new()
+        .address(params.address.as_deref().map(normalize_address).transpose().map_err(ApiError::bad_request)?)
+        .direction(params.direction.unwrap_or_default())
+        .include_archived(params.archived);
+    let points = params.points.unwrap_or(DEFAULT_POINTS).min(MAX_POINTS);
+    let method = params.method.unwrap_or_default();
+    Ok(Json(volume_series(&state.database, &filter, from, to, points, method, state.token_decimals).await?))
+}
+
+/// Tracked supply at holder snapshots, downsampled to `points` for charting.
+#[utoipa::path(
+    get,
+    path = "/api/series/supply",
+    params(SeriesParams),
+    responses((status = 200, body = Series), (status = 400, body = ErrorBody))
+)]
+async fn supply_chart(
+    State(state): State<ApiState>,
+    params: Result<Query<SeriesParams>, QueryRejection>,
+) -> ApiResult<Series> {
+    let Query(params) = params.map_err(ApiError::bad_request)?;
+    let (from, to) = series_range(&params, state.database.now()).map_err(ApiError::bad_request)?;
+    let method = params.method.unwrap_or_default();
+    if method == Downsample::Sum {
+        return Err(ApiError::bad_request("Supply cannot be summed; use lttb, max or avg"));
+    }
+    let points = params.points.unwrap_or(DEFAULT_POINTS).min(MAX_POINTS);
+    Ok(Json(supply_series(&state.database, from, to, points, method, state.token_decimals).await?))
+}
+
+/// Stored API keys, without their tokens.
+#[utoipa::path(get, path = "/api/keys", responses((status = 200, body = Vec<ApiKey>)))]
+async fn api_keys(State(state): State<ApiState>) -> ApiResult<Vec<ApiKey>> {
+    Ok(Json(state.database.list_api_keys().await?))
+}
+
+/// `api-key create`: the token is only in this response.
+#[utoipa::path(
+    post,
+    path = "/api/keys",
+    request_body = NewApiKey,
+    responses(
+        (status = 201, body = CreatedApiKey),
+        (status = 400, body = ErrorBody),
+        (status = 403, description = "The server is read-only", body = ErrorBody),
+        (status = 409, description = "A key with this name exists", body = ErrorBody)
+    )
+)]
+async fn create_key(
+    State(state): State<ApiState>,
+    body: Result<Json<NewApiKey>, JsonRejection>,
+) -> std::result::Result<(StatusCode, Json<CreatedApiKey>), ApiError> {
+    if state.database.is_read_only() {
+        return Err(ApiError::forbidden("The server is read-only; create keys with `api-key create`"));
+    }
+    let Json(key) = body.map_err(ApiError::bad_request)?;
+    let name = key_name(&key.name).map_err(ApiError::bad_request)?;
+    if state.database.list_api_keys().await?.iter().any(|existing| existing.name == name) {
+        return Err(ApiError::conflict(format!("An API key named {} already exists", name)));
+    }
+    let token = create_api_key(&state.database, name, key.scope).await?;
+    let created = CreatedApiKey {
+        name: name.to_string(),
+        scope: key.scope,
+        token,
+    };
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// `api-key revoke`: the token stops working immediately.
+#[utoipa::path(
+    delete,
+    path = "/api/keys/{name}",
+    params(("name" = String, Path)),
+    responses(
+        (status = 204),
+        (status = 403, description = "The server is read-only", body = ErrorBody),
+        (status = 404, body = ErrorBody)
+    )
+)]
+async fn revoke_key(State(state): State<ApiState>, Path(name): Path<String>) -> std::result::Result<StatusCode, ApiError> {
+    if state.database.is_read_only() {
+        return Err(ApiError::forbidden("The server is read-only; revoke keys with `api-key revoke`"));
+    }
+    if !state.database.revoke_api_key(&name).await? {
+        return Err(ApiError::not_found(format!("No API key named {}", name)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn series_range(params: &SeriesParams, now: chrono::DateTime<chrono::Utc>) -> Result<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> {
+    let from = parse_time(params.from_time.as_deref().unwrap_or("30d"), now)?;
+    let to = params.to_time.as_deref().map(|input| parse_time(input, now)).transpose()?.unwrap_or(now);
+    Ok((from, to))
+}
+
+/// The same filter `query` builds from its flags.
+pub(crate) fn transfer_filter(params: &TransferParams, decimals: u8, now: chrono::DateTime<chrono::Utc>) -> Result<TransferFilter> {
+    let parse_value = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|v| parse_amount(v, decimals).map(|amount| amount.to_string()))
+            .transpose()
+    };
+    let parse_time = |value: &Option<String>| value.as_deref().map(|input| parse_time(input, now)).transpose();
+
+    Ok(TransferFilter::new()
+        .address(params.address.as_deref().map(normalize_address).transpose()?)
+        .direction(params.direction.unwrap_or_default())
+        .block_range(params.from_block, params.to_block)
+        .value_range(parse_value(&params.min_value)?, parse_value(&params.max_value)?)
+        .time_range(parse_time(&params.from_time)?, parse_time(&params.to_time)?)
+        .cursor(params.cursor.as_deref().map(Cursor::decode).transpose()?)
+        .include_archived(params.archived))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::{database, TransferBuilder};
+    use futures::StreamExt;
+    use usdcwatch_client::ApiClient;
+
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const BOB: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    async fn insert(database: &Database, block_number: i64, from: &str, to: &str, value: &str) {
+        let transfer = TransferBuilder::new(block_number).from(from).to(to).value(value).build();
+        database.insert_transfer_event(&transfer).await.unwrap();
+    }
+
+    /// Serves the API on a free local port and returns its base URL.
+    async fn spawn(database: Database, dashboard: bool) -> String {
+        spawn_state(state(database), dashboard).await
+    }
+
+    fn state(database: Database) -> ApiState {
+        ApiState {
+            auth: Authenticator::new(database.clone(), &[]),
+            rate_limiter: None,
+            database,
+            contract_address: ALICE.to_string(),
+            token_symbol: "USDC".to_string(),
+            token_decimals: 6,
+            query_timeout: None,
+        }
+    }
+
+    async fn spawn_state(state: ApiState, dashboard: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let service = router(state, dashboard).into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, service).await });
+        base
+    }
+
+    #[tokio::test]
+    async fn serves_filtered_pages_and_errors_as_json() {
+        let database = database().await;
+        insert(&database, 1, ALICE, BOB, "5000000").await;
+        insert(&database, 2, BOB, ALICE, "1000000").await;
+        insert(&database, 3, ALICE, BOB, "2000000").await;
+        database.add_label(BOB, "Bob", None).await.unwrap();
+        let base = spawn(database, false).await;
+        let client = reqwest::Client::new();
+
+        let page: serde_json::Value = client
+            .get(format!("{}/api/transfers?address={}&direction=sent&min_value=2&limit=1", base, ALICE))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(page["transfers"][0]["block_number"], 3);
+        assert_eq!(page["transfers"][0]["to_label"], "Bob");
+
+        let next = format!(
+            "{}/api/transfers?address={}&direction=sent&min_value=2&limit=1&cursor={}",
+            base,
+            ALICE,
+            page["next_cursor"].as_str().unwrap()
+        );
+        let page: serde_json::Value = client.get(next).send().await.unwrap().json().await.unwrap();
+        assert_eq!(page["transfers"][0]["block_number"], 1);
+
+        for query in ["min_value=lots", "direction=up"] {
+            let response = client.get(format!("{}/api/transfers?{}", base, query)).send().await.unwrap();
+            assert_eq!(response.status().as_u16(), 400);
+            assert!(response.json::<serde_json::Value>().await.unwrap()["error"].is_string());
+        }
+
+        let response = client.get(format!("{}/api/addresses/{}", base, ALICE)).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+        let response = client.get(format!("{}/", base)).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn tokens_are_required_once_any_exist() {
+        let database = database().await;
+        let admin = create_api_key(&database, "ops", Scope::Admin).await.unwrap();
+        let base = spawn(database, false).await;
+        let client = reqwest::Client::new();
+        let status = |request: reqwest::RequestBuilder| async move { request.send().await.unwrap().status().as_u16() };
+
+        let missing = client.get(format!("{}/api/info", base)).send().await.unwrap();
+        assert_eq!(missing.status().as_u16(), 401);
+        assert_eq!(missing.headers()["www-authenticate"], "Bearer");
+        assert_eq!(status(client.get(format!("{}/api/info", base)).bearer_auth("uwk_guess")).await, 401);
+        assert_eq!(status(client.get(format!("{}/openapi.json", base))).await, 200);
+
+        let created: CreatedApiKey = client
+            .post(format!("{}/api/keys", base))
+            .bearer_auth(&admin)
+            .json(&serde_json::json!({ "name": "grafana" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(created.scope, Scope::Read);
+        let duplicate = client
+            .post(format!("{}/api/keys", base))
+            .bearer_auth(&admin)
+            .json(&serde_json::json!({ "name": "grafana", "scope": "admin" }));
+        assert_eq!(status(duplicate).await, 409);
+
+        assert_eq!(status(client.get(format!("{}/api/info", base)).bearer_auth(&created.token)).await, 200);
+        assert_eq!(status(client.get(format!("{}/api/keys", base)).bearer_auth(&created.token)).await, 403);
+        assert_eq!(status(client.delete(format!("{}/api/keys/grafana", base)).bearer_auth(&admin)).await, 204);
+        assert_eq!(status(client.get(format!("{}/api/info", base)).bearer_auth(&created.token)).await, 401);
+    }
+
+    #[tokio::test]
+    async fn callers_over_the_rate_limit_get_429() {
+        let database = database().await;
+        let token = create_api_key(&database, "grafana", Scope::Read).await.unwrap();
+        let state = ApiState {
+            rate_limiter: Some(Arc::new(RateLimiter::new(1, 2).unwrap())),
+            ..state(database)
+        };
+        let base = spawn_state(state, false).await;
+        let client = reqwest::Client::new();
+        let info = |token: Option<&str>| {
+            let request = client.get(format!("{}/api/info", base));
+            match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+            .send()
+        };
+
+        assert_eq!(info(Some(&token)).await.unwrap().status().as_u16(), 200);
+        assert_eq!(info(Some(&token)).await.unwrap().status().as_u16(), 200);
+        let limited = info(Some(&token)).await.unwrap();
+        assert_eq!(limited.status().as_u16(), 429);
+        let retry_after: u64 = limited.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        // Unauthenticated requests, rejected or not, share their address's own bucket.
+        assert_eq!(info(None).await.unwrap().status().as_u16(), 401);
+        assert_eq!(info(Some("uwk_guess")).await.unwrap().status().as_u16(), 401);
+        assert_eq!(info(None).await.unwrap().status().as_u16(), 429);
+    }
+
+    #[test]
+    fn openapi_document_is_self_contained() {
+        let document = serde_json::to_value(openapi()).unwrap();
+        let paths: Vec<&String> = document["paths"].as_object().unwrap().keys().collect();
+        assert_eq!(paths.len(), 10);
+
+        fn refs<'a>(value: &'a serde_json::Value, found: &mut Vec<&'a str>) {
+            match value {
+                serde_json::Value::Object(map) => {
+                    found.extend(map.get("$ref").and_then(|r| r.as_str()));
+                    map.values().for_each(|v| refs(v, found));
+                }
+                serde_json::Value::Array(items) => items.iter().for_each(|v| refs(v, found)),
+                _ => {}
+            }
+        }
+        let mut found = Vec::new();
+        refs(&document, &mut found);
+        for reference in found {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            assert!(document["components"]["schemas"].get(name).is_some(), "dangling {}", reference);
+        }
+    }
+
+    #[tokio::test]
+    async fn dashboard_is_served_when_enabled() {
+        let base = spawn(database().await, true).await;
+        let page = reqwest::get(format!("{}/", base)).await.unwrap().text().await.unwrap();
+        assert!(page.contains("/api/transfers"));
+
+        let info: serde_json::Value = reqwest::get(format!("{}/api/info", base)).await.unwrap().json().await.unwrap();
+        assert_eq!((info["token_symbol"].as_str(), info["token_decimals"].as_u64()), (Some("USDC"), Some(6)));
+    }
+
+    #[tokio::test]
+    async fn client_decodes_responses() {
+        let database = database().await;
+        insert(&database, 1, ALICE, BOB, "5000000").await;
+        insert(&database, 2, BOB, ALICE, "1000000").await;
+        let client = ApiClient::new(&format!("{}/", spawn(database, false).await));
+
+        assert_eq!(client.info().await.unwrap().token_decimals, 6);
+        assert_eq!(client.stats().await.unwrap().database.total_transfers, 2);
+        let params = usdcwatch_client::TransferParams {
+            address: Some(BOB.to_string()),
+            direction: Some(usdcwatch_client::Direction::Received),
+            ..Default::default()
+        };
+        let page = client.transfers(&params).await.unwrap();
+        assert_eq!(page.transfers.iter().map(|t| t.block_number).collect::<Vec<_>>(), [1]);
+        assert!(client.address(ALICE).await.unwrap().is_none());
+
+        let params = usdcwatch_client::SeriesParams {
+            points: Some(10),
+            method: Some(usdcwatch_client::Downsample::Sum),
+            ..Default::default()
+        };
+        let series = client.volume_series(&params).await.unwrap();
+        assert!(series.points.len() <= 10);
+        assert_eq!(series.points.iter().map(|p| p.value).sum::<f64>(), 6.0);
+        assert!(client.supply_series(&params).await.is_err());
+
+        let params = usdcwatch_client::TransferParams {
+            min_value: Some("lots".to_string()),
+            ..Default::default()
+        };
+        assert!(client.transfers(&params).await.unwrap_err().to_string().contains("400"));
+    }
+
+    #[tokio::test]
+    async fn client_subscription_yields_only_new_matching_transfers() {
+        let database = database().await;
+        insert(&database, 1, ALICE, BOB, "5000000").await;
+        let client = ApiClient::new(&spawn(database.clone(), false).await);
+
+        let params = usdcwatch_client::TransferParams {
+            address: Some(ALICE.to_string()),
+            direction: Some(usdcwatch_client::Direction::Sent),
+            ..Default::default()
+        };
+        let mut transfers = Box::pin(client.subscribe_transfers(params, Duration::from_millis(20)));
+        let first = tokio::spawn(async move {
+            let mut blocks = Vec::new();
+            while blocks.len() < 2 {
+                blocks.push(transfers.next().await.unwrap().unwrap().block_number);
+            }
+            blocks
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        insert(&database, 2, BOB, ALICE, "1000000").await;
+        insert(&database, 3, ALICE, BOB, "2000000").await;
+        insert(&database, 4, ALICE, BOB, "3000000").await;
+
+        let blocks = tokio::time::timeout(Duration::from_secs(5), first).await.unwrap().unwrap();
+        assert_eq!(blocks, [3, 4]);
+    }
+}
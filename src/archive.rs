@@ -0,0 +1,38 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{address::normalize_address, models::TransferEvent};
+
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// Outcome of moving old `transfer_events` rows into compressed archive batches.
+#[derive(Debug, Default, Serialize)]
+pub struct ArchiveSummary {
+    pub batches: u64,
+    pub rows: u64,
+    pub raw_bytes: u64,
+    pub compressed_bytes: u64,
+}
+
+/// Serializes a batch of transfers as JSON and compresses it with zstd.
+/// Returns the compressed blob and the uncompressed size.
+pub fn compress_batch(events: &[TransferEvent]) -> Result<(Vec<u8>, usize)> {
+    let raw = serde_json::to_vec(events)?;
+    let compressed = zstd::encode_all(raw.as_slice(), COMPRESSION_LEVEL)?;
+    Ok((compressed, raw.len()))
+}
+
+/// Batches archived before addresses were checksummed hold lowercase addresses; they are
+/// normalized here so callers always see the stored form.
+pub fn decompress_batch(data: &[u8]) -> Result<Vec<TransferEvent>> {
+    let raw = zstd::decode_all(data)?;
+    let events: Vec<TransferEvent> = serde_json::from_slice(&raw)?;
+    events
+        .into_iter()
+        .map(|mut event| {
+            event.from_address = normalize_address(&event.from_address)?;
+            event.to_address = normalize_address(&event.to_address)?;
+            Ok(event)
+        })
+        .collect()
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use ethers_core::{rand::RngCore, utils::keccak256};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::Duration,
+};
+use utoipa::ToSchema;
+
+use crate::{
+    database::Database,
+    rate_limit::{RateKey, RateLimiter},
+};
+
+/// Start of every generated token, so leaked ones are easy to search for.
+const TOKEN_PREFIX: &str = "uwk_";
+
+/// What a token may do; `admin` includes everything `read` can.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Every `GET` endpoint.
+    #[default]
+    Read,
+    /// Also creating, listing and revoking API keys.
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+/// A token from `API_TOKENS`, given as `name:scope:token`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfiguredToken {
+    pub name: String,
+    pub scope: Scope,
+    pub token: String,
+}
+
+/// Who sent a request, added to its extensions once its token checks out. Requests to an
+/// instance without any tokens are `anonymous` with `read` scope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caller {
+    pub name: String,
+    pub scope: Scope,
+}
+
+/// Why a request was turned away.
+#[derive(Debug)]
+pub enum Rejection {
+    /// No token was given while some exist, or the given one matched none.
+    Unauthorized(&'static str),
+    /// The token's scope is narrower than this.
+    Forbidden(Scope),
+    /// The caller is over the rate limit for this long.
+    RateLimited(Duration),
+    Failed(anyhow::Error),
+}
+
+/// Checks bearer tokens against `API_TOKENS` and the `api_keys` table.
+#[derive(Clone)]
+pub struct Authenticator {
+    database: Database,
+    /// Hashes of the configured tokens, with whom they identify.
+    configured: Arc<Vec<(String, Caller)>>,
+}
+
+impl Authenticator {
+    pub fn new(database: Database, configured: &[ConfiguredToken]) -> Self {
+        let configured = configured
+            .iter()
+            .map(|token| {
+                let caller = Caller {
+                    name: token.name.clone(),
+                    scope: token.scope,
+                };
+                (hash_token(&token.token), caller)
+            })
+            .collect();
+        Self {
+            database,
+            configured: Arc::new(configured),
+        }
+    }
+
+    /// The caller of a request bearing `token` from `client`, if it may use `scope` now.
+    /// Requests without a valid token, rejected ones included, are metered by client address.
+    pub async fn admit(
+        &self,
+        token: Option<&str>,
+        client: Option<IpAddr>,
+        scope: Scope,
+        limiter: Option<&RateLimiter>,
+    ) -> std::result::Result<Caller, Rejection> {
+        let caller = self.authenticate(token).await.map_err(Rejection::Failed)?;
+        if let Some(limiter) = limiter {
+            let key = match (token, &caller) {
+                (Some(_), Some(caller)) => RateKey::Token(caller.name.clone()),
+                _ => RateKey::Ip(client.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))),
+            };
+            limiter.check(&key).map_err(Rejection::RateLimited)?;
+        }
+        match caller {
+            None if token.is_some() => Err(Rejection::Unauthorized("Unknown API token")),
+            None => Err(Rejection::Unauthorized("Missing bearer token")),
+            Some(caller) if caller.scope < scope => Err(Rejection::Forbidden(scope)),
+            Some(caller) => Ok(caller),
+        }
+    }
+
+    /// The caller `token` belongs to, or `None` when it matches no configured or stored key.
+    /// Without a token, requests pass as `anonymous` readers only while no tokens exist at all.
+    pub async fn authenticate(&self, token: Option<&str>) -> Result<Option<Caller>> {
+        let Some(token) = token else {
+            let open = self.configured.is_empty() && !self.database.has_api_keys().await?;
+            return Ok(open.then(|| Caller {
+                name: "anonymous".to_string(),
+                scope: Scope::Read,
+            }));
+        };
+
+        let hash = hash_token(token);
+        if let Some((_, caller)) = self.configured.iter().find(|(configured, _)| *configured == hash) {
+            return Ok(Some(caller.clone()));
+        }
+        Ok(self.database.use_api_key(&hash).await?.map(|key| Caller {
+            name: key.name,
+            scope: key.scope,
+        }))
+    }
+}
+
+/// Stores a new key named `name` and returns its token, which is not kept and cannot be shown
+/// again.
+pub async fn create_api_key(database: &Database, name: &str, scope: Scope) -> Result<String> {
+    let name = key_name(name)?;
+    let mut secret = [0u8; 32];
+    ethers_core::rand::thread_rng().fill_bytes(&mut secret);
+    let token = format!("{}{}", TOKEN_PREFIX, hex::encode(secret));
+    database.insert_api_key(name, scope, &hash_token(&token)).await?;
+    Ok(token)
+}
+
+/// `name` without surrounding whitespace, if it is usable as a key name.
+pub fn key_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) || name.contains('/') {
+        return Err(anyhow::anyhow!("Invalid API key name: {:?} (expected one word)", name));
+    }
+    Ok(name)
+}
+
+/// Tokens are random, so a fast hash is enough to keep a database leak from exposing them.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(keccak256(token.trim().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{testing::database, PoolSettings};
+
+    #[tokio::test]
+    async fn tokens_identify_their_key_until_revoked() {
+        let database = database().await;
+        let auth = Authenticator::new(database.clone(), &[]);
+        let anonymous = auth.authenticate(None).await.unwrap().unwrap();
+        assert_eq!(anonymous.scope, Scope::Read);
+
+        let token = create_api_key(&database, "ops", Scope::Admin).await.unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(create_api_key(&database, "ops", Scope::Read).await.is_err());
+        assert!(auth.authenticate(None).await.unwrap().is_none());
+        assert!(auth.authenticate(Some("uwk_wrong")).await.unwrap().is_none());
+
+        let caller = auth.authenticate(Some(&token)).await.unwrap().unwrap();
+        assert_eq!((caller.name.as_str(), caller.scope), ("ops", Scope::Admin));
+        let keys = database.list_api_keys().await.unwrap();
+        assert!(keys[0].last_used_at.is_some());
+
+        assert!(database.revoke_api_key("ops").await.unwrap());
+        assert!(auth.authenticate(Some(&token)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn configured_tokens_need_no_database_row() {
+        let configured = ConfiguredToken {
+            name: "grafana".to_string(),
+            scope: Scope::Read,
+            token: "s3cret".to_string(),
+        };
+        let auth = Authenticator::new(database().await, &[configured]);
+        assert!(auth.authenticate(None).await.unwrap().is_none());
+        let caller = auth.authenticate(Some("s3cret")).await.unwrap().unwrap();
+        assert_eq!(caller.name, "grafana");
+    }
+
+    #[tokio::test]
+    async fn stored_keys_work_on_a_read_only_database() {
+        let path = std::env::temp_dir().join(format!("usdcwatch-auth-{}.db", std::process::id()));
+        let url = format!("sqlite:{}", path.display());
+        let writer = Database::new(&url, PoolSettings::default()).await.unwrap();
+        let token = create_api_key(&writer, "grafana", Scope::Read).await.unwrap();
+
+        let settings = PoolSettings {
+            read_only: true,
+            ..PoolSettings::default()
+        };
+        let auth = Authenticator::new(Database::new(&url, settings).await.unwrap(), &[]);
+        let caller = auth.authenticate(Some(&token)).await.unwrap().unwrap();
+        assert_eq!((caller.name.as_str(), caller.scope), ("grafana", Scope::Read));
+        assert!(writer.list_api_keys().await.unwrap()[0].last_used_at.is_none());
+
+        drop((writer, auth));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers_core::utils::keccak256;
+use serde::Serialize;
+use std::{path::Path, time::Instant};
+
+use crate::{
+    config::Config,
+    database::{Database, PoolSettings},
+    dedup::EventSource,
+    ethereum::{decode_transfer_logs, TRANSFER_EVENT_SIGNATURE},
+    models::{ProcessedBlock, RawLog},
+};
+
+/// Distinct senders and recipients in generated logs, so the address rollups update existing rows
+/// the way real traffic does instead of only inserting.
+const SYNTHETIC_ADDRESSES: u64 = 1_000;
+const SYNTHETIC_FIRST_BLOCK: u64 = 20_000_000;
+
+/// Throughput of decoding and committing one set of logs `batch_blocks` blocks per transaction.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    pub batch_blocks: u64,
+    pub blocks: u64,
+    pub events: u64,
+    pub secs: f64,
+    pub events_per_sec: f64,
+    pub blocks_per_sec: f64,
+}
+
+/// `transfers_per_block` `Transfer` logs of `contract` in each of `blocks` consecutive blocks,
+/// the same on every call.
+pub fn synthetic_logs(contract: &str, blocks: u64, transfers_per_block: u64) -> Vec<RawLog> {
+    let address = |n: u64| format!("0x{:0>64}", hex::encode(&keccak256(n.to_be_bytes())[12..]));
+    let mut logs = Vec::with_capacity((blocks * transfers_per_block) as usize);
+    for block_number in SYNTHETIC_FIRST_BLOCK..SYNTHETIC_FIRST_BLOCK + blocks {
+        let block_timestamp = DateTime::from_timestamp(1_700_000_000 + 12 * block_number as i64, 0).unwrap_or_default();
+        for log_index in 0..transfers_per_block {
+            let n = logs.len() as u64;
+            logs.push(RawLog {
+                block_number,
+                block_hash: format!("0x{:064x}", block_number),
+                block_timestamp,
+                transaction_hash: format!("0x{:064x}", n),
+                transaction_index: Some(log_index),
+                log_index,
+                address: contract.to_lowercase(),
+                topics: vec![
+                    TRANSFER_EVENT_SIGNATURE.to_string(),
+                    address(n % SYNTHETIC_ADDRESSES),
+                    address((n * 31 + 7) % SYNTHETIC_ADDRESSES),
+                ],
+                data: format!("0x{:064x}", (n % 10_000 + 1) * 1_000_000),
+            });
+        }
+    }
+    logs
+}
+
+/// Decodes `logs` and commits them `batch_blocks` blocks at a time, the way `index` commits a
+/// range, into a scratch database under `dir` opened with the configured SQLite settings. Only
+/// the decode and commit calls are timed; the scratch database is deleted afterwards.
+pub async fn bench(config: &Config, dir: &Path, logs: &[RawLog], batch_blocks: u64) -> Result<BenchResult> {
+    if batch_blocks == 0 {
+        return Err(anyhow::anyhow!("Batch size must be at least 1 block"));
+    }
+
+    let path = dir.join(format!("usdcwatch-bench-{}-{}.db", std::process::id(), batch_blocks));
+    let files = ["", "-wal", "-shm"].map(|suffix| format!("{}{}", path.display(), suffix));
+    for file in &files {
+        let _ = std::fs::remove_file(file);
+    }
+    let settings = PoolSettings {
+        auto_migrate: true,
+        ..PoolSettings::from(config)
+    };
+    let database = Database::new(&format!("sqlite:{}", path.display()), settings).await?;
+
+    let blocks: Vec<&[RawLog]> = logs.chunk_by(|a, b| a.block_number == b.block_number).collect();
+    let mut events = 0;
+    let mut elapsed = std::time::Duration::ZERO;
+    for batch in blocks.chunks(batch_blocks as usize) {
+        let batch_logs = batch.concat();
+        let headers: Vec<ProcessedBlock> = batch.iter().map(|logs| header(&logs[0])).collect();
+
+        let started = Instant::now();
+        let transfers = decode_transfer_logs(&batch_logs, Utc::now())?;
+        database
+            .commit_block_range(
+                &transfers,
+                &headers,
+                config.raw_logs.then_some(batch_logs.as_slice()),
+                EventSource::Rpc,
+                config.dedup_window_blocks,
+            )
+            .await?;
+        elapsed += started.elapsed();
+        events += transfers.len() as u64;
+    }
+
+    drop(database);
+    for file in &files {
+        let _ = std::fs::remove_file(file);
+    }
+
+    let secs = elapsed.as_secs_f64();
+    let per_sec = |count: u64| if secs > 0.0 { count as f64 / secs } else { 0.0 };
+    Ok(BenchResult {
+        batch_blocks,
+        blocks: blocks.len() as u64,
+        events,
+        secs,
+        events_per_sec: per_sec(events),
+        blocks_per_sec: per_sec(blocks.len() as u64),
+    })
+}
+
+/// The header `index` would store for the block `log` is in, minus the gas fields logs don't carry.
+fn header(log: &RawLog) -> ProcessedBlock {
+    ProcessedBlock {
+        block_number: log.block_number,
+        block_hash: log.block_hash.clone(),
+        timestamp: log.block_timestamp,
+        base_fee_per_gas: None,
+        gas_used: 0,
+        gas_limit: 0,
+        fee_recipient: format!("0x{:040x}", 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+    #[tokio::test]
+    async fn commits_every_generated_transfer_at_any_batch_size() {
+        let config = Config::from_env().unwrap();
+        let logs = synthetic_logs(TOKEN, 7, 3);
+        assert_eq!(decode_transfer_logs(&logs, Utc::now()).unwrap().len(), 21);
+
+        for batch_blocks in [1, 5] {
+            let result = bench(&config, &std::env::temp_dir(), &logs, batch_blocks).await.unwrap();
+            assert_eq!((result.blocks, result.events), (7, 21));
+        }
+        assert!(bench(&config, &std::env::temp_dir(), &logs, 0).await.is_err());
+    }
+}
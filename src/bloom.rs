@@ -0,0 +1,159 @@
+use anyhow::Result;
+use ethers_core::utils::keccak256;
+use sqlx::{Executor, Row, Sqlite};
+
+/// Addresses the filter is sized for when first created.
+pub const DEFAULT_CAPACITY: u64 = 1 << 20;
+
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Bloom filter over every address that appears in `address_stats`. A negative answer means
+/// the address has no transfers at or below the rollup watermark.
+#[derive(Debug, Clone)]
+pub struct AddressBloom {
+    pub bits: Vec<u8>,
+    pub num_hashes: u32,
+    pub capacity: u64,
+    pub items: u64,
+}
+
+impl AddressBloom {
+    pub fn with_capacity(capacity: u64) -> Self {
+        let capacity = capacity.max(1);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(capacity as f64) * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil() as u64;
+        let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round().max(1.0) as u32;
+
+        Self {
+            bits: vec![0; num_bits.div_ceil(8) as usize],
+            num_hashes,
+            capacity,
+            items: 0,
+        }
+    }
+
+    /// The filter stored in `address_bloom`, if one has been built.
+    pub(crate) async fn load<'c>(executor: impl Executor<'c, Database = Sqlite>) -> Result<Option<Self>> {
+        let row = sqlx::query("SELECT * FROM address_bloom WHERE id = 0")
+            .fetch_optional(executor)
+            .await?;
+        Ok(row.map(|row| Self {
+            bits: row.get("bits"),
+            num_hashes: row.get::<i64, _>("num_hashes") as u32,
+            capacity: row.get::<i64, _>("capacity") as u64,
+            items: row.get::<i64, _>("items") as u64,
+        }))
+    }
+
+    /// Replaces the filter stored in `address_bloom`.
+    pub(crate) async fn save<'c>(&self, executor: impl Executor<'c, Database = Sqlite>) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO address_bloom (id, capacity, num_hashes, items, bits) VALUES (0, ?, ?, ?, ?)")
+            .bind(self.capacity as i64)
+            .bind(self.num_hashes as i64)
+            .bind(self.items as i64)
+            .bind(&self.bits)
+            .execute(executor)
+            .await?;
+        Ok(())
+    }
+
+    /// Past capacity the false-positive rate degrades, so the filter should be rebuilt larger.
+    pub fn is_full(&self) -> bool {
+        self.items > self.capacity
+    }
+
+    pub fn insert(&mut self, address: &str) {
+        let mut added = false;
+        for bit in self.bit_indexes(address) {
+            let (byte, mask) = ((bit / 8) as usize, 1u8 << (bit % 8));
+            added |= self.bits[byte] & mask == 0;
+            self.bits[byte] |= mask;
+        }
+        if added {
+            self.items += 1;
+        }
+    }
+
+    pub fn contains(&self, address: &str) -> bool {
+        self.bit_indexes(address)
+            .all(|bit| self.bits[(bit / 8) as usize] & (1u8 << (bit % 8)) != 0)
+    }
+
+    /// Double hashing over the two halves of the address's keccak digest.
+    fn bit_indexes(&self, address: &str) -> impl Iterator<Item = u64> {
+        let digest = keccak256(address.to_lowercase().as_bytes());
+        let h1 = u64::from_le_bytes(digest[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap());
+        let num_bits = self.bits.len() as u64 * 8;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Connection, SqliteConnection};
+
+    fn address(i: u64) -> String {
+        format!("0x{:040x}", i)
+    }
+
+    #[test]
+    fn has_no_false_negatives() {
+        let mut bloom = AddressBloom::with_capacity(1_000);
+        for i in 0..1_000 {
+            bloom.insert(&address(i));
+        }
+        assert!(!bloom.is_full());
+        assert!((0..1_000).all(|i| bloom.contains(&address(i))));
+    }
+
+    #[test]
+    fn ignores_address_case() {
+        let mut bloom = AddressBloom::with_capacity(10);
+        bloom.insert("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+        assert!(bloom.contains("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"));
+        assert!(bloom.contains("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED"));
+    }
+
+    #[test]
+    fn false_positive_rate_stays_near_target_at_capacity() {
+        let mut bloom = AddressBloom::with_capacity(10_000);
+        for i in 0..10_000 {
+            bloom.insert(&address(i));
+        }
+        let false_positives = (10_000..30_000).filter(|i| bloom.contains(&address(*i))).count();
+        assert!(false_positives < 400, "{} false positives in 20000", false_positives);
+    }
+
+    #[test]
+    fn counts_only_new_items_and_reports_full() {
+        let mut bloom = AddressBloom::with_capacity(2);
+        bloom.insert(&address(1));
+        bloom.insert(&address(1));
+        assert_eq!(bloom.items, 1);
+        bloom.insert(&address(2));
+        bloom.insert(&address(3));
+        assert!(bloom.is_full());
+    }
+
+    #[tokio::test]
+    async fn survives_save_and_load() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        crate::database::MIGRATOR.run(&mut conn).await.unwrap();
+        assert!(AddressBloom::load(&mut conn).await.unwrap().is_none());
+
+        let mut bloom = AddressBloom::with_capacity(100);
+        for i in 0..50 {
+            bloom.insert(&address(i));
+        }
+        bloom.save(&mut conn).await.unwrap();
+
+        let loaded = AddressBloom::load(&mut conn).await.unwrap().unwrap();
+        assert_eq!(loaded.bits, bloom.bits);
+        assert_eq!(loaded.num_hashes, bloom.num_hashes);
+        assert_eq!(loaded.capacity, bloom.capacity);
+        assert_eq!(loaded.items, bloom.items);
+        assert!((0..50).all(|i| loaded.contains(&address(i))));
+    }
+}
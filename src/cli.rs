@@ -1,4 +1,16 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use ethereum_erc20_indexer::{
+    auth::Scope,
+    daemon::DEFAULT_PID_FILE,
+    graph::GraphFormat,
+    import::LabelConflict,
+    notify::{is_sink_name, SINK_NAMES},
+    output::{ExportFormat, OutputFormat},
+    query::{parse_duration, AddressKind, CounterpartyRank, Direction, FlowInterval},
+    telemetry::LogFormat,
+};
 
 #[derive(Parser)]
 #[command(name = "ethereum-erc20-indexer")]
@@ -6,8 +18,16 @@ use clap::{Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// How log lines are written to stderr; `json` emits one object per line for log pipelines
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+    /// Use a fresh in-memory database instead of DATABASE_URL, discarded on exit; ignores SHARD_SIZE_BLOCKS
+    #[arg(long, global = true)]
+    pub ephemeral: bool,
 }
 
+// Parsed once at startup, so variant size is irrelevant.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     Index {
@@ -15,16 +35,672 @@ pub enum Commands {
         start_block: Option<u64>,
         #[arg(long)]
         latest: bool,
+        /// Detach from the terminal and keep indexing in the background; set LOG_FILE to keep its logs
+        #[arg(long)]
+        daemon: bool,
+        /// Where --daemon records its process id, locked while it runs
+        #[arg(long, default_value = DEFAULT_PID_FILE)]
+        pid_file: PathBuf,
+        /// Fetch and decode the first block range and print what would be stored, writing nothing
+        #[arg(long, conflicts_with = "daemon")]
+        dry_run: bool,
+    },
+    /// Roll the database back to a block and index forward from it again
+    Reindex {
+        #[arg(long)]
+        from: u64,
     },
     Query {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Every transfer emitted by this transaction, in log order
+        #[arg(long)]
+        tx_hash: Option<String>,
         #[arg(short, long)]
-        address: Option<String>,
+        limit: Option<i64>,
+        #[arg(long)]
+        pending: bool,
+        #[arg(long)]
+        cursor: Option<String>,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+        /// Show unscaled integer values instead of token units in table, CSV and totals output
+        #[arg(long)]
+        raw: bool,
+        /// Keep polling and print new matching transfers as they are indexed
+        #[arg(short, long)]
+        follow: bool,
+        /// Print the number of matching transfers instead of the rows
+        #[arg(long)]
+        count: bool,
+        /// Print the total value moved by matching transfers instead of the rows
+        #[arg(long)]
+        sum: bool,
+        /// Print the average raw value of matching transfers instead of the rows
+        #[arg(long)]
+        avg: bool,
+        /// Print the largest raw value among matching transfers instead of the rows
+        #[arg(long)]
+        max: bool,
+        /// Add each transfer's dollar value at transfer time, and dollar totals to --sum, --avg and --max (needs PRICE_FEED_ADDRESS)
+        #[arg(long)]
+        usd: bool,
+    },
+    /// Show processed blocks with their base fee, gas used and transfer activity, newest first
+    Blocks {
         #[arg(long)]
         from_block: Option<u64>,
         #[arg(long)]
         to_block: Option<u64>,
         #[arg(short, long)]
         limit: Option<i64>,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+        /// Show unscaled integer volumes instead of token units in table and CSV output
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Show indexed ERC-1155 transfers, newest first
+    Erc1155 {
+        /// Only transfers of this token contract
+        #[arg(long)]
+        contract: Option<String>,
+        /// Only transfers sent from or received by this address
+        #[arg(long)]
+        address: Option<String>,
+        /// Only transfers of this token id (decimal)
+        #[arg(long)]
+        token_id: Option<String>,
+        #[arg(short, long)]
+        limit: Option<i64>,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Stream transfers to a file or stdout, oldest first
+    Export {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Jsonl)]
+        format: ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+    /// Write the transfer network of matching transfers as a DOT or GraphML graph with weighted edges
+    ExportGraph {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+    /// Load transfers written by `export`, deduplicating on (transaction_hash, log_index)
+    Import {
+        file: PathBuf,
+        /// Defaults to csv for .csv files and jsonl otherwise
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+    },
+    /// Print database totals, recent throughput, mints and burns, and indexing lag
+    Stats {
+        /// Report on one address or ENS name instead, from the address_stats rollups
+        #[arg(long)]
+        address: Option<String>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Report whether an `index --daemon` is running, its last processed block and its lag; exits 3 when not running
+    Status {
+        #[arg(long, default_value = DEFAULT_PID_FILE)]
+        pid_file: PathBuf,
+    },
+    /// Full-screen live view of indexing progress, large transfers, top holders and hourly volume
+    Dashboard {
+        /// Smallest transfer shown in the feed, in token units
+        #[arg(long, default_value = "100000")]
+        min_value: String,
+        /// Seconds between reloads from the database
+        #[arg(long, default_value_t = 2)]
+        refresh_secs: u64,
+    },
+    /// Serve stored transfers, stats, volume and holders as a JSON HTTP API
+    Serve {
+        /// Address and port to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Also serve the web dashboard at /
+        #[arg(long)]
+        dashboard: bool,
+        /// Also serve the gRPC API of proto/usdcwatch.proto on this address and port
+        #[arg(long)]
+        grpc_listen: Option<String>,
+        /// Seconds a request's queries may run before they are stopped; 0 for no limit
+        #[arg(long, default_value_t = 30)]
+        query_timeout_secs: u64,
+        /// Requests a minute each API key, or each client address without one, may make; 0 for
+        /// no limit
+        #[arg(long, default_value_t = 600)]
+        rate_limit: u32,
+        /// Requests a caller may make at once before the per-minute rate applies
+        #[arg(long, default_value_t = 60)]
+        rate_limit_burst: u32,
+        /// Open the database read-only, e.g. on a read-only mount; creating and revoking keys is refused
+        #[arg(long)]
+        read_only: bool,
+    },
+    WatchMempool,
+    Archive {
+        /// Archive every transfer below this block
+        #[arg(long)]
+        before_block: u64,
+    },
+    /// Rebuild stored transfers from the raw logs kept with RAW_LOGS, without the RPC
+    Replay {
+        #[arg(long, default_value_t = 0)]
+        from_block: u64,
+        /// Defaults to the latest processed block
+        #[arg(long)]
+        to_block: Option<u64>,
+    },
+    /// Delete transfers outside the retention window and compact the database
+    Prune {
+        /// Keep blocks processed within the last N days
+        #[arg(long, conflicts_with = "keep_blocks", required_unless_present = "keep_blocks")]
+        keep_days: Option<i64>,
+        /// Keep the newest N processed blocks
+        #[arg(long)]
+        keep_blocks: Option<u64>,
+    },
+    Rollups {
+        #[arg(short, long)]
+        address: Option<String>,
+        /// Recompute address_stats from all stored transfers
+        #[arg(long)]
+        rebuild: bool,
+    },
+    Finality {
+        #[arg(long, default_value_t = 30)]
+        days: i64,
+    },
+    /// Reports computed from stored transfers
+    Analytics {
+        #[command(subcommand)]
+        command: AnalyticsCommand,
+    },
+    /// Summarize an address: first and last seen, totals, net flow, top counterparties, largest transfers and activity by month
+    Report {
+        /// Address or ENS name
+        #[arg(short, long)]
+        address: String,
+        /// How many counterparties and largest transfers to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Print JSON instead of a text report
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the addresses that transferred most with --address, with counts and volumes in each direction
+    Counterparties {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Rank by number of transfers or by value moved
+        #[arg(long, value_enum, default_value_t = CounterpartyRank::Count)]
+        by: CounterpartyRank,
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Show what --address received, sent and netted per hour, day, week or month
+    Netflow {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t = FlowInterval::Day)]
+        interval: FlowInterval,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Find chains of transfers that could have moved value from one address to another
+    TraceFlow {
+        /// Address or ENS name the value left
+        #[arg(long)]
+        from: String,
+        /// Address or ENS name the value reached
+        #[arg(long)]
+        to: String,
+        #[arg(long, default_value_t = 3)]
+        max_hops: usize,
+        /// RFC3339 timestamp or relative age like 30d
+        #[arg(long)]
+        from_time: Option<String>,
+        /// RFC3339 timestamp or relative age like 24h
+        #[arg(long)]
+        to_time: Option<String>,
+        /// Ignore hops below this value, in token units (e.g. 1000) or raw units with a `raw` suffix
+        #[arg(long)]
+        min_value: Option<String>,
+        /// How many paths to print
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Count addresses holding a non-zero balance, now or over time
+    Holders {
+        /// List the recorded snapshots instead of the current count
+        #[arg(long)]
+        history: bool,
+        /// Rebuild the snapshots by replaying every stored transfer first
+        #[arg(long)]
+        backfill: bool,
+        /// RFC3339 timestamp or relative age like 90d
+        #[arg(long)]
+        from_time: Option<String>,
+        /// RFC3339 timestamp or relative age like 24h
+        #[arg(long)]
+        to_time: Option<String>,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Count current holders and their combined balance per balance range
+    HolderDistribution {
+        /// Balance in token units at which a new range starts; repeat for several, defaults to 100, 10000 and 1000000
+        #[arg(long = "bucket")]
+        buckets: Vec<String>,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Print the Gini coefficient of holder balances and the share held by the largest holders, as JSON
+    Concentration {
+        /// Number of largest holders to report the share of; repeat for several, defaults to 10, 100 and 1000
+        #[arg(long = "top")]
+        tops: Vec<usize>,
+    },
+    Latency {
+        #[arg(long, default_value_t = 24)]
+        hours: i64,
+    },
+    /// Time decoding and committing transfers at several batch sizes, in a scratch database
+    Bench {
+        /// RPC_RECORD fixture whose eth_getLogs answers to commit instead of generated logs
+        #[arg(long)]
+        fixture: Option<PathBuf>,
+        /// Blocks of generated logs
+        #[arg(long, default_value_t = 1_000, conflicts_with = "fixture")]
+        blocks: u64,
+        /// Generated transfers per block
+        #[arg(long, default_value_t = 20, conflicts_with = "fixture")]
+        transfers_per_block: u64,
+        /// Blocks committed per transaction, comma-separated
+        #[arg(long, value_delimiter = ',', default_values_t = [1, 10, 100])]
+        batch_blocks: Vec<u64>,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Replay alert rules over stored history and print the alerts that would have fired
+    Backtest {
+        /// RFC3339 timestamp or relative age like 30d
+        #[arg(long)]
+        from: String,
+        /// RFC3339 timestamp or relative age; defaults to now
+        #[arg(long)]
+        to: Option<String>,
+        /// Simulated clock step between evaluations
+        #[arg(long, default_value_t = 5)]
+        step_mins: i64,
+    },
+    Conflicts {
+        #[arg(short, long)]
+        limit: Option<i64>,
+    },
+    /// List block ranges that were skipped or stored without transfers
+    Gaps {
+        /// Re-index the reported ranges
+        #[arg(long)]
+        fill: bool,
+    },
+    /// Re-fetch logs for indexed blocks and report missing, extra or mismatched transfers
+    Verify {
+        /// Check this range instead of a random sample
+        #[arg(long, requires = "to_block")]
+        from_block: Option<u64>,
+        #[arg(long, requires = "from_block")]
+        to_block: Option<u64>,
+        /// Number of random processed blocks to check
+        #[arg(long, default_value_t = 20, conflicts_with = "from_block")]
+        sample: i64,
+    },
+    /// Names attached to addresses, shown next to them in query output
+    Label {
+        #[command(subcommand)]
+        command: LabelCommand,
+    },
+    /// Print an alert line for each new matching transfer
+    Watch {
+        #[command(flatten)]
+        filter: FilterArgs,
     },
-    Stats,
-}
\ No newline at end of file
+    /// Print each new transfer as one JSON line on stdout
+    Stream {
+        /// Block to start indexing from when indexing in this process
+        #[arg(short, long)]
+        start_block: Option<u64>,
+        /// Follow transfers stored by an indexer running elsewhere instead of indexing here
+        #[arg(long, conflicts_with = "start_block")]
+        follow: bool,
+    },
+    /// Addresses indexed when WATCHLIST_ONLY is on
+    Watchlist {
+        #[command(subcommand)]
+        command: WatchlistCommand,
+    },
+    /// The sanctions address list loaded from SANCTIONS_LIST
+    Sanctions {
+        #[command(subcommand)]
+        command: SanctionsCommand,
+    },
+    /// Rules evaluated against every newly indexed transfer
+    Alert {
+        #[command(subcommand)]
+        command: AlertCommand,
+    },
+    /// Alert sinks configured in ALERT_SINKS
+    Sinks {
+        #[command(subcommand)]
+        command: SinksCommand,
+    },
+    /// Manage the ClickHouse export of stored transfers
+    Clickhouse {
+        #[command(subcommand)]
+        command: ClickHouseCommand,
+    },
+    /// Bearer tokens for `serve`
+    ApiKey {
+        #[command(subcommand)]
+        command: ApiKeyCommand,
+    },
+    /// ERC-20 metadata of the configured contract
+    Token {
+        #[command(subcommand)]
+        command: TokenCommand,
+    },
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Apply pending schema migrations
+    Migrate {
+        /// List applied and pending migrations without running them
+        #[arg(long)]
+        status: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LabelCommand {
+    /// Attach a name to an address, replacing any existing one
+    Add {
+        address: String,
+        name: String,
+        /// Kind of entity, e.g. exchange or bridge
+        #[arg(long)]
+        category: Option<String>,
+    },
+    Remove { address: String },
+    /// Load `address,name[,category]` rows from a CSV file with a header row
+    Import {
+        file: PathBuf,
+        /// What to do when an address already has a label
+        #[arg(long, value_enum, default_value_t = LabelConflict::Skip)]
+        on_conflict: LabelConflict,
+    },
+    /// Load the bundled dataset of well-known exchange, bridge and issuer addresses
+    ImportKnown {
+        /// What to do when an address already has a label
+        #[arg(long, value_enum, default_value_t = LabelConflict::Skip)]
+        on_conflict: LabelConflict,
+    },
+    List {
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WatchlistCommand {
+    Add { address: String },
+    Remove { address: String },
+    List {
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SanctionsCommand {
+    /// Reload the list now
+    Refresh {
+        /// File or URL to load instead of SANCTIONS_LIST
+        #[arg(long)]
+        source: Option<String>,
+    },
+    /// Show how many addresses are loaded, from where and when
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum SinksCommand {
+    /// Deliver the alerts a sink would have received for stored transfers, so a new consumer can catch up
+    Backfill {
+        /// Sink to deliver to
+        #[arg(long, value_parser = sink_name)]
+        target: String,
+        #[arg(long)]
+        from_block: u64,
+        /// Defaults to the latest processed block
+        #[arg(long)]
+        to_block: Option<u64>,
+        /// Replay only this rule, without ALERT_MIN_VALUE alerts; defaults to every rule routed to the target
+        #[arg(long)]
+        rule: Option<i64>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ClickHouseCommand {
+    /// Replace the table's rows from --from-block on with the stored transfers, e.g. to load history
+    Backfill {
+        #[arg(long, default_value_t = 0)]
+        from_block: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AlertCommand {
+    /// Store a rule; every condition given must hold for a transfer to match
+    Add {
+        #[arg(long)]
+        name: String,
+        #[arg(short, long)]
+        address: Option<String>,
+        /// Which side of the transfer --address must be on
+        #[arg(long, value_enum, default_value_t = Direction::Both)]
+        direction: Direction,
+        /// Minimum value in token units (e.g. 1000) or raw units with a `raw` suffix
+        #[arg(long)]
+        min_value: Option<String>,
+        /// Maximum value in token units (e.g. 1000) or raw units with a `raw` suffix
+        #[arg(long)]
+        max_value: Option<String>,
+        /// Match only when the other party's label contains this text (case-insensitive)
+        #[arg(long)]
+        counterparty_label: Option<String>,
+        /// Fire when this many matching transfers occur within --window
+        #[arg(long, requires = "window", value_parser = clap::value_parser!(i64).range(1..))]
+        min_count: Option<i64>,
+        /// Span for --min-count, like 10m or 1h
+        #[arg(long, requires = "min_count", value_parser = positive_duration)]
+        window: Option<chrono::Duration>,
+        /// Sink to deliver to (log, console, webhook, telegram, slack, discord, email, or slack:<route> / discord:<route>); repeat for several, defaults to all of ALERT_SINKS
+        #[arg(long = "channel")]
+        channels: Vec<String>,
+    },
+    List {
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    Delete { id: i64 },
+    /// Replay a rule over stored transfers and print the alerts it would have fired
+    Test {
+        id: i64,
+        /// RFC3339 timestamp or relative age like 7d
+        #[arg(long, default_value = "24h")]
+        from_time: String,
+        /// Also deliver the most recent alert through the rule's channels
+        #[arg(long)]
+        send: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AnalyticsCommand {
+    /// ETH fees an address paid for transactions that moved its tokens, per counterparty
+    GasSpent {
+        #[arg(long)]
+        address: String,
+    },
+    /// Daily transfer count and volume into or out of every address labeled with a category
+    CategoryVolume {
+        /// Label category, e.g. exchange or bridge
+        #[arg(long)]
+        category: String,
+        /// Which side of the transfer the labeled addresses must be on
+        #[arg(long, value_enum, default_value_t = Direction::Received)]
+        direction: Direction,
+        /// RFC3339 timestamp or relative age like 30d
+        #[arg(long)]
+        from_time: Option<String>,
+        /// RFC3339 timestamp or relative age like 24h
+        #[arg(long)]
+        to_time: Option<String>,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Volume, volume over tracked supply, and distinct active addresses per period
+    Velocity {
+        #[arg(long, value_enum, default_value_t = FlowInterval::Day)]
+        interval: FlowInterval,
+        /// RFC3339 timestamp or relative age like 30d
+        #[arg(long, default_value = "30d")]
+        from_time: String,
+        /// RFC3339 timestamp or relative age like 24h
+        #[arg(long)]
+        to_time: Option<String>,
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ApiKeyCommand {
+    /// Store a new key and print its token, which cannot be shown again
+    Create {
+        name: String,
+        #[arg(long, value_enum, default_value_t = Scope::Read)]
+        scope: Scope,
+    },
+    /// Stop accepting a key's token
+    Revoke { name: String },
+    List {
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TokenCommand {
+    /// Print the stored name, symbol and decimals, fetching them from the contract if missing
+    Info {
+        /// Fetch from the contract even when metadata is already stored
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// List registered tokens and how far each has been indexed
+    List {
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    /// VACUUM and ANALYZE the database and every shard, reporting space reclaimed
+    Compact,
+    /// Snapshot the database and every shard while the indexer keeps running
+    Backup {
+        /// Destination for the main database; shard files are written alongside it
+        path: PathBuf,
+    },
+}
+
+/// Transfer filters shared by commands that read `transfer_events`.
+#[derive(Args, Debug, Clone)]
+pub struct FilterArgs {
+    #[arg(short, long)]
+    pub address: Option<String>,
+    /// Which side of the transfer --address must be on
+    #[arg(long, value_enum, default_value_t = Direction::Both)]
+    pub direction: Direction,
+    #[arg(long)]
+    pub from_block: Option<u64>,
+    #[arg(long)]
+    pub to_block: Option<u64>,
+    /// Minimum value in token units (e.g. 1000) or raw units with a `raw` suffix
+    #[arg(long)]
+    pub min_value: Option<String>,
+    /// Maximum value in token units (e.g. 1000) or raw units with a `raw` suffix
+    #[arg(long)]
+    pub max_value: Option<String>,
+    /// RFC3339 timestamp or relative age like 24h
+    #[arg(long)]
+    pub from_time: Option<String>,
+    /// RFC3339 timestamp or relative age like 24h
+    #[arg(long)]
+    pub to_time: Option<String>,
+    /// Only transfers sent by this kind of address (needs ADDRESS_CLASSIFICATION)
+    #[arg(long, value_enum)]
+    pub from_kind: Option<AddressKind>,
+    /// Only transfers received by this kind of address (needs ADDRESS_CLASSIFICATION)
+    #[arg(long, value_enum)]
+    pub to_kind: Option<AddressKind>,
+    /// Only transfers touching an address on the sanctions list
+    #[arg(long)]
+    pub sanctioned: bool,
+    /// Also search compressed archive batches
+    #[arg(long)]
+    pub archived: bool,
+}
+
+/// A duration like `10m` that is longer than zero.
+fn positive_duration(input: &str) -> Result<chrono::Duration, String> {
+    match parse_duration(input) {
+        Ok(duration) if duration > chrono::Duration::zero() => Ok(duration),
+        Ok(_) => Err("must be longer than zero".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// One of `SINK_NAMES`, or a `slack:<route>` / `discord:<route>` name.
+fn sink_name(input: &str) -> Result<String, String> {
+    let name = input.trim().to_lowercase();
+    if is_sink_name(&name) {
+        Ok(name)
+    } else {
+        Err(format!("expected one of {}, or slack:<route> / discord:<route>", SINK_NAMES.join(", ")))
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    database::Database,
+    models::TransferEvent,
+    publish::{Outbox, OutboxBatch},
+};
+
+/// Exports committed transfers to a ClickHouse table, one bulk `INSERT` per outbox batch over the
+/// HTTP interface, for analytical queries run directly against ClickHouse. Nothing reads the table
+/// back: SQLite stays the source of truth for queries, progress and reorgs, and the table is
+/// rebuilt from it with `clickhouse backfill`.
+pub struct ClickHouseWriter {
+    http: reqwest::Client,
+    url: String,
+    table: String,
+    user: Option<String>,
+    password: Option<String>,
+    outbox: Outbox,
+}
+
+/// One `JSONEachRow` line of the transfers table.
+#[derive(Serialize)]
+struct Row<'a> {
+    block_number: i64,
+    log_index: i64,
+    transaction_hash: &'a str,
+    block_hash: &'a str,
+    timestamp: String,
+    from_address: &'a str,
+    to_address: &'a str,
+    value: &'a str,
+    usd_value: Option<&'a str>,
+    sanctioned: bool,
+}
+
+impl<'a> From<&'a TransferEvent> for Row<'a> {
+    fn from(transfer: &'a TransferEvent) -> Self {
+        Self {
+            block_number: transfer.block_number,
+            log_index: transfer.log_index,
+            transaction_hash: &transfer.transaction_hash,
+            block_hash: &transfer.block_hash,
+            timestamp: transfer.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+            from_address: &transfer.from_address,
+            to_address: &transfer.to_address,
+            value: &transfer.value,
+            usd_value: transfer.usd_value.as_deref(),
+            sanctioned: transfer.sanctioned,
+        }
+    }
+}
+
+impl ClickHouseWriter {
+    /// `None` when `CLICKHOUSE_URL` is unset.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(url) = &config.clickhouse_url else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            table: format!("{}.{}", config.clickhouse_database, config.clickhouse_table),
+            user: config.clickhouse_user.clone(),
+            password: config.clickhouse_password.clone(),
+            outbox: Outbox::new("clickhouse"),
+        }))
+    }
+
+    /// Creates the table if missing. Rows are ordered by block for range scans and partitioned by
+    /// month, so old months can be dropped or moved whole. Batches sent twice after a failure
+    /// collapse on merge; `SELECT ... FINAL` hides the copies before that.
+    pub async fn create_table(&self) -> Result<()> {
+        self.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    block_number UInt64,
+                    log_index UInt32,
+                    transaction_hash String,
+                    block_hash String,
+                    timestamp DateTime('UTC'),
+                    from_address String,
+                    to_address String,
+                    value UInt256,
+                    usd_value Nullable(Decimal(38, 2)),
+                    sanctioned Bool
+                )
+                ENGINE = ReplacingMergeTree
+                PARTITION BY toYYYYMM(timestamp)
+                ORDER BY (block_number, log_index)",
+                self.table
+            ),
+            String::new(),
+        )
+        .await
+    }
+
+    pub async fn start(&self, database: &Database, start_block: u64) -> Result<()> {
+        self.create_table().await?;
+        self.outbox.start(database, start_block).await
+    }
+
+    /// Sends everything committed through `through_block` that ClickHouse has not accepted yet.
+    pub async fn publish(&self, database: &Database, through_block: u64) -> Result<u64> {
+        let mut published = 0;
+        while let Some(batch) = self.outbox.next_batch(database, through_block).await? {
+            self.write(&batch).await?;
+            self.outbox.commit(database, &batch).await?;
+            published += batch.transfers.len() as u64;
+        }
+        Ok(published)
+    }
+
+    /// Deletes the table's rows from `from_block` on and sends them again from the database,
+    /// through `through_block`.
+    pub async fn backfill(&self, database: &Database, from_block: u64, through_block: u64) -> Result<u64> {
+        self.create_table().await?;
+        self.delete_from(from_block).await?;
+        self.outbox.rewind(database, from_block).await?;
+        self.publish(database, through_block).await
+    }
+
+    async fn write(&self, batch: &OutboxBatch) -> Result<()> {
+        // Only the earliest rollback matters: everything after it is deleted anyway.
+        if let Some(&from_block) = batch.rollbacks.iter().min() {
+            self.delete_from(from_block).await?;
+        }
+        if batch.transfers.is_empty() {
+            return Ok(());
+        }
+        self.execute(&format!("INSERT INTO {} FORMAT JSONEachRow", self.table), rows(&batch.transfers)?)
+            .await
+    }
+
+    /// Waits for the delete to apply, so rows inserted after it are never caught by it.
+    async fn delete_from(&self, from_block: u64) -> Result<()> {
+        self.execute(
+            &format!(
+                "ALTER TABLE {} DELETE WHERE block_number >= {} SETTINGS mutations_sync = 1",
+                self.table, from_block
+            ),
+            String::new(),
+        )
+        .await
+    }
+
+    async fn execute(&self, query: &str, body: String) -> Result<()> {
+        let mut request = self.http.post(&self.url).query(&[("query", query)]).body(body);
+        if let Some(user) = &self.user {
+            request = request.header("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &self.password {
+            request = request.header("X-ClickHouse-Key", password);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("ClickHouse returned {}: {}", status, message.trim()));
+        }
+        Ok(())
+    }
+}
+
+/// The `JSONEachRow` body inserting `transfers`.
+fn rows(transfers: &[TransferEvent]) -> Result<String> {
+    let mut body = String::new();
+    for transfer in transfers {
+        body.push_str(&serde_json::to_string(&Row::from(transfer))?);
+        body.push('\n');
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::TransferBuilder;
+    use chrono::DateTime;
+
+    #[test]
+    fn rows_are_one_json_object_per_line() {
+        let transfer = TransferEvent {
+            from_label: Some("Alice".to_string()),
+            usd_value: Some("5.00".to_string()),
+            ..TransferBuilder::new(19_000_000)
+                .log_index(3)
+                .value("115792089237316195423570985008687907853269984665640564039457584007913129639935")
+                .at(DateTime::from_timestamp(1_700_000_000, 0).unwrap())
+                .build()
+        };
+
+        let body = rows(&[transfer.clone(), transfer]).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let row: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(row["timestamp"], "2023-11-14 22:13:20");
+        // Quoted, since a UInt256 does not fit a JSON number.
+        assert_eq!(row["value"], "115792089237316195423570985008687907853269984665640564039457584007913129639935");
+        assert_eq!(row["usd_value"], "5.00");
+        assert!(row.get("from_label").is_none());
+    }
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Duration, Utc};
+use std::sync::{Arc, RwLock};
+
+/// Source of "now" for everything that timestamps rows or evaluates time windows, so
+/// backtests and embedding applications can run against simulated time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<RwLock<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(RwLock::new(start)),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.write().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.write().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.read().unwrap()
+    }
+}
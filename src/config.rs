@@ -1,24 +1,170 @@
 use anyhow::Result;
+use ethers_core::types::U256;
 use serde::Deserialize;
+use std::path::PathBuf;
 
+use crate::{
+    auth::{ConfiguredToken, Scope},
+    telemetry::LogRotation,
+    units::parse_amount,
+};
+
+/// Runtime settings, read from environment variables. Fields can be changed after loading when
+/// embedding the indexer.
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub ethereum_rpc_url: String,
+    /// Chain `ETHEREUM_RPC_URL` must serve for `index` to start; `None` accepts any.
+    pub chain_id: Option<u64>,
+    /// JSON-lines file every RPC request and answer is appended to.
+    pub rpc_record: Option<PathBuf>,
+    /// Fixture written through `rpc_record` that RPC requests are answered from instead of the node.
+    pub rpc_replay: Option<PathBuf>,
     pub database_url: String,
     pub usdc_contract_address: String,
+    /// Block a fresh database starts indexing from instead of the contract's deployment block.
+    pub deploy_block: Option<u64>,
     pub blocks_per_request: u64,
     pub finality_blocks: u64,
+    /// Seconds `index` waits before polling again once caught up with finality.
+    pub poll_interval_secs: u64,
+    pub pending_blocks: u64,
+    pub ethereum_ws_url: Option<String>,
+    pub mempool_drop_after_secs: u64,
+    pub shard_size_blocks: u64,
+    pub token_decimals: u8,
+    pub token_symbol: String,
+    pub dedup_window_blocks: u64,
+    pub archive_batch_blocks: u64,
+    pub freshness_slo_secs: u64,
+    pub freshness_slo_percent: f64,
+    pub sqlite_journal_mode: String,
+    pub sqlite_synchronous: String,
+    pub sqlite_busy_timeout_ms: u64,
+    pub database_max_connections: u32,
+    pub auto_migrate: bool,
+    pub ens_resolution: bool,
+    pub ens_cache_ttl_secs: u64,
+    pub watchlist_only: bool,
+    /// Keep undecoded logs in `raw_logs` so `replay` can rebuild transfers without the RPC.
+    pub raw_logs: bool,
+    /// Fetch each new transaction's receipt to store its sender, gas and fee in `transactions`.
+    pub tx_enrichment: bool,
+    /// Check each new address for contract code and store the result in `address_info`.
+    pub address_classification: bool,
+    /// Chainlink aggregator whose answer values each transfer in dollars; `None` disables pricing.
+    pub price_feed_address: Option<String>,
+    /// Blocks that share one feed read, so prices are cached per interval rather than per block.
+    pub price_block_interval: u64,
+    /// ERC-1155 contracts whose transfers are indexed into `erc1155_transfers`; empty disables it.
+    pub erc1155_contracts: Vec<String>,
+    /// `ALERT_MIN_VALUE` as written, in token units or with a `raw` suffix.
+    pub alert_min_amount: Option<String>,
+    /// Raw value at or above which a transfer is sent to the alert sinks.
+    pub alert_min_value: Option<U256>,
+    pub alert_sinks: Vec<String>,
+    /// Sinks that `ALERT_MIN_VALUE` alerts go to; empty means every sink.
+    pub alert_min_value_channels: Vec<String>,
+    /// File or URL of the sanctions address list; `None` disables screening.
+    pub sanctions_list: Option<String>,
+    /// Age after which `index` reloads the sanctions list.
+    pub sanctions_refresh_secs: u64,
+    /// Alert when a newly indexed transfer touches a sanctioned address.
+    pub sanctions_alerts: bool,
+    /// Sinks that sanctions alerts go to; empty means every sink.
+    pub sanctions_alert_channels: Vec<String>,
+    /// Alert when an address, or the whole token, moves far more than usual in a window.
+    pub anomaly_detection: bool,
+    /// Length of the windows volume is compared across.
+    pub anomaly_window_secs: u64,
+    /// Windows the rolling baselines average over.
+    pub anomaly_baseline_windows: u64,
+    /// Multiple of its baseline at which a window's volume alerts.
+    pub anomaly_sensitivity: f64,
+    /// Volume, in token units, below which a window never alerts.
+    pub anomaly_min_volume: f64,
+    /// Sinks that anomaly alerts go to; empty means every sink.
+    pub anomaly_alert_channels: Vec<String>,
+    /// Blocks behind the chain head, `finality_blocks` included, above which the indexer counts as
+    /// falling behind; 0 disables lag alerts.
+    pub lag_alert_blocks: u64,
+    /// How long the lag must stay above `lag_alert_blocks` before it alerts.
+    pub lag_alert_minutes: u64,
+    /// Sinks that lag alerts go to; empty means every sink.
+    pub lag_alert_channels: Vec<String>,
+    /// Period of the holder count snapshots `index` records; 0 disables them.
+    pub holder_snapshot_secs: u64,
+    pub alert_webhook_url: Option<String>,
+    pub explorer_tx_url: String,
+    pub telegram_bot_token: Option<String>,
+    pub telegram_chat_id: Option<String>,
+    pub slack_webhook_url: Option<String>,
+    pub discord_webhook_url: Option<String>,
+    /// Extra named Slack webhooks from `SLACK_ROUTES`, used as sinks `slack:<name>`.
+    pub slack_routes: Vec<(String, String)>,
+    /// Extra named Discord webhooks from `DISCORD_ROUTES`, used as sinks `discord:<name>`.
+    pub discord_routes: Vec<(String, String)>,
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    /// `starttls`, `tls` (implicit TLS) or `none`.
+    pub smtp_tls: String,
+    pub email_from: Option<String>,
+    pub email_to: Vec<String>,
+    /// `immediate` sends one email per alert; `daily` batches each UTC day into one digest.
+    pub email_mode: String,
+    /// Comma-separated bootstrap servers; publishing to Kafka is off when unset.
+    pub kafka_brokers: Option<String>,
+    pub kafka_topic: String,
+    /// Redis server transfers are streamed to; off when unset.
+    pub redis_url: Option<String>,
+    pub redis_stream: String,
+    /// Approximate `MAXLEN` applied on every `XADD`; 0 never trims.
+    pub redis_stream_max_len: usize,
+    /// Pub/sub channel each message is also published on.
+    pub redis_channel: Option<String>,
+    /// ClickHouse HTTP interface transfers are exported to; off when unset.
+    pub clickhouse_url: Option<String>,
+    pub clickhouse_database: String,
+    pub clickhouse_table: String,
+    pub clickhouse_user: Option<String>,
+    pub clickhouse_password: Option<String>,
+    /// Bearer tokens `serve` accepts besides those stored with `api-key create`.
+    pub api_tokens: Vec<ConfiguredToken>,
+    /// OTLP/gRPC collector that spans are exported to; tracing stays local when unset.
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` the exported spans carry.
+    pub otel_service_name: String,
+    /// File events are also written to, in the same format and filter as stderr; off when unset.
+    pub log_file: Option<PathBuf>,
+    pub log_file_rotation: LogRotation,
+    /// Size in MiB at which the log file is rotated early; 0 rotates on `log_file_rotation` only.
+    pub log_file_max_mb: u64,
+    /// Rotated log files kept next to the current one (`<file>.1` is the newest); at least 1.
+    pub log_file_keep: usize,
 }
 
 impl Config {
+    /// Reads `.env` from the working directory, if there is one, then [`Config::from_env`].
     pub fn load() -> Result<Self> {
         let _ = dotenvy::dotenv();
-        let config = Config {
+        Self::from_env()
+    }
+
+    /// Reads settings from the process environment only, with defaults for anything unset.
+    /// Fails on values that cannot be interpreted, such as a malformed `ALERT_MIN_VALUE`.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Config {
             ethereum_rpc_url: std::env::var("ETHEREUM_RPC_URL")
                 .unwrap_or_else(|_| "https://ethereum.publicnode.com".to_string()),
+            chain_id: parse_chain_id()?,
+            rpc_record: std::env::var("RPC_RECORD").ok().filter(|path| !path.trim().is_empty()).map(PathBuf::from),
+            rpc_replay: std::env::var("RPC_REPLAY").ok().filter(|path| !path.trim().is_empty()).map(PathBuf::from),
             database_url: std::env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "sqlite:./transfers.db".to_string()),
             usdc_contract_address: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
+            deploy_block: std::env::var("DEPLOY_BLOCK").ok().and_then(|block| block.trim().parse().ok()),
             blocks_per_request: std::env::var("BLOCKS_PER_REQUEST")
                 .unwrap_or_else(|_| "100".to_string())
                 .parse()
@@ -27,8 +173,324 @@ impl Config {
                 .unwrap_or_else(|_| "12".to_string())
                 .parse()
                 .unwrap_or(12),
+            poll_interval_secs: std::env::var("POLL_INTERVAL_SECS")
+                .unwrap_or_else(|_| "12".to_string())
+                .parse()
+                .unwrap_or(12),
+            pending_blocks: std::env::var("PENDING_BLOCKS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
+            ethereum_ws_url: std::env::var("ETHEREUM_WS_URL").ok(),
+            mempool_drop_after_secs: std::env::var("MEMPOOL_DROP_AFTER_SECS")
+                .unwrap_or_else(|_| "600".to_string())
+                .parse()
+                .unwrap_or(600),
+            shard_size_blocks: std::env::var("SHARD_SIZE_BLOCKS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
+            token_decimals: std::env::var("TOKEN_DECIMALS")
+                .unwrap_or_else(|_| "6".to_string())
+                .parse()
+                .unwrap_or(6),
+            token_symbol: std::env::var("TOKEN_SYMBOL")
+                .unwrap_or_else(|_| "USDC".to_string()),
+            dedup_window_blocks: std::env::var("DEDUP_WINDOW_BLOCKS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
+            archive_batch_blocks: std::env::var("ARCHIVE_BATCH_BLOCKS")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse()
+                .unwrap_or(10000),
+            freshness_slo_secs: std::env::var("FRESHNESS_SLO_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .unwrap_or(60),
+            freshness_slo_percent: std::env::var("FRESHNESS_SLO_PERCENT")
+                .unwrap_or_else(|_| "95".to_string())
+                .parse()
+                .unwrap_or(95.0),
+            sqlite_journal_mode: std::env::var("SQLITE_JOURNAL_MODE")
+                .unwrap_or_else(|_| "wal".to_string()),
+            sqlite_synchronous: std::env::var("SQLITE_SYNCHRONOUS")
+                .unwrap_or_else(|_| "normal".to_string()),
+            sqlite_busy_timeout_ms: std::env::var("SQLITE_BUSY_TIMEOUT_MS")
+                .unwrap_or_else(|_| "5000".to_string())
+                .parse()
+                .unwrap_or(5000),
+            database_max_connections: std::env::var("DATABASE_MAX_CONNECTIONS")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+            auto_migrate: std::env::var("AUTO_MIGRATE")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
+            ens_resolution: std::env::var("ENS_RESOLUTION")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            ens_cache_ttl_secs: std::env::var("ENS_CACHE_TTL_SECS")
+                .unwrap_or_else(|_| "86400".to_string())
+                .parse()
+                .unwrap_or(86400),
+            watchlist_only: std::env::var("WATCHLIST_ONLY")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            raw_logs: std::env::var("RAW_LOGS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            tx_enrichment: std::env::var("TX_ENRICHMENT")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            address_classification: std::env::var("ADDRESS_CLASSIFICATION")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            price_feed_address: std::env::var("PRICE_FEED_ADDRESS").ok().filter(|a| !a.trim().is_empty()),
+            price_block_interval: std::env::var("PRICE_BLOCK_INTERVAL")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .unwrap_or(100),
+            erc1155_contracts: std::env::var("ERC1155_CONTRACTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|address| address.trim().to_string())
+                .filter(|address| !address.is_empty())
+                .collect(),
+            alert_min_amount: std::env::var("ALERT_MIN_VALUE").ok(),
+            alert_min_value: None,
+            alert_sinks: std::env::var("ALERT_SINKS")
+                .unwrap_or_else(|_| "log".to_string())
+                .split(',')
+                .map(|sink| sink.trim().to_lowercase())
+                .filter(|sink| !sink.is_empty())
+                .collect(),
+            alert_min_value_channels: std::env::var("ALERT_MIN_VALUE_CHANNELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|channel| channel.trim().to_lowercase())
+                .filter(|channel| !channel.is_empty())
+                .collect(),
+            sanctions_list: std::env::var("SANCTIONS_LIST").ok().filter(|s| !s.trim().is_empty()),
+            sanctions_refresh_secs: std::env::var("SANCTIONS_REFRESH_SECS")
+                .unwrap_or_else(|_| "86400".to_string())
+                .parse()
+                .unwrap_or(86400),
+            sanctions_alerts: std::env::var("SANCTIONS_ALERTS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            sanctions_alert_channels: std::env::var("SANCTIONS_ALERT_CHANNELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|channel| channel.trim().to_lowercase())
+                .filter(|channel| !channel.is_empty())
+                .collect(),
+            anomaly_detection: std::env::var("ANOMALY_DETECTION")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            anomaly_window_secs: std::env::var("ANOMALY_WINDOW_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .unwrap_or(3600),
+            anomaly_baseline_windows: std::env::var("ANOMALY_BASELINE_WINDOWS")
+                .unwrap_or_else(|_| "24".to_string())
+                .parse()
+                .unwrap_or(24),
+            anomaly_sensitivity: std::env::var("ANOMALY_SENSITIVITY")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10.0),
+            anomaly_min_volume: std::env::var("ANOMALY_MIN_VOLUME")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse()
+                .unwrap_or(10_000.0),
+            anomaly_alert_channels: std::env::var("ANOMALY_ALERT_CHANNELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|channel| channel.trim().to_lowercase())
+                .filter(|channel| !channel.is_empty())
+                .collect(),
+            lag_alert_blocks: std::env::var("LAG_ALERT_BLOCKS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
+            lag_alert_minutes: std::env::var("LAG_ALERT_MINUTES")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+            lag_alert_channels: std::env::var("LAG_ALERT_CHANNELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|channel| channel.trim().to_lowercase())
+                .filter(|channel| !channel.is_empty())
+                .collect(),
+            holder_snapshot_secs: std::env::var("HOLDER_SNAPSHOT_SECS")
+                .unwrap_or_else(|_| "86400".to_string())
+                .parse()
+                .unwrap_or(86_400),
+            alert_webhook_url: std::env::var("ALERT_WEBHOOK_URL").ok(),
+            explorer_tx_url: std::env::var("EXPLORER_TX_URL")
+                .unwrap_or_else(|_| "https://etherscan.io/tx/".to_string()),
+            telegram_bot_token: std::env::var("TELEGRAM_BOT_TOKEN").ok(),
+            telegram_chat_id: std::env::var("TELEGRAM_CHAT_ID").ok(),
+            slack_webhook_url: std::env::var("SLACK_WEBHOOK_URL").ok(),
+            discord_webhook_url: std::env::var("DISCORD_WEBHOOK_URL").ok(),
+            slack_routes: parse_routes("SLACK_ROUTES")?,
+            discord_routes: parse_routes("DISCORD_ROUTES")?,
+            smtp_host: std::env::var("SMTP_HOST").ok(),
+            smtp_port: std::env::var("SMTP_PORT")
+                .unwrap_or_else(|_| "587".to_string())
+                .parse()
+                .unwrap_or(587),
+            smtp_username: std::env::var("SMTP_USERNAME").ok(),
+            smtp_password: std::env::var("SMTP_PASSWORD").ok(),
+            smtp_tls: std::env::var("SMTP_TLS")
+                .unwrap_or_else(|_| "starttls".to_string())
+                .to_lowercase(),
+            email_from: std::env::var("EMAIL_FROM").ok(),
+            email_to: std::env::var("EMAIL_TO")
+                .unwrap_or_default()
+                .split(',')
+                .map(|address| address.trim().to_string())
+                .filter(|address| !address.is_empty())
+                .collect(),
+            email_mode: std::env::var("EMAIL_MODE")
+                .unwrap_or_else(|_| "immediate".to_string())
+                .to_lowercase(),
+            kafka_brokers: std::env::var("KAFKA_BROKERS").ok(),
+            kafka_topic: std::env::var("KAFKA_TOPIC")
+                .unwrap_or_else(|_| "usdc-transfers".to_string()),
+            redis_url: std::env::var("REDIS_URL").ok(),
+            redis_stream: std::env::var("REDIS_STREAM")
+                .unwrap_or_else(|_| "usdc:transfers".to_string()),
+            redis_stream_max_len: std::env::var("REDIS_STREAM_MAX_LEN")
+                .unwrap_or_else(|_| "1000000".to_string())
+                .parse()
+                .unwrap_or(1_000_000),
+            redis_channel: std::env::var("REDIS_CHANNEL").ok(),
+            clickhouse_url: std::env::var("CLICKHOUSE_URL").ok(),
+            clickhouse_database: parse_identifier("CLICKHOUSE_DATABASE", "default")?,
+            clickhouse_table: parse_identifier("CLICKHOUSE_TABLE", "usdc_transfers")?,
+            clickhouse_user: std::env::var("CLICKHOUSE_USER").ok(),
+            clickhouse_password: std::env::var("CLICKHOUSE_PASSWORD").ok(),
+            api_tokens: parse_api_tokens()?,
+            otlp_endpoint: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok(),
+            otel_service_name: std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "usdcwatch".to_string()),
+            log_file: std::env::var("LOG_FILE").ok().filter(|path| !path.is_empty()).map(PathBuf::from),
+            log_file_rotation: parse_log_rotation()?,
+            log_file_max_mb: std::env::var("LOG_FILE_MAX_MB")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .unwrap_or(100),
+            log_file_keep: std::env::var("LOG_FILE_KEEP")
+                .unwrap_or_else(|_| "7".to_string())
+                .parse()
+                .unwrap_or(7),
         };
-        
+
+        config.parse_alert_min_value()?;
         Ok(config)
     }
-}
\ No newline at end of file
+
+    /// Switches to the token's on-chain decimals and symbol, re-reading `ALERT_MIN_VALUE` in
+    /// the new units.
+    pub fn set_token(&mut self, decimals: u8, symbol: String) -> Result<()> {
+        self.token_decimals = decimals;
+        self.token_symbol = symbol;
+        self.parse_alert_min_value()
+    }
+
+    fn parse_alert_min_value(&mut self) -> Result<()> {
+        self.alert_min_value = self
+            .alert_min_amount
+            .as_deref()
+            .map(|amount| {
+                parse_amount(amount, self.token_decimals)
+                    .map_err(|e| anyhow::anyhow!("Invalid ALERT_MIN_VALUE: {}", e))
+            })
+            .transpose()?;
+        Ok(())
+    }
+}
+
+/// Reads `CHAIN_ID`, mainnet by default to match the USDC contract; empty or `any` turns the check off.
+fn parse_chain_id() -> Result<Option<u64>> {
+    match std::env::var("CHAIN_ID") {
+        Ok(id) if id.trim().is_empty() || id.trim().eq_ignore_ascii_case("any") => Ok(None),
+        Ok(id) => id
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("Invalid CHAIN_ID: {} (expected a number or any)", id)),
+        Err(_) => Ok(Some(1)),
+    }
+}
+
+/// Reads a name that is spliced into SQL, so only letters, digits and underscores are allowed.
+fn parse_identifier(var: &str, default: &str) -> Result<String> {
+    let name = std::env::var(var).unwrap_or_else(|_| default.to_string());
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(anyhow::anyhow!("Invalid {}: {} (expected letters, digits and underscores)", var, name));
+    }
+    Ok(name)
+}
+
+/// Reads `LOG_FILE_ROTATION`: `daily` (the default), `hourly` or `never`.
+fn parse_log_rotation() -> Result<LogRotation> {
+    match std::env::var("LOG_FILE_ROTATION") {
+        Ok(rotation) => <LogRotation as clap::ValueEnum>::from_str(rotation.trim(), true)
+            .map_err(|_| anyhow::anyhow!("Invalid LOG_FILE_ROTATION: {} (expected daily, hourly or never)", rotation)),
+        Err(_) => Ok(LogRotation::default()),
+    }
+}
+
+/// Reads `API_TOKENS`, `name:scope:token` entries separated by commas, e.g.
+/// `grafana:read:...,ops:admin:...`.
+fn parse_api_tokens() -> Result<Vec<ConfiguredToken>> {
+    std::env::var("API_TOKENS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut parts = entry.splitn(3, ':').map(str::trim);
+            let (Some(name), Some(scope), Some(token)) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(anyhow::anyhow!("Invalid API_TOKENS entry for {} (expected name:scope:token)", entry.split(':').next().unwrap_or_default()));
+            };
+            let scope = <Scope as clap::ValueEnum>::from_str(scope, true)
+                .map_err(|_| anyhow::anyhow!("Invalid API_TOKENS scope for {}: {} (expected read or admin)", name, scope))?;
+            if name.is_empty() || token.is_empty() {
+                return Err(anyhow::anyhow!("Invalid API_TOKENS entry for {} (expected name:scope:token)", name));
+            }
+            Ok(ConfiguredToken {
+                name: name.to_string(),
+                scope,
+                token: token.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Reads `name=url` pairs separated by commas, e.g. `treasury=https://...,compliance=https://...`.
+fn parse_routes(var: &str) -> Result<Vec<(String, String)>> {
+    std::env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|route| !route.is_empty())
+        .map(|route| match route.split_once('=') {
+            Some((name, url)) if !name.trim().is_empty() && !url.trim().is_empty() => {
+                Ok((name.trim().to_lowercase(), url.trim().to_string()))
+            }
+            _ => Err(anyhow::anyhow!("Invalid {} entry: {} (expected name=url)", var, route)),
+        })
+        .collect()
+}
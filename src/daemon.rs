@@ -0,0 +1,53 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{File, TryLockError},
+    io::ErrorKind,
+    path::Path,
+};
+
+/// Where `index --daemon` records its process id unless told otherwise.
+pub const DEFAULT_PID_FILE: &str = "usdcwatch.pid";
+
+/// Process id of the daemon holding the lock on `pid_file`, or `None` when none is running. A file
+/// left behind by a daemon that died is no longer locked, so it reads as not running rather than
+/// pointing at whatever process reused the id.
+pub fn running_pid(pid_file: &Path) -> Result<Option<u32>> {
+    let file = match File::open(pid_file) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {}", pid_file.display())),
+    };
+    match file.try_lock() {
+        Ok(()) => Ok(None),
+        Err(TryLockError::WouldBlock) => {
+            let pid = std::fs::read_to_string(pid_file)?;
+            let pid = pid
+                .trim()
+                .parse()
+                .with_context(|| format!("{} is locked but holds no process id", pid_file.display()))?;
+            Ok(Some(pid))
+        }
+        Err(TryLockError::Error(e)) => Err(e).with_context(|| format!("Failed to lock {}", pid_file.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_locked_pid_file_counts_as_running() {
+        let path = std::env::temp_dir().join(format!("usdcwatch-{}.pid", std::process::id()));
+        assert_eq!(running_pid(&path).unwrap(), None);
+
+        std::fs::write(&path, "4242\n").unwrap();
+        assert_eq!(running_pid(&path).unwrap(), None);
+
+        let daemon = File::open(&path).unwrap();
+        daemon.lock().unwrap();
+        assert_eq!(running_pid(&path).unwrap(), Some(4242));
+        drop(daemon);
+        assert_eq!(running_pid(&path).unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use ethers_core::types::U256;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Sparkline, Table},
+    DefaultTerminal, Frame,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration as StdDuration, Instant},
+};
+
+use crate::{
+    database::Database,
+    ethereum::EthereumClient,
+    models::{BlockActivity, PeriodActivity, TransferEvent},
+    output::AmountFormat,
+    query::{FlowInterval, TransferFilter},
+};
+
+/// Span the blocks/s and transfers/s rates are measured over.
+const RATE_WINDOW_SECS: i64 = 60;
+
+/// Rows in the large transfer feed.
+const LARGE_TRANSFERS: i64 = 50;
+
+const TOP_HOLDERS: usize = 20;
+
+/// Holder balances scan all of `address_stats`, so they are reloaded less often than the rest.
+const HOLDERS_REFRESH: StdDuration = StdDuration::from_secs(30);
+
+/// A full-screen view of indexing progress, recent large transfers, top holders and volume,
+/// reloaded from the database every `refresh`.
+pub struct Dashboard {
+    pub database: Database,
+    /// Used for the chain head; without it lag is not shown.
+    pub client: Option<EthereumClient>,
+    pub amounts: AmountFormat,
+    /// Raw token units at or above which transfers are shown in the feed.
+    pub min_value: String,
+    pub refresh: StdDuration,
+}
+
+/// What is on screen.
+#[derive(Default)]
+struct View {
+    last_block: Option<BlockActivity>,
+    chain_head: Option<u64>,
+    blocks_per_sec: f64,
+    transfers_per_sec: f64,
+    /// Raw token units; the feed's threshold.
+    min_value: String,
+    large_transfers: Vec<TransferEvent>,
+    hourly_volume: Vec<PeriodActivity>,
+    top_holders: Vec<(String, U256)>,
+    holders: usize,
+    /// Combined balance of every holder.
+    held: U256,
+    labels: HashMap<String, String>,
+    updated: Option<DateTime<Utc>>,
+    /// The last refresh failed; the data shown is from the one before.
+    error: Option<String>,
+}
+
+impl Dashboard {
+    /// Takes over the terminal until q, Esc or Ctrl-C is pressed.
+    pub async fn run(&self) -> Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal).await;
+        ratatui::restore();
+        result
+    }
+
+    async fn event_loop(&self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let mut view = View::default();
+        let mut holders_loaded: Option<Instant> = None;
+        loop {
+            if let Err(e) = self.reload(&mut view, &mut holders_loaded).await {
+                view.error = Some(e.to_string());
+            }
+            terminal.draw(|frame| draw(frame, &view, &self.amounts))?;
+
+            let deadline = Instant::now() + self.refresh;
+            while Instant::now() < deadline {
+                if !event::poll(StdDuration::from_millis(100))? {
+                    continue;
+                }
+                match event::read()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                        if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                            return Ok(());
+                        }
+                    }
+                    Event::Resize(..) => {
+                        terminal.draw(|frame| draw(frame, &view, &self.amounts))?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    async fn reload(&self, view: &mut View, holders_loaded: &mut Option<Instant>) -> Result<()> {
+        let now = self.database.now();
+        view.last_block = self.database.query_block_activity(None, None, 1).await?.into_iter().next();
+        if let Some(client) = &self.client {
+            // Keep the last known head when the node is slow or down.
+            if let Ok(Ok(head)) = tokio::time::timeout(StdDuration::from_secs(5), client.get_latest_block_number()).await {
+                view.chain_head = Some(head);
+            }
+        }
+
+        let (blocks, transfers) = self.database.indexed_since(now - Duration::seconds(RATE_WINDOW_SECS)).await?;
+        view.blocks_per_sec = blocks as f64 / RATE_WINDOW_SECS as f64;
+        view.transfers_per_sec = transfers as f64 / RATE_WINDOW_SECS as f64;
+
+        let filter = TransferFilter::new().value_range(Some(self.min_value.clone()), None);
+        view.min_value = self.min_value.clone();
+        view.large_transfers = self.database.query_transfers(&filter, LARGE_TRANSFERS).await?;
+        view.hourly_volume = self
+            .database
+            .period_activity(FlowInterval::Hour, Some(now - Duration::hours(24)), None)
+            .await?;
+
+        if holders_loaded.is_none_or(|loaded| loaded.elapsed() >= HOLDERS_REFRESH) {
+            let mut balances = self.database.holder_balances().await?;
+            balances.sort_unstable_by_key(|(_, balance)| std::cmp::Reverse(*balance));
+            view.holders = balances.len();
+            view.held = balances.iter().fold(U256::zero(), |sum, (_, balance)| sum + balance);
+            balances.truncate(TOP_HOLDERS);
+            view.top_holders = balances;
+            *holders_loaded = Some(Instant::now());
+        }
+
+        let addresses: Vec<String> = view
+            .large_transfers
+            .iter()
+            .flat_map(|t| [t.from_address.clone(), t.to_address.clone()])
+            .chain(view.top_holders.iter().map(|(address, _)| address.clone()))
+            .collect();
+        view.labels = self.database.get_labels(&addresses).await?;
+        view.updated = Some(now);
+        view.error = None;
+        Ok(())
+    }
+}
+
+fn draw(frame: &mut Frame, view: &View, amounts: &AmountFormat) {
+    let [status_area, middle, volume_area] =
+        Layout::vertical([Constraint::Length(4), Constraint::Min(8), Constraint::Length(7)]).areas(frame.area());
+    let [feed_area, holders_area] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(middle);
+    let now = view.updated.unwrap_or_else(Utc::now);
+    let name = |address: &str| match view.labels.get(address) {
+        Some(label) => label.clone(),
+        None if address.len() > 12 => format!("{}…{}", &address[..6], &address[address.len() - 4..]),
+        None => address.to_string(),
+    };
+
+    let progress = match &view.last_block {
+        Some(block) => {
+            let lag = match view.chain_head {
+                Some(head) => format!("head {}, {} blocks behind", head, head.saturating_sub(block.block_number as u64)),
+                None => "head unknown".to_string(),
+            };
+            format!(
+                "Block {} from {}s ago  |  {}",
+                block.block_number,
+                (now - block.timestamp).num_seconds().max(0),
+                lag
+            )
+        }
+        None => "No blocks processed yet".to_string(),
+    };
+    let mut status = vec![
+        Line::from(progress),
+        Line::from(format!(
+            "{:.2} blocks/s, {:.1} transfers/s over the last minute  |  updated {}  |  q to quit",
+            view.blocks_per_sec,
+            view.transfers_per_sec,
+            now.format("%H:%M:%S")
+        )),
+    ];
+    if let Some(error) = &view.error {
+        status[1] = Line::from(format!("Refresh failed: {}", error));
+    }
+    frame.render_widget(Paragraph::new(status).block(Block::bordered().title(" Indexing ")), status_area);
+
+    let header_style = Style::default().add_modifier(Modifier::BOLD);
+    let feed = Table::new(
+        view.large_transfers.iter().map(|t| {
+            Row::new(vec![
+                t.timestamp.format("%m-%d %H:%M").to_string(),
+                name(&t.from_address),
+                name(&t.to_address),
+                amounts.format(&t.value),
+            ])
+        }),
+        [Constraint::Length(11), Constraint::Fill(1), Constraint::Fill(1), Constraint::Length(24)],
+    )
+    .header(Row::new(vec!["time", "from", "to", "amount"]).style(header_style))
+    .block(Block::bordered().title(format!(" Large transfers, at least {} ", amounts.format(&view.min_value))));
+    frame.render_widget(feed, feed_area);
+
+    let to_f64 = |value: &U256| value.to_string().parse::<f64>().unwrap_or(0.0);
+    let holders = Table::new(
+        view.top_holders.iter().enumerate().map(|(i, (address, balance))| {
+            let share = if view.held.is_zero() { 0.0 } else { to_f64(balance) * 100.0 / to_f64(&view.held) };
+            Row::new(vec![
+                (i + 1).to_string(),
+                name(address),
+                amounts.format(&balance.to_string()),
+                format!("{:.2}%", share),
+            ])
+        }),
+        [Constraint::Length(3), Constraint::Fill(1), Constraint::Length(24), Constraint::Length(7)],
+    )
+    .header(Row::new(vec!["#", "holder", "balance", "share"]).style(header_style))
+    .block(Block::bordered().title(format!(" Top holders of {} ", view.holders)));
+    frame.render_widget(holders, holders_area);
+
+    // One bar per hour, quiet hours included, oldest first.
+    let volumes: HashMap<&str, U256> = view
+        .hourly_volume
+        .iter()
+        .map(|p| (p.period.as_str(), U256::from_dec_str(&p.volume).unwrap_or_default()))
+        .collect();
+    let unit = U256::exp10(amounts.decimals as usize);
+    let mut total = U256::zero();
+    let bars: Vec<u64> = (0..24)
+        .rev()
+        .map(|hours_ago| {
+            let volume = volumes
+                .get(FlowInterval::Hour.bucket(now - Duration::hours(hours_ago)).as_str())
+                .copied()
+                .unwrap_or_default();
+            total += volume;
+            (volume / unit).min(U256::from(u64::MAX)).as_u64()
+        })
+        .collect();
+    let sparkline = Sparkline::default()
+        .data(&bars)
+        .block(Block::bordered().title(format!(" Hourly volume, last 24h: {} ", amounts.format(&total.to_string()))));
+    frame.render_widget(sparkline, volume_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::TransferBuilder;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    const WHALE: &str = "0x28C6c06298d514Db089934071355E5743bf21d60";
+    const SHOP: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn draws_progress_feed_and_holders() {
+        let now: DateTime<Utc> = "2026-01-01T12:00:30Z".parse().unwrap();
+        let view = View {
+            last_block: Some(BlockActivity {
+                block_number: 1_000,
+                block_hash: "0x00".to_string(),
+                timestamp: now - Duration::seconds(12),
+                base_fee_per_gas: None,
+                gas_used: None,
+                gas_limit: None,
+                fee_recipient: None,
+                transfers: 1,
+                volume: "0".to_string(),
+            }),
+            chain_head: Some(1_064),
+            min_value: "100000000000".to_string(),
+            blocks_per_sec: 0.5,
+            transfers_per_sec: 12.25,
+            large_transfers: vec![TransferBuilder::new(999)
+                .from(WHALE)
+                .to(SHOP)
+                .value("2500000000000")
+                .at(now)
+                .build()],
+            top_holders: vec![(SHOP.to_string(), U256::from(7_500_000_000_000u64))],
+            holders: 3,
+            held: U256::from(10_000_000_000_000u64),
+            labels: [(WHALE.to_string(), "Binance 14".to_string())].into_iter().collect(),
+            updated: Some(now),
+            ..View::default()
+        };
+        let amounts = AmountFormat {
+            decimals: 6,
+            symbol: "USDC".to_string(),
+            raw: false,
+        };
+
+        let mut terminal = Terminal::new(TestBackend::new(140, 20)).unwrap();
+        terminal.draw(|frame| draw(frame, &view, &amounts)).unwrap();
+        let screen = terminal.backend().to_string();
+        assert!(screen.contains("Block 1000 from 12s ago  |  head 1064, 64 blocks behind"));
+        assert!(screen.contains("0.50 blocks/s, 12.2 transfers/s"));
+        assert!(screen.contains("Binance 14"));
+        assert!(screen.contains("0x5aAe…eAed"));
+        assert!(screen.contains("2,500,000.00 USDC"));
+        assert!(screen.contains("75.00%"));
+        assert!(screen.contains("at least 100,000.00 USDC"));
+        assert!(screen.contains("Top holders of 3"));
+    }
+}
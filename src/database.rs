@@ -1,233 +1,4298 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use sqlx::{Row, SqlitePool};
+use clap::ValueEnum;
+use ethers_core::types::U256;
+use futures::TryStreamExt;
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    sqlite::{SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions, SqliteRow, SqliteSynchronous},
+    QueryBuilder, Row, Sqlite, SqlitePool,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::instrument;
 
-use crate::models::{DatabaseStats, TransferEvent};
+use crate::{
+    address::{normalize_address, ZERO_ADDRESS},
+    archive::{compress_batch, decompress_batch, ArchiveSummary},
+    auth::Scope,
+    bloom::{AddressBloom, DEFAULT_CAPACITY},
+    clock::{Clock, SystemClock},
+    config::Config,
+    dedup::{payload_mismatches, resolve, EventSource, IngestOutcome},
+    import::{LabelConflict, LabelImportSummary, LabelRow},
+    latency::LatencySample,
+    models::{
+        ActivityRange, AddressInfo, AddressLabel, AddressStats, AlertRule, ApiKey, BackupSummary, BlockActivity, BlockPrice, BlockGap, CompactSummary, Counterparty, DailyVolume, DatabaseStats, DigestEntry, Erc1155Transfer, GapKind, HolderSnapshot, IngestConflict, MigrationStatus, NetFlow, PendingTx, PeriodActivity, ProcessedBlock, PruneSummary, RawLog, ReorgRecord,
+        TokenCheckpoint, TokenMetadata, TransactionDetails, TransferEvent, SanctionsStatus, TransferPage, TransferTotals, UsdTotals, VolumeBaseline, WatchedAddress,
+    },
+    price::{format_cents, parse_cents},
+    query::{CounterpartyRank, Cursor, Direction, FlowInterval, TransferFilter},
+    rollup::{aggregate, AddressDelta},
+    shard::{shard_file_name, ShardSet},
+    units::net_flow,
+};
 
+/// Versioned schema for the main database, embedded from `migrations/`.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Bound parameters per statement, under the 999 limit of older SQLite builds.
+const MAX_BOUND_PARAMS: usize = 900;
+
+/// Rows per multi-row `transfer_events` insert (10 columns each).
+const INSERT_BATCH_ROWS: usize = MAX_BOUND_PARAMS / 10;
+
+/// Rows per multi-row `erc1155_transfers` insert (14 columns each).
+const ERC1155_INSERT_BATCH_ROWS: usize = MAX_BOUND_PARAMS / 14;
+
+/// Block span folded into `address_stats` per transaction.
+const ROLLUP_CHUNK_BLOCKS: i64 = 10_000;
+
+/// Connection options applied to the main database and every shard.
+///
+/// WAL lets `query` read while `index` writes, and the busy timeout makes the remaining
+/// writer/writer contention wait instead of failing with "database is locked".
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    pub journal_mode: String,
+    pub synchronous: String,
+    pub busy_timeout_ms: u64,
+    pub max_connections: u32,
+    /// Apply pending migrations on open instead of refusing to start.
+    pub auto_migrate: bool,
+    /// Let a `QueryBudget` interrupt statements. Costs a round trip to the connection's worker
+    /// thread on every acquire, so only `serve` turns it on.
+    pub interruptible: bool,
+    /// Open with SQLite's read-only flag and never write: no migrations, no journal mode change
+    /// and no shard tables, so files on read-only mounts or without write permission open.
+    pub read_only: bool,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            journal_mode: "wal".to_string(),
+            synchronous: "normal".to_string(),
+            busy_timeout_ms: 5000,
+            max_connections: 10,
+            auto_migrate: true,
+            interruptible: false,
+            read_only: false,
+        }
+    }
+}
+
+impl From<&Config> for PoolSettings {
+    fn from(config: &Config) -> Self {
+        Self {
+            journal_mode: config.sqlite_journal_mode.clone(),
+            synchronous: config.sqlite_synchronous.clone(),
+            busy_timeout_ms: config.sqlite_busy_timeout_ms,
+            max_connections: config.database_max_connections,
+            auto_migrate: config.auto_migrate,
+            interruptible: false,
+            read_only: false,
+        }
+    }
+}
+
+impl PoolSettings {
+    pub async fn connect(&self, database_url: &str) -> Result<SqlitePool> {
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .synchronous(SqliteSynchronous::from_str(&self.synchronous)?)
+            .busy_timeout(Duration::from_millis(self.busy_timeout_ms));
+        // Setting the journal mode writes to the file; a read-only open keeps whatever it has.
+        let options = if self.read_only {
+            options.read_only(true).create_if_missing(false)
+        } else {
+            options
+                .journal_mode(SqliteJournalMode::from_str(&self.journal_mode)?)
+                .create_if_missing(true)
+        };
+
+        let mut pool = SqlitePoolOptions::new().max_connections(self.max_connections);
+        // An in-memory database is shared by the pool's connections and vanishes with the last
+        // one, so one is kept open for the pool's lifetime instead of being reaped when idle.
+        if is_in_memory(database_url) {
+            pool = pool.min_connections(1).idle_timeout(None).max_lifetime(None);
+        }
+        if self.interruptible {
+            pool = pool
+                .after_connect(|conn, _| Box::pin(apply_query_budget(conn)))
+                .before_acquire(|conn, _| Box::pin(async move { apply_query_budget(conn).await.map(|_| true) }));
+        }
+        Ok(pool.connect_with(options).await?)
+    }
+}
+
+/// Whether `database_url` names an in-memory database, like `sqlite::memory:`, rather than a file.
+pub fn is_in_memory(database_url: &str) -> bool {
+    let (path, query) = database_url.split_once('?').unwrap_or((database_url, ""));
+    path.ends_with(":memory:") || query.split('&').any(|param| param == "mode=memory")
+}
+
+/// Approximate SQLite VM instructions between checks of a statement's `QueryBudget`.
+const BUDGET_CHECK_OPS: i32 = 10_000;
+
+tokio::task_local! {
+    static QUERY_BUDGET: QueryBudget;
+}
+
+/// A deadline and cancellation flag for every query made by one unit of work, such as an API
+/// request. On databases opened with `PoolSettings::interruptible`, SQLite's progress handler
+/// interrupts any statement the work is running once either trips, so a runaway aggregate stops
+/// instead of holding its connection until it finishes.
+#[derive(Debug, Clone)]
+pub struct QueryBudget {
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl QueryBudget {
+    /// `None` never times out, leaving only cancellation.
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Runs `work` with every connection it acquires bound to this budget. Dropping the returned
+    /// future before it completes, as axum does when a client disconnects, cancels the budget.
+    pub async fn run<F: Future>(self, work: F) -> F::Output {
+        struct CancelOnDrop(Arc<AtomicBool>);
+        impl Drop for CancelOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let _cancel = CancelOnDrop(self.cancelled.clone());
+        QUERY_BUDGET.scope(self, work).await
+    }
+}
+
+/// Whether `error` comes from a statement SQLite interrupted, i.e. an exhausted `QueryBudget`.
+pub fn is_interrupted(error: &anyhow::Error) -> bool {
+    const SQLITE_INTERRUPT: &str = "9";
+    error.chain().any(|cause| match cause.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(e)) => e.code().as_deref() == Some(SQLITE_INTERRUPT),
+        _ => false,
+    })
+}
+
+/// Points the connection's progress handler at the acquiring task's budget, or removes the one
+/// left by its previous user.
+async fn apply_query_budget(conn: &mut SqliteConnection) -> std::result::Result<(), sqlx::Error> {
+    let budget = QUERY_BUDGET.try_with(QueryBudget::clone).ok();
+    let mut handle = conn.lock_handle().await?;
+    match budget {
+        Some(budget) => handle.set_progress_handler(BUDGET_CHECK_OPS, move || !budget.exhausted()),
+        None => handle.remove_progress_handler(),
+    }
+    Ok(())
+}
+
+/// Handle to the main database and its shards. Clones share the same pools.
+#[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
+    shards: Option<Arc<ShardSet>>,
+    settings: PoolSettings,
+    clock: Arc<dyn Clock>,
 }
 
 impl Database {
-    pub async fn new(database_url: &str) -> Result<Self> {
-        let pool = SqlitePool::connect(database_url).await?;
-        let db = Self { pool };
-        db.create_tables().await?;
+    /// Opens (creating if needed) the SQLite database at `database_url` and applies pending
+    /// migrations, or refuses to open with pending ones when `auto_migrate` is off.
+    pub async fn new(database_url: &str, settings: PoolSettings) -> Result<Self> {
+        let pool = settings.connect(database_url).await?;
+        let db = Self {
+            pool,
+            shards: None,
+            settings,
+            clock: Arc::new(SystemClock),
+        };
+
+        if db.settings.auto_migrate && !db.settings.read_only {
+            MIGRATOR.run(&db.pool).await?;
+        } else {
+            let pending: Vec<i64> = migration_status(&db.pool)
+                .await?
+                .into_iter()
+                .filter(|status| !status.applied)
+                .map(|status| status.version)
+                .collect();
+            if !pending.is_empty() && db.settings.read_only {
+                return Err(anyhow::anyhow!(
+                    "Database has pending migrations {:?} and is opened read-only; run `migrate` with write access first",
+                    pending
+                ));
+            }
+            if !pending.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Database has pending migrations {:?}; run `migrate` or set AUTO_MIGRATE=true",
+                    pending
+                ));
+            }
+        }
         Ok(db)
     }
 
-    async fn create_tables(&self) -> Result<()> {
-        sqlx::query!(
-            r#"
-            CREATE TABLE IF NOT EXISTS transfer_events (
-                id TEXT PRIMARY KEY,
-                transaction_hash TEXT NOT NULL,
-                log_index INTEGER NOT NULL,
-                block_number INTEGER NOT NULL,
-                block_hash TEXT NOT NULL,
-                from_address TEXT NOT NULL,
-                to_address TEXT NOT NULL,
-                value TEXT NOT NULL,
-                timestamp TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                UNIQUE(transaction_hash, log_index)
-            )
-            "#
-        )
-        .execute(&self.pool)
-        .await?;
+    /// Routes `transfer_events` into per-block-range shard files next to the main database.
+    /// A `shard_size` of 0 keeps everything in the main file.
+    pub async fn with_shards(mut self, database_url: &str, shard_size: u64) -> Result<Self> {
+        if shard_size > 0 {
+            self.shards = Some(Arc::new(ShardSet::open(database_url, shard_size, self.settings.clone()).await?));
+        }
+        Ok(self)
+    }
 
-        sqlx::query!(
-            r#"
-            CREATE INDEX IF NOT EXISTS idx_block_number ON transfer_events(block_number);
-            CREATE INDEX IF NOT EXISTS idx_from_address ON transfer_events(from_address);
-            CREATE INDEX IF NOT EXISTS idx_to_address ON transfer_events(to_address);
-            CREATE INDEX IF NOT EXISTS idx_timestamp ON transfer_events(timestamp);
-            "#
-        )
-        .execute(&self.pool)
-        .await?;
+    /// Whether this handle was opened with `PoolSettings::read_only`, so writes would fail.
+    pub fn is_read_only(&self) -> bool {
+        self.settings.read_only
+    }
 
-        sqlx::query!(
-            r#"
-            CREATE TABLE IF NOT EXISTS processed_blocks (
-                block_number INTEGER PRIMARY KEY,
-                block_hash TEXT NOT NULL,
-                timestamp TEXT NOT NULL,
-                processed_at TEXT NOT NULL
-            )
-            "#
-        )
-        .execute(&self.pool)
-        .await?;
+    /// Replaces the system clock used for row timestamps and time windows.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    pub async fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        let row = sqlx::query("SELECT value FROM meta WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("value")))
+    }
+
+    pub async fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)")
+            .bind(key)
+            .bind(value)
+            .execute(&self.pool)
+            .await?;
 
         Ok(())
     }
 
-    pub async fn insert_transfer_event(&self, event: &TransferEvent) -> Result<()> {
-        sqlx::query!(
-            r#"
-            INSERT OR IGNORE INTO transfer_events 
-            (id, transaction_hash, log_index, block_number, block_hash, from_address, to_address, value, timestamp, created_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-            "#,
-            event.id,
-            event.transaction_hash,
-            event.log_index,
-            event.block_number,
-            event.block_hash,
-            event.from_address,
-            event.to_address,
-            event.value,
-            event.timestamp,
-            event.created_at
+    pub async fn add_label(&self, address: &str, name: &str, category: Option<&str>) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO labels (address, name, category, created_at) VALUES (?, ?, ?, ?)")
+            .bind(normalize_address(address)?)
+            .bind(name)
+            .bind(category.map(str::to_lowercase))
+            .bind(self.now())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Loads labels in one transaction. Later rows for the same address win under `Overwrite`.
+    pub async fn import_labels(&self, labels: &[LabelRow], conflict: LabelConflict) -> Result<LabelImportSummary> {
+        let mut summary = LabelImportSummary::default();
+        let now = self.now();
+        let mut tx = self.pool.begin().await?;
+
+        for label in labels {
+            summary.rows += 1;
+            let existing = sqlx::query("SELECT 1 FROM labels WHERE address = ?")
+                .bind(&label.address)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            match (existing, conflict) {
+                (Some(_), LabelConflict::Skip) => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                (Some(_), LabelConflict::Overwrite) => summary.overwritten += 1,
+                (None, _) => summary.inserted += 1,
+            }
+
+            sqlx::query("INSERT OR REPLACE INTO labels (address, name, category, created_at) VALUES (?, ?, ?, ?)")
+                .bind(&label.address)
+                .bind(&label.name)
+                .bind(&label.category)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(summary)
+    }
+
+    /// Returns whether a label was removed.
+    pub async fn remove_label(&self, address: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM labels WHERE address = ?")
+            .bind(normalize_address(address)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_labels(&self) -> Result<Vec<AddressLabel>> {
+        let rows = sqlx::query("SELECT * FROM labels ORDER BY name, address")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| AddressLabel {
+                address: row.get("address"),
+                name: row.get("name"),
+                category: row.get("category"),
+                created_at: row.get("created_at"),
+            })
+            .collect())
+    }
+
+    /// Labeled addresses in `category`.
+    pub async fn label_addresses(&self, category: &str) -> Result<Vec<String>> {
+        let rows = sqlx::query("SELECT address FROM labels WHERE category = ? ORDER BY address")
+            .bind(category.to_lowercase())
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(|row| row.get("address")).collect())
+    }
+
+    /// Adds `address` to the watchlist. Returns false if it was already watched.
+    pub async fn add_watch(&self, address: &str) -> Result<bool> {
+        let result = sqlx::query("INSERT OR IGNORE INTO watchlist (address, created_at) VALUES (?, ?)")
+            .bind(normalize_address(address)?)
+            .bind(self.now())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove_watch(&self, address: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM watchlist WHERE address = ?")
+            .bind(normalize_address(address)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_watchlist(&self) -> Result<Vec<WatchedAddress>> {
+        let rows = sqlx::query(
+            "SELECT w.address, l.name AS label, w.created_at FROM watchlist w
+             LEFT JOIN labels l ON l.address = w.address ORDER BY w.created_at, w.address",
         )
-        .execute(&self.pool)
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(())
+        Ok(rows
+            .iter()
+            .map(|row| WatchedAddress {
+                address: row.get("address"),
+                label: row.get("label"),
+                created_at: row.get("created_at"),
+            })
+            .collect())
     }
 
-    pub async fn insert_processed_block(&self, block_number: u64, block_hash: &str, timestamp: DateTime<Utc>) -> Result<()> {
-        let block_num = block_number as i64;
-        let processed_at = Utc::now();
-        
-        sqlx::query!(
-            r#"
-            INSERT OR REPLACE INTO processed_blocks (block_number, block_hash, timestamp, processed_at)
-            VALUES (?, ?, ?, ?)
-            "#,
-            block_num,
-            block_hash,
-            timestamp,
-            processed_at
+    /// Fails when a key named `name` already exists.
+    pub async fn insert_api_key(&self, name: &str, scope: Scope, key_hash: &str) -> Result<()> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO api_keys (name, key_hash, scope, created_at) VALUES (?, ?, ?, ?)",
         )
+        .bind(name)
+        .bind(key_hash)
+        .bind(scope.as_str())
+        .bind(self.now())
         .execute(&self.pool)
         .await?;
-
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("An API key named {} already exists", name));
+        }
         Ok(())
     }
 
-    pub async fn get_latest_processed_block(&self) -> Result<Option<u64>> {
-        let row = sqlx::query!("SELECT MAX(block_number) as max_block FROM processed_blocks")
+    pub async fn revoke_api_key(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let rows = sqlx::query("SELECT name, scope, created_at, last_used_at FROM api_keys ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(row_to_api_key).collect()
+    }
+
+    pub async fn has_api_keys(&self) -> Result<bool> {
+        Ok(sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM api_keys)")
             .fetch_one(&self.pool)
+            .await?)
+    }
+
+    /// The key whose token hashes to `key_hash`, marking it used unless it already was within
+    /// the last minute, so busy clients do not write on every request. A read-only handle leaves
+    /// `last_used_at` as it is.
+    pub async fn use_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        if !self.is_read_only() {
+            let now = self.now();
+            sqlx::query(
+                "UPDATE api_keys SET last_used_at = ? WHERE key_hash = ? AND (last_used_at IS NULL OR last_used_at < ?)",
+            )
+            .bind(now)
+            .bind(key_hash)
+            .bind(now - chrono::Duration::minutes(1))
+            .execute(&self.pool)
             .await?;
+        }
+        let row = sqlx::query("SELECT name, scope, created_at, last_used_at FROM api_keys WHERE key_hash = ?")
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(row_to_api_key).transpose()
+    }
 
-        Ok(row.max_block.map(|b| b as u64))
+    pub async fn watched_addresses(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query("SELECT address FROM watchlist ORDER BY address")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(|row| row.get("address")).collect())
     }
 
-    pub async fn query_transfers(
-        &self,
-        address: Option<&str>,
-        from_block: Option<u64>,
-        to_block: Option<u64>,
-        limit: i64,
-    ) -> Result<Vec<TransferEvent>> {
-        let mut query = "SELECT * FROM transfer_events WHERE 1=1".to_string();
-        let mut conditions = Vec::new();
+    /// Fills `from_label`/`to_label` from `labels`. Transfers may live in shard files, so labels
+    /// are looked up for the page's addresses rather than joined in each shard.
+    async fn attach_labels(&self, transfers: &mut [TransferEvent]) -> Result<()> {
+        let addresses: Vec<String> = transfers
+            .iter()
+            .flat_map(|t| [t.from_address.clone(), t.to_address.clone()])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
 
-        if let Some(addr) = address {
-            conditions.push(format!("(from_address = '{}' OR to_address = '{}')", addr, addr));
+        let labels = self.get_labels(&addresses).await?;
+        if labels.is_empty() {
+            return Ok(());
+        }
+        for transfer in transfers {
+            transfer.from_label = labels.get(&transfer.from_address).cloned();
+            transfer.to_label = labels.get(&transfer.to_address).cloned();
         }
+        Ok(())
+    }
 
-        if let Some(from) = from_block {
-            conditions.push(format!("block_number >= {}", from));
+    /// Fills `tx_sender`/`tx_to`/`tx_fee` for transfers whose transaction has been enriched.
+    async fn attach_transactions(&self, transfers: &mut [TransferEvent]) -> Result<()> {
+        let hashes: Vec<String> = transfers
+            .iter()
+            .map(|t| t.transaction_hash.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        let transactions = self.get_transactions(&hashes).await?;
+        if transactions.is_empty() {
+            return Ok(());
+        }
+        for transfer in transfers {
+            if let Some(tx) = transactions.get(&transfer.transaction_hash) {
+                transfer.tx_sender = Some(tx.sender.clone());
+                transfer.tx_to = tx.recipient.clone();
+                transfer.tx_fee = Some(tx.fee.clone());
+            }
         }
+        Ok(())
+    }
 
-        if let Some(to) = to_block {
-            conditions.push(format!("block_number <= {}", to));
+    /// Fills `usd_value` for transfers that were priced.
+    async fn attach_usd_values(&self, transfers: &mut [TransferEvent]) -> Result<()> {
+        let ids: Vec<&String> = transfers.iter().map(|t| &t.id).collect();
+        let mut values: HashMap<String, String> = HashMap::new();
+        for chunk in ids.chunks(MAX_BOUND_PARAMS) {
+            let mut builder =
+                QueryBuilder::<Sqlite>::new("SELECT transfer_id, usd_value FROM transfer_usd_values WHERE transfer_id IN (");
+            let mut separated = builder.separated(", ");
+            for id in chunk {
+                separated.push_bind(*id);
+            }
+            builder.push(")");
+
+            for row in builder.build().fetch_all(&self.pool).await? {
+                values.insert(row.get("transfer_id"), row.get("usd_value"));
+            }
         }
 
-        if !conditions.is_empty() {
-            query.push_str(" AND ");
-            query.push_str(&conditions.join(" AND "));
+        for transfer in transfers {
+            transfer.usd_value = values.remove(&transfer.id);
         }
+        Ok(())
+    }
 
-        query.push_str(" ORDER BY block_number DESC, log_index ASC");
-        query.push_str(&format!(" LIMIT {}", limit));
+    /// Stores the `usd_value` of those `transfers` that have one.
+    #[instrument(skip_all, fields(transfers = transfers.len()))]
+    pub async fn insert_usd_values(&self, transfers: &[TransferEvent]) -> Result<()> {
+        let priced: Vec<&TransferEvent> = transfers.iter().filter(|t| t.usd_value.is_some()).collect();
+        for chunk in priced.chunks(INSERT_BATCH_ROWS) {
+            let mut builder =
+                QueryBuilder::<Sqlite>::new("INSERT OR REPLACE INTO transfer_usd_values (transfer_id, block_number, usd_value) ");
+            builder.push_values(chunk, |mut row, transfer| {
+                row.push_bind(&transfer.id)
+                    .push_bind(transfer.block_number)
+                    .push_bind(&transfer.usd_value);
+            });
+            builder.build().execute(&self.pool).await?;
+        }
+        Ok(())
+    }
 
-        let rows = sqlx::query(&query).fetch_all(&self.pool).await?;
-        
-        let mut transfers = Vec::new();
-        for row in rows {
-            transfers.push(TransferEvent {
-                id: row.get("id"),
-                transaction_hash: row.get("transaction_hash"),
-                log_index: row.get("log_index"),
-                block_number: row.get("block_number"),
-                block_hash: row.get("block_hash"),
-                from_address: row.get("from_address"),
-                to_address: row.get("to_address"),
-                value: row.get("value"),
-                timestamp: row.get::<String, _>("timestamp").parse()?,
-                created_at: row.get::<String, _>("created_at").parse()?,
+    /// Cached answers of `feed` at those `blocks` that have been read, keyed by block.
+    pub async fn get_block_prices(&self, feed: &str, blocks: &[u64]) -> Result<HashMap<u64, BlockPrice>> {
+        let mut prices = HashMap::new();
+        for chunk in blocks.chunks(MAX_BOUND_PARAMS - 1) {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM block_prices WHERE feed = ");
+            builder.push_bind(feed).push(" AND block_number IN (");
+            let mut separated = builder.separated(", ");
+            for block in chunk {
+                separated.push_bind(*block as i64);
+            }
+            builder.push(")");
+
+            for row in builder.build().fetch_all(&self.pool).await? {
+                let price = BlockPrice {
+                    feed: row.get("feed"),
+                    block_number: row.get("block_number"),
+                    answer: row.get("answer"),
+                    decimals: row.get::<i64, _>("decimals") as u8,
+                    updated_at: row.get("updated_at"),
+                };
+                prices.insert(price.block_number as u64, price);
+            }
+        }
+        Ok(prices)
+    }
+
+    pub async fn insert_block_prices(&self, prices: &[BlockPrice]) -> Result<()> {
+        for chunk in prices.chunks(INSERT_BATCH_ROWS) {
+            let mut builder = QueryBuilder::<Sqlite>::new(
+                "INSERT OR REPLACE INTO block_prices (feed, block_number, answer, decimals, updated_at) ",
+            );
+            builder.push_values(chunk, |mut row, price| {
+                row.push_bind(&price.feed)
+                    .push_bind(price.block_number)
+                    .push_bind(&price.answer)
+                    .push_bind(price.decimals as i64)
+                    .push_bind(price.updated_at);
             });
+            builder.build().execute(&self.pool).await?;
         }
+        Ok(())
+    }
 
-        Ok(transfers)
+    /// Replaces the stored sanctions list with `addresses`, read from `source`.
+    pub async fn replace_sanctioned_addresses(&self, source: &str, addresses: &[String]) -> Result<()> {
+        let now = self.now();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM sanctioned_addresses").execute(&mut *tx).await?;
+        for chunk in addresses.chunks(INSERT_BATCH_ROWS) {
+            let mut builder =
+                QueryBuilder::<Sqlite>::new("INSERT OR IGNORE INTO sanctioned_addresses (address, source, loaded_at) ");
+            builder.push_values(chunk, |mut row, address| {
+                row.push_bind(address).push_bind(source).push_bind(now);
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
     }
 
-    pub async fn get_stats(&self) -> Result<DatabaseStats> {
-        let total_transfers_row = sqlx::query!("SELECT COUNT(*) as count FROM transfer_events")
+    /// Those `addresses` that are on the sanctions list, lowercased.
+    pub async fn get_sanctioned(&self, addresses: &[String]) -> Result<HashSet<String>> {
+        let mut sanctioned = HashSet::new();
+        for chunk in addresses.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT address FROM sanctioned_addresses WHERE address IN (");
+            let mut separated = builder.separated(", ");
+            for address in chunk {
+                separated.push_bind(address);
+            }
+            builder.push(")");
+
+            for row in builder.build().fetch_all(&self.pool).await? {
+                sanctioned.insert(row.get::<String, _>("address").to_lowercase());
+            }
+        }
+        Ok(sanctioned)
+    }
+
+    pub async fn get_sanctions_status(&self) -> Result<SanctionsStatus> {
+        let count = sqlx::query("SELECT COUNT(*) AS count FROM sanctioned_addresses")
             .fetch_one(&self.pool)
             .await?;
+        let loaded = sqlx::query("SELECT source, loaded_at FROM sanctioned_addresses LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(SanctionsStatus {
+            addresses: count.get("count"),
+            source: loaded.as_ref().map(|row| row.get("source")),
+            loaded_at: loaded.as_ref().map(|row| row.get("loaded_at")),
+        })
+    }
 
-        let unique_addresses_row = sqlx::query!(
-            r#"
-            SELECT COUNT(DISTINCT address) as count FROM (
-                SELECT from_address as address FROM transfer_events
-                UNION
-                SELECT to_address as address FROM transfer_events
-            )
-            "#
-        )
-        .fetch_one(&self.pool)
-        .await?;
+    /// Stored baselines of `addresses`, keyed by lowercased address.
+    pub async fn get_volume_baselines(&self, addresses: &[String]) -> Result<HashMap<String, VolumeBaseline>> {
+        let mut baselines = HashMap::new();
+        for chunk in addresses.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM volume_baselines WHERE address IN (");
+            let mut separated = builder.separated(", ");
+            for address in chunk {
+                separated.push_bind(address);
+            }
+            builder.push(")");
 
-        let block_stats_row = sqlx::query!(
-            "SELECT MIN(block_number) as min_block, MAX(block_number) as max_block FROM transfer_events"
-        )
-        .fetch_one(&self.pool)
-        .await?;
+            for row in builder.build().fetch_all(&self.pool).await? {
+                let baseline = VolumeBaseline {
+                    address: row.get("address"),
+                    average: row.get("average"),
+                    windows: row.get("windows"),
+                    last_window: row.get("last_window"),
+                };
+                baselines.insert(baseline.address.to_lowercase(), baseline);
+            }
+        }
+        Ok(baselines)
+    }
 
-        Ok(DatabaseStats {
-            total_transfers: total_transfers_row.count as i64,
-            unique_addresses: unique_addresses_row.count as i64,
-            latest_block: block_stats_row.max_block.map(|b| b as i64),
-            earliest_block: block_stats_row.min_block.map(|b| b as i64),
-        })
+    /// The last window folded into `volume_baselines` and the window length it was cut with.
+    pub async fn get_volume_baselines_window(&self) -> Result<Option<(i64, u64)>> {
+        let window = self.get_meta("volume_baselines_window").await?;
+        let window_secs = self.get_meta("volume_baselines_window_secs").await?;
+        Ok(window.zip(window_secs).and_then(|(window, secs)| Some((window.parse().ok()?, secs.parse().ok()?))))
     }
 
-    pub async fn handle_reorg(&self, invalid_block: u64) -> Result<()> {
-        let invalid_block_i64 = invalid_block as i64;
-        
-        sqlx::query!(
-            "DELETE FROM transfer_events WHERE block_number >= ?",
-            invalid_block_i64
-        )
-        .execute(&self.pool)
-        .await?;
+    /// Stores `baselines` updated with `window` and records it as folded in.
+    pub async fn save_volume_baselines(&self, baselines: &[VolumeBaseline], window: i64, window_secs: u64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for chunk in baselines.chunks(INSERT_BATCH_ROWS) {
+            let mut builder =
+                QueryBuilder::<Sqlite>::new("INSERT OR REPLACE INTO volume_baselines (address, average, windows, last_window) ");
+            builder.push_values(chunk, |mut row, baseline| {
+                row.push_bind(&baseline.address)
+                    .push_bind(baseline.average)
+                    .push_bind(baseline.windows)
+                    .push_bind(baseline.last_window);
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+        for (key, value) in [("volume_baselines_window", window.to_string()), ("volume_baselines_window_secs", window_secs.to_string())] {
+            sqlx::query("INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)")
+                .bind(key)
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
 
-        sqlx::query!(
-            "DELETE FROM processed_blocks WHERE block_number >= ?",
-            invalid_block_i64
-        )
-        .execute(&self.pool)
-        .await?;
+    /// Forgets every baseline, e.g. after the window length changed.
+    pub async fn clear_volume_baselines(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM volume_baselines").execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM meta WHERE key IN ('volume_baselines_window', 'volume_baselines_window_secs')")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Sets `sanctioned` on transfers touching a listed address, and returns the listed addresses
+    /// among them, lowercased.
+    pub async fn mark_sanctioned(&self, transfers: &mut [TransferEvent]) -> Result<HashSet<String>> {
+        let addresses: Vec<String> = transfers
+            .iter()
+            .flat_map(|t| [t.from_address.clone(), t.to_address.clone()])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let sanctioned = self.get_sanctioned(&addresses).await?;
+        for transfer in transfers {
+            transfer.sanctioned = sanctioned.contains(&transfer.from_address.to_lowercase())
+                || sanctioned.contains(&transfer.to_address.to_lowercase());
+        }
+        Ok(sanctioned)
+    }
+
+    /// Stored details of those `transaction_hashes` that have been enriched, keyed by hash.
+    pub async fn get_transactions(&self, transaction_hashes: &[String]) -> Result<HashMap<String, TransactionDetails>> {
+        let mut transactions = HashMap::new();
+        for chunk in transaction_hashes.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM transactions WHERE transaction_hash IN (");
+            let mut separated = builder.separated(", ");
+            for hash in chunk {
+                separated.push_bind(hash);
+            }
+            builder.push(")");
+
+            for row in builder.build().fetch_all(&self.pool).await? {
+                let tx = TransactionDetails {
+                    transaction_hash: row.get("transaction_hash"),
+                    block_number: row.get("block_number"),
+                    sender: row.get("sender"),
+                    recipient: row.get("recipient"),
+                    gas_used: row.get("gas_used"),
+                    effective_gas_price: row.get("effective_gas_price"),
+                    fee: row.get("fee"),
+                    fetched_at: row.get("fetched_at"),
+                };
+                transactions.insert(tx.transaction_hash.clone(), tx);
+            }
+        }
+        Ok(transactions)
+    }
+
+    #[instrument(skip_all, fields(transactions = transactions.len()))]
+    pub async fn insert_transactions(&self, transactions: &[TransactionDetails]) -> Result<()> {
+        for chunk in transactions.chunks(INSERT_BATCH_ROWS) {
+            let mut builder = QueryBuilder::<Sqlite>::new(
+                "INSERT OR REPLACE INTO transactions \
+                 (transaction_hash, block_number, sender, recipient, gas_used, effective_gas_price, fee, fetched_at) ",
+            );
+            builder.push_values(chunk, |mut row, tx| {
+                row.push_bind(&tx.transaction_hash)
+                    .push_bind(tx.block_number)
+                    .push_bind(&tx.sender)
+                    .push_bind(&tx.recipient)
+                    .push_bind(&tx.gas_used)
+                    .push_bind(&tx.effective_gas_price)
+                    .push_bind(&tx.fee)
+                    .push_bind(tx.fetched_at);
+            });
+            builder.build().execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Stored classifications of those `addresses` that have been checked, keyed by address.
+    pub async fn get_address_info(&self, addresses: &[String]) -> Result<HashMap<String, AddressInfo>> {
+        let mut info = HashMap::new();
+        for chunk in addresses.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM address_info WHERE address IN (");
+            let mut separated = builder.separated(", ");
+            for address in chunk {
+                separated.push_bind(address);
+            }
+            builder.push(")");
 
+            for row in builder.build().fetch_all(&self.pool).await? {
+                let entry = AddressInfo {
+                    address: row.get("address"),
+                    is_contract: row.get("is_contract"),
+                    checked_at: row.get("checked_at"),
+                };
+                info.insert(entry.address.clone(), entry);
+            }
+        }
+        Ok(info)
+    }
+
+    #[instrument(skip_all, fields(addresses = info.len()))]
+    pub async fn insert_address_info(&self, info: &[AddressInfo]) -> Result<()> {
+        for chunk in info.chunks(INSERT_BATCH_ROWS) {
+            let mut builder =
+                QueryBuilder::<Sqlite>::new("INSERT OR REPLACE INTO address_info (address, is_contract, checked_at) ");
+            builder.push_values(chunk, |mut row, entry| {
+                row.push_bind(&entry.address)
+                    .push_bind(entry.is_contract)
+                    .push_bind(entry.checked_at);
+            });
+            builder.build().execute(&self.pool).await?;
+        }
         Ok(())
     }
 
-    pub async fn get_block_hash(&self, block_number: u64) -> Result<Option<String>> {
-        let block_num = block_number as i64;
-        
-        let row = sqlx::query!(
-            "SELECT block_hash FROM processed_blocks WHERE block_number = ?",
-            block_num
+    /// Labels of those `addresses` that have one, keyed by address.
+    pub async fn get_labels(&self, addresses: &[String]) -> Result<HashMap<String, String>> {
+        let mut labels: HashMap<String, String> = HashMap::new();
+        for chunk in addresses.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT address, name FROM labels WHERE address IN (");
+            let mut separated = builder.separated(", ");
+            for address in chunk {
+                separated.push_bind(address);
+            }
+            builder.push(")");
+
+            for row in builder.build().fetch_all(&self.pool).await? {
+                labels.insert(row.get("address"), row.get("name"));
+            }
+        }
+        Ok(labels)
+    }
+
+    /// Stores `rule`, assigning its `id` and `created_at`. Returns the new id.
+    pub async fn add_alert_rule(&self, rule: &AlertRule) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO alert_rules
+            (name, address, direction, min_value, max_value, counterparty_label, min_count, window_secs, channels, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
         )
-        .fetch_optional(&self.pool)
+        .bind(&rule.name)
+        .bind(rule.address.as_deref().map(normalize_address).transpose()?)
+        .bind(direction_name(rule.direction))
+        .bind(&rule.min_value)
+        .bind(&rule.max_value)
+        .bind(&rule.counterparty_label)
+        .bind(rule.min_count)
+        .bind(rule.window_secs)
+        .bind((!rule.channels.is_empty()).then(|| rule.channels.join(",")))
+        .bind(self.now())
+        .execute(&self.pool)
         .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn delete_alert_rule(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM alert_rules WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
 
-        Ok(row.map(|r| r.block_hash))
+    pub async fn get_alert_rule(&self, id: i64) -> Result<Option<AlertRule>> {
+        let row = sqlx::query("SELECT * FROM alert_rules WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(row_to_alert_rule).transpose()
+    }
+
+    pub async fn list_alert_rules(&self) -> Result<Vec<AlertRule>> {
+        let rows = sqlx::query("SELECT * FROM alert_rules ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(row_to_alert_rule).collect()
+    }
+
+    /// Holds an alert message for the daily email digest.
+    pub async fn add_digest_entry(&self, at: DateTime<Utc>, message: &str) -> Result<()> {
+        sqlx::query("INSERT INTO email_digest (at, message) VALUES (?, ?)")
+            .bind(at)
+            .bind(message)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Digest entries that fired before `before`, oldest first.
+    pub async fn get_digest_entries(&self, before: DateTime<Utc>) -> Result<Vec<DigestEntry>> {
+        let rows = sqlx::query("SELECT * FROM email_digest WHERE at < ? ORDER BY at, id")
+            .bind(before)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| DigestEntry {
+                id: row.get("id"),
+                at: row.get("at"),
+                message: row.get("message"),
+            })
+            .collect())
+    }
+
+    pub async fn delete_digest_entries(&self, ids: &[i64]) -> Result<()> {
+        for chunk in ids.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("DELETE FROM email_digest WHERE id IN (");
+            let mut separated = builder.separated(", ");
+            for id in chunk {
+                separated.push_bind(*id);
+            }
+            builder.push(")");
+            builder.build().execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_token(&self, address: &str) -> Result<Option<TokenMetadata>> {
+        let row = sqlx::query("SELECT * FROM tokens WHERE address = ?")
+            .bind(normalize_address(address)?)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| TokenMetadata {
+            address: row.get("address"),
+            name: row.get("name"),
+            symbol: row.get("symbol"),
+            decimals: row.get::<i64, _>("decimals") as u8,
+            standard: row.get("standard"),
+            fetched_at: row.get("fetched_at"),
+        }))
+    }
+
+    pub async fn set_token(&self, token: &TokenMetadata) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO tokens (address, name, symbol, decimals, standard, fetched_at) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&token.address)
+        .bind(&token.name)
+        .bind(&token.symbol)
+        .bind(token.decimals as i64)
+        .bind(&token.standard)
+        .bind(token.fetched_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Registers `token_address` on `chain_id` starting at `start_block`, with nothing indexed
+    /// yet from `next_block` on. A token that is already registered keeps its progress.
+    pub async fn register_token(
+        &self,
+        chain_id: u64,
+        token_address: &str,
+        standard: &str,
+        start_block: u64,
+        next_block: u64,
+    ) -> Result<TokenCheckpoint> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO token_checkpoints (chain_id, token_address, standard, start_block, next_block, updated_at) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(chain_id as i64)
+        .bind(normalize_address(token_address)?)
+        .bind(standard)
+        .bind(start_block as i64)
+        .bind(next_block as i64)
+        .bind(self.now())
+        .execute(&self.pool)
+        .await?;
+
+        self.get_token_checkpoints(Some(chain_id))
+            .await?
+            .into_iter()
+            .find(|checkpoint| checkpoint.token_address.eq_ignore_ascii_case(token_address))
+            .ok_or_else(|| anyhow::anyhow!("Token {} was not registered", token_address))
+    }
+
+    /// Registered tokens with their progress, on one chain or all of them.
+    pub async fn get_token_checkpoints(&self, chain_id: Option<u64>) -> Result<Vec<TokenCheckpoint>> {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "SELECT c.*, t.symbol FROM token_checkpoints c \
+             LEFT JOIN tokens t ON t.address = c.token_address COLLATE NOCASE",
+        );
+        if let Some(chain_id) = chain_id {
+            builder.push(" WHERE c.chain_id = ").push_bind(chain_id as i64);
+        }
+        builder.push(" ORDER BY c.chain_id ASC, c.start_block ASC, c.token_address ASC");
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        Ok(rows
+            .iter()
+            .map(|row| TokenCheckpoint {
+                chain_id: row.get::<i64, _>("chain_id") as u64,
+                token_address: row.get("token_address"),
+                standard: row.get("standard"),
+                symbol: row.get("symbol"),
+                start_block: row.get::<i64, _>("start_block") as u64,
+                next_block: row.get::<i64, _>("next_block") as u64,
+                updated_at: row.get("updated_at"),
+            })
+            .collect())
+    }
+
+    /// Moves the given tokens' progress up to `next_block`; tokens already past it are unchanged.
+    pub async fn advance_token_checkpoints(&self, chain_id: u64, token_addresses: &[String], next_block: u64) -> Result<()> {
+        for chunk in token_addresses.chunks(MAX_BOUND_PARAMS - 3) {
+            let mut builder = QueryBuilder::<Sqlite>::new("UPDATE token_checkpoints SET next_block = MAX(next_block, ");
+            builder
+                .push_bind(next_block as i64)
+                .push("), updated_at = ")
+                .push_bind(self.now())
+                .push(" WHERE chain_id = ")
+                .push_bind(chain_id as i64)
+                .push(" AND token_address IN (");
+            let mut separated = builder.separated(", ");
+            for address in chunk {
+                separated.push_bind(address);
+            }
+            builder.push(")");
+            builder.build().execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Stores a backfilled range of one ERC-1155 contract and moves its checkpoint past the range,
+    /// leaving every other token's rows untouched. `raw_logs` are the contract's logs in the range.
+    #[instrument(skip(self, transfers, raw_logs), fields(transfers = transfers.len()))]
+    pub async fn commit_token_backfill(
+        &self,
+        chain_id: u64,
+        contract: &str,
+        from_block: u64,
+        to_block: u64,
+        transfers: &[Erc1155Transfer],
+        raw_logs: Option<&[RawLog]>,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "DELETE FROM erc1155_transfers WHERE contract_address = ? COLLATE NOCASE AND block_number >= ? AND block_number <= ?",
+        )
+        .bind(contract)
+        .bind(from_block as i64)
+        .bind(to_block as i64)
+        .execute(&mut *tx)
+        .await?;
+        insert_erc1155_rows(&mut tx, transfers).await?;
+        if let Some(logs) = raw_logs {
+            insert_raw_log_rows(&mut tx, logs).await?;
+        }
+
+        sqlx::query(
+            "UPDATE token_checkpoints SET next_block = MAX(next_block, ?), updated_at = ? \
+             WHERE chain_id = ? AND token_address = ?",
+        )
+        .bind((to_block + 1) as i64)
+        .bind(self.now())
+        .bind(chain_id as i64)
+        .bind(contract)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Cached reverse lookups resolved at or after `since`, keyed by address. A `None` value is a
+    /// cached miss.
+    pub async fn get_ens_names(&self, addresses: &[String], since: DateTime<Utc>) -> Result<HashMap<String, Option<String>>> {
+        let mut names = HashMap::new();
+        for chunk in addresses.chunks(MAX_BOUND_PARAMS - 1) {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT address, name FROM ens_names WHERE resolved_at >= ");
+            builder.push_bind(since).push(" AND address IN (");
+            let mut separated = builder.separated(", ");
+            for address in chunk {
+                separated.push_bind(address);
+            }
+            builder.push(")");
+
+            for row in builder.build().fetch_all(&self.pool).await? {
+                names.insert(row.get("address"), row.get("name"));
+            }
+        }
+        Ok(names)
+    }
+
+    pub async fn set_ens_name(&self, address: &str, name: Option<&str>) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO ens_names (address, name, resolved_at) VALUES (?, ?, ?)")
+            .bind(address)
+            .bind(name)
+            .bind(self.now())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Cached forward lookup of `name` resolved at or after `since`: `Some(None)` is a cached miss.
+    pub async fn get_ens_address(&self, name: &str, since: DateTime<Utc>) -> Result<Option<Option<String>>> {
+        let row = sqlx::query("SELECT address FROM ens_addresses WHERE name = ? AND resolved_at >= ?")
+            .bind(name.to_lowercase())
+            .bind(since)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| row.get("address")))
+    }
+
+    pub async fn set_ens_address(&self, name: &str, address: Option<&str>) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO ens_addresses (name, address, resolved_at) VALUES (?, ?, ?)")
+            .bind(name.to_lowercase())
+            .bind(address)
+            .bind(self.now())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Inserts `event` unless its `(transaction_hash, log_index)` is already stored.
+    /// Returns whether a row was written.
+    pub async fn insert_transfer_event(&self, event: &TransferEvent) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO transfer_events 
+            (id, transaction_hash, log_index, block_number, block_hash, from_address, to_address, value, timestamp, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&event.id)
+        .bind(&event.transaction_hash)
+        .bind(event.log_index)
+        .bind(event.block_number)
+        .bind(&event.block_hash)
+        .bind(&event.from_address)
+        .bind(&event.to_address)
+        .bind(&event.value)
+        .bind(event.timestamp)
+        .bind(event.created_at)
+        .execute(&self.transfer_pool(event.block_number as u64).await?)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Ingests events from `source`, comparing payloads against already stored copies for
+    /// events within `window_blocks` of the latest processed block (0 compares everything).
+    pub async fn ingest_transfer_events(
+        &self,
+        events: &[TransferEvent],
+        source: EventSource,
+        window_blocks: u64,
+    ) -> Result<Vec<IngestOutcome>> {
+        let latest = self.get_latest_processed_block().await?.unwrap_or(0);
+
+        let mut outcomes = Vec::with_capacity(events.len());
+        for event in events {
+            let compare = window_blocks == 0 || event.block_number as u64 + window_blocks >= latest;
+            outcomes.push(self.ingest_transfer_event(event, source, compare).await?);
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Stores the events of a fetched block range and marks its blocks processed in one
+    /// transaction, using multi-row inserts. Events that are already stored go through the
+    /// per-event dedup path instead.
+    ///
+    /// With sharding, each shard file commits its events before the main database commits the
+    /// blocks, so a crash can only leave events without their block marked processed, which the
+    /// next run re-ingests as duplicates.
+    ///
+    /// `raw_logs`, when given, are the undecoded logs of `blocks` and are stored with them.
+    #[instrument(skip_all, fields(events = events.len(), blocks = blocks.len()))]
+    pub async fn commit_block_range(
+        &self,
+        events: &[TransferEvent],
+        blocks: &[ProcessedBlock],
+        raw_logs: Option<&[RawLog]>,
+        source: EventSource,
+        window_blocks: u64,
+    ) -> Result<Vec<IngestOutcome>> {
+        let latest = self.get_latest_processed_block().await?.unwrap_or(0);
+        let stored = self.stored_transfer_keys(events).await?;
+
+        let mut outcomes = vec![IngestOutcome::Inserted; events.len()];
+        let mut fresh: Vec<&TransferEvent> = Vec::new();
+        let mut seen = HashSet::new();
+        for (i, event) in events.iter().enumerate() {
+            let key = (event.transaction_hash.clone(), event.log_index);
+            if stored.contains(&key) {
+                let compare = window_blocks == 0 || event.block_number as u64 + window_blocks >= latest;
+                outcomes[i] = if compare {
+                    self.ingest_transfer_event(event, source, true).await?
+                } else {
+                    IngestOutcome::Duplicate
+                };
+            } else if !seen.insert(key) {
+                outcomes[i] = IngestOutcome::Duplicate;
+            } else {
+                fresh.push(event);
+            }
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        match &self.shards {
+            Some(shards) => {
+                let mut by_shard: HashMap<u64, Vec<&TransferEvent>> = HashMap::new();
+                for event in &fresh {
+                    by_shard.entry(shards.shard_index(event.block_number as u64)).or_default().push(event);
+                }
+                for shard_events in by_shard.values() {
+                    let pool = shards.pool_for_block(shard_events[0].block_number as u64).await?;
+                    let mut shard_tx = pool.begin().await?;
+                    insert_transfer_rows(&mut shard_tx, shard_events).await?;
+                    shard_tx.commit().await?;
+                }
+            }
+            None => insert_transfer_rows(&mut tx, &fresh).await?,
+        }
+
+        if source != EventSource::Rpc {
+            let ingested_at = self.now();
+            for chunk in fresh.chunks(INSERT_BATCH_ROWS) {
+                let mut builder = QueryBuilder::<Sqlite>::new("INSERT OR REPLACE INTO event_sources (event_id, source, ingested_at) ");
+                builder.push_values(chunk, |mut row, event| {
+                    row.push_bind(&event.id).push_bind(source.as_str()).push_bind(ingested_at);
+                });
+                builder.build().execute(&mut *tx).await?;
+            }
+        }
+
+        if let (Some(logs), Some(first), Some(last)) = (raw_logs, blocks.first(), blocks.last()) {
+            sqlx::query("DELETE FROM raw_logs WHERE block_number >= ? AND block_number <= ?")
+                .bind(first.block_number as i64)
+                .bind(last.block_number as i64)
+                .execute(&mut *tx)
+                .await?;
+
+            insert_raw_log_rows(&mut tx, logs).await?;
+        }
+
+        let processed_at = self.now();
+        for chunk in blocks.chunks(INSERT_BATCH_ROWS) {
+            let mut builder = QueryBuilder::<Sqlite>::new(
+                "INSERT OR REPLACE INTO processed_blocks \
+                 (block_number, block_hash, timestamp, processed_at, raw_logs, base_fee_per_gas, gas_used, gas_limit, fee_recipient) ",
+            );
+            builder.push_values(chunk, |mut row, block| {
+                row.push_bind(block.block_number as i64)
+                    .push_bind(&block.block_hash)
+                    .push_bind(block.timestamp)
+                    .push_bind(processed_at)
+                    .push_bind(raw_logs.is_some())
+                    .push_bind(&block.base_fee_per_gas)
+                    .push_bind(block.gas_used as i64)
+                    .push_bind(block.gas_limit as i64)
+                    .push_bind(&block.fee_recipient);
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+        Ok(outcomes)
+    }
+
+    /// `(transaction_hash, log_index)` of the given events that are already stored in any shard.
+    async fn stored_transfer_keys(&self, events: &[TransferEvent]) -> Result<HashSet<(String, i64)>> {
+        let hashes: Vec<&str> = events
+            .iter()
+            .map(|e| e.transaction_hash.as_str())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut stored = HashSet::new();
+        for pool in self.transfer_pools_for(None, None).await {
+            for chunk in hashes.chunks(MAX_BOUND_PARAMS) {
+                let mut builder =
+                    QueryBuilder::<Sqlite>::new("SELECT transaction_hash, log_index FROM transfer_events WHERE transaction_hash IN (");
+                let mut separated = builder.separated(", ");
+                for hash in chunk {
+                    separated.push_bind(*hash);
+                }
+                builder.push(")");
+
+                for row in builder.build().fetch_all(&pool).await? {
+                    stored.insert((row.get("transaction_hash"), row.get("log_index")));
+                }
+            }
+        }
+        Ok(stored)
+    }
+
+    async fn ingest_transfer_event(&self, event: &TransferEvent, source: EventSource, compare: bool) -> Result<IngestOutcome> {
+        let existing = if compare {
+            self.find_transfer(&event.transaction_hash, event.log_index).await?
+        } else {
+            None
+        };
+
+        let Some((existing, existing_pool)) = existing else {
+            if !self.insert_transfer_event(event).await? {
+                return Ok(IngestOutcome::Duplicate);
+            }
+            self.set_event_source(&event.id, source).await?;
+            return Ok(IngestOutcome::Inserted);
+        };
+
+        let existing_source = self.get_event_source(&existing.id).await?;
+        let mismatches = payload_mismatches(&existing, event);
+        let outcome = resolve(existing_source, source, !mismatches.is_empty());
+
+        if !mismatches.is_empty() {
+            sqlx::query(
+                r#"
+                INSERT INTO ingest_conflicts
+                (event_id, existing_source, incoming_source, fields, resolution, detected_at)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&event.id)
+            .bind(existing_source.as_str())
+            .bind(source.as_str())
+            .bind(mismatches.join(","))
+            .bind(outcome.as_str())
+            .bind(self.now())
+            .execute(&self.pool)
+            .await?;
+        }
+
+        match outcome {
+            IngestOutcome::Replaced => {
+                sqlx::query("DELETE FROM transfer_events WHERE id = ?")
+                    .bind(&existing.id)
+                    .execute(&existing_pool)
+                    .await?;
+                self.set_event_source(&existing.id, EventSource::Rpc).await?;
+                self.insert_transfer_event(event).await?;
+                self.set_event_source(&event.id, source).await?;
+            }
+            IngestOutcome::Duplicate if source > existing_source => {
+                self.set_event_source(&existing.id, source).await?;
+            }
+            _ => {}
+        }
+
+        Ok(outcome)
+    }
+
+    /// Looks up a stored transfer by its canonical key, along with the pool that holds it.
+    async fn find_transfer(&self, transaction_hash: &str, log_index: i64) -> Result<Option<(TransferEvent, SqlitePool)>> {
+        for pool in self.transfer_pools_for(None, None).await {
+            let row = sqlx::query("SELECT * FROM transfer_events WHERE transaction_hash = ? AND log_index = ?")
+                .bind(transaction_hash)
+                .bind(log_index)
+                .fetch_optional(&pool)
+                .await?;
+            if let Some(row) = row {
+                return Ok(Some((row_to_transfer(&row)?, pool)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Events without an `event_sources` row were indexed from RPC.
+    async fn get_event_source(&self, event_id: &str) -> Result<EventSource> {
+        let row = sqlx::query("SELECT source FROM event_sources WHERE event_id = ?")
+            .bind(event_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => EventSource::parse(&row.get::<String, _>("source")),
+            None => Ok(EventSource::Rpc),
+        }
+    }
+
+    async fn set_event_source(&self, event_id: &str, source: EventSource) -> Result<()> {
+        if source == EventSource::Rpc {
+            sqlx::query("DELETE FROM event_sources WHERE event_id = ?")
+                .bind(event_id)
+                .execute(&self.pool)
+                .await?;
+        } else {
+            sqlx::query("INSERT OR REPLACE INTO event_sources (event_id, source, ingested_at) VALUES (?, ?, ?)")
+                .bind(event_id)
+                .bind(source.as_str())
+                .bind(self.now())
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_ingest_conflicts(&self, limit: i64) -> Result<Vec<IngestConflict>> {
+        let rows = sqlx::query("SELECT * FROM ingest_conflicts ORDER BY id DESC LIMIT ?")
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut conflicts = Vec::new();
+        for row in rows {
+            conflicts.push(IngestConflict {
+                event_id: row.get("event_id"),
+                existing_source: row.get("existing_source"),
+                incoming_source: row.get("incoming_source"),
+                fields: row.get("fields"),
+                resolution: row.get("resolution"),
+                detected_at: row.get::<String, _>("detected_at").parse()?,
+            });
+        }
+
+        Ok(conflicts)
+    }
+
+    /// Replaces the unfinalized window starting at `from_block` with freshly fetched events.
+    #[instrument(skip(self, events), fields(events = events.len()))]
+    pub async fn replace_pending_transfers(&self, from_block: u64, events: &[TransferEvent]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM pending_transfers WHERE block_number >= ?")
+            .bind(from_block as i64)
+            .execute(&mut *tx)
+            .await?;
+
+        for event in events {
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO pending_transfers
+                (id, transaction_hash, log_index, block_number, block_hash, from_address, to_address, value, timestamp, created_at, confirmed)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0)
+                "#,
+            )
+            .bind(&event.id)
+            .bind(&event.transaction_hash)
+            .bind(event.log_index)
+            .bind(event.block_number)
+            .bind(&event.block_hash)
+            .bind(&event.from_address)
+            .bind(&event.to_address)
+            .bind(&event.value)
+            .bind(event.timestamp)
+            .bind(event.created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Moves pending rows up to `through_block` into `transfer_events`, keeping only those
+    /// whose block hash matches the finalized block recorded in `processed_blocks`. Returns how
+    /// many were inserted; rows already stored are dropped without counting.
+    #[instrument(skip(self))]
+    pub async fn promote_pending_transfers(&self, through_block: u64) -> Result<u64> {
+        let through = through_block as i64;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT p.* FROM pending_transfers p
+            JOIN processed_blocks b ON b.block_number = p.block_number AND b.block_hash = p.block_hash
+            WHERE p.block_number <= ?
+            "#,
+        )
+        .bind(through)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut promoted = 0;
+        for row in &rows {
+            if self.insert_transfer_event(&row_to_transfer(row)?).await? {
+                promoted += 1;
+            }
+        }
+
+        sqlx::query("DELETE FROM pending_transfers WHERE block_number <= ?")
+            .bind(through)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(promoted)
+    }
+
+    /// Block hashes of buffered unfinalized blocks from `from_block` onward, keyed by block number.
+    pub async fn get_pending_block_hashes(&self, from_block: u64) -> Result<HashMap<i64, String>> {
+        let rows = sqlx::query("SELECT DISTINCT block_number, block_hash FROM pending_transfers WHERE block_number >= ?")
+            .bind(from_block as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("block_number"), row.get("block_hash")))
+            .collect())
+    }
+
+    pub async fn record_reorg(&self, fork_block: u64, chain_head: u64, depth: u64, finality_blocks: u64) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO reorg_events (fork_block, chain_head, depth, finality_blocks, detected_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(fork_block as i64)
+        .bind(chain_head as i64)
+        .bind(depth as i64)
+        .bind(finality_blocks as i64)
+        .bind(self.now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Reorgs recorded after event `after_id`, as `(id, fork_block)` pairs in the order they were seen.
+    pub async fn get_reorg_forks_after(&self, after_id: i64) -> Result<Vec<(i64, u64)>> {
+        let rows = sqlx::query("SELECT id, fork_block FROM reorg_events WHERE id > ? ORDER BY id ASC")
+            .bind(after_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("id"), row.get::<i64, _>("fork_block") as u64))
+            .collect())
+    }
+
+    pub async fn get_reorg_depths(&self, since: DateTime<Utc>) -> Result<Vec<i64>> {
+        let rows = sqlx::query("SELECT depth FROM reorg_events WHERE detected_at >= ?")
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("depth")).collect())
+    }
+
+    pub async fn count_processed_blocks_since(&self, since: DateTime<Utc>) -> Result<i64> {
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM processed_blocks WHERE processed_at >= ?")
+            .bind(since)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get("count"))
+    }
+
+    /// Blocks processed since `since`, and the transfers stored in them.
+    pub async fn indexed_since(&self, since: DateTime<Utc>) -> Result<(i64, i64)> {
+        let row = sqlx::query("SELECT COUNT(*) AS count, MIN(block_number) AS first_block FROM processed_blocks WHERE processed_at >= ?")
+            .bind(since)
+            .fetch_one(&self.pool)
+            .await?;
+        let Some(first_block) = row.get::<Option<i64>, _>("first_block") else {
+            return Ok((0, 0));
+        };
+
+        let mut transfers = 0;
+        for pool in self.transfer_pools_for(Some(first_block as u64), None).await {
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transfer_events WHERE block_number >= ?")
+                .bind(first_block)
+                .fetch_one(&pool)
+                .await?;
+            transfers += count;
+        }
+        Ok((row.get("count"), transfers))
+    }
+
+    pub async fn get_reorg_events(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<ReorgRecord>> {
+        let rows = sqlx::query("SELECT * FROM reorg_events WHERE detected_at >= ? AND detected_at < ? ORDER BY detected_at")
+            .bind(since)
+            .bind(until)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ReorgRecord {
+                fork_block: row.get("fork_block"),
+                chain_head: row.get("chain_head"),
+                depth: row.get("depth"),
+                finality_blocks: row.get("finality_blocks"),
+                detected_at: row.get("detected_at"),
+            })
+            .collect())
+    }
+
+    /// Blocks that became queryable in `[since, until)`.
+    pub async fn get_latency_samples(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<LatencySample>> {
+        let rows = sqlx::query(
+            "SELECT block_number, timestamp, processed_at FROM processed_blocks WHERE processed_at >= ? AND processed_at < ?",
+        )
+        .bind(since)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| LatencySample {
+                block_number: row.get("block_number"),
+                timestamp: row.get("timestamp"),
+                processed_at: row.get("processed_at"),
+            })
+            .collect())
+    }
+
+    pub async fn insert_pending_tx(&self, pending: &PendingTx) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO pending_tx
+            (tx_hash, sender, method, from_address, to_address, value, first_seen)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&pending.tx_hash)
+        .bind(&pending.sender)
+        .bind(&pending.method)
+        .bind(&pending.from_address)
+        .bind(&pending.to_address)
+        .bind(&pending.value)
+        .bind(pending.first_seen)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_pending_txs(&self) -> Result<Vec<PendingTx>> {
+        let rows = sqlx::query("SELECT * FROM pending_tx ORDER BY first_seen ASC")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut pending = Vec::new();
+        for row in rows {
+            pending.push(PendingTx {
+                tx_hash: row.get("tx_hash"),
+                sender: row.get("sender"),
+                method: row.get("method"),
+                from_address: row.get("from_address"),
+                to_address: row.get("to_address"),
+                value: row.get("value"),
+                first_seen: row.get::<String, _>("first_seen").parse()?,
+            });
+        }
+
+        Ok(pending)
+    }
+
+    pub async fn delete_pending_tx(&self, tx_hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM pending_tx WHERE tx_hash = ?")
+            .bind(tx_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn insert_processed_block(&self, block_number: u64, block_hash: &str, timestamp: DateTime<Utc>) -> Result<()> {
+        let block_num = block_number as i64;
+        let processed_at = self.now();
+        
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO processed_blocks (block_number, block_hash, timestamp, processed_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(block_num)
+        .bind(block_hash)
+        .bind(timestamp)
+        .bind(processed_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Up to `count` processed blocks picked at random, ascending.
+    pub async fn sample_processed_blocks(&self, count: i64) -> Result<Vec<u64>> {
+        let rows = sqlx::query(
+            "SELECT block_number FROM (SELECT block_number FROM processed_blocks ORDER BY RANDOM() LIMIT ?) ORDER BY block_number",
+        )
+        .bind(count)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(|row| row.get::<i64, _>("block_number") as u64).collect())
+    }
+
+    /// Holes in `processed_blocks`, and processed blocks with no stored or archived transfers.
+    /// The latter usually mean an insert failed after the block was marked processed.
+    pub async fn find_gaps(&self) -> Result<Vec<BlockGap>> {
+        let mut gaps: Vec<BlockGap> = sqlx::query(
+            r#"
+            SELECT block_number + 1 AS from_block, next_block - 1 AS to_block FROM (
+                SELECT block_number, LEAD(block_number) OVER (ORDER BY block_number) AS next_block
+                FROM processed_blocks
+            )
+            WHERE next_block > block_number + 1
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| BlockGap {
+            from_block: row.get::<i64, _>("from_block") as u64,
+            to_block: row.get::<i64, _>("to_block") as u64,
+            kind: GapKind::Unprocessed,
+        })
+        .collect();
+
+        let bounds = sqlx::query("SELECT MIN(block_number) AS min_block, MAX(block_number) AS max_block FROM processed_blocks")
+            .fetch_one(&self.pool)
+            .await?;
+        let (Some(min_block), Some(max_block)) = (
+            bounds.get::<Option<i64>, _>("min_block"),
+            bounds.get::<Option<i64>, _>("max_block"),
+        ) else {
+            return Ok(gaps);
+        };
+
+        let archived: Vec<(i64, i64)> = sqlx::query("SELECT start_block, end_block FROM archived_transfers")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| (row.get("start_block"), row.get("end_block")))
+            .collect();
+
+        let mut empty: Vec<i64> = Vec::new();
+        let mut from = min_block;
+        while from <= max_block {
+            let to = std::cmp::min(from + ROLLUP_CHUNK_BLOCKS - 1, max_block);
+
+            let mut stored = HashSet::new();
+            for pool in self.transfer_pools_for(Some(from as u64), Some(to as u64)).await {
+                let rows = sqlx::query("SELECT DISTINCT block_number FROM transfer_events WHERE block_number BETWEEN ? AND ?")
+                    .bind(from)
+                    .bind(to)
+                    .fetch_all(&pool)
+                    .await?;
+                stored.extend(rows.iter().map(|row| row.get::<i64, _>("block_number")));
+            }
+
+            let processed = sqlx::query("SELECT block_number FROM processed_blocks WHERE block_number BETWEEN ? AND ? ORDER BY block_number")
+                .bind(from)
+                .bind(to)
+                .fetch_all(&self.pool)
+                .await?;
+            empty.extend(
+                processed
+                    .iter()
+                    .map(|row| row.get::<i64, _>("block_number"))
+                    .filter(|block| !stored.contains(block))
+                    .filter(|block| !archived.iter().any(|(start, end)| (start..=end).contains(&block))),
+            );
+
+            from = to + 1;
+        }
+
+        for block in empty {
+            let block = block as u64;
+            match gaps.last_mut() {
+                Some(gap) if gap.kind == GapKind::NoEvents && gap.to_block + 1 == block => gap.to_block = block,
+                _ => gaps.push(BlockGap {
+                    from_block: block,
+                    to_block: block,
+                    kind: GapKind::NoEvents,
+                }),
+            }
+        }
+
+        gaps.sort_by_key(|gap| gap.from_block);
+        Ok(gaps)
+    }
+
+    pub async fn get_latest_processed_block(&self) -> Result<Option<u64>> {
+        let row = sqlx::query("SELECT MAX(block_number) as max_block FROM processed_blocks")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get::<Option<i64>, _>("max_block").map(|b| b as u64))
+    }
+
+    /// Up to `limit` processed blocks in the range, newest first, with the number and raw volume
+    /// of transfers stored in each.
+    pub async fn query_block_activity(&self, from_block: Option<u64>, to_block: Option<u64>, limit: i64) -> Result<Vec<BlockActivity>> {
+        let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM processed_blocks WHERE 1 = 1");
+        if let Some(from_block) = from_block {
+            builder.push(" AND block_number >= ").push_bind(from_block as i64);
+        }
+        if let Some(to_block) = to_block {
+            builder.push(" AND block_number <= ").push_bind(to_block as i64);
+        }
+        builder.push(" ORDER BY block_number DESC LIMIT ").push_bind(limit);
+        let rows = builder.build().fetch_all(&self.pool).await?;
+
+        let (Some(last), Some(first)) = (rows.first(), rows.last()) else {
+            return Ok(Vec::new());
+        };
+        let first_block = first.get::<i64, _>("block_number");
+        let last_block = last.get::<i64, _>("block_number");
+
+        // Transfers may live in shard files, so they are totalled per pool and merged here.
+        let mut totals: HashMap<i64, (i64, U256)> = HashMap::new();
+        for pool in self.transfer_pools_for(Some(first_block as u64), Some(last_block as u64)).await {
+            let transfer_rows = sqlx::query(
+                "SELECT block_number, COUNT(*) AS count, SUM(value_high) AS high, SUM(value_low) AS low \
+                 FROM transfer_events WHERE block_number >= ? AND block_number <= ? GROUP BY block_number",
+            )
+            .bind(first_block)
+            .bind(last_block)
+            .fetch_all(&pool)
+            .await?;
+
+            for row in transfer_rows {
+                let entry = totals.entry(row.get("block_number")).or_insert((0, U256::zero()));
+                entry.0 += row.get::<i64, _>("count");
+                entry.1 += U256::from(row.get::<i64, _>("high") as u64) * U256::exp10(9)
+                    + U256::from(row.get::<i64, _>("low") as u64);
+            }
+        }
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let block_number: i64 = row.get("block_number");
+                let (transfers, volume) = totals.get(&block_number).copied().unwrap_or_default();
+                BlockActivity {
+                    block_number,
+                    block_hash: row.get("block_hash"),
+                    timestamp: row.get("timestamp"),
+                    base_fee_per_gas: row.get("base_fee_per_gas"),
+                    gas_used: row.get("gas_used"),
+                    gas_limit: row.get("gas_limit"),
+                    fee_recipient: row.get("fee_recipient"),
+                    transfers,
+                    volume: volume.to_string(),
+                }
+            })
+            .collect())
+    }
+
+    /// Per-day count and volume of transfers sent by (`Sent`), received by (`Received`) or touching
+    /// (`Both`) any of `addresses`, oldest day first. Days are UTC.
+    pub async fn daily_volume(
+        &self,
+        addresses: &[String],
+        direction: Direction,
+        from_time: Option<DateTime<Utc>>,
+        to_time: Option<DateTime<Utc>>,
+    ) -> Result<Vec<DailyVolume>> {
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Both sides are matched in one statement so a transfer between two of the addresses is
+        // only counted once.
+        if addresses.len() * 2 + 2 > MAX_BOUND_PARAMS {
+            return Err(anyhow::anyhow!(
+                "Too many addresses for a volume query ({}, at most {})",
+                addresses.len(),
+                (MAX_BOUND_PARAMS - 2) / 2
+            ));
+        }
+
+        let mut totals: BTreeMap<String, (i64, U256)> = BTreeMap::new();
+        for pool in self.transfer_pools_for(None, None).await {
+            let mut builder = QueryBuilder::<Sqlite>::new(
+                "SELECT DATE(timestamp) AS day, COUNT(*) AS count, SUM(value_high) AS high, SUM(value_low) AS low \
+                 FROM transfer_events WHERE (",
+            );
+            let columns: &[&str] = match direction {
+                Direction::Sent => &["from_address"],
+                Direction::Received => &["to_address"],
+                Direction::Both => &["from_address", "to_address"],
+            };
+            for (i, column) in columns.iter().enumerate() {
+                if i > 0 {
+                    builder.push(" OR ");
+                }
+                builder.push(column).push(" COLLATE NOCASE IN (");
+                let mut separated = builder.separated(", ");
+                for address in addresses {
+                    separated.push_bind(address);
+                }
+                builder.push(")");
+            }
+            builder.push(")");
+            if let Some(from) = from_time {
+                builder.push(" AND timestamp >= ").push_bind(from);
+            }
+            if let Some(to) = to_time {
+                builder.push(" AND timestamp <= ").push_bind(to);
+            }
+            builder.push(" GROUP BY day");
+
+            for row in builder.build().fetch_all(&pool).await? {
+                let entry = totals.entry(row.get("day")).or_insert((0, U256::zero()));
+                entry.0 += row.get::<i64, _>("count");
+                entry.1 += U256::from(row.get::<i64, _>("high") as u64) * U256::exp10(9)
+                    + U256::from(row.get::<i64, _>("low") as u64);
+            }
+        }
+
+        Ok(totals
+            .into_iter()
+            .map(|(day, (transfers, volume))| DailyVolume {
+                day,
+                transfers,
+                volume: volume.to_string(),
+            })
+            .collect())
+    }
+
+    pub async fn get_earliest_processed_block(&self) -> Result<Option<u64>> {
+        let row = sqlx::query("SELECT MIN(block_number) as min_block FROM processed_blocks")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get::<Option<i64>, _>("min_block").map(|b| b as u64))
+    }
+
+    /// Up to `limit` stored transfers matching `filter`, newest first.
+    pub async fn query_transfers(&self, filter: &TransferFilter, limit: i64) -> Result<Vec<TransferEvent>> {
+        self.query_table("transfer_events", filter, limit).await
+    }
+
+    /// Fetches one page of transfers plus the cursor for the next page, if there may be one.
+    pub async fn query_transfers_page(&self, filter: &TransferFilter, limit: i64) -> Result<TransferPage> {
+        let transfers = self.query_transfers(filter, limit).await?;
+        let next_cursor = match transfers.last() {
+            Some(last) if transfers.len() as i64 == limit => Some(Cursor::after(last).encode()),
+            _ => None,
+        };
+
+        Ok(TransferPage {
+            transfers,
+            next_cursor,
+        })
+    }
+
+    /// Every transfer emitted by `transaction_hash`, in log-index order.
+    pub async fn get_transfers_by_tx(&self, transaction_hash: &str) -> Result<Vec<TransferEvent>> {
+        let transaction_hash = transaction_hash.to_lowercase();
+
+        let mut transfers = Vec::new();
+        for pool in self.transfer_pools_for(None, None).await {
+            let rows = sqlx::query("SELECT * FROM transfer_events WHERE transaction_hash = ? ORDER BY log_index ASC")
+                .bind(&transaction_hash)
+                .fetch_all(&pool)
+                .await?;
+            for row in rows {
+                transfers.push(row_to_transfer(&row)?);
+            }
+        }
+
+        transfers.sort_by_key(|t| t.log_index);
+        self.attach_labels(&mut transfers).await?;
+        self.mark_sanctioned(&mut transfers).await?;
+        self.attach_transactions(&mut transfers).await?;
+        self.attach_usd_values(&mut transfers).await?;
+        Ok(transfers)
+    }
+
+    pub async fn query_pending_transfers(&self, filter: &TransferFilter, limit: i64) -> Result<Vec<TransferEvent>> {
+        self.query_table("pending_transfers", filter, limit).await
+    }
+
+    async fn query_table(&self, table: &str, filter: &TransferFilter, limit: i64) -> Result<Vec<TransferEvent>> {
+        self.check_lookup_filters(filter)?;
+        let filter = &if table == "transfer_events" {
+            self.narrow_by_bloom(filter).await?
+        } else {
+            filter.clone()
+        };
+        let pools = if table == "transfer_events" {
+            self.transfer_pools_for(filter.from_block, filter.to_block).await
+        } else {
+            vec![self.pool.clone()]
+        };
+
+        // Pools are ordered newest range first, so concatenating keeps the global ordering.
+        let mut transfers = Vec::new();
+        for pool in pools {
+            let remaining = limit - transfers.len() as i64;
+            if remaining <= 0 {
+                break;
+            }
+
+            let mut builder = QueryBuilder::<Sqlite>::new(format!("SELECT * FROM {}", table));
+            filter.push_where(&mut builder);
+            builder.push(" ORDER BY block_number DESC, log_index ASC LIMIT ").push_bind(remaining);
+
+            for row in builder.build().fetch_all(&pool).await? {
+                transfers.push(row_to_transfer(&row)?);
+            }
+        }
+
+        // Archived batches only ever hold blocks older than every live row.
+        if table == "transfer_events" && filter.include_archived && (transfers.len() as i64) < limit {
+            let remaining = (limit - transfers.len() as i64) as usize;
+            transfers.extend(self.query_archived_transfers(filter, remaining).await?);
+        }
+
+        self.attach_labels(&mut transfers).await?;
+        self.mark_sanctioned(&mut transfers).await?;
+        self.attach_transactions(&mut transfers).await?;
+        self.attach_usd_values(&mut transfers).await?;
+        Ok(transfers)
+    }
+
+    /// Counts and sums the transfers matching `filter` without fetching the rows.
+    pub async fn aggregate_transfers(&self, filter: &TransferFilter) -> Result<TransferTotals> {
+        self.aggregate_table("transfer_events", filter).await
+    }
+
+    pub async fn aggregate_pending_transfers(&self, filter: &TransferFilter) -> Result<TransferTotals> {
+        self.aggregate_table("pending_transfers", filter).await
+    }
+
+    async fn aggregate_table(&self, table: &str, filter: &TransferFilter) -> Result<TransferTotals> {
+        self.check_lookup_filters(filter)?;
+        let filter = &if table == "transfer_events" {
+            self.narrow_by_bloom(filter).await?
+        } else {
+            filter.clone()
+        };
+        let pools = if table == "transfer_events" {
+            self.transfer_pools_for(filter.from_block, filter.to_block).await
+        } else {
+            vec![self.pool.clone()]
+        };
+
+        let mut count = 0i64;
+        let mut total = U256::zero();
+        let mut max: Option<U256> = None;
+        for pool in pools {
+            // Summed values can exceed i64, so add the high digits and the low nine digits separately.
+            let mut builder = QueryBuilder::<Sqlite>::new(format!(
+                r#"
+                SELECT COUNT(*) AS count,
+                       COALESCE(SUM(value_high), 0) AS high,
+                       COALESCE(SUM(value_low), 0) AS low
+                FROM {}
+                "#,
+                table
+            ));
+            filter.push_where(&mut builder);
+
+            let row = builder.build().fetch_one(&pool).await?;
+            let high: i64 = row.get("high");
+            let low: i64 = row.get("low");
+            count += row.get::<i64, _>("count");
+            total += U256::from(high as u64) * U256::exp10(9) + U256::from(low as u64);
+
+            let mut builder = QueryBuilder::<Sqlite>::new(format!("SELECT value FROM {}", table));
+            filter.push_where(&mut builder);
+            builder.push(" ORDER BY value_high DESC, value_low DESC LIMIT 1");
+            if let Some(row) = builder.build().fetch_optional(&pool).await? {
+                let value = U256::from_dec_str(&row.get::<String, _>("value"))?;
+                max = max.max(Some(value));
+            }
+        }
+
+        if table == "transfer_events" && filter.include_archived {
+            let rows = sqlx::query("SELECT data FROM archived_transfers WHERE end_block >= ? AND start_block <= ?")
+                .bind(filter.from_block.unwrap_or(0) as i64)
+                .bind(filter.to_block.map_or(i64::MAX, |to| to as i64))
+                .fetch_all(&self.pool)
+                .await?;
+
+            for row in rows {
+                let data: Vec<u8> = row.get("data");
+                for event in decompress_batch(&data)?.into_iter().filter(|e| filter.matches(e)) {
+                    let value = U256::from_dec_str(&event.value)?;
+                    count += 1;
+                    total += value;
+                    max = max.max(Some(value));
+                }
+            }
+        }
+
+        Ok(TransferTotals {
+            count,
+            total_value: total.to_string(),
+            average_value: (count > 0).then(|| (total / U256::from(count as u64)).to_string()),
+            max_value: max.map(|max| max.to_string()),
+        })
+    }
+
+    /// The addresses that transferred with `filter`'s address, with transfer counts and volumes
+    /// in each direction, ranked by `rank` over both directions and capped at `limit`.
+    /// Transfer count, volume and distinct active addresses per `interval` bucket within the time
+    /// range, oldest first. `supply` and `velocity` are left for the caller.
+    pub async fn period_activity(
+        &self,
+        interval: FlowInterval,
+        from_time: Option<DateTime<Utc>>,
+        to_time: Option<DateTime<Utc>>,
+    ) -> Result<Vec<PeriodActivity>> {
+        let push_range = |builder: &mut QueryBuilder<Sqlite>| {
+            builder.push(" WHERE 1 = 1");
+            if let Some(from) = from_time {
+                builder.push(" AND timestamp >= ").push_bind(from);
+            }
+            if let Some(to) = to_time {
+                builder.push(" AND timestamp <= ").push_bind(to);
+            }
+        };
+        // Every (period, lowercased address) pair, once per side of a transfer.
+        let push_participants = |builder: &mut QueryBuilder<Sqlite>| {
+            for (i, column) in ["from_address", "to_address"].into_iter().enumerate() {
+                if i > 0 {
+                    builder.push(" UNION ALL ");
+                }
+                builder
+                    .push("SELECT ")
+                    .push(interval.sql_bucket())
+                    .push(" AS period, LOWER(")
+                    .push(column)
+                    .push(") AS address FROM transfer_events");
+                push_range(builder);
+            }
+        };
+
+        // Keyed by period: (transfers, volume, active addresses, pools the period appears in).
+        let mut periods: BTreeMap<String, (i64, U256, i64, usize)> = BTreeMap::new();
+        let pools = self.transfer_pools_for(None, None).await;
+        for pool in &pools {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT ");
+            builder.push(interval.sql_bucket()).push(
+                " AS period, COUNT(*) AS count, COALESCE(SUM(value_high), 0) AS high, \
+                 COALESCE(SUM(value_low), 0) AS low FROM transfer_events",
+            );
+            push_range(&mut builder);
+            builder.push(" GROUP BY period");
+            for row in builder.build().fetch_all(pool).await? {
+                let entry = periods.entry(row.get("period")).or_default();
+                entry.0 += row.get::<i64, _>("count");
+                entry.1 += U256::from(row.get::<i64, _>("high") as u64) * U256::exp10(9)
+                    + U256::from(row.get::<i64, _>("low") as u64);
+                entry.3 += 1;
+            }
+
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT period, COUNT(DISTINCT address) AS active FROM (");
+            push_participants(&mut builder);
+            builder.push(") WHERE address != ").push_bind(ZERO_ADDRESS).push(" GROUP BY period");
+            for row in builder.build().fetch_all(pool).await? {
+                periods.entry(row.get("period")).or_default().2 += row.get::<i64, _>("active");
+            }
+        }
+
+        // A period split across shards is counted again over the union of its addresses.
+        for (period, entry) in periods.iter_mut().filter(|(_, entry)| entry.3 > 1) {
+            let mut addresses = HashSet::new();
+            for pool in &pools {
+                let mut builder = QueryBuilder::<Sqlite>::new("SELECT DISTINCT address FROM (");
+                push_participants(&mut builder);
+                builder
+                    .push(") WHERE address != ")
+                    .push_bind(ZERO_ADDRESS)
+                    .push(" AND period = ")
+                    .push_bind(period.clone());
+                for row in builder.build().fetch_all(pool).await? {
+                    addresses.insert(row.get::<String, _>("address"));
+                }
+            }
+            entry.2 = addresses.len() as i64;
+        }
+
+        Ok(periods
+            .into_iter()
+            .map(|(period, (transfers, volume, active_addresses, _))| PeriodActivity {
+                period,
+                transfers,
+                volume: volume.to_string(),
+                active_addresses,
+                supply: None,
+                velocity: None,
+            })
+            .collect())
+    }
+
+    /// Inflow, outflow and net flow of the filter's address per `interval` bucket, oldest first.
+    /// Buckets without transfers are left out.
+    pub async fn net_flows(&self, filter: &TransferFilter, interval: FlowInterval) -> Result<Vec<NetFlow>> {
+        let Some(address) = filter.address.clone() else {
+            return Err(anyhow::anyhow!("Net flows need an address"));
+        };
+        self.check_lookup_filters(filter)?;
+
+        // Keyed by bucket: (inflow, outflow), each (count, volume). A transfer to itself counts
+        // on both sides.
+        type Flows = ((i64, U256), (i64, U256));
+        let mut flows: BTreeMap<String, Flows> = BTreeMap::new();
+        let mut add = |period: String, received: bool, sent: bool, count: i64, volume: U256| {
+            let entry = flows.entry(period).or_default();
+            if received {
+                entry.0 .0 += count;
+                entry.0 .1 += volume;
+            }
+            if sent {
+                entry.1 .0 += count;
+                entry.1 .1 += volume;
+            }
+        };
+
+        for pool in self.transfer_pools_for(filter.from_block, filter.to_block).await {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT ");
+            builder
+                .push(interval.sql_bucket())
+                .push(" AS period, to_address = ")
+                .push_bind(address.clone())
+                .push(" COLLATE NOCASE AS received, from_address = ")
+                .push_bind(address.clone())
+                .push(
+                    " COLLATE NOCASE AS sent, COUNT(*) AS count, \
+                     COALESCE(SUM(value_high), 0) AS high, COALESCE(SUM(value_low), 0) AS low FROM transfer_events",
+                );
+            filter.push_where(&mut builder);
+            builder.push(" GROUP BY period, received, sent");
+
+            for row in builder.build().fetch_all(&pool).await? {
+                let volume = U256::from(row.get::<i64, _>("high") as u64) * U256::exp10(9)
+                    + U256::from(row.get::<i64, _>("low") as u64);
+                add(row.get("period"), row.get("received"), row.get("sent"), row.get("count"), volume);
+            }
+        }
+
+        if filter.include_archived {
+            let rows = sqlx::query("SELECT data FROM archived_transfers WHERE end_block >= ? AND start_block <= ?")
+                .bind(filter.from_block.unwrap_or(0) as i64)
+                .bind(filter.to_block.map_or(i64::MAX, |to| to as i64))
+                .fetch_all(&self.pool)
+                .await?;
+            for row in rows {
+                let data: Vec<u8> = row.get("data");
+                for event in decompress_batch(&data)?.into_iter().filter(|e| filter.matches(e)) {
+                    add(
+                        interval.bucket(event.timestamp),
+                        event.to_address.eq_ignore_ascii_case(&address),
+                        event.from_address.eq_ignore_ascii_case(&address),
+                        1,
+                        U256::from_dec_str(&event.value)?,
+                    );
+                }
+            }
+        }
+
+        Ok(flows
+            .into_iter()
+            .map(|(period, (inflow, outflow))| NetFlow {
+                period,
+                inflow_transfers: inflow.0,
+                inflow: inflow.1.to_string(),
+                outflow_transfers: outflow.0,
+                outflow: outflow.1.to_string(),
+                net: net_flow(inflow.1, outflow.1),
+            })
+            .collect())
+    }
+
+    /// Raw volume of the transfers matching `filter` per `slot_secs` wide slot of Unix time, keyed
+    /// by slot start, oldest first. Slots without transfers are left out.
+    pub async fn volume_slots(&self, filter: &TransferFilter, slot_secs: i64) -> Result<Vec<(i64, U256)>> {
+        self.check_lookup_filters(filter)?;
+        let slot_secs = slot_secs.max(1);
+        let mut slots: BTreeMap<i64, U256> = BTreeMap::new();
+
+        for pool in self.transfer_pools_for(filter.from_block, filter.to_block).await {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT CAST(strftime('%s', timestamp) AS INTEGER) / ");
+            builder
+                .push_bind(slot_secs)
+                .push(" AS slot, COALESCE(SUM(value_high), 0) AS high, COALESCE(SUM(value_low), 0) AS low FROM transfer_events");
+            filter.push_where(&mut builder);
+            builder.push(" GROUP BY slot");
+            for row in builder.build().fetch_all(&pool).await? {
+                *slots.entry(row.get::<i64, _>("slot") * slot_secs).or_default() +=
+                    U256::from(row.get::<i64, _>("high") as u64) * U256::exp10(9) + U256::from(row.get::<i64, _>("low") as u64);
+            }
+        }
+
+        if filter.include_archived {
+            let rows = sqlx::query("SELECT data FROM archived_transfers WHERE end_block >= ? AND start_block <= ?")
+                .bind(filter.from_block.unwrap_or(0) as i64)
+                .bind(filter.to_block.map_or(i64::MAX, |to| to as i64))
+                .fetch_all(&self.pool)
+                .await?;
+            for row in rows {
+                let data: Vec<u8> = row.get("data");
+                for event in decompress_batch(&data)?.into_iter().filter(|e| filter.matches(e)) {
+                    let slot = event.timestamp.timestamp().div_euclid(slot_secs) * slot_secs;
+                    *slots.entry(slot).or_default() += U256::from_dec_str(&event.value)?;
+                }
+            }
+        }
+
+        Ok(slots.into_iter().collect())
+    }
+
+    pub async fn counterparties(&self, filter: &TransferFilter, rank: CounterpartyRank, limit: usize) -> Result<Vec<Counterparty>> {
+        let Some(address) = filter.address.clone() else {
+            return Err(anyhow::anyhow!("Counterparties need an address"));
+        };
+        self.check_lookup_filters(filter)?;
+
+        // Keyed by lowercased counterparty: (address as stored, sent, received), each (count, volume).
+        type Flows = ((i64, U256), (i64, U256));
+        let mut flows: HashMap<String, (String, Flows)> = HashMap::new();
+        let mut add = |counterparty: String, sent: bool, count: i64, volume: U256| {
+            // Rows written before addresses were checksummed are lowercase.
+            let counterparty = normalize_address(&counterparty).unwrap_or(counterparty);
+            let entry = flows
+                .entry(counterparty.to_lowercase())
+                .or_insert_with(|| (counterparty, Default::default()));
+            let side = if sent { &mut entry.1 .0 } else { &mut entry.1 .1 };
+            side.0 += count;
+            side.1 += volume;
+        };
+
+        for pool in self.transfer_pools_for(filter.from_block, filter.to_block).await {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT CASE WHEN from_address = ");
+            builder
+                .push_bind(address.clone())
+                .push(" COLLATE NOCASE THEN to_address ELSE from_address END AS counterparty, from_address = ")
+                .push_bind(address.clone())
+                .push(
+                    " COLLATE NOCASE AS sent, COUNT(*) AS count, \
+                     COALESCE(SUM(value_high), 0) AS high, COALESCE(SUM(value_low), 0) AS low FROM transfer_events",
+                );
+            filter.push_where(&mut builder);
+            builder.push(" GROUP BY counterparty, sent");
+
+            for row in builder.build().fetch_all(&pool).await? {
+                let volume = U256::from(row.get::<i64, _>("high") as u64) * U256::exp10(9)
+                    + U256::from(row.get::<i64, _>("low") as u64);
+                add(row.get("counterparty"), row.get("sent"), row.get("count"), volume);
+            }
+        }
+
+        if filter.include_archived {
+            let rows = sqlx::query("SELECT data FROM archived_transfers WHERE end_block >= ? AND start_block <= ?")
+                .bind(filter.from_block.unwrap_or(0) as i64)
+                .bind(filter.to_block.map_or(i64::MAX, |to| to as i64))
+                .fetch_all(&self.pool)
+                .await?;
+            for row in rows {
+                let data: Vec<u8> = row.get("data");
+                for event in decompress_batch(&data)?.into_iter().filter(|e| filter.matches(e)) {
+                    let sent = event.from_address.eq_ignore_ascii_case(&address);
+                    let counterparty = if sent { event.to_address } else { event.from_address };
+                    add(counterparty, sent, 1, U256::from_dec_str(&event.value)?);
+                }
+            }
+        }
+
+        let mut flows: Vec<(String, Flows)> = flows.into_values().collect();
+        flows.sort_by(|(a, (a_sent, a_received)), (b, (b_sent, b_received))| {
+            let order = match rank {
+                CounterpartyRank::Count => (b_sent.0 + b_received.0).cmp(&(a_sent.0 + a_received.0)),
+                CounterpartyRank::Volume => (b_sent.1 + b_received.1).cmp(&(a_sent.1 + a_received.1)),
+            };
+            order.then_with(|| a.cmp(b))
+        });
+        flows.truncate(limit);
+
+        let addresses: Vec<String> = flows.iter().map(|(address, _)| address.clone()).collect();
+        let labels = self.get_labels(&addresses).await?;
+        Ok(flows
+            .into_iter()
+            .map(|(address, (sent, received))| Counterparty {
+                label: labels.get(&address).cloned(),
+                address,
+                sent_transfers: sent.0,
+                sent_volume: sent.1.to_string(),
+                received_transfers: received.0,
+                received_volume: received.1.to_string(),
+            })
+            .collect())
+    }
+
+    /// Transfers matching `filter` that were sent by any of `senders`, in no particular order.
+    pub async fn transfers_sent_by(&self, senders: &[String], filter: &TransferFilter) -> Result<Vec<TransferEvent>> {
+        self.check_lookup_filters(filter)?;
+        let mut transfers = Vec::new();
+        for pool in self.transfer_pools_for(filter.from_block, filter.to_block).await {
+            // Half the parameters are left for the filter's own conditions.
+            for chunk in senders.chunks(MAX_BOUND_PARAMS / 2) {
+                let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM (SELECT * FROM transfer_events");
+                filter.push_where(&mut builder);
+                builder.push(") WHERE from_address COLLATE NOCASE IN (");
+                let mut separated = builder.separated(", ");
+                for sender in chunk {
+                    separated.push_bind(sender);
+                }
+                builder.push(")");
+
+                for row in builder.build().fetch_all(&pool).await? {
+                    transfers.push(row_to_transfer(&row)?);
+                }
+            }
+        }
+        Ok(transfers)
+    }
+
+    /// Sums the stored dollar values of the transfers matching `filter`.
+    pub async fn aggregate_usd_values(&self, filter: &TransferFilter) -> Result<UsdTotals> {
+        let mut ids = Vec::new();
+        self.for_each_transfer(filter, |transfer| {
+            ids.push(transfer.id);
+            Ok(())
+        })
+        .await?;
+
+        let mut priced = 0i64;
+        let mut total = U256::zero();
+        let mut max: Option<U256> = None;
+        for chunk in ids.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT usd_value FROM transfer_usd_values WHERE transfer_id IN (");
+            let mut separated = builder.separated(", ");
+            for id in chunk {
+                separated.push_bind(id);
+            }
+            builder.push(")");
+
+            for row in builder.build().fetch_all(&self.pool).await? {
+                let cents = parse_cents(&row.get::<String, _>("usd_value"))?;
+                priced += 1;
+                total += cents;
+                max = max.max(Some(cents));
+            }
+        }
+
+        Ok(UsdTotals {
+            priced,
+            unpriced: ids.len() as i64 - priced,
+            total_usd: format_cents(total),
+            average_usd: (priced > 0).then(|| format_cents(total / U256::from(priced as u64))),
+            max_usd: max.map(format_cents),
+        })
+    }
+
+    pub async fn sum_transfer_values(&self, filter: &TransferFilter) -> Result<U256> {
+        Ok(U256::from_dec_str(&self.aggregate_transfers(filter).await?.total_value)?)
+    }
+
+    /// Mean raw value of matching transfers, rounded down; `None` when nothing matches.
+    pub async fn average_transfer_value(&self, filter: &TransferFilter) -> Result<Option<U256>> {
+        let totals = self.aggregate_transfers(filter).await?;
+        Ok(totals.average_value.map(|avg| U256::from_dec_str(&avg)).transpose()?)
+    }
+
+    pub async fn max_transfer_value(&self, filter: &TransferFilter) -> Result<Option<U256>> {
+        let totals = self.aggregate_transfers(filter).await?;
+        Ok(totals.max_value.map(|max| U256::from_dec_str(&max)).transpose()?)
+    }
+
+    /// Calls `f` for every transfer matching `filter`, oldest first, streaming rows instead of
+    /// collecting them. Returns the number of rows visited.
+    pub async fn for_each_transfer<F>(&self, filter: &TransferFilter, mut f: F) -> Result<u64>
+    where
+        F: FnMut(TransferEvent) -> Result<()>,
+    {
+        self.check_lookup_filters(filter)?;
+        let mut rows = 0u64;
+
+        // Archived batches only ever hold blocks older than every live row.
+        if filter.include_archived {
+            let batches = sqlx::query(
+                r#"
+                SELECT start_block, data FROM archived_transfers
+                WHERE end_block >= ? AND start_block <= ?
+                ORDER BY start_block ASC
+                "#,
+            )
+            .bind(filter.from_block.unwrap_or(0) as i64)
+            .bind(filter.to_block.map_or(i64::MAX, |to| to as i64))
+            .fetch_all(&self.pool)
+            .await?;
+
+            // Several blobs can cover the same range, so merge each range before ordering it.
+            let mut i = 0;
+            while i < batches.len() {
+                let start_block: i64 = batches[i].get("start_block");
+                let mut bucket = Vec::new();
+                while i < batches.len() && batches[i].get::<i64, _>("start_block") == start_block {
+                    let data: Vec<u8> = batches[i].get("data");
+                    bucket.extend(decompress_batch(&data)?.into_iter().filter(|e| filter.matches(e)));
+                    i += 1;
+                }
+                bucket.sort_by_key(|e| (e.block_number, e.log_index));
+                for event in bucket {
+                    f(event)?;
+                    rows += 1;
+                }
+            }
+        }
+
+        let filter = &self.narrow_by_bloom(filter).await?;
+        let mut pools = self.transfer_pools_for(filter.from_block, filter.to_block).await;
+        pools.reverse();
+        for pool in pools {
+            let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM transfer_events");
+            filter.push_where(&mut builder);
+            builder.push(" ORDER BY block_number ASC, log_index ASC");
+
+            let mut stream = builder.build().fetch(&pool);
+            while let Some(row) = stream.try_next().await? {
+                f(row_to_transfer(&row)?)?;
+                rows += 1;
+            }
+        }
+
+        Ok(rows)
+    }
+
+    /// Decompresses archive batches overlapping the filter's block range, newest first,
+    /// until `limit` matching rows are found.
+    async fn query_archived_transfers(&self, filter: &TransferFilter, limit: usize) -> Result<Vec<TransferEvent>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT start_block, data FROM archived_transfers
+            WHERE end_block >= ? AND start_block <= ?
+            ORDER BY start_block DESC
+            "#,
+        )
+        .bind(filter.from_block.unwrap_or(0) as i64)
+        .bind(filter.to_block.map_or(i64::MAX, |to| to as i64))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut transfers = Vec::new();
+        let mut bucket: Vec<TransferEvent> = Vec::new();
+        let mut bucket_start = None;
+
+        // Several blobs can cover the same range, so merge each range before ordering it.
+        let flush = |bucket: &mut Vec<TransferEvent>, transfers: &mut Vec<TransferEvent>| {
+            bucket.sort_by(|a, b| b.block_number.cmp(&a.block_number).then(a.log_index.cmp(&b.log_index)));
+            transfers.append(bucket);
+        };
+
+        for row in rows {
+            let start_block: i64 = row.get("start_block");
+            if bucket_start != Some(start_block) {
+                flush(&mut bucket, &mut transfers);
+                if transfers.len() >= limit {
+                    break;
+                }
+                bucket_start = Some(start_block);
+            }
+
+            let data: Vec<u8> = row.get("data");
+            bucket.extend(decompress_batch(&data)?.into_iter().filter(|e| filter.matches(e)));
+        }
+        flush(&mut bucket, &mut transfers);
+
+        transfers.truncate(limit);
+        Ok(transfers)
+    }
+
+    /// Moves `transfer_events` rows below `before_block` into zstd-compressed batches of
+    /// `batch_blocks` blocks in `archived_transfers`.
+    pub async fn archive_transfers(&self, before_block: u64, batch_blocks: u64) -> Result<ArchiveSummary> {
+        let mut summary = ArchiveSummary::default();
+        if before_block == 0 {
+            return Ok(summary);
+        }
+        let last_block = before_block as i64 - 1;
+
+        for pool in self.transfer_pools_for(None, Some(before_block - 1)).await {
+            let buckets: Vec<i64> = sqlx::query("SELECT DISTINCT block_number / ? AS bucket FROM transfer_events WHERE block_number <= ?")
+                .bind(batch_blocks as i64)
+                .bind(last_block)
+                .fetch_all(&pool)
+                .await?
+                .iter()
+                .map(|row| row.get("bucket"))
+                .collect();
+
+            for bucket in buckets {
+                let start_block = bucket * batch_blocks as i64;
+                let end_block = std::cmp::min(start_block + batch_blocks as i64 - 1, last_block);
+
+                let rows = sqlx::query("SELECT * FROM transfer_events WHERE block_number BETWEEN ? AND ? ORDER BY block_number, log_index")
+                    .bind(start_block)
+                    .bind(end_block)
+                    .fetch_all(&pool)
+                    .await?;
+                let events = rows.iter().map(row_to_transfer).collect::<Result<Vec<_>>>()?;
+                if events.is_empty() {
+                    continue;
+                }
+
+                let (data, raw_len) = compress_batch(&events)?;
+                sqlx::query(
+                    r#"
+                    INSERT INTO archived_transfers (start_block, end_block, row_count, min_timestamp, max_timestamp, data, archived_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(start_block)
+                .bind(end_block)
+                .bind(events.len() as i64)
+                .bind(events.iter().map(|e| e.timestamp).min())
+                .bind(events.iter().map(|e| e.timestamp).max())
+                .bind(&data)
+                .bind(self.now())
+                .execute(&self.pool)
+                .await?;
+
+                sqlx::query("DELETE FROM transfer_events WHERE block_number BETWEEN ? AND ?")
+                    .bind(start_block)
+                    .bind(end_block)
+                    .execute(&pool)
+                    .await?;
+
+                summary.batches += 1;
+                summary.rows += events.len() as u64;
+                summary.raw_bytes += raw_len as u64;
+                summary.compressed_bytes += data.len() as u64;
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Deletes transfers, archive batches and processed blocks below `before_block`, then compacts
+    /// every file. The latest processed block is always kept so indexing resumes where it left off.
+    /// `address_stats` keeps its lifetime totals.
+    pub async fn prune(&self, before_block: u64) -> Result<PruneSummary> {
+        let mut summary = PruneSummary {
+            before_block,
+            ..Default::default()
+        };
+        if before_block == 0 {
+            return Ok(summary);
+        }
+        let before = before_block as i64;
+
+        for pool in self.transfer_pools_for(None, Some(before_block - 1)).await {
+            summary.transfers += sqlx::query("DELETE FROM transfer_events WHERE block_number < ?")
+                .bind(before)
+                .execute(&pool)
+                .await?
+                .rows_affected();
+        }
+
+        summary.archive_batches = sqlx::query("DELETE FROM archived_transfers WHERE end_block < ?")
+            .bind(before)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        summary.processed_blocks = sqlx::query(
+            "DELETE FROM processed_blocks WHERE block_number < ? AND block_number < (SELECT MAX(block_number) FROM processed_blocks)",
+        )
+        .bind(before)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        summary.raw_logs = sqlx::query("DELETE FROM raw_logs WHERE block_number < ?")
+            .bind(before)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        summary.erc1155_transfers = sqlx::query("DELETE FROM erc1155_transfers WHERE block_number < ?")
+            .bind(before)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        summary.transactions = sqlx::query("DELETE FROM transactions WHERE block_number < ?")
+            .bind(before)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        summary.usd_values = sqlx::query("DELETE FROM transfer_usd_values WHERE block_number < ?")
+            .bind(before)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        self.compact().await?;
+        Ok(summary)
+    }
+
+    /// Stored raw logs in `from_block..=to_block`, in log order.
+    pub async fn get_raw_logs(&self, from_block: u64, to_block: u64) -> Result<Vec<RawLog>> {
+        let rows = sqlx::query(
+            "SELECT * FROM raw_logs WHERE block_number >= ? AND block_number <= ? ORDER BY block_number ASC, log_index ASC",
+        )
+        .bind(from_block as i64)
+        .bind(to_block as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| RawLog {
+                block_number: row.get::<i64, _>("block_number") as u64,
+                block_hash: row.get("block_hash"),
+                block_timestamp: row.get("block_timestamp"),
+                transaction_hash: row.get("transaction_hash"),
+                transaction_index: row.get::<Option<i64>, _>("transaction_index").map(|i| i as u64),
+                log_index: row.get::<i64, _>("log_index") as u64,
+                address: row.get("address"),
+                topics: row.get::<String, _>("topics").split(',').map(str::to_string).collect(),
+                data: row.get("data"),
+            })
+            .collect())
+    }
+
+    /// Processed blocks in `from_block..=to_block`, each with whether its raw logs were kept.
+    pub async fn get_raw_log_coverage(&self, from_block: u64, to_block: u64) -> Result<Vec<(u64, bool)>> {
+        let rows = sqlx::query(
+            "SELECT block_number, raw_logs FROM processed_blocks WHERE block_number >= ? AND block_number <= ? ORDER BY block_number ASC",
+        )
+        .bind(from_block as i64)
+        .bind(to_block as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<i64, _>("block_number") as u64, row.get("raw_logs")))
+            .collect())
+    }
+
+    /// Replaces every stored transfer in `blocks` (ascending) with `events`, which must lie in
+    /// those blocks. The new rows count as indexed from RPC.
+    #[instrument(skip_all, fields(blocks = blocks.len(), events = events.len()))]
+    pub async fn replace_block_transfers(&self, blocks: &[u64], events: &[TransferEvent]) -> Result<()> {
+        let (Some(&first), Some(&last)) = (blocks.first(), blocks.last()) else {
+            return Ok(());
+        };
+        let events: Vec<&TransferEvent> = events.iter().collect();
+
+        match &self.shards {
+            Some(shards) => {
+                for pool in self.transfer_pools_for(Some(first), Some(last)).await {
+                    let mut tx = pool.begin().await?;
+                    delete_block_rows(&mut tx, blocks).await?;
+                    tx.commit().await?;
+                }
+
+                let mut by_shard: HashMap<u64, Vec<&TransferEvent>> = HashMap::new();
+                for event in &events {
+                    by_shard.entry(shards.shard_index(event.block_number as u64)).or_default().push(event);
+                }
+                for shard_events in by_shard.values() {
+                    let pool = shards.pool_for_block(shard_events[0].block_number as u64).await?;
+                    let mut tx = pool.begin().await?;
+                    insert_transfer_rows(&mut tx, shard_events).await?;
+                    tx.commit().await?;
+                }
+            }
+            None => {
+                let mut tx = self.pool.begin().await?;
+                delete_block_rows(&mut tx, blocks).await?;
+                insert_transfer_rows(&mut tx, &events).await?;
+                tx.commit().await?;
+            }
+        }
+
+        for chunk in events.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("DELETE FROM event_sources WHERE event_id IN (");
+            let mut separated = builder.separated(", ");
+            for event in chunk {
+                separated.push_bind(&event.id);
+            }
+            builder.push(")");
+            builder.build().execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Replaces every stored ERC-1155 transfer in `blocks` with `transfers`, which must lie in
+    /// those blocks.
+    #[instrument(skip_all, fields(blocks = blocks.len(), transfers = transfers.len()))]
+    pub async fn replace_block_erc1155_transfers(&self, blocks: &[u64], transfers: &[Erc1155Transfer]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for chunk in blocks.chunks(MAX_BOUND_PARAMS) {
+            let mut builder = QueryBuilder::<Sqlite>::new("DELETE FROM erc1155_transfers WHERE block_number IN (");
+            let mut separated = builder.separated(", ");
+            for block in chunk {
+                separated.push_bind(*block as i64);
+            }
+            builder.push(")");
+            builder.build().execute(&mut *tx).await?;
+        }
+
+        insert_erc1155_rows(&mut tx, transfers).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Most recent ERC-1155 transfers, optionally limited to one contract, one sender or
+    /// recipient, and one token id.
+    pub async fn query_erc1155_transfers(
+        &self,
+        contract: Option<&str>,
+        address: Option<&str>,
+        token_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Erc1155Transfer>> {
+        let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM erc1155_transfers WHERE 1 = 1");
+        if let Some(contract) = contract {
+            builder.push(" AND contract_address = ").push_bind(contract).push(" COLLATE NOCASE");
+        }
+        if let Some(address) = address {
+            builder
+                .push(" AND (from_address = ")
+                .push_bind(address)
+                .push(" COLLATE NOCASE OR to_address = ")
+                .push_bind(address)
+                .push(" COLLATE NOCASE)");
+        }
+        if let Some(token_id) = token_id {
+            builder.push(" AND token_id = ").push_bind(token_id);
+        }
+        builder
+            .push(" ORDER BY block_number DESC, log_index DESC, batch_index DESC LIMIT ")
+            .push_bind(limit);
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        Ok(rows
+            .iter()
+            .map(|row| Erc1155Transfer {
+                id: row.get("id"),
+                contract_address: row.get("contract_address"),
+                transaction_hash: row.get("transaction_hash"),
+                log_index: row.get("log_index"),
+                batch_index: row.get("batch_index"),
+                block_number: row.get("block_number"),
+                block_hash: row.get("block_hash"),
+                operator: row.get("operator"),
+                from_address: row.get("from_address"),
+                to_address: row.get("to_address"),
+                token_id: row.get("token_id"),
+                value: row.get("value"),
+                timestamp: row.get("timestamp"),
+                created_at: row.get("created_at"),
+            })
+            .collect())
+    }
+
+    /// First processed block whose timestamp is at or after `since`.
+    pub async fn first_block_since(&self, since: DateTime<Utc>) -> Result<Option<u64>> {
+        let row = sqlx::query("SELECT MIN(block_number) AS block FROM processed_blocks WHERE timestamp >= ?")
+            .bind(since)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get::<Option<i64>, _>("block").map(|block| block as u64))
+    }
+
+    /// Bytes used by the main database and every shard file.
+    pub async fn size_bytes(&self) -> Result<u64> {
+        let mut bytes = database_size(&self.pool).await?;
+        if let Some(shards) = &self.shards {
+            for (_, pool) in shards.pools_desc().await {
+                bytes += database_size(&pool).await?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Rebuilds the main database and every shard file to release space freed by deletes, then
+    /// refreshes planner statistics. Each file is locked only while it is vacuumed; a concurrent
+    /// indexer waits up to the busy timeout and otherwise retries its batch.
+    pub async fn compact(&self) -> Result<CompactSummary> {
+        let mut pools = vec![self.pool.clone()];
+        if let Some(shards) = &self.shards {
+            pools.extend(shards.pools_desc().await.into_iter().map(|(_, pool)| pool));
+        }
+
+        let mut summary = CompactSummary::default();
+        for pool in pools {
+            summary.files += 1;
+            summary.bytes_before += database_size(&pool).await?;
+
+            sqlx::query("VACUUM").execute(&pool).await?;
+            sqlx::query("ANALYZE").execute(&pool).await?;
+            // In WAL mode the vacuumed pages land in the log; checkpoint so the main file shrinks.
+            sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&pool).await?;
+
+            summary.bytes_after += database_size(&pool).await?;
+        }
+        Ok(summary)
+    }
+
+    /// Writes a consistent snapshot of the database to `path` with `VACUUM INTO`, which reads in a
+    /// single transaction and so is safe while the indexer is writing. Shards are written next to
+    /// it as `<stem>.shardNNNNN.db`, so the backup opens like the original.
+    pub async fn backup(&self, path: &Path) -> Result<BackupSummary> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid backup path {}", path.display()))?;
+
+        let mut targets = vec![(self.pool.clone(), path.to_path_buf())];
+        if let Some(shards) = &self.shards {
+            for (index, pool) in shards.pools_desc().await {
+                targets.push((pool, dir.join(shard_file_name(stem, index))));
+            }
+        }
+
+        if let Some((_, existing)) = targets.iter().find(|(_, target)| target.exists()) {
+            return Err(anyhow::anyhow!("Backup target {} already exists", existing.display()));
+        }
+
+        let mut summary = BackupSummary::default();
+        for (pool, target) in targets {
+            let target = target.to_string_lossy().to_string();
+            sqlx::query("VACUUM INTO ?").bind(&target).execute(&pool).await?;
+            summary.bytes += std::fs::metadata(&target)?.len();
+            summary.files.push(target);
+        }
+        Ok(summary)
+    }
+
+    /// Highest block held in an archive batch.
+    pub async fn latest_archived_block(&self) -> Result<Option<u64>> {
+        let row = sqlx::query("SELECT MAX(end_block) AS end_block FROM archived_transfers")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get::<Option<i64>, _>("end_block").map(|block| block as u64))
+    }
+
+    async fn transfer_pool(&self, block_number: u64) -> Result<SqlitePool> {
+        match &self.shards {
+            Some(shards) => shards.pool_for_block(block_number).await,
+            None => Ok(self.pool.clone()),
+        }
+    }
+
+    /// Address kind and sanctions filters join lookup tables in SQL, so they cannot reach shard
+    /// files or archive batches.
+    fn check_lookup_filters(&self, filter: &TransferFilter) -> Result<()> {
+        if !filter.uses_lookup_tables() {
+            return Ok(());
+        }
+        if self.shards.is_some() {
+            return Err(anyhow::anyhow!("Address kind and sanctions filters are not supported on a sharded database"));
+        }
+        if filter.include_archived {
+            return Err(anyhow::anyhow!("Address kind and sanctions filters cannot be combined with archived transfers"));
+        }
+        Ok(())
+    }
+
+    /// Pools holding `transfer_events` rows in the given block range, newest range first.
+    async fn transfer_pools_for(&self, from_block: Option<u64>, to_block: Option<u64>) -> Vec<SqlitePool> {
+        let Some(shards) = &self.shards else {
+            return vec![self.pool.clone()];
+        };
+
+        shards
+            .pools_desc()
+            .await
+            .into_iter()
+            .filter(|(index, _)| {
+                let (start, end) = shards.block_range(*index);
+                from_block.is_none_or(|from| end >= from) && to_block.is_none_or(|to| start <= to)
+            })
+            .map(|(_, pool)| pool)
+            .collect()
+    }
+
+    pub async fn get_stats(&self) -> Result<DatabaseStats> {
+        if let Some(shards) = &self.shards {
+            return self.get_sharded_stats(shards).await;
+        }
+
+        let total_transfers_row = sqlx::query("SELECT COUNT(*) as count FROM transfer_events")
+            .fetch_one(&self.pool)
+            .await?;
+
+        let unique_addresses_row = sqlx::query(
+            r#"
+            SELECT COUNT(DISTINCT LOWER(address)) as count FROM (
+                SELECT from_address as address FROM transfer_events
+                UNION
+                SELECT to_address as address FROM transfer_events
+            )
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let block_stats_row = sqlx::query(
+            "SELECT MIN(block_number) as min_block, MAX(block_number) as max_block FROM transfer_events",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(DatabaseStats {
+            total_transfers: total_transfers_row.get("count"),
+            unique_addresses: unique_addresses_row.get("count"),
+            latest_block: block_stats_row.get("max_block"),
+            earliest_block: block_stats_row.get("min_block"),
+        })
+    }
+
+    /// Computes stats over every shard on one connection with the shard files attached,
+    /// so unique addresses are counted across shards rather than summed.
+    async fn get_sharded_stats(&self, shards: &ShardSet) -> Result<DatabaseStats> {
+        let indexes: Vec<u64> = shards.pools_desc().await.into_iter().map(|(index, _)| index).collect();
+        if indexes.is_empty() {
+            return Ok(DatabaseStats {
+                total_transfers: 0,
+                unique_addresses: 0,
+                latest_block: None,
+                earliest_block: None,
+            });
+        }
+
+        let mut conn = self.pool.acquire().await?;
+        for index in &indexes {
+            sqlx::query(&format!("ATTACH DATABASE ? AS shard{}", index))
+                .bind(shards.path(*index).display().to_string())
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        let union = indexes
+            .iter()
+            .map(|index| format!("SELECT from_address, to_address, block_number FROM shard{}.transfer_events", index))
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+
+        let result = sqlx::query(&format!(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM ({union})) AS total,
+                (SELECT COUNT(DISTINCT LOWER(address)) FROM (
+                    SELECT from_address AS address FROM ({union})
+                    UNION
+                    SELECT to_address AS address FROM ({union})
+                )) AS unique_addresses,
+                (SELECT MIN(block_number) FROM ({union})) AS min_block,
+                (SELECT MAX(block_number) FROM ({union})) AS max_block
+            "#
+        ))
+        .fetch_one(&mut *conn)
+        .await;
+
+        for index in &indexes {
+            sqlx::query(&format!("DETACH DATABASE shard{}", index))
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        let row = result?;
+        Ok(DatabaseStats {
+            total_transfers: row.get("total"),
+            unique_addresses: row.get("unique_addresses"),
+            latest_block: row.get("max_block"),
+            earliest_block: row.get("min_block"),
+        })
+    }
+
+    /// Deletes everything stored from `invalid_block` on so it can be indexed again.
+    pub async fn handle_reorg(&self, invalid_block: u64) -> Result<()> {
+        let invalid_block_i64 = invalid_block as i64;
+
+        self.rollback_address_stats(invalid_block_i64).await?;
+        
+        for pool in self.transfer_pools_for(Some(invalid_block), None).await {
+            sqlx::query("DELETE FROM transfer_events WHERE block_number >= ?")
+                .bind(invalid_block_i64)
+                .execute(&pool)
+                .await?;
+        }
+
+        sqlx::query("DELETE FROM processed_blocks WHERE block_number >= ?")
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM pending_transfers WHERE block_number >= ?")
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM raw_logs WHERE block_number >= ?")
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM erc1155_transfers WHERE block_number >= ?")
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM transactions WHERE block_number >= ?")
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM transfer_usd_values WHERE block_number >= ?")
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM block_prices WHERE block_number >= ?")
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM holder_snapshots WHERE block_number >= ?")
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("UPDATE token_checkpoints SET next_block = ? WHERE next_block > ?")
+            .bind(invalid_block_i64)
+            .bind(invalid_block_i64)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_address_stats(&self, address: &str) -> Result<Option<AddressStats>> {
+        let row = sqlx::query("SELECT * FROM address_stats WHERE address = ?")
+            .bind(address)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| AddressStats {
+            address: row.get("address"),
+            total_in: row.get("total_in"),
+            total_out: row.get("total_out"),
+            tx_count: row.get("tx_count"),
+            last_block: row.get("last_block"),
+        }))
+    }
+
+    /// Block and time of the first and of the last stored transfer sent or received by `address`.
+    pub async fn address_activity_range(&self, address: &str) -> Result<Option<ActivityRange>> {
+        let mut range: Option<ActivityRange> = None;
+        for pool in self.transfer_pools_for(None, None).await {
+            let row = sqlx::query(
+                "SELECT MIN(block_number) AS first_block, MIN(timestamp) AS first_seen, \
+                 MAX(block_number) AS last_block, MAX(timestamp) AS last_seen \
+                 FROM transfer_events WHERE from_address = ? COLLATE NOCASE OR to_address = ? COLLATE NOCASE",
+            )
+            .bind(address)
+            .bind(address)
+            .fetch_one(&pool)
+            .await?;
+            let (Some(first_block), Some(first_seen), Some(last_block), Some(last_seen)) = (
+                row.get::<Option<i64>, _>("first_block"),
+                row.get::<Option<DateTime<Utc>>, _>("first_seen"),
+                row.get::<Option<i64>, _>("last_block"),
+                row.get::<Option<DateTime<Utc>>, _>("last_seen"),
+            ) else {
+                continue;
+            };
+            range = Some(match range {
+                Some(range) => ActivityRange {
+                    first_block: range.first_block.min(first_block),
+                    first_seen: range.first_seen.min(first_seen),
+                    last_block: range.last_block.max(last_block),
+                    last_seen: range.last_seen.max(last_seen),
+                },
+                None => ActivityRange {
+                    first_block,
+                    first_seen,
+                    last_block,
+                    last_seen,
+                },
+            });
+        }
+        Ok(range)
+    }
+
+    /// Folds transfers committed since the last refresh, up to `through_block`, into `address_stats`.
+    #[instrument(skip(self))]
+    pub async fn refresh_address_stats(&self, through_block: u64) -> Result<()> {
+        let through = through_block as i64;
+        let mut from = match self.get_meta("address_stats_block").await? {
+            Some(watermark) => watermark.parse::<i64>()? + 1,
+            None => match self.earliest_transfer_block().await? {
+                Some(earliest) => earliest,
+                None => through + 1,
+            },
+        };
+
+        while from <= through {
+            let to = std::cmp::min(from + ROLLUP_CHUNK_BLOCKS - 1, through);
+            let deltas = aggregate(&self.transfers_in_block_range(from, to).await?)?;
+            self.apply_address_deltas(&deltas, false, to).await?;
+            from = to + 1;
+        }
+
+        if self.get_meta("address_stats_block").await?.is_none() {
+            self.set_meta("address_stats_block", &through.to_string()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Recomputes `address_stats` from scratch up to the latest processed block.
+    pub async fn rebuild_address_stats(&self) -> Result<()> {
+        sqlx::query("DELETE FROM address_stats").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM address_bloom").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM meta WHERE key = 'address_stats_block'")
+            .execute(&self.pool)
+            .await?;
+
+        if let Some(latest) = self.get_latest_processed_block().await? {
+            self.refresh_address_stats(latest).await?;
+        }
+        Ok(())
+    }
+
+    /// Holders and supply as of the `address_stats` watermark, or `None` before the first refresh.
+    pub async fn holder_snapshot(&self) -> Result<Option<HolderSnapshot>> {
+        let Some(watermark) = self.get_meta("address_stats_block").await? else {
+            return Ok(None);
+        };
+        let block_number: i64 = watermark.parse()?;
+        let Some(timestamp) =
+            sqlx::query_scalar("SELECT timestamp FROM processed_blocks WHERE block_number <= ? ORDER BY block_number DESC LIMIT 1")
+                .bind(block_number)
+                .fetch_optional(&self.pool)
+                .await?
+        else {
+            return Ok(None);
+        };
+
+        // Canonical decimal strings, so a zero balance is exactly equal totals.
+        let holders: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM address_stats WHERE total_in != total_out AND address != ?")
+                .bind(ZERO_ADDRESS)
+                .fetch_one(&self.pool)
+                .await?;
+        let supply = match self.get_address_stats(ZERO_ADDRESS).await? {
+            Some(zero) => U256::from_dec_str(&zero.total_out)?.saturating_sub(U256::from_dec_str(&zero.total_in)?),
+            None => U256::zero(),
+        };
+
+        Ok(Some(HolderSnapshot {
+            block_number,
+            timestamp,
+            holders,
+            supply: supply.to_string(),
+        }))
+    }
+
+    /// Every holder's balance as of the `address_stats` watermark, in no particular order.
+    /// Addresses that sent more than they received, which only happens when the token was not
+    /// indexed from its deployment, are left out along with the zero address.
+    pub async fn holder_balances(&self) -> Result<Vec<(String, U256)>> {
+        let mut rows = sqlx::query("SELECT address, total_in, total_out FROM address_stats WHERE total_in != total_out AND address != ?")
+            .bind(ZERO_ADDRESS)
+            .fetch(&self.pool);
+        let mut balances = Vec::new();
+        while let Some(row) = rows.try_next().await? {
+            let total_in = U256::from_dec_str(row.get("total_in"))?;
+            let total_out = U256::from_dec_str(row.get("total_out"))?;
+            if total_in > total_out {
+                balances.push((row.get("address"), total_in - total_out));
+            }
+        }
+        Ok(balances)
+    }
+
+    pub async fn latest_holder_snapshot(&self) -> Result<Option<HolderSnapshot>> {
+        let row = sqlx::query("SELECT * FROM holder_snapshots ORDER BY block_number DESC LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| row_to_holder_snapshot(&row)))
+    }
+
+    /// Recorded snapshots within the time range, oldest first.
+    pub async fn get_holder_snapshots(
+        &self,
+        from_time: Option<DateTime<Utc>>,
+        to_time: Option<DateTime<Utc>>,
+    ) -> Result<Vec<HolderSnapshot>> {
+        let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM holder_snapshots WHERE 1 = 1");
+        if let Some(from) = from_time {
+            builder.push(" AND timestamp >= ").push_bind(from);
+        }
+        if let Some(to) = to_time {
+            builder.push(" AND timestamp <= ").push_bind(to);
+        }
+        builder.push(" ORDER BY block_number ASC");
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        Ok(rows.iter().map(row_to_holder_snapshot).collect())
+    }
+
+    pub async fn insert_holder_snapshot(&self, snapshot: &HolderSnapshot) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO holder_snapshots (block_number, timestamp, holders, supply) VALUES (?, ?, ?, ?)")
+            .bind(snapshot.block_number)
+            .bind(snapshot.timestamp)
+            .bind(snapshot.holders)
+            .bind(&snapshot.supply)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces every recorded snapshot with `snapshots`.
+    pub async fn replace_holder_snapshots(&self, snapshots: &[HolderSnapshot]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM holder_snapshots").execute(&mut *tx).await?;
+        for chunk in snapshots.chunks(INSERT_BATCH_ROWS) {
+            let mut builder =
+                QueryBuilder::<Sqlite>::new("INSERT OR REPLACE INTO holder_snapshots (block_number, timestamp, holders, supply) ");
+            builder.push_values(chunk, |mut row, snapshot| {
+                row.push_bind(snapshot.block_number)
+                    .push_bind(snapshot.timestamp)
+                    .push_bind(snapshot.holders)
+                    .push_bind(&snapshot.supply);
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Subtracts rollup contributions of blocks at or above `fork_block` before they are deleted.
+    async fn rollback_address_stats(&self, fork_block: i64) -> Result<()> {
+        let watermark = match self.get_meta("address_stats_block").await? {
+            Some(watermark) => watermark.parse::<i64>()?,
+            None => return Ok(()),
+        };
+        if watermark < fork_block {
+            return Ok(());
+        }
+
+        let deltas = aggregate(&self.transfers_in_block_range(fork_block, watermark).await?)?;
+        self.apply_address_deltas(&deltas, true, fork_block - 1).await?;
+
+        for address in deltas.keys() {
+            let mut last_block: Option<i64> = None;
+            for pool in self.transfer_pools_for(None, Some(fork_block as u64)).await {
+                let row = sqlx::query(
+                    "SELECT MAX(block_number) AS last_block FROM transfer_events WHERE (from_address = ? COLLATE NOCASE OR to_address = ? COLLATE NOCASE) AND block_number < ?",
+                )
+                .bind(address)
+                .bind(address)
+                .bind(fork_block)
+                .fetch_one(&pool)
+                .await?;
+                last_block = last_block.max(row.get("last_block"));
+            }
+
+            sqlx::query("UPDATE address_stats SET last_block = ? WHERE address = ?")
+                .bind(last_block.unwrap_or(0))
+                .bind(address)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn apply_address_deltas(&self, deltas: &HashMap<String, AddressDelta>, subtract: bool, watermark: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for (address, delta) in deltas {
+            let row = sqlx::query("SELECT total_in, total_out, tx_count, last_block FROM address_stats WHERE address = ?")
+                .bind(address)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            let (mut total_in, mut total_out, mut tx_count, mut last_block) = match row {
+                Some(row) => (
+                    U256::from_dec_str(&row.get::<String, _>("total_in"))?,
+                    U256::from_dec_str(&row.get::<String, _>("total_out"))?,
+                    row.get::<i64, _>("tx_count"),
+                    row.get::<i64, _>("last_block"),
+                ),
+                None => (U256::zero(), U256::zero(), 0, 0),
+            };
+
+            if subtract {
+                total_in = total_in.saturating_sub(delta.total_in);
+                total_out = total_out.saturating_sub(delta.total_out);
+                tx_count -= delta.tx_count;
+            } else {
+                total_in += delta.total_in;
+                total_out += delta.total_out;
+                tx_count += delta.tx_count;
+                last_block = last_block.max(delta.last_block);
+            }
+
+            if tx_count <= 0 {
+                sqlx::query("DELETE FROM address_stats WHERE address = ?")
+                    .bind(address)
+                    .execute(&mut *tx)
+                    .await?;
+            } else {
+                sqlx::query(
+                    "INSERT OR REPLACE INTO address_stats (address, total_in, total_out, tx_count, last_block) VALUES (?, ?, ?, ?, ?)",
+                )
+                .bind(address)
+                .bind(total_in.to_string())
+                .bind(total_out.to_string())
+                .bind(tx_count)
+                .bind(last_block)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        // Removing addresses from a bloom filter is impossible; rolled-back ones just stay as false positives.
+        if !subtract && !deltas.is_empty() {
+            let mut bloom = match AddressBloom::load(&mut *tx).await? {
+                Some(bloom) => bloom,
+                None => AddressBloom::with_capacity(DEFAULT_CAPACITY.max(deltas.len() as u64 * 2)),
+            };
+
+            for address in deltas.keys() {
+                bloom.insert(address);
+            }
+
+            if bloom.is_full() {
+                bloom = AddressBloom::with_capacity(bloom.capacity * 2);
+                let addresses = sqlx::query("SELECT address FROM address_stats")
+                    .fetch_all(&mut *tx)
+                    .await?;
+                for row in addresses {
+                    bloom.insert(row.get("address"));
+                }
+            }
+
+            bloom.save(&mut *tx).await?;
+        }
+
+        sqlx::query("INSERT OR REPLACE INTO meta (key, value) VALUES ('address_stats_block', ?)")
+            .bind(watermark.to_string())
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// When the address bloom filter rules the address out, narrows the filter to blocks above
+    /// the rollup watermark, the only place the address could still appear.
+    async fn narrow_by_bloom(&self, filter: &TransferFilter) -> Result<TransferFilter> {
+        let mut narrowed = filter.clone();
+        let Some(address) = &filter.address else {
+            return Ok(narrowed);
+        };
+        if filter.include_archived {
+            return Ok(narrowed);
+        }
+
+        let bloom = AddressBloom::load(&self.pool).await?;
+        let (Some(bloom), Some(watermark)) = (bloom, self.get_meta("address_stats_block").await?) else {
+            return Ok(narrowed);
+        };
+
+        if !bloom.contains(address) {
+            let above = watermark.parse::<u64>()? + 1;
+            narrowed.from_block = Some(filter.from_block.map_or(above, |from| from.max(above)));
+        }
+        Ok(narrowed)
+    }
+
+    async fn earliest_transfer_block(&self) -> Result<Option<i64>> {
+        let mut earliest: Option<i64> = None;
+        for pool in self.transfer_pools_for(None, None).await {
+            let row = sqlx::query("SELECT MIN(block_number) AS min_block FROM transfer_events")
+                .fetch_one(&pool)
+                .await?;
+            if let Some(block) = row.get::<Option<i64>, _>("min_block") {
+                earliest = Some(earliest.map_or(block, |e| e.min(block)));
+            }
+        }
+        Ok(earliest)
+    }
+
+    async fn transfers_in_block_range(&self, from_block: i64, to_block: i64) -> Result<Vec<TransferEvent>> {
+        let mut transfers = Vec::new();
+        for pool in self.transfer_pools_for(Some(from_block as u64), Some(to_block as u64)).await {
+            let rows = sqlx::query("SELECT * FROM transfer_events WHERE block_number BETWEEN ? AND ?")
+                .bind(from_block)
+                .bind(to_block)
+                .fetch_all(&pool)
+                .await?;
+            for row in rows {
+                transfers.push(row_to_transfer(&row)?);
+            }
+        }
+        Ok(transfers)
+    }
+
+    pub async fn get_block_hash(&self, block_number: u64) -> Result<Option<String>> {
+        let block_num = block_number as i64;
+        
+        let row = sqlx::query("SELECT block_hash FROM processed_blocks WHERE block_number = ?")
+            .bind(block_num)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("block_hash")))
+    }
+}
+
+/// Lists every embedded migration against the `_sqlx_migrations` table. Without that table nothing
+/// is applied yet; it is not created here, so read-only handles can ask too.
+pub async fn migration_status(pool: &SqlitePool) -> Result<Vec<MigrationStatus>> {
+    let mut conn = pool.acquire().await?;
+    let tracked = sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'")
+        .fetch_optional(&mut *conn)
+        .await?
+        .is_some();
+    let applied: HashSet<i64> = if tracked {
+        conn.list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    Ok(MIGRATOR
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains(&migration.version),
+        })
+        .collect())
+}
+
+async fn database_size(pool: &SqlitePool) -> Result<u64> {
+    let row = sqlx::query("SELECT page_count * page_size AS size FROM pragma_page_count(), pragma_page_size()")
+        .fetch_one(pool)
+        .await?;
+    Ok(row.get::<i64, _>("size") as u64)
+}
+
+pub(crate) async fn create_transfer_events_table(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS transfer_events (
+            id TEXT PRIMARY KEY,
+            transaction_hash TEXT NOT NULL,
+            log_index INTEGER NOT NULL,
+            block_number INTEGER NOT NULL,
+            block_hash TEXT NOT NULL,
+            from_address TEXT NOT NULL,
+            to_address TEXT NOT NULL,
+            value TEXT NOT NULL,
+            timestamp TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            UNIQUE(transaction_hash, log_index)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // One statement per query: a prepared statement only ever runs the first.
+    for statement in [
+        "CREATE INDEX IF NOT EXISTS idx_block_number ON transfer_events(block_number)",
+        "DROP INDEX IF EXISTS idx_from_address",
+        "DROP INDEX IF EXISTS idx_to_address",
+        "CREATE INDEX IF NOT EXISTS idx_from_address_nocase ON transfer_events(from_address COLLATE NOCASE)",
+        "CREATE INDEX IF NOT EXISTS idx_to_address_nocase ON transfer_events(to_address COLLATE NOCASE)",
+        "CREATE INDEX IF NOT EXISTS idx_timestamp ON transfer_events(timestamp)",
+    ] {
+        sqlx::query(statement).execute(pool).await?;
+    }
+
+    // Mirrors migration 0003 for shard files, which are not migrated.
+    let has_value_columns: i64 =
+        sqlx::query("SELECT COUNT(*) AS count FROM pragma_table_xinfo('transfer_events') WHERE name = 'value_high'")
+            .fetch_one(pool)
+            .await?
+            .get("count");
+    if has_value_columns == 0 {
+        for statement in [
+            "ALTER TABLE transfer_events ADD COLUMN value_high INTEGER GENERATED ALWAYS AS (CAST(SUBSTR(value, 1, LENGTH(value) - 9) AS INTEGER)) VIRTUAL",
+            "ALTER TABLE transfer_events ADD COLUMN value_low INTEGER GENERATED ALWAYS AS (CAST(SUBSTR(value, -9) AS INTEGER)) VIRTUAL",
+        ] {
+            sqlx::query(statement).execute(pool).await?;
+        }
+    }
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_value ON transfer_events(value_high, value_low)")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+async fn delete_block_rows(conn: &mut sqlx::SqliteConnection, blocks: &[u64]) -> Result<()> {
+    for chunk in blocks.chunks(MAX_BOUND_PARAMS) {
+        let mut builder = QueryBuilder::<Sqlite>::new("DELETE FROM transfer_events WHERE block_number IN (");
+        let mut separated = builder.separated(", ");
+        for block in chunk {
+            separated.push_bind(*block as i64);
+        }
+        builder.push(")");
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+async fn insert_transfer_rows(conn: &mut sqlx::SqliteConnection, events: &[&TransferEvent]) -> Result<()> {
+    for chunk in events.chunks(INSERT_BATCH_ROWS) {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "INSERT OR IGNORE INTO transfer_events \
+             (id, transaction_hash, log_index, block_number, block_hash, from_address, to_address, value, timestamp, created_at) ",
+        );
+        builder.push_values(chunk, |mut row, event| {
+            row.push_bind(&event.id)
+                .push_bind(&event.transaction_hash)
+                .push_bind(event.log_index)
+                .push_bind(event.block_number)
+                .push_bind(&event.block_hash)
+                .push_bind(&event.from_address)
+                .push_bind(&event.to_address)
+                .push_bind(&event.value)
+                .push_bind(event.timestamp)
+                .push_bind(event.created_at);
+        });
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+async fn insert_erc1155_rows(conn: &mut sqlx::SqliteConnection, transfers: &[Erc1155Transfer]) -> Result<()> {
+    for chunk in transfers.chunks(ERC1155_INSERT_BATCH_ROWS) {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "INSERT OR IGNORE INTO erc1155_transfers \
+             (id, contract_address, transaction_hash, log_index, batch_index, block_number, block_hash, \
+             operator, from_address, to_address, token_id, value, timestamp, created_at) ",
+        );
+        builder.push_values(chunk, |mut row, transfer| {
+            row.push_bind(&transfer.id)
+                .push_bind(&transfer.contract_address)
+                .push_bind(&transfer.transaction_hash)
+                .push_bind(transfer.log_index)
+                .push_bind(transfer.batch_index)
+                .push_bind(transfer.block_number)
+                .push_bind(&transfer.block_hash)
+                .push_bind(&transfer.operator)
+                .push_bind(&transfer.from_address)
+                .push_bind(&transfer.to_address)
+                .push_bind(&transfer.token_id)
+                .push_bind(&transfer.value)
+                .push_bind(transfer.timestamp)
+                .push_bind(transfer.created_at);
+        });
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+async fn insert_raw_log_rows(conn: &mut sqlx::SqliteConnection, logs: &[RawLog]) -> Result<()> {
+    for chunk in logs.chunks(INSERT_BATCH_ROWS) {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "INSERT OR REPLACE INTO raw_logs \
+             (block_number, log_index, block_hash, block_timestamp, transaction_hash, transaction_index, address, topics, data) ",
+        );
+        builder.push_values(chunk, |mut row, log| {
+            row.push_bind(log.block_number as i64)
+                .push_bind(log.log_index as i64)
+                .push_bind(&log.block_hash)
+                .push_bind(log.block_timestamp)
+                .push_bind(&log.transaction_hash)
+                .push_bind(log.transaction_index.map(|i| i as i64))
+                .push_bind(&log.address)
+                .push_bind(log.topics.join(","))
+                .push_bind(&log.data);
+        });
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Sent => "sent",
+        Direction::Received => "received",
+        Direction::Both => "both",
+    }
+}
+
+fn row_to_api_key(row: &SqliteRow) -> Result<ApiKey> {
+    let scope: String = row.get("scope");
+    Ok(ApiKey {
+        name: row.get("name"),
+        scope: <Scope as ValueEnum>::from_str(&scope, true).map_err(|e| anyhow::anyhow!(e))?,
+        created_at: row.get("created_at"),
+        last_used_at: row.get("last_used_at"),
+    })
+}
+
+fn row_to_alert_rule(row: &SqliteRow) -> Result<AlertRule> {
+    let direction: String = row.get("direction");
+    Ok(AlertRule {
+        id: row.get("id"),
+        name: row.get("name"),
+        address: row.get("address"),
+        direction: <Direction as ValueEnum>::from_str(&direction, true).map_err(|e| anyhow::anyhow!(e))?,
+        min_value: row.get("min_value"),
+        max_value: row.get("max_value"),
+        counterparty_label: row.get("counterparty_label"),
+        min_count: row.get("min_count"),
+        window_secs: row.get("window_secs"),
+        channels: row
+            .get::<Option<String>, _>("channels")
+            .map(|channels| channels.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+        created_at: row.get("created_at"),
+    })
+}
+
+fn row_to_holder_snapshot(row: &SqliteRow) -> HolderSnapshot {
+    HolderSnapshot {
+        block_number: row.get("block_number"),
+        timestamp: row.get("timestamp"),
+        holders: row.get("holders"),
+        supply: row.get("supply"),
+    }
+}
+
+fn row_to_transfer(row: &SqliteRow) -> Result<TransferEvent> {
+    Ok(TransferEvent {
+        id: row.get("id"),
+        transaction_hash: row.get("transaction_hash"),
+        log_index: row.get("log_index"),
+        block_number: row.get("block_number"),
+        block_hash: row.get("block_hash"),
+        // Rows written before addresses were checksummed are lowercase.
+        from_address: normalize_address(&row.get::<String, _>("from_address"))?,
+        to_address: normalize_address(&row.get::<String, _>("to_address"))?,
+        value: row.get("value"),
+        timestamp: row.get::<String, _>("timestamp").parse()?,
+        created_at: row.get::<String, _>("created_at").parse()?,
+        from_label: None,
+        to_label: None,
+        from_ens: None,
+        to_ens: None,
+        tx_sender: None,
+        tx_to: None,
+        tx_fee: None,
+        usd_value: None,
+        sanctioned: false,
+    })
+}
+
+/// Fixtures shared by the tests of every module.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// A fresh in-memory database with every migration applied.
+    pub(crate) async fn database() -> Database {
+        Database::new("sqlite::memory:", PoolSettings::default()).await.unwrap()
+    }
+
+    /// A `TransferEvent` that is the only one in its block, with hashes derived from the block
+    /// number, moving 1 USDC between two fixed addresses now unless told otherwise.
+    pub(crate) struct TransferBuilder(TransferEvent);
+
+    impl TransferBuilder {
+        pub(crate) fn new(block_number: i64) -> Self {
+            Self(TransferEvent {
+                id: format!("0x{:064x}_0", block_number),
+                transaction_hash: format!("0x{:064x}", block_number),
+                log_index: 0,
+                block_number,
+                block_hash: format!("0x{:064x}", block_number),
+                from_address: format!("0x{:040x}", 1),
+                to_address: format!("0x{:040x}", 2),
+                value: "1000000".to_string(),
+                timestamp: Utc::now(),
+                created_at: Utc::now(),
+                from_label: None,
+                to_label: None,
+                from_ens: None,
+                to_ens: None,
+                tx_sender: None,
+                tx_to: None,
+                tx_fee: None,
+                usd_value: None,
+                sanctioned: false,
+            })
+        }
+
+        pub(crate) fn log_index(mut self, log_index: i64) -> Self {
+            self.0.id = format!("{}_{}", self.0.transaction_hash, log_index);
+            self.0.log_index = log_index;
+            self
+        }
+
+        pub(crate) fn from(mut self, address: &str) -> Self {
+            self.0.from_address = address.to_string();
+            self
+        }
+
+        pub(crate) fn to(mut self, address: &str) -> Self {
+            self.0.to_address = address.to_string();
+            self
+        }
+
+        pub(crate) fn value(mut self, value: &str) -> Self {
+            self.0.value = value.to_string();
+            self
+        }
+
+        /// Sets the block timestamp; `created_at` stays the time of the call to `new`.
+        pub(crate) fn at(mut self, timestamp: DateTime<Utc>) -> Self {
+            self.0.timestamp = timestamp;
+            self
+        }
+
+        pub(crate) fn build(self) -> TransferEvent {
+            self.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{database, TransferBuilder};
+    use super::*;
+    use crate::query::AddressKind;
+
+    const CONTRACT: &str = "0x495f947276749Ce646f68AC8c248420045cb7b5e";
+    const OTHER: &str = "0x76BE3b62873462d2142405439777e971754E8E77";
+
+    #[tokio::test]
+    async fn in_memory_database_is_shared_across_the_pool() {
+        assert!(is_in_memory("sqlite::memory:"));
+        assert!(is_in_memory("sqlite://file:scratch?mode=memory&cache=shared"));
+        assert!(!is_in_memory("sqlite:./transfers.db"));
+
+        let database = Database::new("sqlite::memory:", PoolSettings::default()).await.unwrap();
+        database.set_meta("chain_id", "1").await.unwrap();
+        // Held connections force the reads below onto other ones.
+        let mut held = Vec::new();
+        for _ in 0..3 {
+            held.push(database.pool.acquire().await.unwrap());
+            assert_eq!(database.get_meta("chain_id").await.unwrap().as_deref(), Some("1"));
+        }
+
+        let sharded = Database::new("sqlite::memory:", PoolSettings::default())
+            .await
+            .unwrap()
+            .with_shards("sqlite::memory:", 1_000)
+            .await;
+        assert!(sharded.is_err());
+    }
+
+    #[tokio::test]
+    async fn read_only_open_never_writes() {
+        let path = std::env::temp_dir().join(format!("usdcwatch-read-only-{}.db", std::process::id()));
+        let url = format!("sqlite:{}", path.display());
+        let read_only = PoolSettings {
+            read_only: true,
+            ..PoolSettings::default()
+        };
+        assert!(Database::new(&url, read_only.clone()).await.is_err());
+        assert!(!path.exists());
+
+        // An empty file has every migration pending, without `_sqlx_migrations` being created.
+        PoolSettings::default().connect(&url).await.unwrap().close().await;
+        let error = Database::new(&url, read_only.clone()).await.err().unwrap();
+        assert!(error.to_string().contains("pending migrations"), "{}", error);
+
+        let writer = Database::new(&url, PoolSettings::default()).await.unwrap();
+        writer.set_meta("chain_id", "1").await.unwrap();
+
+        let reader = Database::new(&url, read_only).await.unwrap();
+        assert!(reader.is_read_only());
+        assert_eq!(reader.get_meta("chain_id").await.unwrap().as_deref(), Some("1"));
+        assert!(reader.set_meta("chain_id", "5").await.is_err());
+
+        drop((writer, reader));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[tokio::test]
+    async fn query_budget_interrupts_runaway_statements() {
+        const RUNAWAY: &str = "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n) \
+                               SELECT COUNT(*) FROM (SELECT x FROM n LIMIT 10000000000)";
+        let settings = PoolSettings {
+            interruptible: true,
+            ..PoolSettings::default()
+        };
+        let database = Database::new("sqlite::memory:", settings).await.unwrap();
+
+        let started = Instant::now();
+        let budget = QueryBudget::new(Some(Duration::from_millis(50)));
+        let result = budget.run(sqlx::query_scalar::<_, i64>(RUNAWAY).fetch_one(&database.pool)).await;
+        assert!(is_interrupted(&result.unwrap_err().into()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Dropping the work mid-statement frees the only connection for the next caller, whose
+        // queries are not bound by the old budget.
+        let pool = database.pool.clone();
+        let work = tokio::spawn(QueryBudget::new(None).run(async move { sqlx::query(RUNAWAY).fetch_one(&pool).await }));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        work.abort();
+        let started = Instant::now();
+        assert_eq!(database.get_stats().await.unwrap().total_transfers, 0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    fn erc1155_transfer(contract: &str, block_number: i64) -> Erc1155Transfer {
+        Erc1155Transfer {
+            id: format!("0x{:064x}_0_0", block_number),
+            contract_address: contract.to_string(),
+            transaction_hash: format!("0x{:064x}", block_number),
+            log_index: 0,
+            batch_index: 0,
+            block_number,
+            block_hash: format!("0x{:064x}", block_number),
+            operator: format!("0x{:040x}", 1),
+            from_address: format!("0x{:040x}", 1),
+            to_address: format!("0x{:040x}", 2),
+            token_id: "1".to_string(),
+            value: "1".to_string(),
+            timestamp: Utc::now(),
+            created_at: Utc::now(),
+        }
+    }
+
+    async fn next_block(database: &Database, token: &str) -> u64 {
+        database
+            .get_token_checkpoints(Some(1))
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|c| c.token_address == token)
+            .unwrap()
+            .next_block
+    }
+
+    #[tokio::test]
+    async fn registered_tokens_keep_their_progress() {
+        let database = database().await;
+        database.register_token(1, CONTRACT, "erc1155", 100, 100).await.unwrap();
+        database.advance_token_checkpoints(1, &[CONTRACT.to_lowercase()], 150).await.unwrap();
+
+        let checkpoint = database.register_token(1, CONTRACT, "erc1155", 200, 200).await.unwrap();
+        assert_eq!((checkpoint.start_block, checkpoint.next_block), (100, 150));
+
+        // Progress never moves backwards when an older range is re-indexed.
+        database.advance_token_checkpoints(1, &[CONTRACT.to_string()], 120).await.unwrap();
+        assert_eq!(next_block(&database, CONTRACT).await, 150);
+    }
+
+    #[tokio::test]
+    async fn backfill_only_touches_its_contract() {
+        let database = database().await;
+        database.register_token(1, CONTRACT, "erc1155", 100, 100).await.unwrap();
+        database.register_token(1, OTHER, "erc1155", 100, 201).await.unwrap();
+        database
+            .replace_block_erc1155_transfers(&[150], &[erc1155_transfer(OTHER, 150)])
+            .await
+            .unwrap();
+
+        database
+            .commit_token_backfill(1, CONTRACT, 100, 199, &[erc1155_transfer(CONTRACT, 160)], None)
+            .await
+            .unwrap();
+
+        assert_eq!(next_block(&database, CONTRACT).await, 200);
+        assert_eq!(next_block(&database, OTHER).await, 201);
+        assert_eq!(database.query_erc1155_transfers(Some(OTHER), None, None, 10).await.unwrap().len(), 1);
+        assert_eq!(database.query_erc1155_transfers(Some(CONTRACT), None, None, 10).await.unwrap().len(), 1);
+
+        database.handle_reorg(180).await.unwrap();
+        assert_eq!(next_block(&database, CONTRACT).await, 180);
+        assert_eq!(next_block(&database, OTHER).await, 180);
+    }
+
+    #[tokio::test]
+    async fn block_activity_joins_headers_with_transfers() {
+        let database = database().await;
+        let block = |block_number: u64| ProcessedBlock {
+            block_number,
+            block_hash: format!("0x{:064x}", block_number),
+            timestamp: Utc::now(),
+            base_fee_per_gas: Some("30000000000".to_string()),
+            gas_used: 15_000_000,
+            gas_limit: 30_000_000,
+            fee_recipient: OTHER.to_string(),
+        };
+        let events: Vec<TransferEvent> = [(100, 0, "1500000000000"), (100, 1, "2500000000000")]
+            .iter()
+            .map(|&(block_number, log_index, value)| {
+                let mut event = transfer(block_number);
+                event.id = format!("{}_{}", event.transaction_hash, log_index);
+                event.log_index = log_index;
+                event.value = value.to_string();
+                event
+            })
+            .collect();
+        database
+            .commit_block_range(&events, &[block(100), block(101)], None, EventSource::Rpc, 0)
+            .await
+            .unwrap();
+
+        let activity = database.query_block_activity(None, None, 10).await.unwrap();
+        let rows: Vec<_> = activity
+            .iter()
+            .map(|b| (b.block_number, b.transfers, b.volume.as_str(), b.gas_used))
+            .collect();
+        assert_eq!(rows, [(101, 0, "0", Some(15_000_000)), (100, 2, "4000000000000", Some(15_000_000))]);
+        assert_eq!(activity[0].base_fee_per_gas.as_deref(), Some("30000000000"));
+    }
+
+    fn transfer(block_number: i64) -> TransferEvent {
+        TransferBuilder::new(block_number).from(OTHER).to(CONTRACT).build()
+    }
+
+    #[tokio::test]
+    async fn queried_transfers_carry_their_transaction_details() {
+        let database = database().await;
+        let transfer = transfer(100);
+        database.insert_transfer_event(&transfer).await.unwrap();
+        database
+            .insert_transactions(&[TransactionDetails {
+                transaction_hash: transfer.transaction_hash.clone(),
+                block_number: 100,
+                sender: OTHER.to_string(),
+                recipient: Some(CONTRACT.to_string()),
+                gas_used: "50000".to_string(),
+                effective_gas_price: "20000000000".to_string(),
+                fee: "1000000000000000".to_string(),
+                fetched_at: Utc::now(),
+            }])
+            .await
+            .unwrap();
+
+        let queried = database.get_transfers_by_tx(&transfer.transaction_hash).await.unwrap();
+        assert_eq!(queried[0].tx_sender.as_deref(), Some(OTHER));
+        assert_eq!(queried[0].tx_to.as_deref(), Some(CONTRACT));
+        assert_eq!(queried[0].tx_fee.as_deref(), Some("1000000000000000"));
+
+        database.handle_reorg(100).await.unwrap();
+        assert!(database.get_transactions(&[transfer.transaction_hash]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn address_kinds_filter_on_classified_addresses() {
+        let database = database().await;
+        database.insert_transfer_event(&transfer(100)).await.unwrap();
+        // Older rows keep lowercase addresses.
+        let mut lowercase = transfer(101);
+        lowercase.from_address = CONTRACT.to_lowercase();
+        lowercase.to_address = OTHER.to_lowercase();
+        database.insert_transfer_event(&lowercase).await.unwrap();
+
+        let info = |address: &str, is_contract| AddressInfo {
+            address: address.to_string(),
+            is_contract,
+            checked_at: Utc::now(),
+        };
+        database
+            .insert_address_info(&[info(OTHER, false), info(CONTRACT, true)])
+            .await
+            .unwrap();
+
+        let blocks = |from_kind, to_kind| {
+            let database = database.clone();
+            async move {
+                let filter = TransferFilter::new().address_kinds(from_kind, to_kind);
+                let transfers = database.query_transfers(&filter, 10).await.unwrap();
+                transfers.iter().map(|t| t.block_number).collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(blocks(None, Some(AddressKind::Contract)).await, [100]);
+        assert_eq!(blocks(Some(AddressKind::Contract), Some(AddressKind::Eoa)).await, [101]);
+        assert!(blocks(Some(AddressKind::Eoa), Some(AddressKind::Eoa)).await.is_empty());
+
+        let archived = TransferFilter::new().address_kinds(Some(AddressKind::Eoa), None).include_archived(true);
+        assert!(database.query_transfers(&archived, 10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn daily_volume_counts_transfers_between_labeled_addresses_once() {
+        let database = database().await;
+        let day = |block_number: i64, time: &str, to: &str| {
+            let mut event = transfer(block_number);
+            event.timestamp = time.parse().unwrap();
+            event.to_address = to.to_string();
+            event
+        };
+        database.insert_transfer_event(&day(100, "2026-01-01T10:00:00Z", CONTRACT)).await.unwrap();
+        database.insert_transfer_event(&day(101, "2026-01-01T23:59:59Z", &OTHER.to_lowercase())).await.unwrap();
+        database.insert_transfer_event(&day(102, "2026-01-02T00:00:00Z", CONTRACT)).await.unwrap();
+        database.add_label(CONTRACT, "Exchange A", Some("Exchange")).await.unwrap();
+        database.add_label(OTHER, "Exchange B", Some("exchange")).await.unwrap();
+
+        let addresses = database.label_addresses("exchange").await.unwrap();
+        assert_eq!(addresses.len(), 2);
+
+        let volume = |direction| {
+            let database = database.clone();
+            let addresses = addresses.clone();
+            async move {
+                let days = database.daily_volume(&addresses, direction, None, None).await.unwrap();
+                days.into_iter().map(|d| (d.day, d.transfers)).collect::<Vec<_>>()
+            }
+        };
+        let expected = [("2026-01-01".to_string(), 2), ("2026-01-02".to_string(), 1)];
+        assert_eq!(volume(Direction::Received).await, expected);
+        assert_eq!(volume(Direction::Both).await, expected);
+    }
+
+    #[tokio::test]
+    async fn priced_transfers_carry_their_usd_value_until_reorged() {
+        let database = database().await;
+        let mut transfer = transfer(100);
+        database.insert_transfer_event(&transfer).await.unwrap();
+        transfer.usd_value = Some("1.00".to_string());
+        database.insert_usd_values(std::slice::from_ref(&transfer)).await.unwrap();
+        database
+            .insert_block_prices(&[BlockPrice {
+                feed: CONTRACT.to_string(),
+                block_number: 100,
+                answer: "100000000".to_string(),
+                decimals: 8,
+                updated_at: Utc::now(),
+            }])
+            .await
+            .unwrap();
+
+        let queried = database.get_transfers_by_tx(&transfer.transaction_hash).await.unwrap();
+        assert_eq!(queried[0].usd_value.as_deref(), Some("1.00"));
+        let prices = database.get_block_prices(&CONTRACT.to_lowercase(), &[100, 200]).await.unwrap();
+        assert_eq!(prices[&100].answer, "100000000");
+        assert_eq!(prices.len(), 1);
+
+        database.handle_reorg(100).await.unwrap();
+        database.insert_transfer_event(&transfer).await.unwrap();
+        let queried = database.get_transfers_by_tx(&transfer.transaction_hash).await.unwrap();
+        assert!(queried[0].usd_value.is_none());
+        assert!(database.get_block_prices(CONTRACT, &[100]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn usd_totals_cover_priced_transfers_only() {
+        let database = database().await;
+        let mut priced = Vec::new();
+        for (block_number, usd) in [(100, Some("1500.34")), (101, Some("0.66")), (102, None)] {
+            let mut transfer = transfer(block_number);
+            database.insert_transfer_event(&transfer).await.unwrap();
+            transfer.usd_value = usd.map(str::to_string);
+            priced.push(transfer);
+        }
+        database.insert_usd_values(&priced).await.unwrap();
+
+        let totals = database.aggregate_usd_values(&TransferFilter::new()).await.unwrap();
+        assert_eq!((totals.priced, totals.unpriced), (2, 1));
+        assert_eq!(totals.total_usd, "1501.00");
+        assert_eq!(totals.average_usd.as_deref(), Some("750.50"));
+        assert_eq!(totals.max_usd.as_deref(), Some("1500.34"));
+    }
+
+    #[tokio::test]
+    async fn sanctioned_transfers_are_marked_and_filtered() {
+        let database = database().await;
+        database.insert_transfer_event(&transfer(100)).await.unwrap();
+        let mut unrelated = transfer(101);
+        unrelated.to_address = format!("0x{:040x}", 3);
+        database.insert_transfer_event(&unrelated).await.unwrap();
+
+        database.replace_sanctioned_addresses("list.txt", &[OTHER.to_string()]).await.unwrap();
+        // A refresh replaces the whole list.
+        database.replace_sanctioned_addresses("list.txt", &[CONTRACT.to_string()]).await.unwrap();
+        assert_eq!(database.get_sanctions_status().await.unwrap().addresses, 1);
+
+        let all = database.query_transfers(&TransferFilter::new(), 10).await.unwrap();
+        let marked: Vec<_> = all.iter().map(|t| (t.block_number, t.sanctioned)).collect();
+        assert_eq!(marked, [(101, false), (100, true)]);
+
+        let filter = TransferFilter::new().sanctioned(true);
+        let screened = database.query_transfers(&filter, 10).await.unwrap();
+        assert_eq!(screened.iter().map(|t| t.block_number).collect::<Vec<_>>(), [100]);
+    }
+
+    #[tokio::test]
+    async fn counterparties_are_grouped_by_direction_and_ranked() {
+        let database = database().await;
+        let third = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        database.insert_transfer_event(&transfer(100)).await.unwrap();
+        database.insert_transfer_event(&transfer(101)).await.unwrap();
+        let mut back = transfer(102);
+        std::mem::swap(&mut back.from_address, &mut back.to_address);
+        database.insert_transfer_event(&back).await.unwrap();
+        let mut large = transfer(103);
+        // Stored before addresses were checksummed.
+        large.to_address = third.to_lowercase();
+        large.value = "5000000".to_string();
+        database.insert_transfer_event(&large).await.unwrap();
+        database.add_label(CONTRACT, "Marketplace", None).await.unwrap();
+
+        let filter = TransferFilter::new().address(Some(OTHER.to_string()));
+        let by_count = database.counterparties(&filter, CounterpartyRank::Count, 10).await.unwrap();
+        let rows: Vec<_> = by_count
+            .iter()
+            .map(|c| (c.address.as_str(), c.label.as_deref(), c.sent_transfers, c.received_transfers))
+            .collect();
+        assert_eq!(rows, [(CONTRACT, Some("Marketplace"), 2, 1), (third, None, 1, 0)]);
+        assert_eq!(by_count[0].sent_volume, "2000000");
+
+        let by_volume = database.counterparties(&filter, CounterpartyRank::Volume, 1).await.unwrap();
+        assert_eq!(by_volume.iter().map(|c| c.address.as_str()).collect::<Vec<_>>(), [third]);
+
+        let received = filter.direction(Direction::Received);
+        let rows = database.counterparties(&received, CounterpartyRank::Count, 10).await.unwrap();
+        assert_eq!(rows.iter().map(|c| (c.sent_transfers, c.received_transfers)).collect::<Vec<_>>(), [(0, 1)]);
+    }
+
+    #[tokio::test]
+    async fn net_flows_are_bucketed_by_interval() {
+        let database = database().await;
+        for (block_number, time, outgoing, to_self) in [
+            (100, "2026-01-05T09:30:00Z", true, false), // Monday
+            (101, "2026-01-05T22:00:00Z", false, false),
+            (102, "2026-01-11T12:00:00Z", true, false), // Sunday, same week
+            (103, "2026-01-12T08:00:00Z", true, true),
+        ] {
+            let mut event = transfer(block_number);
+            event.timestamp = time.parse().unwrap();
+            if !outgoing {
+                std::mem::swap(&mut event.from_address, &mut event.to_address);
+                event.value = "3000000".to_string();
+            }
+            if to_self {
+                event.to_address = OTHER.to_lowercase();
+            }
+            database.insert_transfer_event(&event).await.unwrap();
+        }
+
+        let filter = TransferFilter::new().address(Some(OTHER.to_string()));
+        let flows = |interval| {
+            let database = database.clone();
+            let filter = filter.clone();
+            async move {
+                let flows = database.net_flows(&filter, interval).await.unwrap();
+                flows
+                    .into_iter()
+                    .map(|f| (f.period, f.inflow_transfers, f.outflow_transfers, f.net))
+                    .collect::<Vec<_>>()
+            }
+        };
+        let row = |period: &str, inflow, outflow, net: &str| (period.to_string(), inflow, outflow, net.to_string());
+        assert_eq!(
+            flows(FlowInterval::Day).await,
+            [row("2026-01-05", 1, 1, "2000000"), row("2026-01-11", 0, 1, "-1000000"), row("2026-01-12", 1, 1, "0")]
+        );
+        assert_eq!(
+            flows(FlowInterval::Week).await,
+            [row("2026-01-05", 1, 2, "1000000"), row("2026-01-12", 1, 1, "0")]
+        );
+        assert_eq!(FlowInterval::Week.bucket("2026-01-11T12:00:00Z".parse().unwrap()), "2026-01-05");
+        assert_eq!(flows(FlowInterval::Month).await, [row("2026-01", 2, 3, "1000000")]);
+    }
+}
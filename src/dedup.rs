@@ -0,0 +1,159 @@
+use anyhow::Result;
+
+use crate::models::TransferEvent;
+
+/// Where a transfer row came from. Variants are ordered by trust: when two sources disagree
+/// about the same canonical event id, the higher one wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventSource {
+    Import,
+    Api,
+    Rpc,
+}
+
+impl EventSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventSource::Import => "import",
+            EventSource::Api => "api",
+            EventSource::Rpc => "rpc",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "import" => Ok(EventSource::Import),
+            "api" => Ok(EventSource::Api),
+            "rpc" => Ok(EventSource::Rpc),
+            other => Err(anyhow::anyhow!("Unknown event source: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestOutcome {
+    Inserted,
+    Duplicate,
+    Replaced,
+    KeptExisting,
+}
+
+impl IngestOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IngestOutcome::Inserted => "inserted",
+            IngestOutcome::Duplicate => "duplicate",
+            IngestOutcome::Replaced => "replaced",
+            IngestOutcome::KeptExisting => "kept_existing",
+        }
+    }
+}
+
+/// Names of the payload fields on which two copies of the same event disagree.
+pub fn payload_mismatches(existing: &TransferEvent, incoming: &TransferEvent) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if existing.block_number != incoming.block_number {
+        fields.push("block_number");
+    }
+    if existing.block_hash != incoming.block_hash {
+        fields.push("block_hash");
+    }
+    if existing.from_address != incoming.from_address {
+        fields.push("from_address");
+    }
+    if existing.to_address != incoming.to_address {
+        fields.push("to_address");
+    }
+    if existing.value != incoming.value {
+        fields.push("value");
+    }
+    fields
+}
+
+/// Decides what to do with an incoming copy of an event that is already stored.
+pub fn resolve(existing: EventSource, incoming: EventSource, mismatched: bool) -> IngestOutcome {
+    if !mismatched {
+        IngestOutcome::Duplicate
+    } else if incoming > existing {
+        IngestOutcome::Replaced
+    } else {
+        IngestOutcome::KeptExisting
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::TransferBuilder;
+    use chrono::{TimeZone, Utc};
+    use EventSource::{Api, Import, Rpc};
+    use IngestOutcome::{Duplicate, KeptExisting, Replaced};
+
+    const SOURCES: [EventSource; 3] = [Import, Api, Rpc];
+
+    fn event() -> TransferEvent {
+        let time = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        TransferBuilder::new(100)
+            .from("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")
+            .to("0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359")
+            .at(time)
+            .build()
+    }
+
+    #[test]
+    fn resolves_every_source_pair() {
+        // (existing, incoming, outcome when the payloads disagree)
+        let table = [
+            (Import, Import, KeptExisting),
+            (Import, Api, Replaced),
+            (Import, Rpc, Replaced),
+            (Api, Import, KeptExisting),
+            (Api, Api, KeptExisting),
+            (Api, Rpc, Replaced),
+            (Rpc, Import, KeptExisting),
+            (Rpc, Api, KeptExisting),
+            (Rpc, Rpc, KeptExisting),
+        ];
+        assert_eq!(table.len(), SOURCES.len() * SOURCES.len());
+
+        for (existing, incoming, outcome) in table {
+            assert_eq!(resolve(existing, incoming, true), outcome, "{:?} -> {:?}", existing, incoming);
+            assert_eq!(resolve(existing, incoming, false), Duplicate, "{:?} -> {:?}", existing, incoming);
+        }
+    }
+
+    #[test]
+    fn source_names_round_trip() {
+        for source in SOURCES {
+            assert_eq!(EventSource::parse(source.as_str()).unwrap(), source);
+        }
+        assert!(EventSource::parse("RPC").is_err());
+    }
+
+    #[test]
+    fn identical_payloads_do_not_mismatch() {
+        let mut incoming = event();
+        // Display and bookkeeping fields are not part of the payload.
+        incoming.created_at = Utc::now();
+        incoming.from_label = Some("Treasury".to_string());
+        assert!(payload_mismatches(&event(), &incoming).is_empty());
+    }
+
+    #[test]
+    fn reports_each_mismatched_field() {
+        let existing = event();
+        let mut incoming = event();
+        incoming.block_number = 101;
+        incoming.block_hash = "0x101".to_string();
+        assert_eq!(payload_mismatches(&existing, &incoming), ["block_number", "block_hash"]);
+
+        let mut incoming = event();
+        incoming.from_address = incoming.to_address.clone();
+        incoming.value = "1000001".to_string();
+        assert_eq!(payload_mismatches(&existing, &incoming), ["from_address", "value"]);
+
+        let mut incoming = event();
+        incoming.to_address = existing.from_address.clone();
+        assert_eq!(payload_mismatches(&existing, &incoming), ["to_address"]);
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use chrono::Duration;
+use futures::{stream, StreamExt};
+use std::collections::{HashMap, HashSet};
+
+use crate::{database::Database, ethereum::EthereumClient, models::TransferEvent};
+
+/// Reverse lookups in flight at once when annotating a page of transfers.
+const LOOKUP_CONCURRENCY: usize = 8;
+
+/// ENS lookups through the RPC endpoint, cached in `ens_names` and `ens_addresses` for `ttl`.
+pub struct EnsResolver {
+    client: EthereumClient,
+    ttl: Duration,
+}
+
+impl EnsResolver {
+    pub fn new(client: EthereumClient, ttl_secs: u64) -> Self {
+        Self {
+            client,
+            ttl: Duration::seconds(ttl_secs as i64),
+        }
+    }
+
+    /// Fills `from_ens` and `to_ens` with verified primary names. Addresses whose lookup fails
+    /// are left blank and retried on the next query.
+    pub async fn annotate(&self, database: &Database, transfers: &mut [TransferEvent]) -> Result<()> {
+        let addresses: Vec<String> = transfers
+            .iter()
+            .flat_map(|t| [t.from_address.clone(), t.to_address.clone()])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut names = database.get_ens_names(&addresses, database.now() - self.ttl).await?;
+        let missing: Vec<String> = addresses.into_iter().filter(|a| !names.contains_key(a)).collect();
+
+        let lookups: Vec<(String, Result<Option<String>>)> = stream::iter(missing)
+            .map(|address| async move {
+                let name = self.client.lookup_ens_name(&address).await;
+                (address, name)
+            })
+            .buffer_unordered(LOOKUP_CONCURRENCY)
+            .collect()
+            .await;
+
+        for (address, name) in lookups {
+            match name {
+                Ok(name) => {
+                    if !database.is_read_only() {
+                        database.set_ens_name(&address, name.as_deref()).await?;
+                    }
+                    names.insert(address, name);
+                }
+                Err(e) => tracing::warn!(%address, error = %e, "ENS lookup failed"),
+            }
+        }
+
+        let names: HashMap<String, String> = names.into_iter().filter_map(|(a, n)| Some((a, n?))).collect();
+        for transfer in transfers {
+            transfer.from_ens = names.get(&transfer.from_address).cloned();
+            transfer.to_ens = names.get(&transfer.to_address).cloned();
+        }
+        Ok(())
+    }
+
+    /// Resolves `name` to a checksummed address, using the cache when it is fresh.
+    pub async fn resolve(&self, database: &Database, name: &str) -> Result<String> {
+        let address = match database.get_ens_address(name, database.now() - self.ttl).await? {
+            Some(address) => address,
+            None => {
+                let address = self.client.resolve_ens_name(name).await?;
+                if !database.is_read_only() {
+                    database.set_ens_address(name, address.as_deref()).await?;
+                }
+                address
+            }
+        };
+        address.ok_or_else(|| anyhow::anyhow!("ENS name {} does not resolve to an address", name))
+    }
+}
+
+/// ENS names contain a dot; addresses never do.
+pub fn is_ens_name(input: &str) -> bool {
+    input.contains('.')
+}
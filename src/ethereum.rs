@@ -1,36 +1,96 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use ethers::prelude::*;
-use ethers_core::types::{Filter, Log, H160, H256, U64};
-use ethers_providers::{Http, Middleware, Provider};
-use std::sync::Arc;
+use ethers_core::{
+    abi::{ParamType, Token},
+    types::{transaction::eip2718::TypedTransaction, Filter, H160, H256, U64},
+};
+use ethers_providers::{Http, Middleware, Provider, ProviderError};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+use tracing::instrument;
 
-use crate::models::TransferEvent;
+use crate::{
+    address::checksum,
+    clock::{Clock, SystemClock},
+    config::Config,
+    models::{BlockPrice, Erc1155Transfer, ProcessedBlock, RawLog, TokenMetadata, TransactionDetails, TransferEvent},
+    rpc::{Recorder, Replay, Transport},
+};
 
-const TRANSFER_EVENT_SIGNATURE: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+pub(crate) const TRANSFER_EVENT_SIGNATURE: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+/// `TransferSingle(address,address,address,uint256,uint256)`
+const TRANSFER_SINGLE_SIGNATURE: &str = "0xc3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62";
+/// `TransferBatch(address,address,address,uint256[],uint256[])`
+const TRANSFER_BATCH_SIGNATURE: &str = "0x4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb";
+const NAME_SELECTOR: [u8; 4] = [0x06, 0xfd, 0xde, 0x03];
+const SYMBOL_SELECTOR: [u8; 4] = [0x95, 0xd8, 0x9b, 0x41];
+const DECIMALS_SELECTOR: [u8; 4] = [0x31, 0x3c, 0xe5, 0x67];
+/// `latestRoundData()` of a Chainlink aggregator.
+const LATEST_ROUND_DATA_SELECTOR: [u8; 4] = [0xfe, 0xaf, 0x96, 0x8c];
+/// Addresses per topic list in one `eth_getLogs` call; providers reject very long OR-lists.
+const MAX_TOPICS_PER_FILTER: usize = 100;
+/// Deployment blocks of well-known tokens, by chain id and lowercase address, so a fresh database
+/// starts at the right place without searching.
+const KNOWN_DEPLOYMENTS: &[(u64, &str, u64)] = &[(1, "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6_082_465)];
 
+/// JSON-RPC client for one chain and one ERC-20 contract.
 pub struct EthereumClient {
-    provider: Arc<Provider<Http>>,
+    provider: Arc<Provider<Transport>>,
     usdc_address: H160,
+    clock: Arc<dyn Clock>,
 }
 
 impl EthereumClient {
+    /// Fails on a malformed URL or contract address; nothing is requested until the first call.
     pub async fn new(rpc_url: &str, usdc_address: &str) -> Result<Self> {
-        let provider = Provider::<Http>::try_from(rpc_url)?;
-        let provider = Arc::new(provider);
-        let usdc_address: H160 = usdc_address.parse()?;
+        Self::with_transport(Transport::Http(Http::from_str(rpc_url)?), usdc_address)
+    }
 
+    /// Client of `ETHEREUM_RPC_URL` for the configured token, recording every exchange to
+    /// `RPC_RECORD`, or answering from `RPC_REPLAY` without a network.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let transport = match (&config.rpc_replay, &config.rpc_record) {
+            (Some(_), Some(_)) => return Err(anyhow::anyhow!("RPC_REPLAY and RPC_RECORD cannot both be set")),
+            (Some(fixture), None) => Transport::Replay(Arc::new(Replay::open(fixture)?)),
+            (None, record) => {
+                let http = Transport::Http(Http::from_str(&config.ethereum_rpc_url)?);
+                match record {
+                    Some(fixture) => Transport::Record(Arc::new(Recorder::create(http, fixture)?)),
+                    None => http,
+                }
+            }
+        };
+        Self::with_transport(transport, &config.usdc_contract_address)
+    }
+
+    /// Client over any transport, such as a [`MockEthereumClient`](crate::rpc::MockEthereumClient).
+    pub fn with_transport(transport: Transport, usdc_address: &str) -> Result<Self> {
         Ok(Self {
-            provider,
-            usdc_address,
+            provider: Arc::new(Provider::new(transport)),
+            usdc_address: usdc_address.parse()?,
+            clock: Arc::new(SystemClock),
         })
     }
 
+    /// Clock used for `created_at` on decoded events.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_chain_id(&self) -> Result<u64> {
+        let chain_id = self.provider.get_chainid().await?;
+        Ok(chain_id.as_u64())
+    }
+
+    #[instrument(skip(self))]
     pub async fn get_latest_block_number(&self) -> Result<u64> {
         let block_number = self.provider.get_block_number().await?;
         Ok(block_number.as_u64())
     }
 
+    #[instrument(skip(self))]
     pub async fn get_block_timestamp(&self, block_number: u64) -> Result<DateTime<Utc>> {
         let block = self
             .provider
@@ -44,73 +104,246 @@ impl EthereumClient {
         Ok(timestamp)
     }
 
+    /// Decoded `Transfer` logs of the contract in `from_block..=to_block`, with block timestamps.
     pub async fn get_transfer_events(
         &self,
         from_block: u64,
         to_block: u64,
     ) -> Result<Vec<TransferEvent>> {
-        let filter = Filter::new()
+        decode_transfer_logs(&self.get_transfer_logs(from_block, to_block).await?, self.clock.now())
+    }
+
+    /// Transfers sent or received by any of `addresses`, filtered by the node on the indexed
+    /// `from` and `to` topics so unrelated logs are never downloaded.
+    pub async fn get_transfer_events_for(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        addresses: &[String],
+    ) -> Result<Vec<TransferEvent>> {
+        let logs = self.get_transfer_logs_for(from_block, to_block, addresses).await?;
+        decode_transfer_logs(&logs, self.clock.now())
+    }
+
+    /// Undecoded `Transfer` logs of the contract in `from_block..=to_block`, in log order.
+    #[instrument(skip(self))]
+    pub async fn get_transfer_logs(&self, from_block: u64, to_block: u64) -> Result<Vec<RawLog>> {
+        let filter = self.transfer_filter(from_block, to_block)?;
+        self.fetch_logs(&filter).await
+    }
+
+    /// Undecoded `Transfer` logs sent or received by any of `addresses`, in log order.
+    #[instrument(skip(self, addresses), fields(addresses = addresses.len()))]
+    pub async fn get_transfer_logs_for(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        addresses: &[String],
+    ) -> Result<Vec<RawLog>> {
+        let addresses = parse_addresses(addresses)?;
+
+        let mut logs = Vec::new();
+        for chunk in addresses.chunks(MAX_TOPICS_PER_FILTER) {
+            let filter = self.transfer_filter(from_block, to_block)?;
+            logs.extend(self.fetch_logs(&filter.clone().topic1(chunk.to_vec())).await?);
+            logs.extend(self.fetch_logs(&filter.topic2(chunk.to_vec())).await?);
+        }
+
+        // Transfers between two watched addresses match both filters.
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        logs.dedup_by_key(|log| (log.block_number, log.log_index));
+        Ok(logs)
+    }
+
+    /// Undecoded ERC-1155 `TransferSingle` and `TransferBatch` logs of `contracts`, in log order.
+    #[instrument(skip(self, contracts), fields(contracts = contracts.len()))]
+    pub async fn get_erc1155_logs(&self, from_block: u64, to_block: u64, contracts: &[String]) -> Result<Vec<RawLog>> {
+        self.fetch_logs(&erc1155_filter(from_block, to_block, contracts)?).await
+    }
+
+    /// Like `get_erc1155_logs`, limited to logs sent from or received by one of `addresses`.
+    #[instrument(skip(self, contracts, addresses), fields(contracts = contracts.len(), addresses = addresses.len()))]
+    pub async fn get_erc1155_logs_for(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        contracts: &[String],
+        addresses: &[String],
+    ) -> Result<Vec<RawLog>> {
+        let addresses = parse_addresses(addresses)?;
+
+        let mut logs = Vec::new();
+        for chunk in addresses.chunks(MAX_TOPICS_PER_FILTER) {
+            let filter = erc1155_filter(from_block, to_block, contracts)?;
+            logs.extend(self.fetch_logs(&filter.clone().topic2(chunk.to_vec())).await?);
+            logs.extend(self.fetch_logs(&filter.topic3(chunk.to_vec())).await?);
+        }
+
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        logs.dedup_by_key(|log| (log.block_number, log.log_index));
+        Ok(logs)
+    }
+
+    fn transfer_filter(&self, from_block: u64, to_block: u64) -> Result<Filter> {
+        Ok(Filter::new()
             .address(self.usdc_address)
             .topic0(H256::from_slice(&hex::decode(&TRANSFER_EVENT_SIGNATURE[2..])?))
             .from_block(BlockNumber::Number(U64::from(from_block)))
-            .to_block(BlockNumber::Number(U64::from(to_block)));
+            .to_block(BlockNumber::Number(U64::from(to_block))))
+    }
 
-        let logs = self.provider.get_logs(&filter).await?;
-        let mut events = Vec::new();
+    async fn fetch_logs(&self, filter: &Filter) -> Result<Vec<RawLog>> {
+        let logs = self.provider.get_logs(filter).await?;
+        let mut timestamps: HashMap<u64, DateTime<Utc>> = HashMap::new();
+        let mut raw_logs = Vec::with_capacity(logs.len());
 
         for log in logs {
-            if let Some(event) = self.parse_transfer_log(log).await? {
-                events.push(event);
-            }
+            let block_number = log
+                .block_number
+                .ok_or_else(|| anyhow::anyhow!("Missing block number"))?
+                .as_u64();
+
+            let block_timestamp = match timestamps.get(&block_number) {
+                Some(timestamp) => *timestamp,
+                None => {
+                    let timestamp = self.get_block_timestamp(block_number).await?;
+                    timestamps.insert(block_number, timestamp);
+                    timestamp
+                }
+            };
+            raw_logs.push(raw_log(&log, block_timestamp)?);
         }
 
-        Ok(events)
+        Ok(raw_logs)
     }
 
-    async fn parse_transfer_log(&self, log: Log) -> Result<Option<TransferEvent>> {
-        if log.topics.len() != 3 {
-            return Ok(None);
-        }
+    /// Reads `name()`, `symbol()` and `decimals()` from the configured contract.
+    #[instrument(skip(self))]
+    pub async fn get_token_metadata(&self) -> Result<TokenMetadata> {
+        let name = decode_string(&self.call_contract(self.usdc_address, NAME_SELECTOR, None).await?).context("name()")?;
+        let symbol = decode_string(&self.call_contract(self.usdc_address, SYMBOL_SELECTOR, None).await?).context("symbol()")?;
 
-        let from_address = format!("0x{:x}", H160::from(log.topics[1]));
-        let to_address = format!("0x{:x}", H160::from(log.topics[2]));
-        let value = U256::from_big_endian(&log.data).to_string();
+        let decimals = decode_decimals(&self.call_contract(self.usdc_address, DECIMALS_SELECTOR, None).await?)?;
 
-        let block_number = log
-            .block_number
-            .ok_or_else(|| anyhow::anyhow!("Missing block number"))?
-            .as_u64();
+        Ok(TokenMetadata {
+            address: checksum(&self.usdc_address),
+            name,
+            symbol,
+            decimals,
+            standard: "erc20".to_string(),
+            fetched_at: self.clock.now(),
+        })
+    }
+
+    /// Metadata of an ERC-1155 contract. `name()` and `symbol()` are not part of the standard,
+    /// so they are left empty when the contract does not answer them.
+    #[instrument(skip(self))]
+    pub async fn get_erc1155_metadata(&self, contract: &str) -> Result<TokenMetadata> {
+        let address: H160 = contract.parse().map_err(|_| anyhow::anyhow!("Invalid address: {}", contract))?;
+        let name = self.call_contract(address, NAME_SELECTOR, None).await.ok();
+        let symbol = self.call_contract(address, SYMBOL_SELECTOR, None).await.ok();
+        let text = |data: Option<Bytes>| data.and_then(|data| decode_string(&data).ok()).unwrap_or_default();
 
-        let block_hash = log
-            .block_hash
-            .ok_or_else(|| anyhow::anyhow!("Missing block hash"))?;
+        Ok(TokenMetadata {
+            address: checksum(&address),
+            name: text(name),
+            symbol: text(symbol),
+            decimals: 0,
+            standard: "erc1155".to_string(),
+            fetched_at: self.clock.now(),
+        })
+    }
 
-        let log_index = log
-            .log_index
-            .ok_or_else(|| anyhow::anyhow!("Missing log index"))?
-            .as_u64();
+    /// Latest answer of the Chainlink aggregator `feed` as of `block_number`. Reading past blocks
+    /// needs an archive node.
+    #[instrument(skip(self))]
+    pub async fn get_chainlink_price(&self, feed: &str, block_number: u64) -> Result<BlockPrice> {
+        let address: H160 = feed.parse().map_err(|_| anyhow::anyhow!("Invalid price feed: {}", feed))?;
+        let block = Some(BlockId::from(block_number));
 
-        let transaction_hash = log
-            .transaction_hash
-            .ok_or_else(|| anyhow::anyhow!("Missing transaction hash"))?;
+        let decimals = decode_decimals(&self.call_contract(address, DECIMALS_SELECTOR, block).await?)?;
+        let round = self.call_contract(address, LATEST_ROUND_DATA_SELECTOR, block).await?;
+        let tokens = ethers_core::abi::decode(
+            &[ParamType::Uint(80), ParamType::Int(256), ParamType::Uint(256), ParamType::Uint(256), ParamType::Uint(80)],
+            &round,
+        )
+        .context("latestRoundData()")?;
 
-        let timestamp = self.get_block_timestamp(block_number).await?;
-        let id = format!("{}_{}", transaction_hash, log_index);
+        let answer = tokens[1].clone().into_int().unwrap_or_default();
+        if answer.bit(255) || answer.is_zero() {
+            return Err(anyhow::anyhow!("Price feed {} has no positive answer at block {}", feed, block_number));
+        }
+        let updated_at = tokens[3].clone().into_uint().unwrap_or_default();
+        let updated_at = DateTime::from_timestamp(updated_at.low_u64() as i64, 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid updatedAt from price feed {}", feed))?;
 
-        Ok(Some(TransferEvent {
-            id,
-            transaction_hash: format!("0x{:x}", transaction_hash),
-            log_index: log_index as i64,
+        Ok(BlockPrice {
+            feed: checksum(&address),
             block_number: block_number as i64,
-            block_hash: format!("0x{:x}", block_hash),
-            from_address,
-            to_address,
-            value,
-            timestamp,
-            created_at: Utc::now(),
-        }))
+            answer: answer.to_string(),
+            decimals,
+            updated_at,
+        })
     }
 
+    async fn call_contract(&self, to: H160, selector: [u8; 4], block: Option<BlockId>) -> Result<Bytes> {
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(to)
+            .data(selector.to_vec())
+            .into();
+        Ok(self.provider.call(&tx, block).await?)
+    }
+
+    /// Block the contract was deployed in: the known one for `chain_id`, or else the first block at
+    /// which it has code, found by binary search over `eth_getCode`. The search needs a node that
+    /// serves historical state. `None` when the contract has no code at `latest_block`.
+    #[instrument(skip(self))]
+    pub async fn get_deployment_block(&self, chain_id: u64, latest_block: u64) -> Result<Option<u64>> {
+        let address = format!("{:#x}", self.usdc_address);
+        if let Some(&(_, _, block)) = KNOWN_DEPLOYMENTS
+            .iter()
+            .find(|(chain, known, _)| *chain == chain_id && *known == address)
+        {
+            return Ok(Some(block));
+        }
+        first_block_with(latest_block, |block| async move {
+            let code = self.provider.get_code(self.usdc_address, Some(BlockId::from(block))).await?;
+            Ok(!code.is_empty())
+        })
+        .await
+    }
+
+    /// Whether `address` currently has contract code, as opposed to being an externally owned
+    /// account.
+    #[instrument(skip(self))]
+    pub async fn is_contract(&self, address: &str) -> Result<bool> {
+        let parsed: H160 = address.parse().map_err(|_| anyhow::anyhow!("Invalid address: {}", address))?;
+        Ok(!self.provider.get_code(parsed, None).await?.is_empty())
+    }
+
+    /// Primary ENS name of `address`, if its reverse record resolves back to the same address.
+    #[instrument(skip(self))]
+    pub async fn lookup_ens_name(&self, address: &str) -> Result<Option<String>> {
+        let address: H160 = address.parse().map_err(|_| anyhow::anyhow!("Invalid address: {}", address))?;
+        match self.provider.lookup_address(address).await {
+            Ok(name) => Ok(Some(name)),
+            Err(ProviderError::EnsError(_)) | Err(ProviderError::EnsNotOwned(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Checksummed address an ENS name points to, or `None` if it has no resolver or address record.
+    #[instrument(skip(self))]
+    pub async fn resolve_ens_name(&self, name: &str) -> Result<Option<String>> {
+        match self.provider.resolve_name(name).await {
+            Ok(address) if address.is_zero() => Ok(None),
+            Ok(address) => Ok(Some(checksum(&address))),
+            Err(ProviderError::EnsError(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[instrument(skip(self))]
     pub async fn get_block_hash(&self, block_number: u64) -> Result<String> {
         let block = self
             .provider
@@ -121,4 +354,401 @@ impl EthereumClient {
         Ok(format!("0x{:x}", block.hash.unwrap_or_default()))
     }
 
-}
\ No newline at end of file
+    /// Hash, timestamp and congestion fields of a block, from one header request.
+    #[instrument(skip(self))]
+    pub async fn get_block_header(&self, block_number: u64) -> Result<ProcessedBlock> {
+        let block = self
+            .provider
+            .get_block(BlockId::Number(BlockNumber::Number(U64::from(block_number))))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Block not found"))?;
+
+        let timestamp = DateTime::from_timestamp(block.timestamp.as_u64() as i64, 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid timestamp"))?;
+
+        Ok(ProcessedBlock {
+            block_number,
+            block_hash: format!("0x{:x}", block.hash.unwrap_or_default()),
+            timestamp,
+            base_fee_per_gas: block.base_fee_per_gas.map(|fee| fee.to_string()),
+            gas_used: block.gas_used.as_u64(),
+            gas_limit: block.gas_limit.as_u64(),
+            fee_recipient: checksum(&block.author.unwrap_or_default()),
+        })
+    }
+
+    /// Sender, gas and fee of a mined transaction, from its receipt. Receipts from nodes that
+    /// predate `effectiveGasPrice` fall back to the transaction's gas price.
+    #[instrument(skip(self))]
+    pub async fn get_transaction_details(&self, transaction_hash: &str) -> Result<TransactionDetails> {
+        let hash: H256 = transaction_hash
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid transaction hash: {}", transaction_hash))?;
+        let receipt = self
+            .provider
+            .get_transaction_receipt(hash)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Receipt of {} not found", transaction_hash))?;
+
+        let gas_used = receipt
+            .gas_used
+            .ok_or_else(|| anyhow::anyhow!("Receipt of {} has no gasUsed", transaction_hash))?;
+        let gas_price = match receipt.effective_gas_price {
+            Some(price) => price,
+            None => self
+                .provider
+                .get_transaction(hash)
+                .await?
+                .and_then(|tx| tx.gas_price)
+                .ok_or_else(|| anyhow::anyhow!("Gas price of {} not found", transaction_hash))?,
+        };
+
+        Ok(TransactionDetails {
+            transaction_hash: format!("{:?}", hash),
+            block_number: receipt.block_number.map_or(0, |n| n.as_u64() as i64),
+            sender: checksum(&receipt.from),
+            recipient: receipt.to.as_ref().map(checksum),
+            gas_used: gas_used.to_string(),
+            effective_gas_price: gas_price.to_string(),
+            fee: (gas_used * gas_price).to_string(),
+            fetched_at: self.clock.now(),
+        })
+    }
+}
+
+/// Decodes a `decimals()` return value.
+fn decode_decimals(data: &[u8]) -> Result<u8> {
+    data.get(..32)
+        .map(U256::from_big_endian)
+        .filter(|d| *d <= U256::from(u8::MAX))
+        .map(|d| d.as_u32() as u8)
+        .ok_or_else(|| anyhow::anyhow!("decimals(): unexpected return data"))
+}
+
+/// Decodes an ABI `string` return value, falling back to the `bytes32` some older tokens return.
+fn decode_string(data: &[u8]) -> Result<String> {
+    if let Ok(tokens) = ethers_core::abi::decode(&[ethers_core::abi::ParamType::String], data) {
+        if let Some(name) = tokens.into_iter().next().and_then(|token| token.into_string()) {
+            return Ok(name);
+        }
+    }
+
+    if data.len() == 32 {
+        let end = data.iter().position(|b| *b == 0).unwrap_or(32);
+        return Ok(String::from_utf8(data[..end].to_vec())?);
+    }
+
+    Err(anyhow::anyhow!("unexpected return data"))
+}
+
+/// Decodes a `Transfer(address indexed, address indexed, uint256)` log, or `None` for logs with
+/// another shape, such as ERC-721 transfers that index the token id.
+pub fn decode_transfer_log(log: &RawLog, created_at: DateTime<Utc>) -> Result<Option<TransferEvent>> {
+    if log.topics.len() != 3 || log.topics[0] != TRANSFER_EVENT_SIGNATURE {
+        return Ok(None);
+    }
+
+    let data = hex::decode(log.data.trim_start_matches("0x"))?;
+    if data.len() > 32 {
+        return Ok(None);
+    }
+
+    let from: H256 = log.topics[1].parse().map_err(|_| anyhow::anyhow!("Invalid topic: {}", log.topics[1]))?;
+    let to: H256 = log.topics[2].parse().map_err(|_| anyhow::anyhow!("Invalid topic: {}", log.topics[2]))?;
+
+    Ok(Some(TransferEvent {
+        id: format!("{}_{}", log.transaction_hash, log.log_index),
+        transaction_hash: log.transaction_hash.clone(),
+        log_index: log.log_index as i64,
+        block_number: log.block_number as i64,
+        block_hash: log.block_hash.clone(),
+        from_address: checksum(&H160::from(from)),
+        to_address: checksum(&H160::from(to)),
+        value: U256::from_big_endian(&data).to_string(),
+        timestamp: log.block_timestamp,
+        created_at,
+        from_label: None,
+        to_label: None,
+        from_ens: None,
+        to_ens: None,
+        tx_sender: None,
+        tx_to: None,
+        tx_fee: None,
+        usd_value: None,
+        sanctioned: false,
+    }))
+}
+
+/// A mined log as stored in `raw_logs`, with the timestamp of its block.
+pub(crate) fn raw_log(log: &Log, block_timestamp: DateTime<Utc>) -> Result<RawLog> {
+    Ok(RawLog {
+        block_number: log
+            .block_number
+            .ok_or_else(|| anyhow::anyhow!("Missing block number"))?
+            .as_u64(),
+        block_hash: format!(
+            "0x{:x}",
+            log.block_hash.ok_or_else(|| anyhow::anyhow!("Missing block hash"))?
+        ),
+        block_timestamp,
+        transaction_hash: format!(
+            "0x{:x}",
+            log.transaction_hash.ok_or_else(|| anyhow::anyhow!("Missing transaction hash"))?
+        ),
+        transaction_index: log.transaction_index.map(|i| i.as_u64()),
+        log_index: log.log_index.ok_or_else(|| anyhow::anyhow!("Missing log index"))?.as_u64(),
+        address: format!("0x{:x}", log.address),
+        topics: log.topics.iter().map(|topic| format!("0x{:x}", topic)).collect(),
+        data: format!("0x{}", hex::encode(&log.data)),
+    })
+}
+
+pub fn decode_transfer_logs(logs: &[RawLog], created_at: DateTime<Utc>) -> Result<Vec<TransferEvent>> {
+    let mut events = Vec::with_capacity(logs.len());
+    for log in logs {
+        if let Some(event) = decode_transfer_log(log, created_at)? {
+            events.push(event);
+        }
+    }
+    Ok(events)
+}
+
+fn parse_addresses(addresses: &[String]) -> Result<Vec<H160>> {
+    addresses
+        .iter()
+        .map(|a| a.parse::<H160>().map_err(|_| anyhow::anyhow!("Invalid address: {}", a)))
+        .collect()
+}
+
+fn erc1155_filter(from_block: u64, to_block: u64, contracts: &[String]) -> Result<Filter> {
+    let signatures = [TRANSFER_SINGLE_SIGNATURE, TRANSFER_BATCH_SIGNATURE]
+        .iter()
+        .map(|signature| signature.parse::<H256>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Filter::new()
+        .address(parse_addresses(contracts)?)
+        .topic0(signatures)
+        .from_block(BlockNumber::Number(U64::from(from_block)))
+        .to_block(BlockNumber::Number(U64::from(to_block))))
+}
+
+/// Decodes an ERC-1155 `TransferSingle` into one row, or a `TransferBatch` into one row per
+/// token id. Other logs decode to nothing.
+pub fn decode_erc1155_log(log: &RawLog, created_at: DateTime<Utc>) -> Result<Vec<Erc1155Transfer>> {
+    let batch = match log.topics.first().map(String::as_str) {
+        Some(TRANSFER_SINGLE_SIGNATURE) => false,
+        Some(TRANSFER_BATCH_SIGNATURE) => true,
+        _ => return Ok(Vec::new()),
+    };
+    if log.topics.len() != 4 {
+        return Err(anyhow::anyhow!("ERC-1155 log {}_{} has {} topics", log.transaction_hash, log.log_index, log.topics.len()));
+    }
+
+    let mut addresses = Vec::with_capacity(3);
+    for topic in &log.topics[1..] {
+        let topic: H256 = topic.parse().map_err(|_| anyhow::anyhow!("Invalid topic: {}", topic))?;
+        addresses.push(checksum(&H160::from(topic)));
+    }
+
+    let data = hex::decode(log.data.trim_start_matches("0x"))?;
+    let (ids, values) = if batch {
+        let array = ParamType::Array(Box::new(ParamType::Uint(256)));
+        let mut tokens = ethers_core::abi::decode(&[array.clone(), array], &data)?.into_iter();
+        let (Some(ids), Some(values)) = (
+            tokens.next().and_then(Token::into_array),
+            tokens.next().and_then(Token::into_array),
+        ) else {
+            return Err(anyhow::anyhow!("Malformed TransferBatch data"));
+        };
+        if ids.len() != values.len() {
+            return Err(anyhow::anyhow!("TransferBatch has {} ids but {} values", ids.len(), values.len()));
+        }
+        (ids, values)
+    } else {
+        let mut tokens = ethers_core::abi::decode(&[ParamType::Uint(256), ParamType::Uint(256)], &data)?;
+        let value = tokens.pop();
+        let id = tokens.pop();
+        (id.into_iter().collect(), value.into_iter().collect())
+    };
+
+    ids.into_iter()
+        .zip(values)
+        .enumerate()
+        .map(|(batch_index, (id, value))| {
+            let (Some(token_id), Some(value)) = (id.into_uint(), value.into_uint()) else {
+                return Err(anyhow::anyhow!("Malformed ERC-1155 id or value"));
+            };
+            Ok(Erc1155Transfer {
+                id: format!("{}_{}_{}", log.transaction_hash, log.log_index, batch_index),
+                contract_address: checksum(&log.address.parse::<H160>()?),
+                transaction_hash: log.transaction_hash.clone(),
+                log_index: log.log_index as i64,
+                batch_index: batch_index as i64,
+                block_number: log.block_number as i64,
+                block_hash: log.block_hash.clone(),
+                operator: addresses[0].clone(),
+                from_address: addresses[1].clone(),
+                to_address: addresses[2].clone(),
+                token_id: token_id.to_string(),
+                value: value.to_string(),
+                timestamp: log.block_timestamp,
+                created_at,
+            })
+        })
+        .collect()
+}
+
+/// Lowest block in `0..=latest_block` for which `has_code` holds, assuming it keeps holding once it
+/// does; `None` when it does not hold at `latest_block`.
+async fn first_block_with<F, Fut>(latest_block: u64, mut has_code: F) -> Result<Option<u64>>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: std::future::Future<Output = Result<bool>>,
+{
+    if !has_code(latest_block).await? {
+        return Ok(None);
+    }
+    let (mut low, mut high) = (0, latest_block);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if has_code(mid).await? {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Ok(Some(low))
+}
+
+pub fn decode_erc1155_logs(logs: &[RawLog], created_at: DateTime<Utc>) -> Result<Vec<Erc1155Transfer>> {
+    let mut transfers = Vec::new();
+    for log in logs {
+        transfers.extend(decode_erc1155_log(log, created_at)?);
+    }
+    Ok(transfers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer_log(topics: Vec<String>, data: &str) -> RawLog {
+        RawLog {
+            block_number: 19_000_000,
+            block_hash: format!("0x{}", "b".repeat(64)),
+            block_timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            transaction_hash: format!("0x{}", "a".repeat(64)),
+            transaction_index: Some(3),
+            log_index: 42,
+            address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string(),
+            topics,
+            data: data.to_string(),
+        }
+    }
+
+    fn topics() -> Vec<String> {
+        vec![
+            TRANSFER_EVENT_SIGNATURE.to_string(),
+            "0x0000000000000000000000005aaeb6053f3e94c9b9a09f33669435e7ef1beaed".to_string(),
+            "0x000000000000000000000000fb6916095ca1df60bb79ce92ce3ea74c37c5d359".to_string(),
+        ]
+    }
+
+    #[test]
+    fn decodes_erc20_transfer() {
+        let log = transfer_log(topics(), "0x00000000000000000000000000000000000000000000000000000000004c4b40");
+        let created_at = DateTime::from_timestamp(1_700_000_100, 0).unwrap();
+        let event = decode_transfer_log(&log, created_at).unwrap().unwrap();
+
+        assert_eq!(event.id, format!("0x{}_42", "a".repeat(64)));
+        assert_eq!(event.block_number, 19_000_000);
+        assert_eq!(event.log_index, 42);
+        assert_eq!(event.from_address, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+        assert_eq!(event.to_address, "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359");
+        assert_eq!(event.value, "5000000");
+        assert_eq!(event.timestamp, log.block_timestamp);
+        assert_eq!(event.created_at, created_at);
+    }
+
+    #[test]
+    fn skips_erc721_transfer() {
+        let mut topics = topics();
+        topics.push(format!("0x{:064x}", 7));
+        let log = transfer_log(topics, "0x");
+        assert!(decode_transfer_log(&log, Utc::now()).unwrap().is_none());
+    }
+
+    #[test]
+    fn erc1155_signatures_match_their_events() {
+        let topic = |event: &str| format!("0x{}", hex::encode(ethers::utils::keccak256(event)));
+        assert_eq!(topic("TransferSingle(address,address,address,uint256,uint256)"), TRANSFER_SINGLE_SIGNATURE);
+        assert_eq!(topic("TransferBatch(address,address,address,uint256[],uint256[])"), TRANSFER_BATCH_SIGNATURE);
+    }
+
+    fn erc1155_topics(signature: &str) -> Vec<String> {
+        let mut topics = topics();
+        topics[0] = signature.to_string();
+        topics.insert(1, format!("0x{:064x}", 0xee));
+        topics
+    }
+
+    #[test]
+    fn decodes_erc1155_transfer_single() {
+        let data = format!("0x{:064x}{:064x}", 7, 250);
+        let log = transfer_log(erc1155_topics(TRANSFER_SINGLE_SIGNATURE), &data);
+        let transfers = decode_erc1155_log(&log, Utc::now()).unwrap();
+
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].id, format!("0x{}_42_0", "a".repeat(64)));
+        assert_eq!(transfers[0].contract_address, "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        assert_eq!(transfers[0].operator, "0x00000000000000000000000000000000000000eE");
+        assert_eq!(transfers[0].from_address, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+        assert_eq!(transfers[0].to_address, "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359");
+        assert_eq!((transfers[0].token_id.as_str(), transfers[0].value.as_str()), ("7", "250"));
+    }
+
+    #[test]
+    fn unrolls_erc1155_transfer_batch() {
+        let data = ethers_core::abi::encode(&[
+            Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into())]),
+            Token::Array(vec![Token::Uint(10.into()), Token::Uint(20.into())]),
+        ]);
+        let log = transfer_log(erc1155_topics(TRANSFER_BATCH_SIGNATURE), &format!("0x{}", hex::encode(data)));
+        let transfers = decode_erc1155_log(&log, Utc::now()).unwrap();
+
+        let rows: Vec<_> = transfers
+            .iter()
+            .map(|t| (t.batch_index, t.token_id.as_str(), t.value.as_str()))
+            .collect();
+        assert_eq!(rows, [(0, "1", "10"), (1, "2", "20")]);
+        assert_eq!(transfers[1].id, format!("0x{}_42_1", "a".repeat(64)));
+    }
+
+    #[test]
+    fn rejects_erc1155_batch_with_mismatched_lengths() {
+        let data = ethers_core::abi::encode(&[
+            Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into())]),
+            Token::Array(vec![Token::Uint(10.into())]),
+        ]);
+        let log = transfer_log(erc1155_topics(TRANSFER_BATCH_SIGNATURE), &format!("0x{}", hex::encode(data)));
+        assert!(decode_erc1155_log(&log, Utc::now()).is_err());
+    }
+
+    #[tokio::test]
+    async fn finds_the_first_block_with_code() {
+        let calls = std::cell::Cell::new(0);
+        let deployed_at = |deployment: u64| {
+            let calls = &calls;
+            move |block: u64| async move {
+                calls.set(calls.get() + 1);
+                Ok(block >= deployment)
+            }
+        };
+        assert_eq!(first_block_with(20_000_000, deployed_at(6_082_465)).await.unwrap(), Some(6_082_465));
+        assert!(calls.get() <= 26);
+        assert_eq!(first_block_with(100, deployed_at(0)).await.unwrap(), Some(0));
+        assert_eq!(first_block_with(100, deployed_at(100)).await.unwrap(), Some(100));
+        assert_eq!(first_block_with(100, deployed_at(101)).await.unwrap(), None);
+    }
+}
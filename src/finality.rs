@@ -0,0 +1,60 @@
+use serde::Serialize;
+
+/// Blocks added on top of the deepest observed reorg when suggesting `FINALITY_BLOCKS`.
+const SAFETY_MARGIN_BLOCKS: u64 = 2;
+
+/// Summary of observed reorg depths over a window of indexed blocks.
+#[derive(Debug, Serialize)]
+pub struct FinalityReport {
+    pub window_days: i64,
+    pub blocks_observed: i64,
+    pub reorgs_observed: usize,
+    pub max_depth: Option<i64>,
+    /// `(depth, count)` pairs, shallowest first.
+    pub depth_histogram: Vec<(i64, usize)>,
+    /// Observed probability per block that a reorg at least this deep occurs.
+    pub reorg_probability_at_finality: f64,
+    pub configured_finality_blocks: u64,
+    pub suggested_finality_blocks: u64,
+}
+
+impl FinalityReport {
+    pub fn new(window_days: i64, depths: &[i64], blocks_observed: i64, configured_finality_blocks: u64) -> Self {
+        let mut depth_histogram: Vec<(i64, usize)> = Vec::new();
+        let mut sorted = depths.to_vec();
+        sorted.sort_unstable();
+        for depth in sorted {
+            match depth_histogram.last_mut() {
+                Some((last, count)) if *last == depth => *count += 1,
+                _ => depth_histogram.push((depth, 1)),
+            }
+        }
+
+        let max_depth = depths.iter().copied().max();
+        let at_or_beyond = depths
+            .iter()
+            .filter(|depth| **depth >= configured_finality_blocks as i64)
+            .count();
+        let reorg_probability_at_finality = if blocks_observed > 0 {
+            at_or_beyond as f64 / blocks_observed as f64
+        } else {
+            0.0
+        };
+
+        let suggested_finality_blocks = match max_depth {
+            Some(depth) => depth.max(0) as u64 + SAFETY_MARGIN_BLOCKS,
+            None => configured_finality_blocks,
+        };
+
+        Self {
+            window_days,
+            blocks_observed,
+            reorgs_observed: depths.len(),
+            max_depth,
+            depth_histogram,
+            reorg_probability_at_finality,
+            configured_finality_blocks,
+            suggested_finality_blocks,
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::Result;
+use ethers_core::types::U256;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::{database::Database, models::TransactionDetails, query::TransferFilter, units::format_amount};
+
+/// ETH fees an address paid for the transactions that moved its tokens. Each transaction is
+/// attributed to the counterparty of its first transfer involving the address.
+#[derive(Debug, Serialize)]
+pub struct GasSpentReport {
+    pub address: String,
+    /// Distinct transactions that moved the address's tokens.
+    pub transactions: usize,
+    /// Of those, the enriched ones the address signed and therefore paid for.
+    pub paid_transactions: usize,
+    /// Transactions without stored details, whose fees are unknown.
+    pub unenriched_transactions: usize,
+    pub fee_wei: String,
+    pub fee_eth: String,
+    /// Highest fee total first.
+    pub counterparties: Vec<CounterpartyGas>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CounterpartyGas {
+    pub counterparty: String,
+    pub transactions: usize,
+    pub fee_wei: String,
+    pub fee_eth: String,
+}
+
+impl GasSpentReport {
+    /// `transfers` are `(transaction_hash, counterparty)` pairs of the address's transfers in log
+    /// order; `details` are the stored transactions among them.
+    pub fn new(
+        address: &str,
+        transfers: &[(String, String)],
+        details: &HashMap<String, TransactionDetails>,
+    ) -> Result<Self> {
+        let mut seen = HashSet::new();
+        let mut transactions = 0;
+        let mut unenriched_transactions = 0;
+        let mut total = U256::zero();
+        let mut by_counterparty: HashMap<&str, (usize, U256)> = HashMap::new();
+
+        for (hash, counterparty) in transfers {
+            if !seen.insert(hash) {
+                continue;
+            }
+            transactions += 1;
+
+            let Some(tx) = details.get(hash) else {
+                unenriched_transactions += 1;
+                continue;
+            };
+            if !tx.sender.eq_ignore_ascii_case(address) {
+                continue;
+            }
+
+            let fee = U256::from_dec_str(&tx.fee).map_err(|_| anyhow::anyhow!("Invalid fee of {}: {}", hash, tx.fee))?;
+            total += fee;
+            let entry = by_counterparty.entry(counterparty.as_str()).or_default();
+            entry.0 += 1;
+            entry.1 += fee;
+        }
+
+        let mut counterparties: Vec<(&str, (usize, U256))> = by_counterparty.into_iter().collect();
+        counterparties.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(b.0)));
+
+        Ok(Self {
+            address: address.to_string(),
+            transactions,
+            paid_transactions: counterparties.iter().map(|(_, (count, _))| count).sum(),
+            unenriched_transactions,
+            fee_wei: total.to_string(),
+            fee_eth: format_amount(total, 18),
+            counterparties: counterparties
+                .into_iter()
+                .map(|(counterparty, (transactions, fee))| CounterpartyGas {
+                    counterparty: counterparty.to_string(),
+                    transactions,
+                    fee_wei: fee.to_string(),
+                    fee_eth: format_amount(fee, 18),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Sums the fees `address` paid across every stored transfer of its tokens, using the
+/// transactions stored by `TX_ENRICHMENT`.
+pub async fn gas_spent(database: &Database, address: &str) -> Result<GasSpentReport> {
+    let mut transfers = Vec::new();
+    database
+        .for_each_transfer(&TransferFilter::new().address(Some(address.to_string())), |transfer| {
+            let counterparty = if transfer.from_address.eq_ignore_ascii_case(address) {
+                transfer.to_address
+            } else {
+                transfer.from_address
+            };
+            transfers.push((transfer.transaction_hash, counterparty));
+            Ok(())
+        })
+        .await?;
+
+    let hashes: Vec<String> = transfers
+        .iter()
+        .map(|(hash, _)| hash.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let details = database.get_transactions(&hashes).await?;
+    GasSpentReport::new(address, &transfers, &details)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    const TREASURY: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const EXCHANGE: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+    const VENDOR: &str = "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB";
+
+    fn details(hash: &str, sender: &str, fee: &str) -> (String, TransactionDetails) {
+        let tx = TransactionDetails {
+            transaction_hash: hash.to_string(),
+            block_number: 100,
+            sender: sender.to_string(),
+            recipient: None,
+            gas_used: "21000".to_string(),
+            effective_gas_price: "1".to_string(),
+            fee: fee.to_string(),
+            fetched_at: Utc::now(),
+        };
+        (hash.to_string(), tx)
+    }
+
+    #[test]
+    fn sums_fees_of_transactions_the_address_signed() {
+        let transfers: Vec<(String, String)> = [
+            ("0x1", EXCHANGE),
+            ("0x1", VENDOR), // Same transaction, counted once for its first counterparty.
+            ("0x2", VENDOR),
+            ("0x3", EXCHANGE), // Signed by the exchange.
+            ("0x4", VENDOR),   // Not enriched.
+        ]
+        .iter()
+        .map(|(hash, counterparty)| (hash.to_string(), counterparty.to_string()))
+        .collect();
+        let details: HashMap<_, _> = [
+            details("0x1", TREASURY, "1000000000000000000"),
+            details("0x2", &TREASURY.to_lowercase(), "500000000000000000"),
+            details("0x3", EXCHANGE, "700000000000000000"),
+        ]
+        .into_iter()
+        .collect();
+
+        let report = GasSpentReport::new(TREASURY, &transfers, &details).unwrap();
+        assert_eq!(report.transactions, 4);
+        assert_eq!(report.paid_transactions, 2);
+        assert_eq!(report.unenriched_transactions, 1);
+        assert_eq!(report.fee_eth, "1.50");
+
+        let counterparties: Vec<_> = report
+            .counterparties
+            .iter()
+            .map(|c| (c.counterparty.as_str(), c.transactions, c.fee_eth.as_str()))
+            .collect();
+        assert_eq!(counterparties, [(EXCHANGE, 1, "1.00"), (VENDOR, 1, "0.50")]);
+    }
+}
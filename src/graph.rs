@@ -0,0 +1,197 @@
+use anyhow::Result;
+use ethers_core::types::U256;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+};
+
+use crate::{models::TransferEvent, output::AmountFormat, units::format_amount};
+
+/// File formats for `export-graph`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz
+    #[default]
+    Dot,
+    /// GraphML, for Gephi and most graph libraries
+    Graphml,
+}
+
+/// Transfers between one ordered pair of addresses.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Edge {
+    pub transfers: u64,
+    pub volume: U256,
+}
+
+/// The transfer network of a set of transfers: one node per address and one weighted edge per
+/// sender and recipient pair.
+#[derive(Debug, Default)]
+pub struct TransferGraph {
+    edges: BTreeMap<(String, String), Edge>,
+    labels: HashMap<String, String>,
+}
+
+impl TransferGraph {
+    pub fn add(&mut self, transfer: &TransferEvent) -> Result<()> {
+        let value = U256::from_dec_str(&transfer.value)
+            .map_err(|_| anyhow::anyhow!("Invalid value of {}: {}", transfer.id, transfer.value))?;
+        let edge = self
+            .edges
+            .entry((transfer.from_address.clone(), transfer.to_address.clone()))
+            .or_default();
+        edge.transfers += 1;
+        edge.volume += value;
+        Ok(())
+    }
+
+    /// Every address in the graph, sorted.
+    pub fn nodes(&self) -> Vec<String> {
+        let mut nodes: Vec<String> = self.edges.keys().flat_map(|(from, to)| [from.clone(), to.clone()]).collect();
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (&str, &str, &Edge)> {
+        self.edges.iter().map(|((from, to), edge)| (from.as_str(), to.as_str(), edge))
+    }
+
+    /// Names shown on nodes, keyed by address.
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn write<W: Write>(&self, out: W, format: GraphFormat, amounts: &AmountFormat) -> Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(out, amounts),
+            GraphFormat::Graphml => self.write_graphml(out, amounts),
+        }
+    }
+
+    /// Edges carry the transfer count as `weight` and the total as their label.
+    fn write_dot<W: Write>(&self, mut out: W, amounts: &AmountFormat) -> Result<()> {
+        writeln!(out, "digraph transfers {{")?;
+        for node in self.nodes() {
+            let label = match self.labels.get(&node) {
+                Some(name) => format!("{}\\n{}", dot_escape(name), node),
+                None => node.clone(),
+            };
+            writeln!(out, "  \"{}\" [label=\"{}\"];", node, label)?;
+        }
+        for (from, to, edge) in self.edges() {
+            writeln!(
+                out,
+                "  \"{}\" -> \"{}\" [weight={}, label=\"{} ({} transfers)\", volume=\"{}\"];",
+                from,
+                to,
+                edge.transfers,
+                dot_escape(&amounts.format(&edge.volume.to_string())),
+                edge.transfers,
+                edge.volume
+            )?;
+        }
+        writeln!(out, "}}")?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Edges carry the volume in token units as `weight`, which Gephi uses for layout, plus the
+    /// transfer count and the exact raw volume.
+    fn write_graphml<W: Write>(&self, mut out: W, amounts: &AmountFormat) -> Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(out, r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#)?;
+        writeln!(out, r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#)?;
+        writeln!(out, r#"  <key id="transfers" for="edge" attr.name="transfers" attr.type="long"/>"#)?;
+        writeln!(out, r#"  <key id="volume" for="edge" attr.name="volume" attr.type="string"/>"#)?;
+        writeln!(out, r#"  <graph id="transfers" edgedefault="directed">"#)?;
+        for node in self.nodes() {
+            match self.labels.get(&node) {
+                Some(name) => writeln!(
+                    out,
+                    r#"    <node id="{}"><data key="label">{}</data></node>"#,
+                    node,
+                    xml_escape(name)
+                )?,
+                None => writeln!(out, r#"    <node id="{}"/>"#, node)?,
+            }
+        }
+        for (i, (from, to, edge)) in self.edges().enumerate() {
+            // Plain digits so the value parses as a double.
+            let weight = format_amount(edge.volume, amounts.decimals).replace(',', "");
+            writeln!(
+                out,
+                r#"    <edge id="e{}" source="{}" target="{}"><data key="weight">{}</data><data key="transfers">{}</data><data key="volume">{}</data></edge>"#,
+                i, from, to, weight, edge.transfers, edge.volume
+            )?;
+        }
+        writeln!(out, "  </graph>")?;
+        writeln!(out, "</graphml>")?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::TransferBuilder;
+
+    const TREASURY: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const EXCHANGE: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    fn transfer(block_number: i64, from: &str, to: &str, value: &str) -> TransferEvent {
+        TransferBuilder::new(block_number).from(from).to(to).value(value).build()
+    }
+
+    #[test]
+    fn merges_transfers_into_weighted_edges() {
+        let mut graph = TransferGraph::default();
+        for t in [
+            transfer(1, TREASURY, EXCHANGE, "1500000"),
+            transfer(2, TREASURY, EXCHANGE, "2500000"),
+            transfer(3, EXCHANGE, TREASURY, "1000000"),
+        ] {
+            graph.add(&t).unwrap();
+        }
+        let labels = [(EXCHANGE.to_string(), "Exchange \"A\" & Co".to_string())].into_iter().collect();
+        let graph = graph.with_labels(labels);
+        let amounts = AmountFormat {
+            decimals: 6,
+            symbol: "USDC".to_string(),
+            raw: false,
+        };
+
+        let mut dot = Vec::new();
+        graph.write(&mut dot, GraphFormat::Dot, &amounts).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [weight=2, label=\"4.00 USDC (2 transfers)\", volume=\"4000000\"];",
+            TREASURY, EXCHANGE
+        )));
+        assert!(dot.contains(&format!("[label=\"Exchange \\\"A\\\" & Co\\n{}\"]", EXCHANGE)));
+
+        let mut graphml = Vec::new();
+        graph.write(&mut graphml, GraphFormat::Graphml, &amounts).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert!(graphml.contains(&format!(
+            r#"<edge id="e0" source="{}" target="{}"><data key="weight">4.00</data><data key="transfers">2</data>"#,
+            TREASURY, EXCHANGE
+        )));
+        assert!(graphml.contains("Exchange &quot;A&quot; &amp; Co"));
+        assert_eq!(graphml.matches("<node ").count(), 2);
+    }
+}
@@ -0,0 +1,490 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, watch},
+};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{metadata::MetadataValue, Request, Response, Status};
+
+use crate::{
+    address::normalize_address,
+    api::{labeled_page, transfer_filter, ApiState, TransferParams, DEFAULT_LIMIT, MAX_LIMIT},
+    auth::{Caller, Rejection, Scope},
+    database::{is_interrupted, QueryBudget},
+    models::{HolderSnapshot, TransferEvent},
+    query::{Cursor, Direction, TransferFilter},
+    stats::{address_stats_report, collect_stats, AddressStatsReport, StatsReport},
+};
+
+/// Code generated from `proto/usdcwatch.proto`, including `IndexerClient` for Rust callers.
+pub mod proto {
+    tonic::include_proto!("usdcwatch.v1");
+}
+
+use proto::indexer_server::{Indexer, IndexerServer};
+
+/// How often `SubscribeTransfers` looks for newly committed blocks.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Transfers a subscription may have queued for a slow client before polling waits for it.
+const SUBSCRIPTION_BUFFER: usize = 1_024;
+
+/// The gRPC counterpart of the HTTP API, sharing its `ApiState`: the same tokens, rate limits and
+/// query budgets apply.
+#[derive(Clone)]
+pub struct GrpcService {
+    state: ApiState,
+    poll_interval: Duration,
+    /// Set on shutdown, which ends every subscription so the server can drain.
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+impl GrpcService {
+    pub fn new(state: ApiState) -> Self {
+        Self {
+            state,
+            poll_interval: POLL_INTERVAL,
+            shutdown: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// The caller of `request` if its `authorization` metadata grants `scope`.
+    async fn authorize<T>(&self, request: &Request<T>, scope: Scope) -> Result<Caller, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim());
+        let client = request.remote_addr().map(|addr| addr.ip());
+        match self.state.auth.admit(token, client, scope, self.state.rate_limiter.as_deref()).await {
+            Ok(caller) => Ok(caller),
+            Err(Rejection::Unauthorized(message)) => Err(Status::unauthenticated(message)),
+            Err(Rejection::Forbidden(scope)) => {
+                Err(Status::permission_denied(format!("Needs a token with {} scope", scope.as_str())))
+            }
+            Err(Rejection::RateLimited(wait)) => {
+                let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                let mut status = Status::resource_exhausted(format!("Rate limit exceeded; retry in {}s", secs));
+                status.metadata_mut().insert("retry-after", MetadataValue::from(secs));
+                Err(status)
+            }
+            Err(Rejection::Failed(e)) => Err(internal(e)),
+        }
+    }
+
+    /// Runs `work` under a fresh query budget, as the HTTP API does per request.
+    async fn run<T>(&self, work: impl Future<Output = Result<T>>) -> Result<T, Status> {
+        QueryBudget::new(self.state.query_timeout).run(work).await.map_err(internal)
+    }
+
+    /// The newest processed block, and the transfers matching `filter` in blocks after
+    /// `last_block` up to it, oldest first. Ranges are committed whole, so nothing more can
+    /// appear in those blocks afterwards.
+    async fn transfers_after(
+        &self,
+        filter: &TransferFilter,
+        last_block: i64,
+        to_block: Option<u64>,
+    ) -> Result<(i64, Vec<TransferEvent>), Status> {
+        let head = self.run(self.state.database.query_block_activity(None, None, 1)).await?;
+        let head = head.first().map_or(last_block, |block| block.block_number);
+        let head = to_block.map_or(head, |to_block| head.min(to_block as i64));
+        if head <= last_block {
+            return Ok((last_block, Vec::new()));
+        }
+
+        let mut filter = filter.clone().block_range(Some((last_block + 1) as u64), Some(head as u64));
+        let mut transfers = Vec::new();
+        loop {
+            let page = self.run(labeled_page(&self.state.database, &filter, MAX_LIMIT)).await?;
+            transfers.extend(page.transfers);
+            match page.next_cursor {
+                Some(cursor) => filter = filter.cursor(Some(Cursor::decode(&cursor).map_err(internal)?)),
+                None => break,
+            }
+        }
+        transfers.reverse();
+        Ok((head, transfers))
+    }
+}
+
+#[tonic::async_trait]
+impl Indexer for GrpcService {
+    async fn get_info(&self, request: Request<proto::GetInfoRequest>) -> Result<Response<proto::Info>, Status> {
+        self.authorize(&request, Scope::Read).await?;
+        let last_block = self.run(self.state.database.query_block_activity(None, None, 1)).await?;
+        Ok(Response::new(proto::Info {
+            contract_address: self.state.contract_address.clone(),
+            token_symbol: self.state.token_symbol.clone(),
+            token_decimals: self.state.token_decimals.into(),
+            last_processed_block: last_block.first().map(|block| block.block_number),
+        }))
+    }
+
+    async fn get_stats(&self, request: Request<proto::GetStatsRequest>) -> Result<Response<proto::Stats>, Status> {
+        self.authorize(&request, Scope::Read).await?;
+        let stats = self.run(collect_stats(&self.state.database, None)).await?;
+        Ok(Response::new(stats.into()))
+    }
+
+    async fn list_transfers(
+        &self,
+        request: Request<proto::ListTransfersRequest>,
+    ) -> Result<Response<proto::TransferPage>, Status> {
+        self.authorize(&request, Scope::Read).await?;
+        let request = request.into_inner();
+        let params = TransferParams {
+            cursor: request.cursor,
+            limit: request.limit,
+            ..transfer_params(request.filter)?
+        };
+        let filter = transfer_filter(&params, self.state.token_decimals, self.state.database.now()).map_err(invalid)?;
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let page = self.run(labeled_page(&self.state.database, &filter, limit)).await?;
+        Ok(Response::new(proto::TransferPage {
+            transfers: page.transfers.into_iter().map(proto::Transfer::from).collect(),
+            next_cursor: page.next_cursor,
+        }))
+    }
+
+    async fn get_address_stats(
+        &self,
+        request: Request<proto::GetAddressStatsRequest>,
+    ) -> Result<Response<proto::AddressStats>, Status> {
+        self.authorize(&request, Scope::Read).await?;
+        let address = normalize_address(&request.into_inner().address).map_err(invalid)?;
+        if self.run(self.state.database.get_address_stats(&address)).await?.is_none() {
+            return Err(Status::not_found(format!("No activity recorded for {}", address)));
+        }
+        let report = self.run(address_stats_report(&self.state.database, &address)).await?;
+        Ok(Response::new(report.into()))
+    }
+
+    async fn get_holders(
+        &self,
+        request: Request<proto::GetHoldersRequest>,
+    ) -> Result<Response<proto::HolderSnapshot>, Status> {
+        self.authorize(&request, Scope::Read).await?;
+        match self.run(self.state.database.holder_snapshot()).await? {
+            Some(snapshot) => Ok(Response::new(snapshot.into())),
+            None => Err(Status::not_found("No holder snapshot yet; the rollups have not run")),
+        }
+    }
+
+    type SubscribeTransfersStream = ReceiverStream<Result<proto::Transfer, Status>>;
+
+    /// Polls for newly committed blocks until the client goes away or the server shuts down. A
+    /// failed poll ends the stream with its error; resubscribe with `from_block` to resume.
+    async fn subscribe_transfers(
+        &self,
+        request: Request<proto::SubscribeTransfersRequest>,
+    ) -> Result<Response<Self::SubscribeTransfersStream>, Status> {
+        self.authorize(&request, Scope::Read).await?;
+        let params = transfer_params(request.into_inner().filter)?;
+        let filter = transfer_filter(&params, self.state.token_decimals, self.state.database.now()).map_err(invalid)?;
+        let mut last_block = match params.from_block {
+            Some(from_block) => from_block as i64 - 1,
+            None => {
+                let head = self.run(self.state.database.query_block_activity(None, None, 1)).await?;
+                head.first().map_or(0, |block| block.block_number)
+            }
+        };
+
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        let service = self.clone();
+        let mut shutdown = self.shutdown.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match service.transfers_after(&filter, last_block, params.to_block).await {
+                    Ok((head, transfers)) => {
+                        for transfer in transfers {
+                            if sender.send(Ok(transfer.into())).await.is_err() {
+                                return;
+                            }
+                        }
+                        last_block = head;
+                    }
+                    Err(status) => {
+                        let _ = sender.send(Err(status)).await;
+                        return;
+                    }
+                }
+                if params.to_block.is_some_and(|to_block| last_block >= to_block as i64) {
+                    return;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(service.poll_interval) => {}
+                    _ = sender.closed() => return,
+                    _ = shutdown.wait_for(|&stopping| stopping) => return,
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+/// Serves `service` on `listener` until Ctrl-C, then ends open subscriptions.
+pub async fn serve_grpc(listener: TcpListener, service: GrpcService) -> Result<()> {
+    let shutdown = service.shutdown.clone();
+    tonic::transport::Server::builder()
+        .add_service(IndexerServer::new(service))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+            let _ = tokio::signal::ctrl_c().await;
+            shutdown.send_replace(true);
+        })
+        .await?;
+    Ok(())
+}
+
+/// The HTTP API's parameters for a request's filter, which `transfer_filter` then validates.
+fn transfer_params(filter: Option<proto::TransferFilter>) -> Result<TransferParams, Status> {
+    let filter = filter.unwrap_or_default();
+    let direction = match proto::Direction::try_from(filter.direction) {
+        Ok(proto::Direction::Both) => Direction::Both,
+        Ok(proto::Direction::Sent) => Direction::Sent,
+        Ok(proto::Direction::Received) => Direction::Received,
+        Err(_) => return Err(Status::invalid_argument(format!("Unknown direction {}", filter.direction))),
+    };
+    Ok(TransferParams {
+        address: filter.address,
+        direction: Some(direction),
+        from_block: filter.from_block,
+        to_block: filter.to_block,
+        min_value: filter.min_value,
+        max_value: filter.max_value,
+        from_time: filter.from_time,
+        to_time: filter.to_time,
+        cursor: None,
+        limit: None,
+        archived: filter.archived,
+    })
+}
+
+fn invalid(error: anyhow::Error) -> Status {
+    Status::invalid_argument(error.to_string())
+}
+
+fn internal(error: anyhow::Error) -> Status {
+    if is_interrupted(&error) {
+        return Status::deadline_exceeded("Query ran past the server's time limit; narrow the filters");
+    }
+    tracing::error!(error = %format!("{:#}", error), "gRPC request failed");
+    Status::internal(error.to_string())
+}
+
+fn timestamp(time: DateTime<Utc>) -> Option<prost_types::Timestamp> {
+    Some(prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    })
+}
+
+impl From<TransferEvent> for proto::Transfer {
+    fn from(transfer: TransferEvent) -> Self {
+        Self {
+            id: transfer.id,
+            transaction_hash: transfer.transaction_hash,
+            log_index: transfer.log_index,
+            block_number: transfer.block_number,
+            block_hash: transfer.block_hash,
+            from_address: transfer.from_address,
+            to_address: transfer.to_address,
+            value: transfer.value,
+            timestamp: timestamp(transfer.timestamp),
+            from_label: transfer.from_label,
+            to_label: transfer.to_label,
+        }
+    }
+}
+
+impl From<StatsReport> for proto::Stats {
+    fn from(stats: StatsReport) -> Self {
+        Self {
+            total_transfers: stats.database.total_transfers,
+            unique_addresses: stats.database.unique_addresses,
+            earliest_block: stats.database.earliest_block,
+            latest_block: stats.database.latest_block,
+            total_volume: stats.total_volume,
+            transfers_last_7d: stats.transfers_last_7d,
+            transfers_per_day_7d: stats.transfers_per_day_7d,
+            transfers_last_30d: stats.transfers_last_30d,
+            transfers_per_day_30d: stats.transfers_per_day_30d,
+            minted: stats.minted,
+            burned: stats.burned,
+            database_bytes: stats.database_bytes,
+            last_processed_block: stats.last_processed_block,
+            lag_secs: stats.lag_secs,
+        }
+    }
+}
+
+impl From<AddressStatsReport> for proto::AddressStats {
+    fn from(report: AddressStatsReport) -> Self {
+        let activity = report.activity.as_ref();
+        Self {
+            address: report.address,
+            label: report.label,
+            transfers: report.transfers,
+            total_in: report.total_in,
+            total_out: report.total_out,
+            balance: report.balance,
+            first_block: activity.map(|a| a.first_block),
+            first_seen: activity.and_then(|a| timestamp(a.first_seen)),
+            last_block: activity.map(|a| a.last_block),
+            last_seen: activity.and_then(|a| timestamp(a.last_seen)),
+            holder_rank: report.holder_rank.map(|rank| rank as u64),
+            holders: report.holders as u64,
+        }
+    }
+}
+
+impl From<HolderSnapshot> for proto::HolderSnapshot {
+    fn from(snapshot: HolderSnapshot) -> Self {
+        Self {
+            block_number: snapshot.block_number,
+            timestamp: timestamp(snapshot.timestamp),
+            holders: snapshot.holders,
+            supply: snapshot.supply,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::{create_api_key, Authenticator},
+        database::{
+            testing::{database, TransferBuilder},
+            Database,
+        },
+    };
+    use proto::indexer_client::IndexerClient;
+    use tokio_stream::StreamExt;
+    use tonic::transport::Channel;
+
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const BOB: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    /// Stores a transfer and marks its block processed, as a committed range would.
+    async fn commit(database: &Database, block_number: i64, from: &str, to: &str, value: &str) {
+        let transfer = TransferBuilder::new(block_number).from(from).to(to).value(value).build();
+        database.insert_transfer_event(&transfer).await.unwrap();
+        database
+            .insert_processed_block(block_number as u64, &transfer.block_hash, transfer.timestamp)
+            .await
+            .unwrap();
+    }
+
+    async fn spawn(database: Database) -> IndexerClient<Channel> {
+        let state = ApiState {
+            auth: Authenticator::new(database.clone(), &[]),
+            rate_limiter: None,
+            database,
+            contract_address: ALICE.to_string(),
+            token_symbol: "USDC".to_string(),
+            token_decimals: 6,
+            query_timeout: None,
+        };
+        let service = GrpcService {
+            poll_interval: Duration::from_millis(20),
+            ..GrpcService::new(state)
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(IndexerServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        IndexerClient::connect(format!("http://{}", address)).await.unwrap()
+    }
+
+    fn filter(address: &str, direction: proto::Direction) -> Option<proto::TransferFilter> {
+        Some(proto::TransferFilter {
+            address: Some(address.to_string()),
+            direction: direction.into(),
+            ..proto::TransferFilter::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn answers_queries_with_the_http_api_filters() {
+        let database = database().await;
+        commit(&database, 1, ALICE, BOB, "5000000").await;
+        commit(&database, 2, BOB, ALICE, "1000000").await;
+        let mut client = spawn(database.clone()).await;
+
+        let info = client.get_info(proto::GetInfoRequest {}).await.unwrap().into_inner();
+        assert_eq!((info.token_decimals, info.last_processed_block), (6, Some(2)));
+        let request = proto::ListTransfersRequest {
+            filter: filter(BOB, proto::Direction::Received),
+            ..proto::ListTransfersRequest::default()
+        };
+        let page = client.list_transfers(request).await.unwrap().into_inner();
+        assert_eq!(page.transfers.iter().map(|t| t.block_number).collect::<Vec<_>>(), [1]);
+        assert!(page.transfers[0].timestamp.is_some());
+
+        let request = proto::GetAddressStatsRequest {
+            address: "0x123".to_string(),
+        };
+        assert_eq!(client.get_address_stats(request).await.unwrap_err().code(), tonic::Code::InvalidArgument);
+        let request = proto::GetAddressStatsRequest {
+            address: ALICE.to_string(),
+        };
+        assert_eq!(client.get_address_stats(request).await.unwrap_err().code(), tonic::Code::NotFound);
+
+        let token = create_api_key(&database, "grafana", Scope::Read).await.unwrap();
+        let status = client.get_info(proto::GetInfoRequest {}).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        let mut request = Request::new(proto::GetInfoRequest {});
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {}", token).parse().unwrap());
+        assert!(client.get_info(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn subscription_streams_newly_committed_matching_transfers() {
+        let database = database().await;
+        commit(&database, 1, ALICE, BOB, "5000000").await;
+        let mut client = spawn(database.clone()).await;
+
+        let request = proto::SubscribeTransfersRequest {
+            filter: filter(ALICE, proto::Direction::Sent),
+        };
+        let mut transfers = client.subscribe_transfers(request).await.unwrap().into_inner();
+        commit(&database, 2, BOB, ALICE, "1000000").await;
+        commit(&database, 3, ALICE, BOB, "2000000").await;
+        commit(&database, 4, ALICE, BOB, "3000000").await;
+
+        let mut blocks = Vec::new();
+        while blocks.len() < 2 {
+            let next = tokio::time::timeout(Duration::from_secs(5), transfers.next()).await.unwrap();
+            blocks.push(next.unwrap().unwrap().block_number);
+        }
+        assert_eq!(blocks, [3, 4]);
+
+        // A bounded replay ends once it reaches `to_block`.
+        let request = proto::SubscribeTransfersRequest {
+            filter: Some(proto::TransferFilter {
+                from_block: Some(1),
+                to_block: Some(3),
+                ..proto::TransferFilter::default()
+            }),
+        };
+        let replayed: Vec<i64> = client
+            .subscribe_transfers(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|transfer| transfer.unwrap().block_number)
+            .collect()
+            .await;
+        assert_eq!(replayed, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::models::{ProcessedBlock, TransferEvent};
+
+/// Custom processing attached to an [`Indexer`](crate::Indexer) with `with_handler`.
+///
+/// Handlers run after each block range is committed to the database, so they only see
+/// deduplicated transfers that are already durable. Within a range, each block's new transfers
+/// are delivered in log order, followed by the block itself. A handler error is logged and does
+/// not stop indexing or the other handlers.
+#[async_trait]
+pub trait EventHandler: Send + Sync {
+    /// A finalized transfer that is newly stored, or that replaced a stored payload from a less
+    /// trusted source.
+    async fn on_transfer(&self, _event: &TransferEvent) -> Result<()> {
+        Ok(())
+    }
+
+    /// Stored data from `fork_block` on was reorged out and has been deleted; it is indexed
+    /// again, and delivered again, from `fork_block`.
+    async fn on_reorg(&self, _fork_block: u64, _chain_head: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// A finalized block whose transfers have all been delivered.
+    async fn on_block(&self, _block: &ProcessedBlock) -> Result<()> {
+        Ok(())
+    }
+}
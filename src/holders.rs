@@ -0,0 +1,266 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers_core::types::U256;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{
+    address::ZERO_ADDRESS,
+    database::Database,
+    models::{HolderBucket, HolderSnapshot},
+    query::TransferFilter,
+};
+
+/// Default `holder-distribution` bucket boundaries, in token units.
+pub const DEFAULT_BUCKETS: [&str; 3] = ["100", "10000", "1000000"];
+
+/// Default `concentration` top holder counts.
+pub const DEFAULT_TOPS: [usize; 3] = [10, 100, 1000];
+
+/// How unevenly the holders' combined balance is spread.
+#[derive(Debug, Serialize)]
+pub struct Concentration {
+    pub holders: usize,
+    /// Raw token units held by all holders together; shares are relative to it.
+    pub total_balance: String,
+    /// 0 when every holder holds the same, approaching 1 when one holds everything.
+    pub gini: f64,
+    pub top: Vec<TopShare>,
+}
+
+/// What the largest `holders` hold together.
+#[derive(Debug, Serialize)]
+pub struct TopShare {
+    pub holders: usize,
+    pub balance: String,
+    /// Percent of `total_balance`.
+    pub share: f64,
+}
+
+/// Records the current holder count unless a snapshot was already taken in its `every_secs`
+/// period. Returns the new snapshot, if any.
+pub async fn record_holder_snapshot(database: &Database, every_secs: u64) -> Result<Option<HolderSnapshot>> {
+    let Some(snapshot) = database.holder_snapshot().await? else {
+        return Ok(None);
+    };
+    if let Some(latest) = database.latest_holder_snapshot().await? {
+        if period(latest.timestamp, every_secs) >= period(snapshot.timestamp, every_secs) {
+            return Ok(None);
+        }
+    }
+    database.insert_holder_snapshot(&snapshot).await?;
+    Ok(Some(snapshot))
+}
+
+/// Rebuilds `holder_snapshots` by replaying every stored transfer, archived ones included, taking
+/// one snapshot at the end of each `every_secs` period that saw transfers. Returns how many were
+/// written.
+pub async fn backfill_holder_snapshots(database: &Database, every_secs: u64) -> Result<usize> {
+    let mut balances = Balances::default();
+    let mut snapshots = Vec::new();
+    let mut last: Option<(i64, DateTime<Utc>)> = None;
+
+    let filter = TransferFilter::new().include_archived(true);
+    database
+        .for_each_transfer(&filter, |transfer| {
+            if let Some((block_number, timestamp)) = last {
+                if period(transfer.timestamp, every_secs) != period(timestamp, every_secs) {
+                    snapshots.push(balances.snapshot(block_number, timestamp));
+                }
+            }
+            balances.apply(&transfer.from_address, &transfer.to_address, U256::from_dec_str(&transfer.value)?);
+            last = Some((transfer.block_number, transfer.timestamp));
+            Ok(())
+        })
+        .await?;
+    if let Some((block_number, timestamp)) = last {
+        snapshots.push(balances.snapshot(block_number, timestamp));
+    }
+
+    database.replace_holder_snapshots(&snapshots).await?;
+    Ok(snapshots.len())
+}
+
+/// Sorts balances into buckets split at `bounds` (raw units), lowest first. Empty buckets are kept
+/// so the ranges always line up.
+pub fn holder_distribution(balances: &[(String, U256)], bounds: &[U256]) -> Vec<HolderBucket> {
+    let mut bounds: Vec<U256> = bounds.iter().copied().filter(|bound| !bound.is_zero()).collect();
+    bounds.sort();
+    bounds.dedup();
+
+    let mut buckets = vec![(0i64, U256::zero()); bounds.len() + 1];
+    let mut total = U256::zero();
+    for (_, balance) in balances {
+        let bucket = &mut buckets[bounds.partition_point(|bound| bound <= balance)];
+        bucket.0 += 1;
+        bucket.1 += *balance;
+        total += *balance;
+    }
+
+    std::iter::once(U256::zero())
+        .chain(bounds.iter().copied())
+        .zip(buckets)
+        .enumerate()
+        .map(|(i, (min, (holders, balance)))| HolderBucket {
+            min: min.to_string(),
+            max: bounds.get(i).map(|max| max.to_string()),
+            holders,
+            balance: balance.to_string(),
+            share: if total.is_zero() { 0.0 } else { to_f64(balance) * 100.0 / to_f64(total) },
+        })
+        .collect()
+}
+
+/// The Gini coefficient of `balances` and the share held by each of the `tops` largest holders.
+pub fn concentration(balances: &[(String, U256)], tops: &[usize]) -> Concentration {
+    let mut sorted: Vec<U256> = balances.iter().map(|(_, balance)| *balance).collect();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    let total = sorted.iter().fold(U256::zero(), |sum, balance| sum + balance);
+
+    // Over holders sorted largest first: G = (n + 1 - 2 * sum(i * x_i) / sum(x)) / n, i from 1.
+    let n = sorted.len() as f64;
+    let weighted: f64 = sorted.iter().enumerate().map(|(i, balance)| (i + 1) as f64 * to_f64(*balance)).sum();
+    let gini = if total.is_zero() { 0.0 } else { ((n + 1.0 - 2.0 * weighted / to_f64(total)) / n).max(0.0) };
+
+    let top = tops
+        .iter()
+        .map(|&holders| {
+            let balance = sorted.iter().take(holders).fold(U256::zero(), |sum, balance| sum + balance);
+            TopShare {
+                holders,
+                balance: balance.to_string(),
+                share: if total.is_zero() { 0.0 } else { to_f64(balance) * 100.0 / to_f64(total) },
+            }
+        })
+        .collect();
+
+    Concentration {
+        holders: sorted.len(),
+        total_balance: total.to_string(),
+        gini,
+        top,
+    }
+}
+
+fn to_f64(value: U256) -> f64 {
+    value.to_string().parse().unwrap_or(f64::MAX)
+}
+
+fn period(timestamp: DateTime<Utc>, every_secs: u64) -> i64 {
+    timestamp.timestamp().div_euclid(every_secs.max(1) as i64)
+}
+
+/// Running totals per lowercased address, and how many of them are unequal.
+#[derive(Default)]
+struct Balances {
+    totals: HashMap<String, (U256, U256)>,
+    holders: i64,
+}
+
+impl Balances {
+    fn apply(&mut self, from: &str, to: &str, value: U256) {
+        self.update(from, |(_, sent)| *sent += value);
+        self.update(to, |(received, _)| *received += value);
+    }
+
+    fn update(&mut self, address: &str, change: impl FnOnce(&mut (U256, U256))) {
+        let address = address.to_lowercase();
+        let counted = address != ZERO_ADDRESS;
+        let totals = self.totals.entry(address).or_default();
+        let held = totals.0 != totals.1;
+        change(totals);
+        if counted {
+            self.holders += (totals.0 != totals.1) as i64 - held as i64;
+        }
+    }
+
+    fn snapshot(&self, block_number: i64, timestamp: DateTime<Utc>) -> HolderSnapshot {
+        let (burned, minted) = self.totals.get(ZERO_ADDRESS).copied().unwrap_or_default();
+        HolderSnapshot {
+            block_number,
+            timestamp,
+            holders: self.holders,
+            supply: minted.saturating_sub(burned).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::{database, TransferBuilder};
+
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const BOB: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    async fn insert(database: &Database, block_number: i64, time: &str, from: &str, to: &str, value: &str) {
+        let transfer = TransferBuilder::new(block_number)
+            .from(from)
+            .to(to)
+            .value(value)
+            .at(time.parse().unwrap())
+            .build();
+        database.insert_transfer_event(&transfer).await.unwrap();
+    }
+
+    #[test]
+    fn distribution_splits_at_bounds() {
+        let balances: Vec<(String, U256)> = [5u64, 99, 100, 2_500, 1_000_000]
+            .into_iter()
+            .enumerate()
+            .map(|(i, balance)| (i.to_string(), U256::from(balance)))
+            .collect();
+        let buckets = holder_distribution(&balances, &[U256::from(10_000), U256::from(100)]);
+        let rows: Vec<_> = buckets
+            .iter()
+            .map(|b| (b.min.as_str(), b.max.as_deref(), b.holders, b.balance.as_str()))
+            .collect();
+        assert_eq!(
+            rows,
+            [("0", Some("100"), 2, "104"), ("100", Some("10000"), 2, "2600"), ("10000", None, 1, "1000000")]
+        );
+        assert!((buckets[2].share - 99.73).abs() < 0.01);
+    }
+
+    #[test]
+    fn concentration_measures_inequality() {
+        let balances = |values: &[u64]| -> Vec<(String, U256)> {
+            values.iter().map(|v| (v.to_string(), U256::from(*v))).collect()
+        };
+
+        let equal = concentration(&balances(&[50, 50, 50, 50]), &[1]);
+        assert!(equal.gini.abs() < 1e-9);
+        assert_eq!((equal.top[0].balance.as_str(), equal.top[0].share), ("50", 25.0));
+
+        let skewed = concentration(&balances(&[1, 1, 1, 97]), &[1, 10]);
+        assert!((skewed.gini - 0.72).abs() < 1e-9);
+        assert_eq!(skewed.top[0].share, 97.0);
+        assert_eq!(skewed.top[1].share, 100.0);
+        assert_eq!(concentration(&[], &[10]).gini, 0.0);
+    }
+
+    #[tokio::test]
+    async fn backfill_replays_balances_per_period() {
+        let database = database().await;
+        insert(&database, 1, "2026-01-01T10:00:00Z", ZERO_ADDRESS, ALICE, "500").await;
+        insert(&database, 2, "2026-01-01T11:00:00Z", ALICE, BOB, "200").await;
+        insert(&database, 3, "2026-01-02T09:00:00Z", BOB, &ALICE.to_lowercase(), "200").await;
+        insert(&database, 4, "2026-01-03T09:00:00Z", ALICE, ZERO_ADDRESS, "100").await;
+
+        assert_eq!(backfill_holder_snapshots(&database, 86_400).await.unwrap(), 3);
+        let snapshots: Vec<_> = database
+            .get_holder_snapshots(None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.block_number, s.holders, s.supply))
+            .collect();
+        assert_eq!(
+            snapshots,
+            [(2, 2, "500".to_string()), (3, 1, "500".to_string()), (4, 1, "400".to_string())]
+        );
+
+        let from = "2026-01-02T00:00:00Z".parse().unwrap();
+        assert_eq!(database.get_holder_snapshots(Some(from), None).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use ethers_core::types::{H256, U256};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{address::normalize_address, dedup::IngestOutcome, models::TransferEvent, output::ExportFormat};
+
+/// Rows handed to the database per ingest call.
+pub const IMPORT_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub rows: u64,
+    pub inserted: u64,
+    pub duplicates: u64,
+    pub replaced: u64,
+    pub kept_existing: u64,
+    pub last_block: Option<i64>,
+}
+
+impl ImportSummary {
+    pub fn record(&mut self, events: &[TransferEvent], outcomes: &[IngestOutcome]) {
+        self.last_block = events.iter().map(|e| e.block_number).chain(self.last_block).max();
+        for outcome in outcomes {
+            self.rows += 1;
+            match outcome {
+                IngestOutcome::Inserted => self.inserted += 1,
+                IngestOutcome::Duplicate => self.duplicates += 1,
+                IngestOutcome::Replaced => self.replaced += 1,
+                IngestOutcome::KeptExisting => self.kept_existing += 1,
+            }
+        }
+    }
+}
+
+/// Guesses the format from the file extension, defaulting to JSON lines.
+pub fn detect_format(path: &Path) -> ExportFormat {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::Csv,
+        _ => ExportFormat::Jsonl,
+    }
+}
+
+/// Lazily reads transfers written by `export`, validating and normalizing each row.
+/// Errors name the offending line so a bad file can be fixed and re-imported.
+pub fn read_transfers(path: &Path, format: ExportFormat) -> Result<Box<dyn Iterator<Item = Result<TransferEvent>>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    Ok(match format {
+        ExportFormat::Csv => {
+            let reader = csv::Reader::from_reader(BufReader::new(file));
+            // Line 1 is the header row.
+            Box::new(reader.into_deserialize::<TransferEvent>().enumerate().map(|(i, row)| {
+                row.map_err(anyhow::Error::from)
+                    .and_then(normalize)
+                    .with_context(|| format!("Line {}", i + 2))
+            }))
+        }
+        ExportFormat::Jsonl => Box::new(
+            BufReader::new(file)
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+                .map(|(i, line)| {
+                    line.map_err(anyhow::Error::from)
+                        .and_then(|line| Ok(serde_json::from_str::<TransferEvent>(&line)?))
+                        .and_then(normalize)
+                        .with_context(|| format!("Line {}", i + 1))
+                }),
+        ),
+    })
+}
+
+/// Checks field formats and rewrites them the way the indexer stores them.
+fn normalize(mut event: TransferEvent) -> Result<TransferEvent> {
+    let transaction_hash = H256::from_str(&event.transaction_hash).context("transaction_hash")?;
+    let block_hash = H256::from_str(&event.block_hash).context("block_hash")?;
+    let from_address = normalize_address(&event.from_address).context("from_address")?;
+    let to_address = normalize_address(&event.to_address).context("to_address")?;
+    let value = U256::from_dec_str(&event.value).map_err(|e| anyhow::anyhow!("value: {}", e))?;
+    if event.log_index < 0 || event.block_number < 0 {
+        return Err(anyhow::anyhow!("block_number and log_index must be non-negative"));
+    }
+
+    event.id = format!("0x{:x}_{}", transaction_hash, event.log_index);
+    event.transaction_hash = format!("0x{:x}", transaction_hash);
+    event.block_hash = format!("0x{:x}", block_hash);
+    event.from_address = from_address;
+    event.to_address = to_address;
+    event.value = value.to_string();
+    Ok(event)
+}
+
+/// What `label import` does with an address that already has a label.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LabelConflict {
+    #[default]
+    Skip,
+    Overwrite,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LabelImportSummary {
+    pub rows: u64,
+    pub inserted: u64,
+    pub overwritten: u64,
+    pub skipped: u64,
+}
+
+/// Well-known exchange, bridge and issuer addresses loaded by `label import-known`.
+const KNOWN_ENTITIES: &str = include_str!("../data/known_entities.csv");
+
+/// One row of a label CSV.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LabelRow {
+    pub address: String,
+    pub name: String,
+    /// Kind of entity, e.g. `exchange` or `bridge`; the column is optional.
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+/// Reads `address,name[,category]` rows from a CSV file with a header row. Extra columns are
+/// ignored, so published label datasets can be loaded as-is.
+pub fn read_labels(path: &Path) -> Result<Vec<LabelRow>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    parse_labels(BufReader::new(file))
+}
+
+/// The bundled dataset of well-known addresses.
+pub fn known_entity_labels() -> Result<Vec<LabelRow>> {
+    parse_labels(KNOWN_ENTITIES.as_bytes())
+}
+
+fn parse_labels<R: Read>(reader: R) -> Result<Vec<LabelRow>> {
+    let reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+
+    reader
+        .into_deserialize::<LabelRow>()
+        .enumerate()
+        .map(|(i, row)| {
+            row.map_err(anyhow::Error::from)
+                .and_then(|row| {
+                    Ok(LabelRow {
+                        address: normalize_address(&row.address)?,
+                        name: row.name,
+                        category: row.category.filter(|c| !c.is_empty()).map(|c| c.to_lowercase()),
+                    })
+                })
+                .with_context(|| format!("Line {}", i + 2))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn known_entities_are_checksummed_and_categorized() {
+        let labels = known_entity_labels().unwrap();
+        assert!(!labels.is_empty());
+
+        let addresses: HashSet<_> = labels.iter().map(|l| l.address.to_lowercase()).collect();
+        assert_eq!(addresses.len(), labels.len());
+        for label in &labels {
+            assert!(matches!(label.category.as_deref(), Some("exchange" | "bridge" | "issuer")));
+        }
+    }
+}
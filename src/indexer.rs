@@ -1,34 +1,122 @@
 use anyhow::Result;
-use log::{error, info, warn};
-use std::time::Duration;
-use tokio::time::sleep;
+use chrono::{DateTime, Utc};
+use tracing::{error, info, instrument, warn};
+use std::{
+    collections::{BTreeSet, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast, watch},
+    time::sleep,
+};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
 
 use crate::{
+    alerts::{Alert, FreshnessMonitor, LagMonitor, RuleMonitor},
+    anomaly::{detect_anomalies, AnomalySettings},
+    clickhouse::ClickHouseWriter,
     config::Config,
     database::Database,
-    ethereum::EthereumClient,
+    dedup::{EventSource, IngestOutcome},
+    ethereum::{decode_erc1155_logs, decode_transfer_logs, EthereumClient},
+    handler::EventHandler,
+    holders::record_holder_snapshot,
+    models::{AddressInfo, BlockGap, Erc1155Transfer, ProcessedBlock, RawLog, TransferEvent},
+    notify::{AlertQueue, Notifier},
+    price::{price_block, usd_value},
+    redis_stream::RedisPublisher,
+    sanctions::refresh_sanctions_if_due,
+    systemd,
 };
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaPublisher;
+
+/// RPC calls made at once when enriching transactions or classifying addresses.
+const ENRICHMENT_CONCURRENCY: usize = 8;
 
+/// Committed events buffered per subscriber before slow consumers start missing events.
+const TRANSFER_CHANNEL_CAPACITY: usize = 1024;
+
+/// What the first block range of [`Indexer::start_indexing`] would store, from
+/// [`Indexer::dry_run`].
+#[derive(Debug)]
+pub struct DryRun {
+    pub chain_id: u64,
+    pub start_block: u64,
+    pub end_block: u64,
+    pub transfers: Vec<TransferEvent>,
+    pub erc1155_transfers: Vec<Erc1155Transfer>,
+}
+
+/// Follows the chain block range by block, storing finalized transfers in the [`Database`] and
+/// feeding alerts, publishers and [`Indexer::transfer_stream`] subscribers as they are committed.
 pub struct Indexer {
     ethereum_client: EthereumClient,
     database: Database,
     config: Config,
+    freshness: FreshnessMonitor,
+    lag: LagMonitor,
+    rules: RuleMonitor,
+    alerts: AlertQueue,
+    transfers: broadcast::Sender<TransferEvent>,
+    #[cfg(feature = "kafka")]
+    kafka: Option<KafkaPublisher>,
+    redis: Option<RedisPublisher>,
+    clickhouse: Option<ClickHouseWriter>,
+    handlers: Vec<Arc<dyn EventHandler>>,
+    /// Set by [`Indexer::stop`]; `start_indexing` returns once the range in progress is done.
+    stop: watch::Sender<bool>,
 }
 
 impl Indexer {
+    /// Connects the RPC client and alert sinks described by `config`. Fails when a configured
+    /// sink or publisher is invalid.
     pub async fn new(config: Config, database: Database) -> Result<Self> {
-        let ethereum_client = EthereumClient::new(
-            &config.ethereum_rpc_url,
-            &config.usdc_contract_address,
-        ).await?;
+        let ethereum_client = EthereumClient::from_config(&config)?;
+        Self::with_client(config, database, ethereum_client).await
+    }
+
+    /// Like [`Indexer::new`], reading the chain through `ethereum_client`, such as one over a
+    /// [`MockEthereumClient`](crate::rpc::MockEthereumClient).
+    pub async fn with_client(config: Config, database: Database, ethereum_client: EthereumClient) -> Result<Self> {
+        let ethereum_client = ethereum_client.with_clock(database.clock());
+
+        let alerts = AlertQueue::spawn(Notifier::from_config(&config)?.with_digest(database.clone()));
+
+        #[cfg(feature = "kafka")]
+        let kafka = KafkaPublisher::from_config(&config)?;
+        let redis = RedisPublisher::from_config(&config)?;
+        let clickhouse = ClickHouseWriter::from_config(&config)?;
+        #[cfg(not(feature = "kafka"))]
+        if config.kafka_brokers.is_some() {
+            warn!("KAFKA_BROKERS is set but this build lacks the kafka feature; nothing will be published");
+        }
 
         Ok(Self {
             ethereum_client,
             database,
             config,
+            freshness: FreshnessMonitor::default(),
+            lag: LagMonitor::default(),
+            rules: RuleMonitor::default(),
+            alerts,
+            transfers: broadcast::channel(TRANSFER_CHANNEL_CAPACITY).0,
+            #[cfg(feature = "kafka")]
+            kafka,
+            redis,
+            clickhouse,
+            handlers: Vec::new(),
+            stop: watch::channel(false).0,
         })
     }
 
+    /// Adds a handler called for every committed transfer, committed block and reorg.
+    pub fn with_handler(mut self, handler: Arc<dyn EventHandler>) -> Self {
+        self.handlers.push(handler);
+        self
+    }
+
     pub fn get_ethereum_client(&self) -> &EthereumClient {
         &self.ethereum_client
     }
@@ -37,46 +125,337 @@ impl Indexer {
         &self.config
     }
 
+    /// Stream of transfers as they are committed to the database. A consumer that falls more than
+    /// `TRANSFER_CHANNEL_CAPACITY` events behind skips the events it missed.
+    pub fn transfer_stream(&self) -> impl Stream<Item = TransferEvent> {
+        BroadcastStream::new(self.transfers.subscribe()).filter_map(|event| event.ok())
+    }
+
+    /// Makes `start_indexing` return after committing the range in progress, if any.
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    /// Indexes from `start_block`, or from where the database left off, and keeps following the
+    /// chain. Returns on a fatal error, such as the database belonging to another chain, or once
+    /// [`Indexer::stop`] is called.
+    ///
+    /// Under systemd, it reports `READY=1` once the first range is processed (or found to be
+    /// waiting for finality) and pings the watchdog after every range, failed ones included.
     pub async fn start_indexing(&self, start_block: Option<u64>) -> Result<()> {
+        self.verify_identity().await?;
+        self.ensure_token_metadata().await;
+
         let mut current_block = match start_block {
             Some(block) => block,
-            None => {
-                match self.database.get_latest_processed_block().await? {
-                    Some(latest) => latest + 1,
-                    None => {
-                        let latest = self.ethereum_client.get_latest_block_number().await?;
-                        latest.saturating_sub(1000)
-                    }
+            None => self.resume_block(self.chain_id().await?).await?,
+        };
+
+        self.register_tokens(current_block).await?;
+
+        if self.config.watchlist_only {
+            match self.database.watched_addresses().await?.len() {
+                0 => warn!("WATCHLIST_ONLY is on but the watchlist is empty; no transfers will be stored"),
+                n => info!(addresses = n, "Indexing transfers of watched addresses"),
+            }
+        }
+
+        #[cfg(feature = "kafka")]
+        if let Some(kafka) = &self.kafka {
+            kafka.start(&self.database, current_block).await?;
+        }
+        if let Some(redis) = &self.redis {
+            redis.start(&self.database, current_block).await?;
+        }
+        if let Some(clickhouse) = &self.clickhouse {
+            clickhouse.start(&self.database, current_block).await?;
+        }
+
+        info!(block_number = current_block, "Starting indexer");
+
+        let mut stop = self.stop.subscribe();
+        let mut ready = false;
+        while !*stop.borrow_and_update() {
+            let result = self.process_blocks(current_block).await;
+            if !ready && result.is_ok() {
+                systemd::notify_ready();
+                ready = true;
+            }
+            let pause = match result {
+                Ok(next_block) if next_block > current_block => {
+                    info!(blocks = next_block - current_block, block_number = next_block, "Processed blocks");
+                    current_block = next_block;
+                    None
+                }
+                Ok(next_block) if next_block < current_block => {
+                    warn!(block_number = next_block, "Re-indexing after a reorg");
+                    current_block = next_block;
+                    None
+                }
+                Ok(_) => Some(Duration::from_secs(self.config.poll_interval_secs)),
+                Err(e) => {
+                    error!(block_number = current_block, error = %e, "Error processing blocks");
+                    Some(Duration::from_secs(30))
+                }
+            };
+            systemd::notify_alive(&format!("Next block {}", current_block));
+            if let Some(pause) = pause {
+                tokio::select! {
+                    _ = sleep(pause) => {}
+                    _ = stop.changed() => {}
                 }
             }
+        }
+        info!(block_number = current_block, "Stopped indexer");
+        Ok(())
+    }
+
+    /// Fetches and decodes the range `start_indexing` would process first, from `start_block` or
+    /// where it would start on its own, without writing anything to the database. Fails like
+    /// `start_indexing` on a node serving another chain than `CHAIN_ID`, or when the range is not
+    /// finalized yet.
+    pub async fn dry_run(&self, start_block: Option<u64>) -> Result<DryRun> {
+        let chain_id = self.ethereum_client.get_chain_id().await?;
+        self.check_chain_id(chain_id)?;
+        let start_block = match start_block {
+            Some(block) => block,
+            None => self.resume_block(chain_id).await?,
         };
 
-        info!("Starting indexer from block {}", current_block);
+        let latest_block = self.ethereum_client.get_latest_block_number().await?;
+        let finalized_block = latest_block.saturating_sub(self.config.finality_blocks);
+        if start_block > finalized_block {
+            return Err(anyhow::anyhow!(
+                "Block {} is not finalized yet; the latest finalized block is {}",
+                start_block,
+                finalized_block
+            ));
+        }
+        let end_block = std::cmp::min(start_block + self.config.blocks_per_request - 1, finalized_block);
 
-        loop {
-            match self.process_blocks(current_block).await {
-                Ok(processed_count) => {
-                    if processed_count > 0 {
-                        current_block += processed_count;
-                        info!("Processed {} blocks, current block: {}", processed_count, current_block);
-                    } else {
-                        sleep(Duration::from_secs(12)).await;
-                    }
+        let transfers = self.fetch_transfer_events(start_block, end_block).await?;
+        let erc1155_logs = self
+            .fetch_erc1155_logs(start_block, end_block, &self.config.erc1155_contracts)
+            .await?;
+        Ok(DryRun {
+            chain_id,
+            start_block,
+            end_block,
+            transfers,
+            erc1155_transfers: decode_erc1155_logs(&erc1155_logs, self.database.now())?,
+        })
+    }
+
+    /// Refuses to ingest from a node on another chain than `CHAIN_ID`. Then records the chain,
+    /// contract and provider this database is indexed from on first run, and refuses to ingest when
+    /// the running configuration points at a different chain or token.
+    pub async fn verify_identity(&self) -> Result<()> {
+        let chain_id = self.ethereum_client.get_chain_id().await?;
+        self.check_chain_id(chain_id)?;
+        let chain_id = chain_id.to_string();
+        let contract_address = self.config.usdc_contract_address.to_lowercase();
+        let provider_fingerprint = hex::encode(&ethers::utils::keccak256(self.config.ethereum_rpc_url.as_bytes())[..8]);
+
+        for (key, current) in [("chain_id", &chain_id), ("contract_address", &contract_address)] {
+            match self.database.get_meta(key).await? {
+                Some(recorded) if &recorded != current => {
+                    return Err(anyhow::anyhow!(
+                        "Database was indexed with {} {} but the configuration uses {}",
+                        key,
+                        recorded,
+                        current
+                    ));
                 }
-                Err(e) => {
-                    error!("Error processing blocks: {}", e);
-                    sleep(Duration::from_secs(30)).await;
+                Some(_) => {}
+                None => self.database.set_meta(key, current).await?,
+            }
+        }
+
+        match self.database.get_meta("provider_fingerprint").await? {
+            Some(recorded) if recorded == provider_fingerprint => {}
+            Some(recorded) => {
+                warn!(%recorded, current = %provider_fingerprint, "RPC provider changed since last run");
+                self.database.set_meta("provider_fingerprint", &provider_fingerprint).await?;
+            }
+            None => self.database.set_meta("provider_fingerprint", &provider_fingerprint).await?,
+        }
+
+        Ok(())
+    }
+
+    fn check_chain_id(&self, chain_id: u64) -> Result<()> {
+        match self.config.chain_id {
+            Some(expected) if expected != chain_id => Err(anyhow::anyhow!(
+                "ETHEREUM_RPC_URL serves chain {} but CHAIN_ID is {}; point it at the right network, or set CHAIN_ID",
+                chain_id,
+                expected
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The block after the last processed one, or where a fresh database starts.
+    async fn resume_block(&self, chain_id: u64) -> Result<u64> {
+        match self.database.get_latest_processed_block().await? {
+            Some(latest) => Ok(latest + 1),
+            None => self.first_block(chain_id).await,
+        }
+    }
+
+    /// Where a fresh database starts: `DEPLOY_BLOCK`, or the block the contract was deployed in.
+    /// Falls back to the last 1000 blocks when the node cannot tell, such as a node without
+    /// historical state.
+    async fn first_block(&self, chain_id: u64) -> Result<u64> {
+        if let Some(block) = self.config.deploy_block {
+            return Ok(block);
+        }
+        let latest = self.ethereum_client.get_latest_block_number().await?;
+        match self.ethereum_client.get_deployment_block(chain_id, latest).await {
+            Ok(Some(block)) => {
+                info!(block_number = block, "Starting from the contract's deployment block");
+                return Ok(block);
+            }
+            Ok(None) => warn!("Contract has no code at the chain head; is the address right?"),
+            Err(e) => warn!(error = %e, "Failed to find the contract's deployment block; set DEPLOY_BLOCK"),
+        }
+        Ok(latest.saturating_sub(1000))
+    }
+
+    /// Fetches and stores the contract's metadata on first run. Not fatal: some tokens do not
+    /// implement the optional `name()`/`symbol()` getters.
+    async fn ensure_token_metadata(&self) {
+        match self.database.get_token(&self.config.usdc_contract_address).await {
+            Ok(Some(_)) => return,
+            Ok(None) => {}
+            Err(e) => {
+                warn!(error = %e, "Failed to read token metadata");
+                return;
+            }
+        }
+
+        match self.ethereum_client.get_token_metadata().await {
+            Ok(token) => {
+                info!(name = %token.name, symbol = %token.symbol, decimals = token.decimals, "Token metadata");
+                if let Err(e) = self.database.set_token(&token).await {
+                    warn!(error = %e, "Failed to store token metadata");
                 }
             }
+            Err(e) => warn!(error = %e, "Failed to fetch token metadata"),
+        }
+    }
+
+    /// Records a checkpoint for the token and every ERC-1155 contract on first sight. A contract
+    /// added to a database that already has history starts at its earliest block and is
+    /// backfilled on its own by `backfill_tokens`; tokens already registered keep their progress.
+    async fn register_tokens(&self, start_block: u64) -> Result<()> {
+        let chain_id = self.chain_id().await?;
+        let earliest = self.database.get_earliest_processed_block().await?.unwrap_or(start_block);
+
+        self.database
+            .register_token(chain_id, &self.config.usdc_contract_address, "erc20", earliest, start_block)
+            .await?;
+
+        for contract in &self.config.erc1155_contracts {
+            let checkpoint = self
+                .database
+                .register_token(chain_id, contract, "erc1155", earliest, earliest)
+                .await?;
+            if checkpoint.next_block < start_block {
+                info!(%contract, block_number = checkpoint.next_block, "Backfilling ERC-1155 contract");
+            }
+
+            match self.database.get_token(contract).await {
+                Ok(Some(_)) => {}
+                Ok(None) => match self.ethereum_client.get_erc1155_metadata(contract).await {
+                    Ok(token) => {
+                        if let Err(e) = self.database.set_token(&token).await {
+                            warn!(%contract, error = %e, "Failed to store token metadata");
+                        }
+                    }
+                    Err(e) => warn!(%contract, error = %e, "Failed to fetch token metadata"),
+                },
+                Err(e) => warn!(%contract, error = %e, "Failed to read token metadata"),
+            }
         }
+        Ok(())
     }
 
+    /// Chain id recorded by `verify_identity`.
+    async fn chain_id(&self) -> Result<u64> {
+        let chain_id = self
+            .database
+            .get_meta("chain_id")
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Chain id has not been recorded yet"))?;
+        chain_id
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid chain_id in meta: {}", chain_id))
+    }
+
+    /// Indexes one range of every ERC-1155 contract that is behind the processed blocks, without
+    /// touching the other tokens.
+    async fn backfill_tokens(&self) -> Result<()> {
+        if self.config.erc1155_contracts.is_empty() {
+            return Ok(());
+        }
+        let Some(latest) = self.database.get_latest_processed_block().await? else {
+            return Ok(());
+        };
+        let chain_id = self.chain_id().await?;
+
+        for checkpoint in self.database.get_token_checkpoints(Some(chain_id)).await? {
+            let configured = self
+                .config
+                .erc1155_contracts
+                .iter()
+                .any(|contract| contract.eq_ignore_ascii_case(&checkpoint.token_address));
+            if !configured || checkpoint.next_block > latest {
+                continue;
+            }
+
+            let start = checkpoint.next_block;
+            let end = std::cmp::min(start + self.config.blocks_per_request - 1, latest);
+            let contract = std::slice::from_ref(&checkpoint.token_address);
+            let logs = self.fetch_erc1155_logs(start, end, contract).await?;
+            let transfers = decode_erc1155_logs(&logs, self.database.now())?;
+
+            self.database
+                .commit_token_backfill(
+                    chain_id,
+                    &checkpoint.token_address,
+                    start,
+                    end,
+                    &transfers,
+                    self.config.raw_logs.then_some(logs.as_slice()),
+                )
+                .await?;
+            info!(
+                transfers = transfers.len(),
+                contract = %checkpoint.token_address,
+                start_block = start,
+                end_block = end,
+                "Backfilled ERC-1155 transfers"
+            );
+        }
+        Ok(())
+    }
+
+    /// Indexes the next range from `start_block` and returns the block to continue from: past the
+    /// range, `start_block` itself while waiting for finality, or the fork block after a reorg.
+    #[instrument(skip(self), fields(lag_blocks))]
     async fn process_blocks(&self, start_block: u64) -> Result<u64> {
+        if let Err(e) = self.backfill_tokens().await {
+            warn!(error = %e, "Token backfill failed");
+        }
+        self.refresh_sanctions().await;
+
         let latest_block = self.ethereum_client.get_latest_block_number().await?;
         let finalized_block = latest_block.saturating_sub(self.config.finality_blocks);
-        
+        self.observe_lag(start_block, latest_block).await;
+
         if start_block > finalized_block {
-            return Ok(0);
+            self.refresh_pending(finalized_block, latest_block).await;
+            return Ok(start_block);
         }
 
         let end_block = std::cmp::min(
@@ -84,40 +463,375 @@ impl Indexer {
             finalized_block,
         );
 
-        if let Err(e) = self.check_for_reorg(start_block).await {
-            warn!("Reorg check failed: {}", e);
+        match self.check_for_reorg(start_block, latest_block).await {
+            Ok(Some(fork_block)) => return Ok(fork_block),
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "Reorg check failed"),
         }
 
-        info!("Processing blocks {} to {}", start_block, end_block);
+        info!(start_block, end_block, "Processing blocks");
 
-        let events = self
-            .ethereum_client
-            .get_transfer_events(start_block, end_block)
-            .await?;
+        let (events, outcomes, blocks) = self.commit_range(start_block, end_block).await?;
 
-        info!("Found {} transfer events", events.len());
+        let mut inserted: Vec<TransferEvent> = events
+            .iter()
+            .zip(&outcomes)
+            .filter(|(_, outcome)| matches!(outcome, IngestOutcome::Inserted | IngestOutcome::Replaced))
+            .map(|(event, _)| event.clone())
+            .collect();
+        let sanctioned = self.screen_transfers(&mut inserted).await;
 
-        for event in events {
-            if let Err(e) = self.database.insert_transfer_event(&event).await {
-                error!("Failed to insert transfer event: {}", e);
+        for event in &inserted {
+            if let Some(alert) = Alert::for_transfer(event, &self.config) {
+                self.alerts.push(alert, self.database.now(), self.config.alert_min_value_channels.clone());
             }
+            if self.config.sanctions_alerts {
+                if let Some(alert) = Alert::for_sanctioned_transfer(event, &sanctioned, &self.config) {
+                    self.alerts.push(alert, self.database.now(), self.config.sanctions_alert_channels.clone());
+                }
+            }
+            // Only fails when nobody is subscribed.
+            let _ = self.transfers.send(event.clone());
+        }
+        self.apply_alert_rules(&inserted).await;
+        self.run_handlers(&inserted, &blocks).await;
+
+        // Undelivered messages stay in the outbox and go out with the next range.
+        #[cfg(feature = "kafka")]
+        if let Some(kafka) = &self.kafka {
+            if let Err(e) = kafka.publish(&self.database, end_block).await {
+                error!(error = %e, "Failed to publish to Kafka");
+            }
+        }
+        if let Some(redis) = &self.redis {
+            if let Err(e) = redis.publish(&self.database, end_block).await {
+                error!(error = %e, "Failed to publish to Redis");
+            }
+        }
+        if let Some(clickhouse) = &self.clickhouse {
+            if let Err(e) = clickhouse.publish(&self.database, end_block).await {
+                error!(error = %e, "Failed to write to ClickHouse");
+            }
+        }
+
+        let conflicts = outcomes
+            .iter()
+            .filter(|o| matches!(o, IngestOutcome::Replaced | IngestOutcome::KeptExisting))
+            .count();
+        if conflicts > 0 {
+            warn!(conflicts, "Resolved conflicting transfer events");
+        }
+
+        if let Err(e) = self.database.refresh_address_stats(end_block).await {
+            error!(error = %e, "Failed to update address stats");
+        }
+        if self.config.anomaly_detection {
+            if let Some(block) = blocks.last() {
+                self.detect_anomalies(block.timestamp).await;
+            }
+        }
+        if self.config.holder_snapshot_secs > 0 {
+            if let Err(e) = record_holder_snapshot(&self.database, self.config.holder_snapshot_secs).await {
+                warn!(error = %e, "Failed to record holder snapshot");
+            }
+        }
+
+        if self.config.pending_blocks > 0 {
+            match self.database.promote_pending_transfers(end_block).await {
+                Ok(promoted) if promoted > 0 => info!(promoted, "Promoted pending transfers"),
+                Ok(_) => {}
+                Err(e) => error!(error = %e, "Failed to promote pending transfers"),
+            }
+
+            if end_block == finalized_block {
+                self.refresh_pending(finalized_block, latest_block).await;
+            }
+        }
+
+        // Latency only reflects the pipeline once we are following the head, not backfilling.
+        if end_block == finalized_block {
+            self.check_freshness_slo().await;
+        }
+
+        Ok(end_block + 1)
+    }
+
+    /// Fetches transfers and block headers for `start_block..=end_block` and commits them together.
+    #[instrument(skip(self), fields(events))]
+    async fn commit_range(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<(Vec<TransferEvent>, Vec<IngestOutcome>, Vec<ProcessedBlock>)> {
+        let mut logs = self.fetch_transfer_logs(start_block, end_block).await?;
+        let mut events = decode_transfer_logs(&logs, self.database.now())?;
+
+        info!(events = events.len(), "Found transfer events");
+        tracing::Span::current().record("events", events.len());
+
+        if self.config.tx_enrichment {
+            self.enrich_transactions(&events).await?;
+        }
+        if self.config.address_classification {
+            self.classify_addresses(&events).await?;
+        }
+        if let Some(feed) = &self.config.price_feed_address {
+            self.price_transfers(feed, &mut events).await?;
+        }
+
+        // Contracts still being backfilled below this range are left to `backfill_tokens`.
+        let chain_id = self.chain_id().await?;
+        let contracts: Vec<String> = self
+            .database
+            .get_token_checkpoints(Some(chain_id))
+            .await?
+            .into_iter()
+            .filter(|checkpoint| checkpoint.standard == "erc1155" && checkpoint.next_block >= start_block)
+            .map(|checkpoint| checkpoint.token_address)
+            .filter(|token| self.config.erc1155_contracts.iter().any(|c| c.eq_ignore_ascii_case(token)))
+            .collect();
+        let erc1155_logs = self.fetch_erc1155_logs(start_block, end_block, &contracts).await?;
+        let erc1155_transfers = decode_erc1155_logs(&erc1155_logs, self.database.now())?;
+        if !erc1155_transfers.is_empty() {
+            info!(transfers = erc1155_transfers.len(), "Found ERC-1155 transfers");
         }
 
+        let mut blocks = Vec::with_capacity((end_block - start_block + 1) as usize);
         for block_num in start_block..=end_block {
-            let block_hash = self.ethereum_client.get_block_hash(block_num).await?;
-            let timestamp = self.ethereum_client.get_block_timestamp(block_num).await?;
-            
-            if let Err(e) = self.database.insert_processed_block(block_num, &block_hash, timestamp).await {
-                error!("Failed to insert processed block: {}", e);
+            blocks.push(self.ethereum_client.get_block_header(block_num).await?);
+        }
+
+        // Stored first so a crash before the range commits only leaves rows that the retry
+        // replaces.
+        if !contracts.is_empty() {
+            let block_numbers: Vec<u64> = (start_block..=end_block).collect();
+            self.database
+                .replace_block_erc1155_transfers(&block_numbers, &erc1155_transfers)
+                .await?;
+            logs.extend(erc1155_logs);
+            logs.sort_by_key(|log| (log.block_number, log.log_index));
+        }
+
+        let outcomes = self
+            .database
+            .commit_block_range(
+                &events,
+                &blocks,
+                self.config.raw_logs.then_some(logs.as_slice()),
+                EventSource::Rpc,
+                self.config.dedup_window_blocks,
+            )
+            .await?;
+
+        let mut tokens = contracts;
+        tokens.push(self.config.usdc_contract_address.clone());
+        self.database.advance_token_checkpoints(chain_id, &tokens, end_block + 1).await?;
+
+        Ok((events, outcomes, blocks))
+    }
+
+    /// Fetches and stores the receipt details of every transaction in `events` not stored yet.
+    #[instrument(skip_all, fields(events = events.len()))]
+    async fn enrich_transactions(&self, events: &[TransferEvent]) -> Result<()> {
+        let hashes: Vec<String> = events
+            .iter()
+            .map(|e| e.transaction_hash.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let stored = self.database.get_transactions(&hashes).await?;
+        let missing: Vec<&String> = hashes.iter().filter(|hash| !stored.contains_key(*hash)).collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut transactions = Vec::with_capacity(missing.len());
+        for chunk in missing.chunks(ENRICHMENT_CONCURRENCY) {
+            let receipts = chunk.iter().map(|hash| self.ethereum_client.get_transaction_details(hash));
+            transactions.extend(futures::future::try_join_all(receipts).await?);
+        }
+        self.database.insert_transactions(&transactions).await?;
+        info!(transactions = transactions.len(), "Enriched transactions");
+        Ok(())
+    }
+
+    /// Checks every sender and recipient in `events` not classified yet for contract code.
+    #[instrument(skip_all, fields(events = events.len()))]
+    async fn classify_addresses(&self, events: &[TransferEvent]) -> Result<()> {
+        let addresses: Vec<String> = events
+            .iter()
+            .flat_map(|e| [e.from_address.clone(), e.to_address.clone()])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let stored = self.database.get_address_info(&addresses).await?;
+        let missing: Vec<&String> = addresses.iter().filter(|address| !stored.contains_key(*address)).collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut info = Vec::with_capacity(missing.len());
+        for chunk in missing.chunks(ENRICHMENT_CONCURRENCY) {
+            let codes = chunk.iter().map(|address| self.ethereum_client.is_contract(address));
+            for (address, is_contract) in chunk.iter().zip(futures::future::try_join_all(codes).await?) {
+                info.push(AddressInfo {
+                    address: (*address).clone(),
+                    is_contract,
+                    checked_at: self.database.now(),
+                });
             }
         }
+        self.database.insert_address_info(&info).await?;
+        info!(addresses = info.len(), "Classified new addresses");
+        Ok(())
+    }
 
-        Ok(end_block - start_block + 1)
+    /// Values `events` in dollars at the feed answer of their price block, reading the feed for
+    /// blocks not cached yet, and stores the values.
+    #[instrument(skip(self, events), fields(events = events.len()))]
+    async fn price_transfers(&self, feed: &str, events: &mut [TransferEvent]) -> Result<()> {
+        let interval = self.config.price_block_interval;
+        let blocks: Vec<u64> = events
+            .iter()
+            .map(|e| price_block(e.block_number as u64, interval))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let mut prices = self.database.get_block_prices(feed, &blocks).await?;
+        let missing: Vec<u64> = blocks.into_iter().filter(|block| !prices.contains_key(block)).collect();
+
+        let mut fetched = Vec::with_capacity(missing.len());
+        for chunk in missing.chunks(ENRICHMENT_CONCURRENCY) {
+            let reads = chunk.iter().map(|block| self.ethereum_client.get_chainlink_price(feed, *block));
+            fetched.extend(futures::future::try_join_all(reads).await?);
+        }
+        if !fetched.is_empty() {
+            self.database.insert_block_prices(&fetched).await?;
+            info!(prices = fetched.len(), %feed, "Read prices");
+        }
+        prices.extend(fetched.into_iter().map(|price| (price.block_number as u64, price)));
+
+        for event in events.iter_mut() {
+            let price = &prices[&price_block(event.block_number as u64, interval)];
+            event.usd_value = Some(usd_value(&event.value, self.config.token_decimals, price)?);
+        }
+        self.database.insert_usd_values(events).await
     }
 
-    async fn check_for_reorg(&self, current_block: u64) -> Result<()> {
+    async fn fetch_transfer_events(&self, start_block: u64, end_block: u64) -> Result<Vec<TransferEvent>> {
+        decode_transfer_logs(&self.fetch_transfer_logs(start_block, end_block).await?, self.database.now())
+    }
+
+    /// Transfer logs in the range, limited to watched addresses when `WATCHLIST_ONLY` is on.
+    async fn fetch_transfer_logs(&self, start_block: u64, end_block: u64) -> Result<Vec<RawLog>> {
+        if !self.config.watchlist_only {
+            return self.ethereum_client.get_transfer_logs(start_block, end_block).await;
+        }
+
+        let watchlist = self.database.watched_addresses().await?;
+        if watchlist.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.ethereum_client.get_transfer_logs_for(start_block, end_block, &watchlist).await
+    }
+
+    /// ERC-1155 logs of `contracts` in the range, limited to watched addresses when
+    /// `WATCHLIST_ONLY` is on.
+    async fn fetch_erc1155_logs(&self, start_block: u64, end_block: u64, contracts: &[String]) -> Result<Vec<RawLog>> {
+        if contracts.is_empty() {
+            return Ok(Vec::new());
+        }
+        if !self.config.watchlist_only {
+            return self.ethereum_client.get_erc1155_logs(start_block, end_block, contracts).await;
+        }
+
+        let watchlist = self.database.watched_addresses().await?;
+        if watchlist.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.ethereum_client
+            .get_erc1155_logs_for(start_block, end_block, contracts, &watchlist)
+            .await
+    }
+
+    /// Re-indexes the given gaps, returning how many transfers were newly stored.
+    #[instrument(skip_all, fields(gaps = gaps.len()))]
+    pub async fn fill_gaps(&self, gaps: &[BlockGap]) -> Result<u64> {
+        self.verify_identity().await?;
+
+        let mut inserted = 0;
+        for gap in gaps {
+            let mut start = gap.from_block;
+            while start <= gap.to_block {
+                let end = std::cmp::min(start + self.config.blocks_per_request - 1, gap.to_block);
+                info!(start_block = start, end_block = end, "Filling blocks");
+
+                let (events, outcomes, blocks) = self.commit_range(start, end).await?;
+                let stored: Vec<TransferEvent> = events
+                    .into_iter()
+                    .zip(&outcomes)
+                    .filter(|(_, outcome)| matches!(outcome, IngestOutcome::Inserted | IngestOutcome::Replaced))
+                    .map(|(event, _)| event)
+                    .collect();
+                self.run_handlers(&stored, &blocks).await;
+                inserted += stored.len() as u64;
+                start = end + 1;
+            }
+        }
+
+        // Gaps sit below the rollup watermark, so incremental refresh does not cover them.
+        if inserted > 0 {
+            self.database.rebuild_address_stats().await?;
+        }
+        Ok(inserted)
+    }
+
+    #[instrument(skip(self))]
+    async fn refresh_pending(&self, finalized_block: u64, latest_block: u64) {
+        if self.config.pending_blocks == 0 || latest_block <= finalized_block {
+            return;
+        }
+
+        let from_block = std::cmp::max(
+            finalized_block + 1,
+            latest_block.saturating_sub(self.config.pending_blocks - 1),
+        );
+
+        let events = match self.fetch_transfer_events(from_block, latest_block).await {
+            Ok(events) => events,
+            Err(e) => {
+                warn!(error = %e, "Failed to fetch pending transfer events");
+                return;
+            }
+        };
+
+        // A buffered block whose hash changed was reorged out while still unfinalized.
+        match self.database.get_pending_block_hashes(from_block).await {
+            Ok(buffered) => {
+                let fork_block = events
+                    .iter()
+                    .filter(|e| buffered.get(&e.block_number).is_some_and(|hash| *hash != e.block_hash))
+                    .map(|e| e.block_number as u64)
+                    .min();
+                if let Some(fork_block) = fork_block {
+                    self.observe_reorg(fork_block, latest_block).await;
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to read buffered block hashes"),
+        }
+
+        info!(events = events.len(), start_block = from_block, end_block = latest_block, "Buffered unfinalized transfer events");
+
+        if let Err(e) = self.database.replace_pending_transfers(from_block, &events).await {
+            error!(error = %e, "Failed to store pending transfers");
+        }
+    }
+
+    /// Rolls stored data back to the first recent block whose hash changed and returns it.
+    #[instrument(skip(self))]
+    async fn check_for_reorg(&self, current_block: u64, chain_head: u64) -> Result<Option<u64>> {
         if current_block == 0 {
-            return Ok(());
+            return Ok(None);
         }
 
         let check_blocks = std::cmp::min(10, current_block);
@@ -127,18 +841,269 @@ impl Indexer {
             if let Ok(actual_hash) = self.ethereum_client.get_block_hash(block_num).await {
                 if let Ok(Some(stored_hash)) = self.get_stored_block_hash(block_num).await {
                     if actual_hash != stored_hash {
-                        warn!("Reorg detected at block {}", block_num);
+                        warn!(block_number = block_num, "Reorg detected");
+                        self.observe_reorg(block_num, chain_head).await;
                         self.database.handle_reorg(block_num).await?;
-                        return Err(anyhow::anyhow!("Reorg detected at block {}", block_num));
+                        for handler in &self.handlers {
+                            if let Err(e) = handler.on_reorg(block_num, chain_head).await {
+                                error!(block_number = block_num, error = %e, "Event handler failed on reorg");
+                            }
+                        }
+                        return Ok(Some(block_num));
                     }
                 }
             }
         }
 
-        Ok(())
+        Ok(None)
+    }
+
+    async fn observe_reorg(&self, fork_block: u64, chain_head: u64) {
+        let depth = chain_head.saturating_sub(fork_block) + 1;
+        if depth > self.config.finality_blocks {
+            error!(
+                depth,
+                block_number = fork_block,
+                finality_blocks = self.config.finality_blocks,
+                "Observed reorg deeper than FINALITY_BLOCKS"
+            );
+        } else {
+            info!(depth, block_number = fork_block, "Observed reorg");
+        }
+
+        if let Err(e) = self
+            .database
+            .record_reorg(fork_block, chain_head, depth, self.config.finality_blocks)
+            .await
+        {
+            error!(error = %e, "Failed to record reorg");
+        }
+    }
+
+    /// Delivers each block's new transfers, then the block, to every handler.
+    async fn run_handlers(&self, inserted: &[TransferEvent], blocks: &[ProcessedBlock]) {
+        if self.handlers.is_empty() {
+            return;
+        }
+
+        let mut transfers = inserted.iter().peekable();
+        for block in blocks {
+            while let Some(event) = transfers.next_if(|t| t.block_number as u64 <= block.block_number) {
+                for handler in &self.handlers {
+                    if let Err(e) = handler.on_transfer(event).await {
+                        error!(transfer = %event.id, block_number = event.block_number, error = %e, "Event handler failed on transfer");
+                    }
+                }
+            }
+            for handler in &self.handlers {
+                if let Err(e) = handler.on_block(block).await {
+                    error!(block_number = block.block_number, error = %e, "Event handler failed on block");
+                }
+            }
+        }
+    }
+
+    /// Reloads `SANCTIONS_LIST` once it is older than `SANCTIONS_REFRESH_SECS`. Not fatal: the
+    /// previous list stays in use.
+    async fn refresh_sanctions(&self) {
+        let Some(source) = &self.config.sanctions_list else {
+            return;
+        };
+        let max_age = chrono::Duration::seconds(self.config.sanctions_refresh_secs as i64);
+        match refresh_sanctions_if_due(&self.database, source, max_age).await {
+            Ok(Some(count)) => info!(addresses = count, %source, "Loaded sanctioned addresses"),
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "Failed to refresh sanctions list"),
+        }
+    }
+
+    /// Marks transfers touching a sanctioned address and returns the listed addresses among them,
+    /// lowercased. Not fatal: screening failures leave the transfers unmarked.
+    async fn screen_transfers(&self, transfers: &mut [TransferEvent]) -> HashSet<String> {
+        if self.config.sanctions_list.is_none() || transfers.is_empty() {
+            return HashSet::new();
+        }
+        match self.database.mark_sanctioned(transfers).await {
+            Ok(sanctioned) => sanctioned,
+            Err(e) => {
+                warn!(error = %e, "Failed to screen transfers");
+                HashSet::new()
+            }
+        }
+    }
+
+    /// Not fatal: a broken rule must not stop indexing.
+    async fn apply_alert_rules(&self, transfers: &[TransferEvent]) {
+        if transfers.is_empty() {
+            return;
+        }
+
+        let rules = match self.database.list_alert_rules().await {
+            Ok(rules) if rules.is_empty() => return,
+            Ok(rules) => rules,
+            Err(e) => {
+                warn!(error = %e, "Failed to load alert rules");
+                return;
+            }
+        };
+
+        match self.rules.evaluate(&self.database, &self.config, &rules, transfers).await {
+            Ok(fired) => {
+                for (rule, fired) in fired {
+                    self.alerts.push(fired.alert, self.database.now(), rule.channels.clone());
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to evaluate alert rules"),
+        }
+    }
+
+    /// Alerts on windows that closed before `through` with unusual volume.
+    async fn detect_anomalies(&self, through: DateTime<Utc>) {
+        match detect_anomalies(&self.database, &AnomalySettings::from_config(&self.config), through).await {
+            Ok(alerts) => {
+                for alert in alerts {
+                    self.alerts.push(alert, self.database.now(), self.config.anomaly_alert_channels.clone());
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to check for volume anomalies"),
+        }
+    }
+
+    /// Records how far the last processed block, `start_block - 1`, trails `latest_block`: on the
+    /// current span, and as the chain head in `meta` for `/api/stats`. Alerts when the lag stays
+    /// above `LAG_ALERT_BLOCKS`.
+    async fn observe_lag(&self, start_block: u64, latest_block: u64) {
+        let lag_blocks = (latest_block + 1).saturating_sub(start_block);
+        tracing::Span::current().record("lag_blocks", lag_blocks);
+        if let Err(e) = self.database.set_meta("chain_head", &latest_block.to_string()).await {
+            warn!(error = %e, "Failed to record chain head");
+        }
+        if let Some(alert) = self.lag.observe(lag_blocks, &self.config, self.database.now()) {
+            self.alerts.push(alert, self.database.now(), self.config.lag_alert_channels.clone());
+        }
+    }
+
+    async fn check_freshness_slo(&self) {
+        match self.freshness.check(&self.database, &self.config, self.database.now()).await {
+            Ok(Some(alert @ Alert::FreshnessSloViolated { .. })) => error!("{}", alert.message()),
+            Ok(Some(alert)) => info!("{}", alert.message()),
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "Failed to check freshness SLO"),
+        }
     }
 
     async fn get_stored_block_hash(&self, block_number: u64) -> Result<Option<String>> {
         self.database.get_block_hash(block_number).await
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::testing::database,
+        query::TransferFilter,
+        rpc::{MockEthereumClient, Recorder, Replay, Transport},
+    };
+
+    const TOKEN: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const BOB: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    async fn indexer(transport: Transport) -> Indexer {
+        let config = Config {
+            chain_id: Some(1),
+            finality_blocks: 2,
+            blocks_per_request: 10,
+            pending_blocks: 0,
+            ..Config::from_env().unwrap()
+        };
+        let client = EthereumClient::with_transport(transport, TOKEN).unwrap();
+        let indexer = Indexer::with_client(config, database().await, client).await.unwrap();
+        indexer.verify_identity().await.unwrap();
+        indexer.register_tokens(0).await.unwrap();
+        indexer
+    }
+
+    /// Processes ranges from `block` until waiting for finality, returning where it waits.
+    async fn catch_up(indexer: &Indexer, mut block: u64) -> u64 {
+        loop {
+            match indexer.process_blocks(block).await.unwrap() {
+                next if next == block => return block,
+                next => block = next,
+            }
+        }
+    }
+
+    async fn stored(indexer: &Indexer) -> Vec<(i64, String, String)> {
+        let page = indexer.database.query_transfers_page(&TransferFilter::new(), 100).await.unwrap();
+        let mut transfers: Vec<_> = page
+            .transfers
+            .into_iter()
+            .map(|t| (t.block_number, t.from_address, t.value))
+            .collect();
+        transfers.sort();
+        transfers
+    }
+
+    fn chain() -> MockEthereumClient {
+        let chain = MockEthereumClient::new(1, TOKEN);
+        chain.mine(30);
+        chain.transfer(5, ALICE, BOB, 100);
+        chain.transfer(12, BOB, ALICE, 40);
+        chain.transfer(25, ALICE, BOB, 7);
+        chain
+    }
+
+    #[tokio::test]
+    async fn indexes_finalized_blocks_and_reindexes_after_a_reorg() {
+        let chain = chain();
+        let indexer = indexer(Transport::Mock(chain.clone())).await;
+
+        assert_eq!(catch_up(&indexer, 0).await, 29);
+        assert_eq!(
+            stored(&indexer).await,
+            vec![
+                (5, ALICE.to_string(), "100".to_string()),
+                (12, BOB.to_string(), "40".to_string()),
+                (25, ALICE.to_string(), "7".to_string()),
+            ]
+        );
+
+        chain.reorg(25);
+        chain.transfer(26, BOB, ALICE, 9);
+        chain.mine(5);
+        assert_eq!(indexer.process_blocks(29).await.unwrap(), 25);
+        assert_eq!(indexer.database.get_reorg_events(DateTime::<Utc>::MIN_UTC, Utc::now()).await.unwrap().len(), 1);
+
+        assert_eq!(catch_up(&indexer, 25).await, 34);
+        assert_eq!(
+            stored(&indexer).await,
+            vec![
+                (5, ALICE.to_string(), "100".to_string()),
+                (12, BOB.to_string(), "40".to_string()),
+                (26, BOB.to_string(), "9".to_string()),
+            ]
+        );
+        assert_eq!(indexer.database.get_block_hash(25).await.unwrap(), Some(chain.block_hash(25)));
+    }
+
+    #[tokio::test]
+    async fn replays_a_recorded_run_without_the_chain() {
+        let fixture = std::env::temp_dir().join(format!("usdcwatch-rpc-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&fixture);
+
+        let recorder = Recorder::create(Transport::Mock(chain()), &fixture).unwrap();
+        let recorded = indexer(Transport::Record(Arc::new(recorder))).await;
+        assert_eq!(catch_up(&recorded, 0).await, 29);
+
+        let replayed = indexer(Transport::Replay(Arc::new(Replay::open(&fixture).unwrap()))).await;
+        assert_eq!(catch_up(&replayed, 0).await, 29);
+        assert_eq!(stored(&replayed).await, stored(&recorded).await);
+        assert_eq!(stored(&replayed).await.len(), 3);
+
+        let err = replayed.ethereum_client.get_block_hash(1_000).await.unwrap_err();
+        assert!(err.to_string().contains("No recorded answer"), "{}", err);
+        std::fs::remove_file(&fixture).unwrap();
+    }
+}
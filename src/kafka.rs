@@ -0,0 +1,74 @@
+use anyhow::Result;
+use rdkafka::{
+    config::ClientConfig,
+    producer::{FutureProducer, FutureRecord},
+    util::Timeout,
+};
+use std::time::Duration;
+
+use crate::{config::Config, database::Database, publish::Outbox};
+
+/// How long a message may wait for broker acknowledgement before the batch is retried.
+const DELIVERY_TIMEOUT_SECS: u64 = 10;
+
+/// Publishes committed transfers and reorg rollback markers to a Kafka topic as JSON.
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+    topic: String,
+    /// Every message is keyed by the token contract so one partition holds the whole stream in order.
+    key: String,
+    outbox: Outbox,
+}
+
+impl KafkaPublisher {
+    /// `None` when `KAFKA_BROKERS` is unset.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(brokers) = &config.kafka_brokers else {
+            return Ok(None);
+        };
+
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", (DELIVERY_TIMEOUT_SECS * 1000).to_string())
+            .set("enable.idempotence", "true")
+            .create()
+            .map_err(|e| anyhow::anyhow!("Failed to create Kafka producer: {}", e))?;
+
+        Ok(Some(Self {
+            producer,
+            topic: config.kafka_topic.clone(),
+            key: config.usdc_contract_address.to_lowercase(),
+            outbox: Outbox::new("kafka"),
+        }))
+    }
+
+    pub async fn start(&self, database: &Database, start_block: u64) -> Result<()> {
+        self.outbox.start(database, start_block).await
+    }
+
+    /// Sends everything committed through `through_block` that the topic has not acknowledged yet.
+    pub async fn publish(&self, database: &Database, through_block: u64) -> Result<u64> {
+        let mut published = 0;
+        while let Some(batch) = self.outbox.next_batch(database, through_block).await? {
+            let payloads = batch
+                .messages()
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let deliveries = payloads.iter().map(|payload| {
+                self.producer.send(
+                    FutureRecord::to(&self.topic).key(&self.key).payload(payload),
+                    Timeout::After(Duration::from_secs(DELIVERY_TIMEOUT_SECS)),
+                )
+            });
+            futures::future::try_join_all(deliveries)
+                .await
+                .map_err(|(e, _)| anyhow::anyhow!("Kafka delivery to {} failed: {}", self.topic, e))?;
+
+            self.outbox.commit(database, &batch).await?;
+            published += payloads.len() as u64;
+        }
+        Ok(published)
+    }
+}
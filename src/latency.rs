@@ -0,0 +1,75 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Nominal post-merge slot time, used when the block that finalizes a sample was not indexed yet.
+const SECONDS_PER_BLOCK: i64 = 12;
+
+/// When a processed block's transfers became queryable.
+#[derive(Debug, Clone)]
+pub struct LatencySample {
+    pub block_number: i64,
+    pub timestamp: DateTime<Utc>,
+    pub processed_at: DateTime<Utc>,
+}
+
+/// Block-timestamp-to-availability latency over a window, checked against the freshness SLO.
+#[derive(Debug, Serialize)]
+pub struct LatencyReport {
+    pub window_hours: i64,
+    pub blocks_observed: usize,
+    pub p50_secs: Option<i64>,
+    pub p95_secs: Option<i64>,
+    pub max_secs: Option<i64>,
+    pub slo_secs: u64,
+    pub slo_percent: f64,
+    /// Share of blocks available within `slo_secs` of becoming final.
+    pub within_slo_percent: Option<f64>,
+    pub slo_met: bool,
+}
+
+impl LatencyReport {
+    pub fn new(window_hours: i64, samples: &[LatencySample], finality_blocks: u64, slo_secs: u64, slo_percent: f64) -> Self {
+        let mut latencies: Vec<i64> = samples
+            .iter()
+            .map(|s| (s.processed_at - s.timestamp).num_seconds().max(0))
+            .collect();
+        latencies.sort_unstable();
+
+        // A block is final once the block `finality_blocks` above it exists.
+        let timestamps: HashMap<i64, DateTime<Utc>> = samples.iter().map(|s| (s.block_number, s.timestamp)).collect();
+        let within = samples
+            .iter()
+            .filter(|s| {
+                let finalized_at = timestamps
+                    .get(&(s.block_number + finality_blocks as i64))
+                    .copied()
+                    .unwrap_or_else(|| s.timestamp + Duration::seconds(finality_blocks as i64 * SECONDS_PER_BLOCK));
+                (s.processed_at - finalized_at).num_seconds() <= slo_secs as i64
+            })
+            .count();
+
+        let within_slo_percent = (!samples.is_empty()).then(|| within as f64 * 100.0 / samples.len() as f64);
+
+        Self {
+            window_hours,
+            blocks_observed: samples.len(),
+            p50_secs: percentile(&latencies, 50.0),
+            p95_secs: percentile(&latencies, 95.0),
+            max_secs: latencies.last().copied(),
+            slo_secs,
+            slo_percent,
+            within_slo_percent,
+            slo_met: within_slo_percent.is_none_or(|p| p >= slo_percent),
+        }
+    }
+}
+
+/// Nearest-rank percentile of an ascending slice.
+fn percentile(sorted: &[i64], percent: f64) -> Option<i64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
@@ -1,6 +1,86 @@
-pub mod cli;
+//! Indexes ERC-20 `Transfer` events (USDC by default) from an Ethereum RPC endpoint into SQLite.
+//!
+//! The `ethereum-erc20-indexer` binary is a thin CLI over this crate, and other services can embed
+//! the same pieces. The library never prints or exits the process: failures are returned as
+//! [`anyhow::Error`] and progress is reported through `tracing` events and spans. The one
+//! exception is the opt-in `console` alert sink, whose job is writing alerts to stdout.
+//!
+//! ```no_run
+//! use ethereum_erc20_indexer::{database::PoolSettings, Config, Database, Indexer};
+//! use std::sync::Arc;
+//! use tokio_stream::StreamExt;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let config = Config::from_env()?;
+//! let database = Database::new(&config.database_url, PoolSettings::from(&config)).await?;
+//! let indexer = Arc::new(Indexer::new(config, database).await?);
+//!
+//! let mut transfers = Box::pin(indexer.transfer_stream());
+//! tokio::spawn({
+//!     let indexer = indexer.clone();
+//!     async move { indexer.start_indexing(None).await }
+//! });
+//! while let Some(transfer) = transfers.next().await {
+//!     tracing::info!("{} -> {}: {}", transfer.from_address, transfer.to_address, transfer.value);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod address;
+pub mod alerts;
+pub mod anomaly;
+pub mod api;
+pub mod archive;
+pub mod auth;
+pub mod bench;
+pub mod bloom;
+pub mod clickhouse;
+pub mod clock;
 pub mod config;
+pub mod daemon;
+pub mod dashboard;
 pub mod database;
+pub mod dedup;
+pub mod ens;
 pub mod ethereum;
+pub mod finality;
+pub mod gas;
+pub mod graph;
+pub mod grpc;
+pub mod handler;
+pub mod holders;
+pub mod import;
 pub mod indexer;
-pub mod models;
\ No newline at end of file
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod latency;
+pub mod mempool;
+pub mod models;
+pub mod notify;
+pub mod output;
+pub mod price;
+pub mod publish;
+pub mod query;
+pub mod rate_limit;
+pub mod redis_stream;
+pub mod replay;
+pub mod report;
+pub mod rollup;
+pub mod rpc;
+pub mod sanctions;
+pub mod series;
+pub mod shard;
+pub mod stats;
+pub mod systemd;
+pub mod telemetry;
+pub mod trace;
+pub mod units;
+pub mod velocity;
+pub mod verify;
+
+pub use config::Config;
+pub use database::Database;
+pub use ethereum::EthereumClient;
+pub use handler::EventHandler;
+pub use indexer::Indexer;
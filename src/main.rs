@@ -1,71 +1,1324 @@
-mod cli;
-mod config;
-mod database;
-mod ethereum;
-mod indexer;
-mod models;
-
 use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
 use clap::Parser;
-use cli::{Cli, Commands};
-use config::Config;
-use database::Database;
-use indexer::Indexer;
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
-    
+use daemonize::Daemonize;
+use std::{collections::BTreeMap, io::IsTerminal, path::Path, sync::Arc};
+use tokio_stream::StreamExt;
+use ethereum_erc20_indexer::{
+    address::normalize_address,
+    alerts::{backtest, RuleMonitor},
+    api::{router, serve, ApiState},
+    auth::{create_api_key, Authenticator},
+    bench::{bench, synthetic_logs},
+    clickhouse::ClickHouseWriter,
+    config::Config,
+    daemon::running_pid,
+    dashboard::Dashboard,
+    database::{is_in_memory, migration_status, Database, PoolSettings, MIGRATOR},
+    dedup::EventSource,
+    ens::{is_ens_name, EnsResolver},
+    ethereum::EthereumClient,
+    finality::FinalityReport,
+    gas::gas_spent,
+    graph::TransferGraph,
+    grpc::{serve_grpc, GrpcService},
+    holders::{backfill_holder_snapshots, concentration, holder_distribution, DEFAULT_BUCKETS, DEFAULT_TOPS},
+    import::{detect_format, known_entity_labels, read_labels, read_transfers, ImportSummary, IMPORT_BATCH_SIZE},
+    indexer::{DryRun, Indexer},
+    latency::LatencyReport,
+    mempool::MempoolWatcher,
+    models::{AlertRule, DirectedTransfer, PendingTransfer, PricedRow, TransferEvent},
+    notify::{is_sink_name, Notifier, SINK_NAMES},
+    output::{alert_line, format_usd, write_rows, AmountFormat, ExportFormat, OutputFormat, RowWriter, Tabular},
+    query::{parse_time, Cursor, TransferFilter},
+    rate_limit::RateLimiter,
+    replay::replay,
+    rpc::recorded_logs,
+    report::address_report,
+    sanctions::refresh_sanctions,
+    stats::{address_stats_report, collect_stats},
+    systemd,
+    telemetry,
+    trace::trace_flow,
+    units::parse_amount,
+    velocity::velocity,
+    verify::{verify_range, VerifyReport},
+};
+
+mod cli;
+
+use cli::{AlertCommand, AnalyticsCommand, ApiKeyCommand, ClickHouseCommand, Cli, Commands, DbCommand, FilterArgs, LabelCommand, SanctionsCommand, SinksCommand, TokenCommand, WatchlistCommand};
+
+const FOLLOW_POLL_SECS: u64 = 5;
+/// Transfers `index --dry-run` shows in full.
+const DRY_RUN_SAMPLES: usize = 10;
+
+fn main() -> Result<()> {
     let cli = Cli::parse();
     let config = Config::load()?;
-    let database = Database::new(&config.database_url).await?;
+    if let Commands::Index { daemon: true, pid_file, .. } = &cli.command {
+        detach(pid_file, &config)?;
+    }
+    tokio::runtime::Runtime::new()?.block_on(run(cli, config))
+}
+
+async fn run(cli: Cli, mut config: Config) -> Result<()> {
+    if cli.ephemeral {
+        config.database_url = "sqlite::memory:".to_string();
+        config.shard_size_blocks = 0;
+    }
+    let _telemetry = telemetry::init(&config, cli.log_format)?;
+
+    // Handled before `Database::new`, which refuses to open with pending migrations when
+    // AUTO_MIGRATE is off.
+    if let Commands::Migrate { status } = cli.command {
+        let pool = PoolSettings::from(&config).connect(&config.database_url).await?;
+        if !status {
+            MIGRATOR.run(&pool).await?;
+        }
+        for migration in migration_status(&pool).await? {
+            let state = if migration.applied { "applied" } else { "pending" };
+            println!("{:>4}  {:<8} {}", migration.version, state, migration.description);
+        }
+        return Ok(());
+    }
+
+    // An in-memory database starts empty, so it is only useful writable.
+    let read_only = matches!(
+        cli.command,
+        Commands::Query { .. } | Commands::Stats { .. } | Commands::Serve { read_only: true, .. }
+    ) && !is_in_memory(&config.database_url);
+    let settings = PoolSettings {
+        interruptible: matches!(cli.command, Commands::Serve { .. }),
+        read_only,
+        ..PoolSettings::from(&config)
+    };
+    let database = Database::new(&config.database_url, settings)
+        .await?
+        .with_shards(&config.database_url, config.shard_size_blocks)
+        .await?;
+
+    // On-chain metadata, once fetched, overrides TOKEN_DECIMALS and TOKEN_SYMBOL.
+    if let Some(token) = database.get_token(&config.usdc_contract_address).await? {
+        config.set_token(token.decimals, token.symbol)?;
+    }
     
     match cli.command {
-        Commands::Index { start_block, latest } => {
-            let indexer = Indexer::new(config, database).await?;
-            
-            let start_block = if latest {
-                if start_block.is_some() {
-                    eprintln!("Error: Cannot specify both --start-block and --latest");
+        Commands::Index { start_block, latest, daemon, pid_file, dry_run } => {
+            if latest && start_block.is_some() {
+                eprintln!("Error: Cannot specify both --start-block and --latest");
+                std::process::exit(1);
+            }
+            let result = async {
+                let indexer = Indexer::new(config, database).await?;
+
+                let start_block = if latest {
+                    let latest_block = indexer.get_ethereum_client().get_latest_block_number().await?;
+                    let finalized_block = latest_block.saturating_sub(indexer.get_config().finality_blocks);
+                    println!("Starting from network latest block {} (latest {} minus {} finality blocks)", 
+                            finalized_block, latest_block, indexer.get_config().finality_blocks);
+                    Some(finalized_block)
+                } else {
+                    start_block
+                };
+
+                if dry_run {
+                    return print_dry_run(&indexer.dry_run(start_block).await?, &AmountFormat::new(indexer.get_config(), false));
+                }
+                index_until_signal(indexer, start_block).await
+            }
+            .await;
+            // A daemon's stderr is gone, so its last error only survives in the log.
+            if daemon {
+                if let Err(e) = &result {
+                    tracing::error!(error = %format!("{:#}", e), "Indexer stopped");
+                }
+                let _ = std::fs::remove_file(&pid_file);
+            }
+            result?;
+        }
+        Commands::Reindex { from } => {
+            // Archived rows would survive the rollback and be stored twice.
+            if let Some(archived) = database.latest_archived_block().await? {
+                if from <= archived {
+                    eprintln!("Error: blocks up to {} are archived; reindex from {} or later", archived, archived + 1);
                     std::process::exit(1);
                 }
-                let latest_block = indexer.get_ethereum_client().get_latest_block_number().await?;
-                let finalized_block = latest_block.saturating_sub(indexer.get_config().finality_blocks);
-                println!("Starting from network latest block {} (latest {} minus {} finality blocks)", 
-                        finalized_block, latest_block, indexer.get_config().finality_blocks);
-                Some(finalized_block)
+            }
+
+            // Nothing is deleted until the node and configuration are known to match the database.
+            let indexer = Indexer::new(config, database.clone()).await?;
+            indexer.verify_identity().await?;
+
+            database.handle_reorg(from).await?;
+            println!("Rolled back to block {}", from);
+
+            index_until_signal(indexer, Some(from)).await?;
+        }
+        Commands::Query {
+            filter,
+            tx_hash,
+            limit,
+            pending,
+            cursor,
+            output,
+            raw,
+            follow,
+            count,
+            sum,
+            avg,
+            max,
+            usd,
+        } => {
+            let aggregate = count || sum || avg || max;
+            if follow && (pending || aggregate) {
+                eprintln!("Error: --follow cannot be combined with --pending, --count, --sum, --avg or --max");
+                std::process::exit(1);
+            }
+            if usd && pending {
+                eprintln!("Error: --usd cannot be combined with --pending; unfinalized transfers are not priced");
+                std::process::exit(1);
+            }
+
+            let mut printer = TransferPrinter::new(&config, &database, output, raw).await?;
+            printer.usd = usd;
+
+            if let Some(tx_hash) = tx_hash {
+                printer.print(database.get_transfers_by_tx(&tx_hash).await?).await?;
+                return Ok(());
+            }
+
+            let cursor = cursor.as_deref().map(Cursor::decode).transpose()?;
+            let filter = resolve_address_arg(filter, &config, &database).await?;
+            let filter = build_filter(&filter, config.token_decimals, database.now())?.cursor(cursor);
+            printer.address = filter.address.clone();
+            let amounts = &printer.amounts;
+
+            if aggregate {
+                let totals = if pending {
+                    database.aggregate_pending_transfers(&filter).await?
+                } else {
+                    database.aggregate_transfers(&filter).await?
+                };
+                if count {
+                    println!("Transfers: {}", totals.count);
+                }
+                let show = |value: Option<&str>| value.map_or("-".to_string(), |v| amounts.format(v));
+                if sum {
+                    println!("Total value: {}", show(Some(&totals.total_value)));
+                }
+                if avg {
+                    println!("Average value: {}", show(totals.average_value.as_deref()));
+                }
+                if max {
+                    println!("Max value: {}", show(totals.max_value.as_deref()));
+                }
+                if usd && (sum || avg || max) {
+                    let usd_totals = database.aggregate_usd_values(&filter).await?;
+                    let show = |value: Option<&str>| value.map_or("-".to_string(), format_usd);
+                    if sum {
+                        println!("Total USD: {}", show(Some(&usd_totals.total_usd)));
+                    }
+                    if avg {
+                        println!("Average USD: {}", show(usd_totals.average_usd.as_deref()));
+                    }
+                    if max {
+                        println!("Max USD: {}", show(usd_totals.max_usd.as_deref()));
+                    }
+                    if usd_totals.unpriced > 0 {
+                        eprintln!(
+                            "{} of {} transfers have no stored USD value and are left out; index them with PRICE_FEED_ADDRESS set",
+                            usd_totals.unpriced,
+                            usd_totals.priced + usd_totals.unpriced
+                        );
+                    }
+                }
+            } else if pending {
+                let mut transfers = database.query_pending_transfers(&filter, limit.unwrap_or(100)).await?;
+                printer.annotate(&mut transfers).await?;
+                let pending: Vec<PendingTransfer> = transfers.into_iter().map(PendingTransfer::from).collect();
+                print_rows(output, amounts, &pending)?;
             } else {
-                start_block
+                // Taken before the historical page so nothing indexed in between is missed.
+                let watermark = database.get_latest_processed_block().await?.unwrap_or(0);
+                let page = database.query_transfers_page(&filter, limit.unwrap_or(100)).await?;
+                printer.print(page.transfers).await?;
+
+                if let Some(next_cursor) = page.next_cursor {
+                    eprintln!("Next cursor: {}", next_cursor);
+                }
+
+                if follow {
+                    follow_transfers(filter, watermark, &printer).await?;
+                }
+            }
+        }
+        Commands::Blocks { from_block, to_block, limit, output, raw } => {
+            let blocks = database.query_block_activity(from_block, to_block, limit.unwrap_or(100)).await?;
+            print_rows(output, &AmountFormat::new(&config, raw), &blocks)?;
+        }
+        Commands::Erc1155 { contract, address, token_id, limit, output } => {
+            let contract = contract.as_deref().map(normalize_address).transpose()?;
+            let address = address.as_deref().map(normalize_address).transpose()?;
+            let transfers = database
+                .query_erc1155_transfers(contract.as_deref(), address.as_deref(), token_id.as_deref(), limit.unwrap_or(100))
+                .await?;
+            print_rows(output, &AmountFormat::new(&config, false), &transfers)?;
+        }
+        Commands::Export { filter, format, file } => {
+            let filter = resolve_address_arg(filter, &config, &database).await?;
+            let filter = build_filter(&filter, config.token_decimals, database.now())?;
+            let out: Box<dyn std::io::Write> = match &file {
+                Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+                None => Box::new(std::io::stdout().lock()),
             };
-            
-            indexer.start_indexing(start_block).await?;
-        }
-        Commands::Query { 
-            address, 
-            from_block, 
-            to_block, 
-            limit 
-        } => {
-            let transfers = database.query_transfers(
-                address.as_deref(),
+
+            let mut writer = RowWriter::<_, TransferEvent>::new(out, format);
+            let rows = database
+                .for_each_transfer(&filter, |transfer| writer.write(&transfer))
+                .await?;
+            writer.finish()?;
+            eprintln!("Exported {} transfers", rows);
+        }
+        Commands::ExportGraph { filter, format, file } => {
+            let filter = resolve_address_arg(filter, &config, &database).await?;
+            let filter = build_filter(&filter, config.token_decimals, database.now())?;
+            let mut graph = TransferGraph::default();
+            let rows = database.for_each_transfer(&filter, |transfer| graph.add(&transfer)).await?;
+            let labels = database.get_labels(&graph.nodes()).await?;
+            let graph = graph.with_labels(labels);
+
+            let out: Box<dyn std::io::Write> = match &file {
+                Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            graph.write(out, format, &AmountFormat::new(&config, false))?;
+            eprintln!("Wrote {} addresses and {} edges from {} transfers", graph.nodes().len(), graph.edges().count(), rows);
+        }
+        Commands::Import { file, format } => {
+            let format = format.unwrap_or_else(|| detect_format(&file));
+            let mut summary = ImportSummary::default();
+            let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+
+            let mut rows = read_transfers(&file, format)?.peekable();
+            while let Some(row) = rows.next() {
+                batch.push(row?);
+                if batch.len() == IMPORT_BATCH_SIZE || rows.peek().is_none() {
+                    let outcomes = database
+                        .commit_block_range(&batch, &[], None, EventSource::Import, config.dedup_window_blocks)
+                        .await?;
+                    summary.record(&batch, &outcomes);
+                    batch.clear();
+                }
+            }
+
+            // Imported rows can land anywhere in history, so incremental refresh does not cover them.
+            if summary.inserted > 0 || summary.replaced > 0 {
+                database.rebuild_address_stats().await?;
+            }
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        }
+        Commands::Stats { address: Some(address), json } => {
+            let address = resolve_address(&address, &config, &database).await?;
+            let report = address_stats_report(&database, &address).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report.render(&AmountFormat::new(&config, false)));
+            }
+        }
+        Commands::Stats { address: None, json } => {
+            let stats = collect_stats(&database, chain_head(&config).await).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print!("{}", stats.render(&AmountFormat::new(&config, false)));
+            }
+        }
+        Commands::Status { pid_file } => {
+            let pid = running_pid(&pid_file)?;
+            match pid {
+                Some(pid) => println!("Running as pid {}", pid),
+                None => println!("Not running (no daemon holds {})", pid_file.display()),
+            }
+            let last_block = database.query_block_activity(None, None, 1).await?.into_iter().next();
+            let head = chain_head(&config).await;
+            match &last_block {
+                Some(block) => println!("Last processed block: {} ({})", block.block_number, block.timestamp.format("%Y-%m-%d %H:%M:%S UTC")),
+                None => println!("Last processed block: none"),
+            }
+            if let Some(block) = &last_block {
+                let lag_blocks = head.map_or("unknown".to_string(), |head| head.saturating_sub(block.block_number as u64).to_string());
+                let lag_secs = (database.now() - block.timestamp).num_seconds().max(0);
+                println!("Lag: {} blocks, {} seconds", lag_blocks, lag_secs);
+            }
+            if pid.is_none() {
+                std::process::exit(3);
+            }
+        }
+        Commands::Dashboard { min_value, refresh_secs } => {
+            let dashboard = Dashboard {
+                client: EthereumClient::from_config(&config).ok(),
+                amounts: AmountFormat::new(&config, false),
+                min_value: parse_amount(&min_value, config.token_decimals)?.to_string(),
+                refresh: std::time::Duration::from_secs(refresh_secs.max(1)),
+                database,
+            };
+            dashboard.run().await?;
+        }
+        Commands::Serve { listen, dashboard, grpc_listen, query_timeout_secs, rate_limit, rate_limit_burst, .. } => {
+            let state = ApiState {
+                auth: Authenticator::new(database.clone(), &config.api_tokens),
+                rate_limiter: match rate_limit {
+                    0 => None,
+                    _ => Some(Arc::new(RateLimiter::new(rate_limit, rate_limit_burst)?)),
+                },
+                database,
+                contract_address: config.usdc_contract_address.clone(),
+                token_symbol: config.token_symbol.clone(),
+                token_decimals: config.token_decimals,
+                query_timeout: (query_timeout_secs > 0).then(|| std::time::Duration::from_secs(query_timeout_secs)),
+            };
+            let listener = tokio::net::TcpListener::bind(&listen).await?;
+            let grpc_listener = match &grpc_listen {
+                Some(address) => Some(tokio::net::TcpListener::bind(address).await?),
+                None => None,
+            };
+            for address in [Some(&listener), grpc_listener.as_ref()].into_iter().flatten().map(|l| l.local_addr()) {
+                let address = address?;
+                if !address.ip().is_loopback() && config.api_tokens.is_empty() && !state.database.has_api_keys().await? {
+                    tracing::warn!(%address, "No API tokens exist, so anyone who can reach the listener can read the API; see `api-key create`");
+                }
+            }
+            println!("Serving the API on http://{}", listener.local_addr()?);
+            if dashboard {
+                println!("Dashboard at http://{}/", listener.local_addr()?);
+            }
+            match grpc_listener {
+                Some(grpc_listener) => {
+                    println!("Serving gRPC on {}", grpc_listener.local_addr()?);
+                    let grpc = serve_grpc(grpc_listener, GrpcService::new(state.clone()));
+                    tokio::try_join!(serve(listener, router(state, dashboard)), grpc)?;
+                }
+                None => serve(listener, router(state, dashboard)).await?,
+            }
+        }
+        Commands::Archive { before_block } => {
+            let summary = database.archive_transfers(before_block, config.archive_batch_blocks).await?;
+            println!("Archived {} transfers into {} batches", summary.rows, summary.batches);
+            println!("Size: {} bytes -> {} bytes compressed", summary.raw_bytes, summary.compressed_bytes);
+        }
+        Commands::Replay { from_block, to_block } => {
+            let Some(latest) = database.get_latest_processed_block().await? else {
+                println!("Nothing indexed yet, nothing to replay");
+                return Ok(());
+            };
+
+            let summary = replay(&database, from_block, to_block.unwrap_or(latest)).await?;
+            println!(
+                "Replayed {} blocks: decoded {} transfers and {} ERC-1155 transfers from {} raw logs",
+                summary.blocks, summary.transfers, summary.erc1155_transfers, summary.logs
+            );
+            if summary.skipped_blocks > 0 {
+                eprintln!("Skipped {} blocks indexed without RAW_LOGS", summary.skipped_blocks);
+            }
+        }
+        Commands::Prune { keep_days, keep_blocks } => {
+            let Some(latest) = database.get_latest_processed_block().await? else {
+                println!("Nothing indexed yet, nothing to prune");
+                return Ok(());
+            };
+
+            let before_block = match (keep_days, keep_blocks) {
+                (Some(days), _) => {
+                    let since = database.now() - Duration::days(days);
+                    database.first_block_since(since).await?.unwrap_or(latest + 1)
+                }
+                (None, Some(blocks)) => (latest + 1).saturating_sub(blocks),
+                (None, None) => unreachable!("clap requires --keep-days or --keep-blocks"),
+            };
+
+            let summary = database.prune(before_block).await?;
+            println!(
+                "Pruned {} transfers, {} ERC-1155 transfers, {} transactions, {} archive batches, {} raw logs and {} processed blocks below block {}",
+                summary.transfers,
+                summary.erc1155_transfers,
+                summary.transactions,
+                summary.archive_batches,
+                summary.raw_logs,
+                summary.processed_blocks,
+                summary.before_block
+            );
+        }
+        Commands::Rollups { address, rebuild } => {
+            if rebuild {
+                database.rebuild_address_stats().await?;
+                println!("Rebuilt address stats");
+            }
+
+            if let Some(address) = address {
+                let address = normalize_address(&address)?;
+                match database.get_address_stats(&address).await? {
+                    Some(stats) => println!("{}", serde_json::to_string_pretty(&stats)?),
+                    None => println!("No activity recorded for {}", address),
+                }
+            }
+        }
+        Commands::Finality { days } => {
+            let since = database.now() - Duration::days(days);
+            let depths = database.get_reorg_depths(since).await?;
+            let blocks_observed = database.count_processed_blocks_since(since).await?;
+            let report = FinalityReport::new(days, &depths, blocks_observed, config.finality_blocks);
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Commands::Analytics { command } => match command {
+            AnalyticsCommand::GasSpent { address } => {
+                let address = normalize_address(&address)?;
+                let report = gas_spent(&database, &address).await?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                if report.unenriched_transactions > 0 {
+                    eprintln!(
+                        "{} transactions have no stored fee; index them with TX_ENRICHMENT=true",
+                        report.unenriched_transactions
+                    );
+                }
+            }
+            AnalyticsCommand::CategoryVolume { category, direction, from_time, to_time, output } => {
+                let addresses = database.label_addresses(&category).await?;
+                if addresses.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "No labels in category {}; add some with label add --category or label import-known",
+                        category
+                    ));
+                }
+                let now = database.now();
+                let from_time = from_time.as_deref().map(|t| parse_time(t, now)).transpose()?;
+                let to_time = to_time.as_deref().map(|t| parse_time(t, now)).transpose()?;
+                let days = database.daily_volume(&addresses, direction, from_time, to_time).await?;
+                print_rows(output, &AmountFormat::new(&config, false), &days)?;
+            }
+            AnalyticsCommand::Velocity { interval, from_time, to_time, output } => {
+                let now = database.now();
+                let from_time = parse_time(&from_time, now)?;
+                let to_time = to_time.as_deref().map(|t| parse_time(t, now)).transpose()?;
+                let periods = velocity(&database, interval, Some(from_time), to_time).await?;
+                print_rows(output, &AmountFormat::new(&config, false), &periods)?;
+            }
+        },
+        Commands::Report { address, top, json } => {
+            let address = resolve_address(&address, &config, &database).await?;
+            let report = address_report(&database, &address, top).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report.render(&AmountFormat::new(&config, false)));
+            }
+        }
+        Commands::Counterparties { filter, by, limit, output } => {
+            if filter.address.is_none() {
+                return Err(anyhow::anyhow!("counterparties needs --address"));
+            }
+            let filter = resolve_address_arg(filter, &config, &database).await?;
+            let filter = build_filter(&filter, config.token_decimals, database.now())?;
+            let counterparties = database.counterparties(&filter, by, limit).await?;
+            print_rows(output, &AmountFormat::new(&config, false), &counterparties)?;
+        }
+        Commands::Netflow { filter, interval, output } => {
+            if filter.address.is_none() {
+                return Err(anyhow::anyhow!("netflow needs --address"));
+            }
+            let filter = resolve_address_arg(filter, &config, &database).await?;
+            let filter = build_filter(&filter, config.token_decimals, database.now())?;
+            let flows = database.net_flows(&filter, interval).await?;
+            print_rows(output, &AmountFormat::new(&config, false), &flows)?;
+        }
+        Commands::TraceFlow { from, to, max_hops, from_time, to_time, min_value, limit, json } => {
+            let from = resolve_address(&from, &config, &database).await?;
+            let to = resolve_address(&to, &config, &database).await?;
+            let now = database.now();
+            let min_value = min_value
+                .as_deref()
+                .map(|v| parse_amount(v, config.token_decimals).map(|amount| amount.to_string()))
+                .transpose()?;
+            let filter = TransferFilter::new()
+                .time_range(
+                    from_time.as_deref().map(|t| parse_time(t, now)).transpose()?,
+                    to_time.as_deref().map(|t| parse_time(t, now)).transpose()?,
+                )
+                .value_range(min_value, None);
+            let trace = trace_flow(&database, &from, &to, max_hops, &filter, limit).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&trace)?);
+            } else {
+                print!("{}", trace.render(&AmountFormat::new(&config, false)));
+            }
+        }
+        Commands::Holders { history, backfill, from_time, to_time, output } => {
+            if backfill {
+                if config.holder_snapshot_secs == 0 {
+                    return Err(anyhow::anyhow!("Set HOLDER_SNAPSHOT_SECS to the snapshot period to backfill"));
+                }
+                let written = backfill_holder_snapshots(&database, config.holder_snapshot_secs).await?;
+                eprintln!("Recorded {} holder snapshots", written);
+            }
+
+            let snapshots = if history || backfill {
+                let now = database.now();
+                database
+                    .get_holder_snapshots(
+                        from_time.as_deref().map(|t| parse_time(t, now)).transpose()?,
+                        to_time.as_deref().map(|t| parse_time(t, now)).transpose()?,
+                    )
+                    .await?
+            } else {
+                match database.holder_snapshot().await? {
+                    Some(snapshot) => vec![snapshot],
+                    None => return Err(anyhow::anyhow!("No address stats yet; index some blocks or run rollups --rebuild")),
+                }
+            };
+            print_rows(output, &AmountFormat::new(&config, false), &snapshots)?;
+        }
+        Commands::HolderDistribution { buckets, output } => {
+            let buckets = if buckets.is_empty() { DEFAULT_BUCKETS.map(str::to_string).to_vec() } else { buckets };
+            let bounds = buckets
+                .iter()
+                .map(|b| parse_amount(b, config.token_decimals))
+                .collect::<Result<Vec<_>>>()?;
+            let balances = database.holder_balances().await?;
+            print_rows(output, &AmountFormat::new(&config, false), &holder_distribution(&balances, &bounds))?;
+        }
+        Commands::Concentration { tops } => {
+            let tops = if tops.is_empty() { DEFAULT_TOPS.to_vec() } else { tops };
+            let balances = database.holder_balances().await?;
+            println!("{}", serde_json::to_string_pretty(&concentration(&balances, &tops))?);
+        }
+        Commands::Bench { fixture, blocks, transfers_per_block, batch_blocks, output } => {
+            let logs = match &fixture {
+                Some(path) => recorded_logs(path)?,
+                None => synthetic_logs(&config.usdc_contract_address, blocks, transfers_per_block),
+            };
+            let mut results = Vec::with_capacity(batch_blocks.len());
+            for batch in batch_blocks {
+                results.push(bench(&config, &std::env::temp_dir(), &logs, batch).await?);
+            }
+            print_rows(output, &AmountFormat::new(&config, false), &results)?;
+        }
+        Commands::Latency { hours } => {
+            let now = database.now();
+            let samples = database.get_latency_samples(now - Duration::hours(hours), now).await?;
+            let report = LatencyReport::new(
+                hours,
+                &samples,
+                config.finality_blocks,
+                config.freshness_slo_secs,
+                config.freshness_slo_percent,
+            );
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Commands::Backtest { from, to, step_mins } => {
+            let now = database.now();
+            let from = parse_time(&from, now)?;
+            let to = to.as_deref().map(|to| parse_time(to, now)).transpose()?.unwrap_or(now);
+
+            let fired = backtest(&database, &config, from, to, Duration::minutes(step_mins)).await?;
+            for alert in &fired {
+                println!("{}", serde_json::to_string(alert)?);
+            }
+            eprintln!("{} alerts would have fired between {} and {}", fired.len(), from, to);
+        }
+        Commands::Conflicts { limit } => {
+            let conflicts = database.list_ingest_conflicts(limit.unwrap_or(100)).await?;
+            println!("Resolved ingest conflicts: {}", conflicts.len());
+            for conflict in conflicts {
+                println!("{}", serde_json::to_string(&conflict)?);
+            }
+        }
+        Commands::Gaps { fill } => {
+            let gaps = database.find_gaps().await?;
+            println!("Gaps: {}", gaps.len());
+            for gap in &gaps {
+                println!("{}", serde_json::to_string(gap)?);
+            }
+
+            if fill && !gaps.is_empty() {
+                let indexer = Indexer::new(config, database).await?;
+                let inserted = indexer.fill_gaps(&gaps).await?;
+                println!("Filled {} gaps, stored {} transfers", gaps.len(), inserted);
+            }
+        }
+        Commands::Verify { from_block, to_block, sample } => {
+            let client = EthereumClient::from_config(&config)?
+                .with_clock(database.clock());
+            let watchlist = if config.watchlist_only {
+                Some(database.watched_addresses().await?)
+            } else {
+                None
+            };
+            let mut report = VerifyReport::default();
+
+            match (from_block, to_block) {
+                (Some(from), Some(to)) => {
+                    let mut start = from;
+                    while start <= to {
+                        let end = std::cmp::min(start + config.blocks_per_request - 1, to);
+                        verify_range(&client, &database, start, end, watchlist.as_deref(), &mut report).await?;
+                        start = end + 1;
+                    }
+                }
+                _ => {
+                    for block in database.sample_processed_blocks(sample).await? {
+                        verify_range(&client, &database, block, block, watchlist.as_deref(), &mut report).await?;
+                    }
+                }
+            }
+
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.is_clean() {
+                eprintln!(
+                    "Found {} missing, {} extra and {} mismatched transfers",
+                    report.missing.len(),
+                    report.extra.len(),
+                    report.mismatched.len()
+                );
+                std::process::exit(1);
+            }
+        }
+        Commands::Label { command } => match command {
+            LabelCommand::Add { address, name, category } => {
+                database.add_label(&address, &name, category.as_deref()).await?;
+                println!("Labeled {} as {}", normalize_address(&address)?, name);
+            }
+            LabelCommand::Remove { address } => {
+                if database.remove_label(&address).await? {
+                    println!("Removed label for {}", address);
+                } else {
+                    println!("No label for {}", address);
+                }
+            }
+            LabelCommand::Import { file, on_conflict } => {
+                let labels = read_labels(&file)?;
+                let summary = database.import_labels(&labels, on_conflict).await?;
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            }
+            LabelCommand::ImportKnown { on_conflict } => {
+                let summary = database.import_labels(&known_entity_labels()?, on_conflict).await?;
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            }
+            LabelCommand::List { output } => {
+                print_rows(output, &AmountFormat::new(&config, false), &database.list_labels().await?)?;
+            }
+        },
+        Commands::Watch { filter } => {
+            let filter = resolve_address_arg(filter, &config, &database).await?;
+            let filter = build_filter(&filter, config.token_decimals, database.now())?;
+            let printer = TransferPrinter::new(&config, &database, OutputFormat::Table, false).await?;
+
+            let watermark = database.get_latest_processed_block().await?.unwrap_or(0);
+            eprintln!("Watching for matching transfers after block {}", watermark);
+
+            let mut tail = TransferTail::new(filter, watermark);
+            while let Some(transfers) = tail.next(&database).await? {
+                printer.alert(transfers).await?;
+            }
+        }
+        Commands::Stream { start_block, follow } => {
+            let mut writer = RowWriter::<_, TransferEvent>::new(std::io::stdout().lock(), ExportFormat::Jsonl);
+
+            if follow {
+                let watermark = database.get_latest_processed_block().await?.unwrap_or(0);
+                eprintln!("Streaming transfers indexed after block {}", watermark);
+
+                let mut tail = TransferTail::new(TransferFilter::new(), watermark);
+                while let Some(transfers) = tail.next(&database).await? {
+                    for transfer in &transfers {
+                        if !write_line(&mut writer, transfer)? {
+                            return Ok(());
+                        }
+                    }
+                }
+            } else {
+                let indexer = Arc::new(Indexer::new(config, database).await?);
+                let mut transfers = Box::pin(indexer.transfer_stream());
+                let mut indexing = tokio::spawn({
+                    let indexer = indexer.clone();
+                    async move { indexer.start_indexing(start_block).await }
+                });
+
+                loop {
+                    tokio::select! {
+                        result = &mut indexing => return result?,
+                        Some(transfer) = transfers.next() => {
+                            if !write_line(&mut writer, &transfer)? {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Commands::Sanctions { command } => match command {
+            SanctionsCommand::Refresh { source } => {
+                let source = source
+                    .or_else(|| config.sanctions_list.clone())
+                    .ok_or_else(|| anyhow::anyhow!("Set SANCTIONS_LIST or pass --source"))?;
+                let count = refresh_sanctions(&database, &source).await?;
+                println!("Loaded {} sanctioned addresses from {}", count, source);
+            }
+            SanctionsCommand::Status => {
+                println!("{}", serde_json::to_string_pretty(&database.get_sanctions_status().await?)?);
+            }
+        },
+        Commands::Watchlist { command } => match command {
+            WatchlistCommand::Add { address } => {
+                let address = normalize_address(&address)?;
+                if database.add_watch(&address).await? {
+                    println!("Watching {}", address);
+                } else {
+                    println!("Already watching {}", address);
+                }
+            }
+            WatchlistCommand::Remove { address } => {
+                if database.remove_watch(&address).await? {
+                    println!("Stopped watching {}", address);
+                } else {
+                    println!("Not watching {}", address);
+                }
+            }
+            WatchlistCommand::List { output } => {
+                print_rows(output, &AmountFormat::new(&config, false), &database.list_watchlist().await?)?;
+            }
+        },
+        Commands::Alert { command } => match command {
+            AlertCommand::Add {
+                name,
+                address,
+                direction,
+                min_value,
+                max_value,
+                counterparty_label,
+                min_count,
+                window,
+                channels,
+            } => {
+                if let Some(channel) = channels.iter().find(|c| !is_sink_name(c)) {
+                    eprintln!(
+                        "Error: unknown channel {} (expected one of {}, or slack:<route> / discord:<route>)",
+                        channel,
+                        SINK_NAMES.join(", ")
+                    );
+                    std::process::exit(1);
+                }
+                let parse_value = |value: Option<String>| {
+                    value
+                        .map(|v| parse_amount(&v, config.token_decimals).map(|amount| amount.to_string()))
+                        .transpose()
+                };
+
+                let rule = AlertRule {
+                    id: 0,
+                    name,
+                    address: address.as_deref().map(normalize_address).transpose()?,
+                    direction,
+                    min_value: parse_value(min_value)?,
+                    max_value: parse_value(max_value)?,
+                    counterparty_label,
+                    min_count,
+                    window_secs: window.map(|w| w.num_seconds()),
+                    channels,
+                    created_at: database.now(),
+                };
+                let id = database.add_alert_rule(&rule).await?;
+                println!("Added alert rule {}", id);
+            }
+            AlertCommand::List { output } => {
+                print_rows(output, &AmountFormat::new(&config, false), &database.list_alert_rules().await?)?;
+            }
+            AlertCommand::Delete { id } => {
+                if database.delete_alert_rule(id).await? {
+                    println!("Deleted alert rule {}", id);
+                } else {
+                    println!("No alert rule {}", id);
+                }
+            }
+            AlertCommand::Test { id, from_time, send } => {
+                let Some(rule) = database.get_alert_rule(id).await? else {
+                    eprintln!("Error: no alert rule {}", id);
+                    std::process::exit(1);
+                };
+
+                let filter = rule.filter().time_range(Some(parse_time(&from_time, database.now())?), None);
+                let mut transfers = Vec::new();
+                database
+                    .for_each_transfer(&filter, |transfer| {
+                        transfers.push(transfer);
+                        Ok(())
+                    })
+                    .await?;
+
+                let rules = [rule];
+                let fired = RuleMonitor::default().evaluate(&database, &config, &rules, &transfers).await?;
+                for (_, alert) in &fired {
+                    println!("{}", serde_json::to_string(alert)?);
+                }
+                eprintln!("Rule {} would have fired {} times over {} candidate transfers", id, fired.len(), transfers.len());
+
+                if send {
+                    match fired.last() {
+                        Some((rule, fired)) => {
+                            Notifier::from_config(&config)?.notify_via(&fired.alert, database.now(), &rule.channels).await;
+                            eprintln!("Sent the most recent alert");
+                        }
+                        None => eprintln!("Nothing to send"),
+                    }
+                }
+            }
+        },
+        Commands::Sinks { command } => match command {
+            SinksCommand::Backfill {
+                target,
                 from_block,
                 to_block,
-                limit.unwrap_or(100)
+                rule,
+            } => {
+                let rules = match rule {
+                    Some(id) => match database.get_alert_rule(id).await? {
+                        Some(rule) => vec![rule],
+                        None => {
+                            eprintln!("Error: no alert rule {}", id);
+                            std::process::exit(1);
+                        }
+                    },
+                    None => database
+                        .list_alert_rules()
+                        .await?
+                        .into_iter()
+                        .filter(|rule| rule.channels.is_empty() || rule.channels.contains(&target))
+                        .collect(),
+                };
+                let large_transfers = rule.is_none()
+                    && config.alert_min_value.is_some()
+                    && (config.alert_min_value_channels.is_empty() || config.alert_min_value_channels.contains(&target));
+                if rules.is_empty() && !large_transfers {
+                    eprintln!("Error: no alert rules or ALERT_MIN_VALUE route alerts to {}", target);
+                    std::process::exit(1);
+                }
+
+                let to_block = match to_block {
+                    Some(to_block) => to_block,
+                    None => database.get_latest_processed_block().await?.unwrap_or(from_block),
+                };
+                let delivered = Notifier::from_config(&config)?
+                    .backfill(&database, &config, &target, &rules, large_transfers, from_block, to_block)
+                    .await?;
+                eprintln!("Delivered {} alerts for blocks {} to {} to {}", delivered, from_block, to_block, target);
+            }
+        },
+        Commands::Clickhouse { command } => match command {
+            ClickHouseCommand::Backfill { from_block } => {
+                let Some(writer) = ClickHouseWriter::from_config(&config)? else {
+                    return Err(anyhow::anyhow!("Set CLICKHOUSE_URL to backfill ClickHouse"));
+                };
+                let Some(latest) = database.get_latest_processed_block().await? else {
+                    println!("Nothing indexed yet, nothing to backfill");
+                    return Ok(());
+                };
+                let written = writer.backfill(&database, from_block, latest).await?;
+                println!("Wrote {} transfers for blocks {} to {} to ClickHouse", written, from_block, latest);
+            }
+        },
+        Commands::ApiKey { command } => match command {
+            ApiKeyCommand::Create { name, scope } => {
+                let token = create_api_key(&database, &name, scope).await?;
+                println!("Created {} key {}. Its token is shown only this once:", scope.as_str(), name.trim());
+                println!("{}", token);
+            }
+            ApiKeyCommand::Revoke { name } => {
+                if database.revoke_api_key(&name).await? {
+                    println!("Revoked {}", name);
+                } else {
+                    println!("No API key named {}", name);
+                }
+            }
+            ApiKeyCommand::List { output } => {
+                print_rows(output, &AmountFormat::new(&config, false), &database.list_api_keys().await?)?;
+            }
+        },
+        Commands::Token { command } => match command {
+            TokenCommand::Info { refresh } => {
+                let token = match database.get_token(&config.usdc_contract_address).await? {
+                    Some(token) if !refresh => token,
+                    _ => {
+                        let client = EthereumClient::from_config(&config)?
+                            .with_clock(database.clock());
+                        let token = client.get_token_metadata().await?;
+                        database.set_token(&token).await?;
+                        token
+                    }
+                };
+                println!("{}", serde_json::to_string_pretty(&token)?);
+            }
+            TokenCommand::List { output } => {
+                let checkpoints = database.get_token_checkpoints(None).await?;
+                print_rows(output, &AmountFormat::new(&config, false), &checkpoints)?;
+            }
+        },
+        Commands::Db { command } => match command {
+            DbCommand::Compact => {
+                let summary = database.compact().await?;
+                println!("Compacted {} database files", summary.files);
+                println!(
+                    "Size: {} bytes -> {} bytes ({} bytes reclaimed)",
+                    summary.bytes_before,
+                    summary.bytes_after,
+                    summary.reclaimed()
+                );
+            }
+            DbCommand::Backup { path } => {
+                let summary = database.backup(&path).await?;
+                for file in &summary.files {
+                    println!("Wrote {}", file);
+                }
+                println!("Backed up {} bytes", summary.bytes);
+            }
+        },
+        Commands::Migrate { .. } => unreachable!("handled before the database is opened"),
+        Commands::WatchMempool => {
+            let ws_url = match config.ethereum_ws_url.clone() {
+                Some(url) => url,
+                None => {
+                    eprintln!("Error: ETHEREUM_WS_URL must be set to watch the mempool");
+                    std::process::exit(1);
+                }
+            };
+
+            let watcher = MempoolWatcher::new(
+                &ws_url,
+                &config.usdc_contract_address,
+                database,
+                config.mempool_drop_after_secs,
             ).await?;
-            
-            for transfer in transfers {
-                println!("{}", serde_json::to_string_pretty(&transfer)?);
+
+            watcher.run(|pending| {
+                match serde_json::to_string(pending) {
+                    Ok(line) => println!("{}", line),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            }).await?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Forks into the background for `index --daemon`. This happens before the Tokio runtime starts,
+/// as its threads would not survive the fork. The parent exits once the daemon is detached; the
+/// daemon keeps the working directory, for `.env` and relative database paths, and holds a lock on
+/// `pid_file` while it runs.
+fn detach(pid_file: &Path, config: &Config) -> Result<()> {
+    if let Some(pid) = running_pid(pid_file)? {
+        return Err(anyhow::anyhow!("Already running as pid {} ({})", pid, pid_file.display()));
+    }
+    if config.log_file.is_none() {
+        eprintln!("Warning: LOG_FILE is not set, so the daemon's logs are discarded");
+    }
+    println!("Indexing in the background; `status --pid-file {}` reports on it", pid_file.display());
+    Daemonize::new()
+        .pid_file(pid_file)
+        .working_directory(std::env::current_dir()?)
+        .umask(0o022)
+        .start()?;
+    Ok(())
+}
+
+/// Latest block number from the RPC node, or `None` with a warning when it does not answer
+/// within 10 seconds, so reports can still show what the database knows.
+async fn chain_head(config: &Config) -> Option<u64> {
+    let head = async {
+        let client = EthereumClient::from_config(config)?;
+        tokio::time::timeout(std::time::Duration::from_secs(10), client.get_latest_block_number())
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out"))?
+    };
+    match head.await {
+        Ok(head) => Some(head),
+        Err(e) => {
+            eprintln!("Could not read the chain head, lag is unknown: {}", e);
+            None
+        }
+    }
+}
+
+/// Runs `indexer` until a fatal error or a shutdown signal. The first Ctrl-C or SIGTERM (which
+/// systemd and `kill` send) lets it finish the block range in progress; a second one exits at once.
+async fn index_until_signal(indexer: Indexer, start_block: Option<u64>) -> Result<()> {
+    let indexer = Arc::new(indexer);
+    let signals = tokio::spawn({
+        let indexer = indexer.clone();
+        async move {
+            shutdown_signal().await;
+            systemd::notify_stopping();
+            eprintln!("Stopping after the current block range; interrupt again to exit now");
+            indexer.stop();
+            shutdown_signal().await;
+            std::process::exit(130);
+        }
+    });
+    let result = indexer.start_indexing(start_block).await;
+    signals.abort();
+    result
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM where there are Unix signals.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    if let Ok(mut terminate) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+        return;
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+fn build_filter(args: &FilterArgs, decimals: u8, now: DateTime<Utc>) -> Result<TransferFilter> {
+    let parse_time = |input: &str| parse_time(input, now);
+    let parse_value = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|v| parse_amount(v, decimals).map(|amount| amount.to_string()))
+            .transpose()
+    };
+
+    Ok(TransferFilter::new()
+        .address(args.address.as_deref().map(normalize_address).transpose()?)
+        .direction(args.direction)
+        .block_range(args.from_block, args.to_block)
+        .value_range(parse_value(&args.min_value)?, parse_value(&args.max_value)?)
+        .time_range(
+            args.from_time.as_deref().map(parse_time).transpose()?,
+            args.to_time.as_deref().map(parse_time).transpose()?,
+        )
+        .address_kinds(args.from_kind, args.to_kind)
+        .sanctioned(args.sanctioned)
+        .include_archived(args.archived))
+}
+
+async fn ens_resolver(config: &Config, database: &Database) -> Result<EnsResolver> {
+    let client = EthereumClient::from_config(config)?
+        .with_clock(database.clock());
+    Ok(EnsResolver::new(client, config.ens_cache_ttl_secs))
+}
+
+/// Checksums an address given on the command line, resolving it first if it is an ENS name.
+async fn resolve_address(input: &str, config: &Config, database: &Database) -> Result<String> {
+    if !is_ens_name(input) {
+        return normalize_address(input);
+    }
+    let address = ens_resolver(config, database).await?.resolve(database, input).await?;
+    eprintln!("Resolved {} to {}", input, address);
+    Ok(address)
+}
+
+/// Replaces an ENS name given as `--address` with the address it resolves to.
+async fn resolve_address_arg(mut args: FilterArgs, config: &Config, database: &Database) -> Result<FilterArgs> {
+    if let Some(name) = args.address.as_deref().filter(|a| is_ens_name(a)) {
+        let address = ens_resolver(config, database).await?.resolve(database, name).await?;
+        eprintln!("Resolved {} to {}", name, address);
+        args.address = Some(address);
+    }
+    Ok(args)
+}
+
+/// Blocks with events, then totals and the first `DRY_RUN_SAMPLES` transfers as `query` prints them.
+fn print_dry_run(run: &DryRun, amounts: &AmountFormat) -> Result<()> {
+    println!("Dry run of blocks {} to {} on chain {}; nothing was written", run.start_block, run.end_block, run.chain_id);
+    let mut per_block: BTreeMap<i64, (usize, usize)> = BTreeMap::new();
+    for transfer in &run.transfers {
+        per_block.entry(transfer.block_number).or_default().0 += 1;
+    }
+    for transfer in &run.erc1155_transfers {
+        per_block.entry(transfer.block_number).or_default().1 += 1;
+    }
+    for (block, (transfers, erc1155)) in &per_block {
+        match erc1155 {
+            0 => println!("Block {}: {} transfers", block, transfers),
+            _ => println!("Block {}: {} transfers, {} ERC-1155 transfers", block, transfers, erc1155),
+        }
+    }
+    println!(
+        "Would store {} transfers and {} ERC-1155 transfers from {} of {} blocks",
+        run.transfers.len(),
+        run.erc1155_transfers.len(),
+        per_block.len(),
+        run.end_block - run.start_block + 1
+    );
+    if !run.transfers.is_empty() {
+        println!();
+        print_rows(OutputFormat::Table, amounts, &run.transfers[..run.transfers.len().min(DRY_RUN_SAMPLES)])?;
+    }
+    Ok(())
+}
+
+/// How `query` renders transfers.
+struct TransferPrinter<'a> {
+    database: &'a Database,
+    output: OutputFormat,
+    amounts: AmountFormat,
+    /// The queried address, which adds a direction column.
+    address: Option<String>,
+    /// Set when `ENS_RESOLUTION` is on.
+    ens: Option<EnsResolver>,
+    /// Adds a `usd_value` column.
+    usd: bool,
+}
+
+impl<'a> TransferPrinter<'a> {
+    async fn new(config: &Config, database: &'a Database, output: OutputFormat, raw: bool) -> Result<Self> {
+        let ens = if config.ens_resolution {
+            Some(ens_resolver(config, database).await?)
+        } else {
+            None
+        };
+        Ok(Self {
+            database,
+            output,
+            amounts: AmountFormat::new(config, raw),
+            address: None,
+            ens,
+            usd: false,
+        })
+    }
+
+    async fn annotate(&self, transfers: &mut [TransferEvent]) -> Result<()> {
+        match &self.ens {
+            Some(ens) => ens.annotate(self.database, transfers).await,
+            None => Ok(()),
+        }
+    }
+
+    /// One highlighted line per transfer, for `watch`.
+    async fn alert(&self, mut transfers: Vec<TransferEvent>) -> Result<()> {
+        self.annotate(&mut transfers).await?;
+        let color = std::io::stdout().is_terminal();
+        for transfer in &transfers {
+            println!("{}", alert_line(transfer, &self.amounts, color));
+        }
+        Ok(())
+    }
+
+    async fn print(&self, mut transfers: Vec<TransferEvent>) -> Result<()> {
+        self.annotate(&mut transfers).await?;
+        match (&self.address, self.usd) {
+            (Some(address), false) => {
+                let directed: Vec<DirectedTransfer> = transfers
+                    .into_iter()
+                    .map(|transfer| DirectedTransfer::new(transfer, address))
+                    .collect();
+                print_rows(self.output, &self.amounts, &directed)
+            }
+            (Some(address), true) => {
+                let directed: Vec<PricedRow<DirectedTransfer>> = transfers
+                    .into_iter()
+                    .map(|transfer| PricedRow {
+                        usd_value: transfer.usd_value.clone(),
+                        row: DirectedTransfer::new(transfer, address),
+                    })
+                    .collect();
+                print_rows(self.output, &self.amounts, &directed)
+            }
+            (None, false) => print_rows(self.output, &self.amounts, &transfers),
+            (None, true) => {
+                let priced: Vec<PricedRow<TransferEvent>> = transfers
+                    .into_iter()
+                    .map(|transfer| PricedRow {
+                        usd_value: transfer.usd_value.clone(),
+                        row: transfer,
+                    })
+                    .collect();
+                print_rows(self.output, &self.amounts, &priced)
             }
         }
-        Commands::Stats => {
-            let stats = database.get_stats().await?;
-            println!("Database Statistics:");
-            println!("Total transfers: {}", stats.total_transfers);
-            println!("Unique addresses: {}", stats.unique_addresses);
-            println!("Latest block: {}", stats.latest_block.unwrap_or(0));
-            println!("Earliest block: {}", stats.earliest_block.unwrap_or(0));
+    }
+}
+
+/// Writes `rows` to stdout in the requested format.
+fn print_rows<T: serde::Serialize + Tabular>(format: OutputFormat, amounts: &AmountFormat, rows: &[T]) -> Result<()> {
+    write_rows(std::io::stdout().lock(), format, amounts, rows)
+}
+
+/// Writes one JSON line, returning `false` once the reader has closed stdout.
+fn write_line<W: std::io::Write>(writer: &mut RowWriter<W, TransferEvent>, transfer: &TransferEvent) -> Result<bool> {
+    match writer.write(transfer) {
+        Ok(()) => Ok(true),
+        Err(e) if is_broken_pipe(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    let kind = match error.downcast_ref::<serde_json::Error>() {
+        Some(e) => e.io_error_kind(),
+        None => error.downcast_ref::<std::io::Error>().map(|e| e.kind()),
+    };
+    kind == Some(std::io::ErrorKind::BrokenPipe)
+}
+
+/// Polls for blocks indexed past a watermark and yields the matching transfers, oldest first.
+struct TransferTail {
+    filter: TransferFilter,
+    watermark: u64,
+}
+
+impl TransferTail {
+    fn new(mut filter: TransferFilter, watermark: u64) -> Self {
+        filter.cursor = None;
+        Self { filter, watermark }
+    }
+
+    /// Waits until new blocks are indexed and returns their matching transfers, or `None` once
+    /// the filter's `to_block` has been passed.
+    async fn next(&mut self, database: &Database) -> Result<Option<Vec<TransferEvent>>> {
+        let to_block = self.filter.to_block;
+        loop {
+            if to_block.is_some_and(|to| self.watermark >= to) {
+                return Ok(None);
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(FOLLOW_POLL_SECS)).await;
+
+            let latest = match database.get_latest_processed_block().await? {
+                Some(latest) if latest > self.watermark => latest,
+                _ => continue,
+            };
+            let through = to_block.map_or(latest, |to| latest.min(to));
+
+            let from = self.filter.from_block.map_or(self.watermark + 1, |from| from.max(self.watermark + 1));
+            let mut page_filter = self.filter.clone().block_range(Some(from), Some(through));
+            let mut transfers = Vec::new();
+            loop {
+                let page = database.query_transfers_page(&page_filter, 1000).await?;
+                transfers.extend(page.transfers);
+                match page.next_cursor {
+                    Some(next) => page_filter = page_filter.cursor(Some(Cursor::decode(&next)?)),
+                    None => break,
+                }
+            }
+
+            transfers.sort_by_key(|t| (t.block_number, t.log_index));
+            self.watermark = latest;
+            return Ok(Some(transfers));
+        }
+    }
+}
+
+/// Prints transfers matching `filter` as blocks past `watermark` are indexed.
+async fn follow_transfers(filter: TransferFilter, watermark: u64, printer: &TransferPrinter<'_>) -> Result<()> {
+    let mut tail = TransferTail::new(filter, watermark);
+    while let Some(transfers) = tail.next(printer.database).await? {
+        if !transfers.is_empty() {
+            printer.print(transfers).await?;
         }
     }
-    
     Ok(())
-}
\ No newline at end of file
+}
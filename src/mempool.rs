@@ -0,0 +1,208 @@
+use anyhow::Result;
+use chrono::Duration as ChronoDuration;
+use ethers::prelude::*;
+use ethers_core::types::{H160, H256};
+use ethers_providers::{Middleware, Provider, Ws};
+use tracing::{error, info, warn};
+use std::time::Duration;
+
+use crate::{address::checksum, database::Database, models::PendingTx};
+
+const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+const TRANSFER_FROM_SELECTOR: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+const CLEANUP_INTERVAL_SECS: u64 = 30;
+
+pub struct MempoolWatcher {
+    provider: Provider<Ws>,
+    usdc_address: H160,
+    database: Database,
+    drop_after: ChronoDuration,
+}
+
+impl MempoolWatcher {
+    pub async fn new(ws_url: &str, usdc_address: &str, database: Database, drop_after_secs: u64) -> Result<Self> {
+        let provider = Provider::<Ws>::connect(ws_url).await?;
+        let usdc_address: H160 = usdc_address.parse()?;
+
+        Ok(Self {
+            provider,
+            usdc_address,
+            database,
+            drop_after: ChronoDuration::seconds(drop_after_secs as i64),
+        })
+    }
+
+    pub async fn run<F>(&self, on_pending: F) -> Result<()>
+    where
+        F: Fn(&PendingTx),
+    {
+        let mut stream = self.provider.subscribe_pending_txs().await?;
+        let mut cleanup = tokio::time::interval(Duration::from_secs(CLEANUP_INTERVAL_SECS));
+
+        info!(contract = ?self.usdc_address, "Watching mempool for transfers");
+
+        loop {
+            tokio::select! {
+                next = stream.next() => {
+                    let Some(tx_hash) = next else {
+                        return Err(anyhow::anyhow!("Pending transaction subscription closed"));
+                    };
+
+                    match self.inspect_transaction(tx_hash).await {
+                        Ok(Some(pending)) => {
+                            if let Err(e) = self.database.insert_pending_tx(&pending).await {
+                                error!(error = %e, "Failed to insert pending transaction");
+                            }
+                            on_pending(&pending);
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!(?tx_hash, error = %e, "Failed to inspect pending transaction"),
+                    }
+                }
+                _ = cleanup.tick() => {
+                    if let Err(e) = self.cleanup().await {
+                        error!(error = %e, "Failed to clean up pending transactions");
+                    }
+                }
+            }
+        }
+    }
+
+    async fn inspect_transaction(&self, tx_hash: H256) -> Result<Option<PendingTx>> {
+        let tx = match self.provider.get_transaction(tx_hash).await? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+
+        if tx.to != Some(self.usdc_address) {
+            return Ok(None);
+        }
+
+        let (method, from_address, to_address, value) = match decode_transfer_call(&tx.input) {
+            Some(decoded) => decoded,
+            None => return Ok(None),
+        };
+
+        Ok(Some(PendingTx {
+            tx_hash: format!("0x{:x}", tx_hash),
+            sender: checksum(&tx.from),
+            method: method.to_string(),
+            from_address: checksum(&from_address.unwrap_or(tx.from)),
+            to_address: checksum(&to_address),
+            value: value.to_string(),
+            first_seen: self.database.now(),
+        }))
+    }
+
+    /// Removes transactions that have been mined, or that have been pending longer than
+    /// the drop window and are no longer known to the node.
+    async fn cleanup(&self) -> Result<()> {
+        let cutoff = self.database.now() - self.drop_after;
+
+        for pending in self.database.list_pending_txs().await? {
+            let tx_hash: H256 = pending.tx_hash.parse()?;
+
+            if self.provider.get_transaction_receipt(tx_hash).await?.is_some() {
+                self.database.delete_pending_tx(&pending.tx_hash).await?;
+                continue;
+            }
+
+            if pending.first_seen < cutoff && self.provider.get_transaction(tx_hash).await?.is_none() {
+                info!(tx_hash = %pending.tx_hash, "Dropping pending transaction");
+                self.database.delete_pending_tx(&pending.tx_hash).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn decode_transfer_call(input: &[u8]) -> Option<(&'static str, Option<H160>, H160, U256)> {
+    if input.len() < 4 {
+        return None;
+    }
+
+    let (selector, args) = input.split_at(4);
+    let word = |i: usize| args.get(i * 32..(i + 1) * 32);
+
+    if selector == TRANSFER_SELECTOR {
+        let to = H160::from_slice(&word(0)?[12..]);
+        let value = U256::from_big_endian(word(1)?);
+        Some(("transfer", None, to, value))
+    } else if selector == TRANSFER_FROM_SELECTOR {
+        let from = H160::from_slice(&word(0)?[12..]);
+        let to = H160::from_slice(&word(1)?[12..]);
+        let value = U256::from_big_endian(word(2)?);
+        Some(("transferFrom", Some(from), to, value))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINANCE: &str = "0x28C6c06298d514Db089934071355E5743bf21d60";
+    const TREASURY: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    /// `transfer(0x28C6…1d60, 100 USDC)`
+    const TRANSFER_CALLDATA: &str = "a9059cbb\
+        00000000000000000000000028c6c06298d514db089934071355e5743bf21d60\
+        0000000000000000000000000000000000000000000000000000000005f5e100";
+
+    /// `transferFrom(0x5aAe…BeAed, 0x28C6…1d60, 2500.5 USDC)`
+    const TRANSFER_FROM_CALLDATA: &str = "23b872dd\
+        0000000000000000000000005aaeb6053f3e94c9b9a09f33669435e7ef1beaed\
+        00000000000000000000000028c6c06298d514db089934071355e5743bf21d60\
+        00000000000000000000000000000000000000000000000000000000950a9a20";
+
+    fn calldata(hex: &str) -> Vec<u8> {
+        hex::decode(hex).unwrap()
+    }
+
+    #[test]
+    fn decodes_transfer() {
+        let input = calldata(TRANSFER_CALLDATA);
+        let (method, from, to, value) = decode_transfer_call(&input).unwrap();
+        assert_eq!(method, "transfer");
+        assert_eq!(from, None);
+        assert_eq!(checksum(&to), BINANCE);
+        assert_eq!(value, U256::from(100_000_000u64));
+    }
+
+    #[test]
+    fn decodes_transfer_from() {
+        let input = calldata(TRANSFER_FROM_CALLDATA);
+        let (method, from, to, value) = decode_transfer_call(&input).unwrap();
+        assert_eq!(method, "transferFrom");
+        assert_eq!(from.map(|from| checksum(&from)).as_deref(), Some(TREASURY));
+        assert_eq!(checksum(&to), BINANCE);
+        assert_eq!(value, U256::from(2_500_500_000u64));
+    }
+
+    #[test]
+    fn ignores_trailing_bytes() {
+        let mut input = calldata(TRANSFER_CALLDATA);
+        input.extend_from_slice(&[0xde, 0xad]);
+        assert!(decode_transfer_call(&input).is_some());
+    }
+
+    #[test]
+    fn rejects_short_input() {
+        let transfer = calldata(TRANSFER_CALLDATA);
+        let transfer_from = calldata(TRANSFER_FROM_CALLDATA);
+        for input in [&[][..], &transfer[..3], &transfer[..4], &transfer[..transfer.len() - 1], &transfer_from[..68]] {
+            assert!(decode_transfer_call(input).is_none(), "{} bytes should not decode", input.len());
+        }
+    }
+
+    #[test]
+    fn rejects_other_selectors() {
+        // approve(address,uint256) has the same argument layout as transfer.
+        let mut approve = calldata(TRANSFER_CALLDATA);
+        approve[..4].copy_from_slice(&[0x09, 0x5e, 0xa7, 0xb3]);
+        assert!(decode_transfer_call(&approve).is_none());
+        assert!(decode_transfer_call(&[0xff; 68]).is_none());
+    }
+}
@@ -1,8 +1,11 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+use crate::{auth::Scope, query::Direction};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct TransferEvent {
     pub id: String,
     pub transaction_hash: String,
@@ -14,9 +17,449 @@ pub struct TransferEvent {
     pub value: String,
     pub timestamp: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
+    /// Names from `labels`, filled in when transfers are queried for display.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub from_label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub to_label: Option<String>,
+    /// Verified ENS primary names, filled in when `ENS_RESOLUTION` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub from_ens: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub to_ens: Option<String>,
+    /// Sender, called contract and fee in wei of the originating transaction, filled in when it
+    /// was fetched with `TX_ENRICHMENT`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub tx_sender: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub tx_to: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub tx_fee: Option<String>,
+    /// Dollar value at the transfer's block, filled in when it was priced with `PRICE_FEED_ADDRESS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub usd_value: Option<String>,
+    /// Whether either side is on the sanctions list loaded from `SANCTIONS_LIST`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[sqlx(default)]
+    pub sanctioned: bool,
+}
+
+/// A block whose transfers have been fetched, ready to be marked processed.
+#[derive(Debug, Clone)]
+pub struct ProcessedBlock {
+    pub block_number: u64,
+    pub block_hash: String,
+    pub timestamp: DateTime<Utc>,
+    /// Wei per gas; `None` before London.
+    pub base_fee_per_gas: Option<String>,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    /// The miner or, after the merge, the proposer's fee recipient.
+    pub fee_recipient: String,
+}
+
+/// A processed block's header fields joined with the transfers stored in it.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockActivity {
+    pub block_number: i64,
+    pub block_hash: String,
+    pub timestamp: DateTime<Utc>,
+    /// Header fields are `None` for blocks processed before they were recorded.
+    pub base_fee_per_gas: Option<String>,
+    pub gas_used: Option<i64>,
+    pub gas_limit: Option<i64>,
+    pub fee_recipient: Option<String>,
+    pub transfers: i64,
+    /// Raw token units moved by `transfers`.
+    pub volume: String,
+}
+
+/// Transfers into or out of a set of addresses on one UTC day.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyVolume {
+    /// `YYYY-MM-DD`.
+    pub day: String,
+    pub transfers: i64,
+    /// Raw token units moved by `transfers`.
+    pub volume: String,
+}
+
+/// Holders whose balance falls in `[min, max)`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HolderBucket {
+    /// Raw token units.
+    pub min: String,
+    /// `None` for the top bucket.
+    pub max: Option<String>,
+    pub holders: i64,
+    /// Raw token units held by the bucket's holders together.
+    pub balance: String,
+    /// Percent of the balance of all holders.
+    pub share: f64,
+}
+
+/// Token-wide activity in one time bucket, with velocity against the tracked supply.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PeriodActivity {
+    /// Start of the bucket, as written by `FlowInterval::bucket`.
+    pub period: String,
+    pub transfers: i64,
+    /// Raw token units moved.
+    pub volume: String,
+    /// Distinct senders and recipients, the zero address aside.
+    pub active_addresses: i64,
+    /// Raw token units; `None` when no supply is tracked.
+    pub supply: Option<String>,
+    /// `volume / supply`.
+    pub velocity: Option<f64>,
+}
+
+/// Transfers into and out of an address in one time bucket.
+#[derive(Debug, Clone, Serialize)]
+pub struct NetFlow {
+    /// Start of the bucket, as written by `FlowInterval::bucket`.
+    pub period: String,
+    pub inflow_transfers: i64,
+    /// Raw token units received.
+    pub inflow: String,
+    pub outflow_transfers: i64,
+    pub outflow: String,
+    /// `inflow - outflow`, with a leading `-` when negative.
+    pub net: String,
+}
+
+/// Transfers between an address and one counterparty, split by direction.
+#[derive(Debug, Clone, Serialize)]
+pub struct Counterparty {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Transfers from the address to the counterparty.
+    pub sent_transfers: i64,
+    /// Raw token units sent to the counterparty.
+    pub sent_volume: String,
+    pub received_transfers: i64,
+    pub received_volume: String,
+}
+
+/// A `Transfer` log as the node returned it, kept when `RAW_LOGS` is on so transfers can be
+/// decoded again without the RPC.
+#[derive(Debug, Clone)]
+pub struct RawLog {
+    pub block_number: u64,
+    pub block_hash: String,
+    pub block_timestamp: DateTime<Utc>,
+    pub transaction_hash: String,
+    pub transaction_index: Option<u64>,
+    pub log_index: u64,
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+/// One token movement from an ERC-1155 `TransferSingle`, or one entry of a `TransferBatch`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Erc1155Transfer {
+    /// `<transaction_hash>_<log_index>_<batch_index>`.
+    pub id: String,
+    pub contract_address: String,
+    pub transaction_hash: String,
+    pub log_index: i64,
+    /// Position within a `TransferBatch`; always 0 for `TransferSingle`.
+    pub batch_index: i64,
+    pub block_number: i64,
+    pub block_hash: String,
+    pub operator: String,
+    pub from_address: String,
+    pub to_address: String,
+    pub token_id: String,
+    pub value: String,
+    pub timestamp: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A Chainlink aggregator's latest answer as of a block.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockPrice {
+    pub feed: String,
+    pub block_number: i64,
+    /// Raw answer; the price is `answer / 10^decimals`.
+    pub answer: String,
+    pub decimals: u8,
+    /// When the feed last updated the answer.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What the stored sanctions list holds.
+#[derive(Debug, Serialize)]
+pub struct SanctionsStatus {
+    pub addresses: i64,
+    /// File or URL the list was last loaded from.
+    pub source: Option<String>,
+    pub loaded_at: Option<DateTime<Utc>>,
+}
+
+/// An address's usual volume per anomaly window, or the whole token's under `*`.
+#[derive(Debug, Clone)]
+pub struct VolumeBaseline {
+    pub address: String,
+    /// Exponential moving average of raw volume over the windows folded in so far.
+    pub average: f64,
+    /// Windows folded in: those the address was active in, or every window for `*`.
+    pub windows: i64,
+    /// Index of the last window folded in, counted from the Unix epoch.
+    pub last_window: i64,
+}
+
+/// Whether an address had contract code when it was first seen.
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressInfo {
+    pub address: String,
+    pub is_contract: bool,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Sender and fee details of a transaction that moved tokens, from its receipt.
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionDetails {
+    pub transaction_hash: String,
+    pub block_number: i64,
+    /// The externally owned account that signed the transaction.
+    pub sender: String,
+    /// The account the transaction called; the token contract itself for direct sends, a router
+    /// or wallet contract otherwise. `None` for contract creations.
+    pub recipient: Option<String>,
+    pub gas_used: String,
+    /// Wei per gas actually paid.
+    pub effective_gas_price: String,
+    /// `gas_used * effective_gas_price`, in wei.
+    pub fee: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Rows removed by `prune`, all below `before_block`.
+#[derive(Debug, Default, Serialize)]
+pub struct PruneSummary {
+    pub before_block: u64,
+    pub transfers: u64,
+    pub processed_blocks: u64,
+    pub archive_batches: u64,
+    pub raw_logs: u64,
+    pub erc1155_transfers: u64,
+    pub transactions: u64,
+    pub usd_values: u64,
+}
+
+/// Outcome of rebuilding `transfer_events` from `raw_logs`.
+#[derive(Debug, Default, Serialize)]
+pub struct ReplaySummary {
+    pub blocks: u64,
+    /// Processed blocks in the range that were indexed without `RAW_LOGS` and left untouched.
+    pub skipped_blocks: u64,
+    pub logs: u64,
+    pub transfers: u64,
+    pub erc1155_transfers: u64,
+}
+
+/// Space used by the database files before and after `db compact`.
+#[derive(Debug, Default, Serialize)]
+pub struct CompactSummary {
+    pub files: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl CompactSummary {
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Files written by `db backup`.
+#[derive(Debug, Default, Serialize)]
+pub struct BackupSummary {
+    pub files: Vec<String>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapKind {
+    /// Missing from `processed_blocks`.
+    Unprocessed,
+    /// Marked processed, but no transfers were stored for it.
+    NoEvents,
+}
+
+/// An inclusive block range that `gaps --fill` re-indexes.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockGap {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub kind: GapKind,
+}
+
+/// `name()`, `symbol()` and `decimals()` of a token contract, as stored in `tokens`. ERC-1155
+/// contracts have no decimals and may leave the name and symbol empty.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenMetadata {
+    pub address: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    /// `erc20` or `erc1155`.
+    pub standard: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// How far one token has been indexed on one chain, from `token_checkpoints`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenCheckpoint {
+    pub chain_id: u64,
+    pub token_address: String,
+    pub standard: String,
+    /// Symbol from `tokens`, when its metadata is stored.
+    pub symbol: Option<String>,
+    pub start_block: u64,
+    /// First block not yet indexed for this token.
+    pub next_block: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A name attached to an address with `label add`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressLabel {
+    pub address: String,
+    pub name: String,
+    /// Kind of entity, e.g. `exchange` or `bridge`.
+    pub category: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A `serve` bearer token stored with `api-key create`; the token itself is never stored.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiKey {
+    pub name: String,
+    pub scope: Scope,
+    pub created_at: DateTime<Utc>,
+    /// Updated at most once a minute.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// An address indexed in watchlist-only mode, with its label if it has one.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchedAddress {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An alert waiting in `email_digest` for its day's digest email.
+#[derive(Debug, Clone)]
+pub struct DigestEntry {
+    pub id: i64,
+    pub at: DateTime<Utc>,
+    pub message: String,
+}
+
+/// A stored alert rule. Every condition that is set must hold; with `min_count`, the rule fires
+/// when the `min_count`th matching transfer within `window_secs` arrives.
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertRule {
+    pub id: i64,
+    pub name: String,
+    pub address: Option<String>,
+    pub direction: Direction,
+    /// Raw values.
+    pub min_value: Option<String>,
+    pub max_value: Option<String>,
+    /// Case-insensitive substring of the other party's label.
+    pub counterparty_label: Option<String>,
+    pub min_count: Option<i64>,
+    pub window_secs: Option<i64>,
+    /// Sink names to deliver to; empty means every configured sink.
+    pub channels: Vec<String>,
+    pub created_at: DateTime<Utc>,
 }
 
+/// A reorg recorded in `reorg_events`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReorgRecord {
+    pub fork_block: i64,
+    pub chain_head: i64,
+    pub depth: i64,
+    pub finality_blocks: i64,
+    pub detected_at: DateTime<Utc>,
+}
+
+/// A transfer annotated with its direction relative to a queried address.
 #[derive(Debug, Serialize)]
+pub struct DirectedTransfer {
+    #[serde(flatten)]
+    pub event: TransferEvent,
+    pub direction: &'static str,
+}
+
+impl DirectedTransfer {
+    pub fn new(event: TransferEvent, address: &str) -> Self {
+        let direction = match (
+            event.from_address.eq_ignore_ascii_case(address),
+            event.to_address.eq_ignore_ascii_case(address),
+        ) {
+            (true, true) => "self",
+            (true, false) => "sent",
+            (false, true) => "received",
+            (false, false) => "unrelated",
+        };
+        Self { event, direction }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TransferPage {
+    pub transfers: Vec<TransferEvent>,
+    pub next_cursor: Option<String>,
+}
+
+/// Materialized per-address totals, maintained incrementally as ranges are committed.
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressStats {
+    pub address: String,
+    pub total_in: String,
+    pub total_out: String,
+    pub tx_count: i64,
+    pub last_block: i64,
+}
+
+/// How many addresses held a non-zero balance as of a block, derived from `address_stats`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HolderSnapshot {
+    pub block_number: i64,
+    pub timestamp: DateTime<Utc>,
+    pub holders: i64,
+    /// Raw token units minted minus burned, i.e. sent from minus sent to the zero address.
+    pub supply: String,
+}
+
+/// When an address first and last sent or received a stored transfer.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ActivityRange {
+    pub first_block: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_block: i64,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct DatabaseStats {
     pub total_transfers: i64,
     pub unique_addresses: i64,
@@ -24,3 +467,80 @@ pub struct DatabaseStats {
     pub earliest_block: Option<i64>,
 }
 
+/// Count and raw value aggregates of the transfers matching a filter.
+#[derive(Debug, Serialize)]
+pub struct TransferTotals {
+    pub count: i64,
+    pub total_value: String,
+    /// Rounded down; `None` when nothing matches.
+    pub average_value: Option<String>,
+    pub max_value: Option<String>,
+}
+
+/// Dollar aggregates of the transfers matching a filter, over those that were priced. Values
+/// are in dollars with two decimals.
+#[derive(Debug, Serialize)]
+pub struct UsdTotals {
+    pub priced: i64,
+    /// Matching transfers without a stored `usd_value`, left out of the totals.
+    pub unpriced: i64,
+    pub total_usd: String,
+    /// Rounded down to the cent; `None` when nothing was priced.
+    pub average_usd: Option<String>,
+    pub max_usd: Option<String>,
+}
+
+/// A transfer row printed with an extra `usd_value` column.
+#[derive(Debug, Serialize)]
+pub struct PricedRow<T> {
+    #[serde(flatten)]
+    pub row: T,
+    /// Already part of the flattened transfer in JSON.
+    #[serde(skip)]
+    pub usd_value: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PendingTransfer {
+    #[serde(flatten)]
+    pub event: TransferEvent,
+    pub confirmed: bool,
+}
+
+impl From<TransferEvent> for PendingTransfer {
+    fn from(event: TransferEvent) -> Self {
+        Self {
+            event,
+            confirmed: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTx {
+    pub tx_hash: String,
+    pub sender: String,
+    pub method: String,
+    pub from_address: String,
+    pub to_address: String,
+    pub value: String,
+    pub first_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestConflict {
+    pub event_id: String,
+    pub existing_source: String,
+    pub incoming_source: String,
+    pub fields: String,
+    pub resolution: String,
+    pub detected_at: DateTime<Utc>,
+}
+
+/// One embedded migration and whether the database has applied it.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
@@ -0,0 +1,531 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message,
+    Tokio1Executor,
+};
+use tracing::{info, warn};
+use serde::Serialize;
+use std::{
+    io::{IsTerminal, Write},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    alerts::{Alert, FiredAlert, RuleMonitor},
+    config::Config,
+    database::Database,
+    models::{AlertRule, DigestEntry},
+    output::highlight,
+    query::TransferFilter,
+};
+
+/// How long a sink may take to accept a notification before it is given up on.
+const SINK_TIMEOUT_SECS: u64 = 10;
+
+/// Alerts waiting for delivery before new ones are dropped.
+const ALERT_QUEUE_CAPACITY: usize = 256;
+
+/// Blocks of stored transfers replayed at a time by `Notifier::backfill`.
+const BACKFILL_BATCH_BLOCKS: u64 = 10_000;
+
+/// How often the delivery task checks for a finished day to send as an email digest.
+const DIGEST_CHECK_SECS: u64 = 300;
+
+/// Every sink name accepted in `ALERT_SINKS` and as an alert rule channel. Slack and Discord
+/// also take named routes, e.g. `slack:treasury`.
+pub const SINK_NAMES: &[&str] = &["log", "console", "webhook", "telegram", "slack", "discord", "email"];
+
+/// Whether `name` is a sink name or a `slack:<route>` / `discord:<route>` name.
+pub fn is_sink_name(name: &str) -> bool {
+    match name.split_once(':') {
+        Some((kind, route)) => (kind == "slack" || kind == "discord") && !route.is_empty(),
+        None => SINK_NAMES.contains(&name),
+    }
+}
+
+/// Where alerts are delivered, chosen with `ALERT_SINKS`.
+#[derive(Clone)]
+pub enum Sink {
+    /// A warning through the logger.
+    Log,
+    /// A line on stdout, highlighted when it is a terminal.
+    Console,
+    /// A JSON `POST` of the alert to `ALERT_WEBHOOK_URL`.
+    Webhook { url: String },
+    /// A message from a Telegram bot to `TELEGRAM_CHAT_ID`.
+    Telegram { bot_token: String, chat_id: String },
+    /// A Slack incoming webhook; `name` is `slack` or `slack:<route>`.
+    Slack { name: String, url: String },
+    /// A Discord channel webhook; `name` is `discord` or `discord:<route>`.
+    Discord { name: String, url: String },
+    /// Email through `SMTP_HOST`, one message per alert or, when `daily`, one digest per day.
+    Email {
+        transport: Box<AsyncSmtpTransport<Tokio1Executor>>,
+        from: Mailbox,
+        to: Vec<Mailbox>,
+        daily: bool,
+    },
+}
+
+impl Sink {
+    pub fn name(&self) -> &str {
+        match self {
+            Sink::Log => "log",
+            Sink::Console => "console",
+            Sink::Webhook { .. } => "webhook",
+            Sink::Telegram { .. } => "telegram",
+            Sink::Slack { name, .. } | Sink::Discord { name, .. } => name,
+            Sink::Email { .. } => "email",
+        }
+    }
+}
+
+/// Body posted to webhooks.
+#[derive(Debug, Serialize)]
+struct Notification<'a> {
+    at: DateTime<Utc>,
+    message: String,
+    #[serde(flatten)]
+    alert: &'a Alert,
+}
+
+/// Fans alerts out to every configured sink.
+pub struct Notifier {
+    sinks: Vec<Sink>,
+    http: reqwest::Client,
+    digest: Option<Database>,
+}
+
+impl Notifier {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let sinks = config
+            .alert_sinks
+            .iter()
+            .map(|name| match name.as_str() {
+                "log" => Ok(Sink::Log),
+                "console" => Ok(Sink::Console),
+                "webhook" => config
+                    .alert_webhook_url
+                    .clone()
+                    .map(|url| Sink::Webhook { url })
+                    .ok_or_else(|| anyhow::anyhow!("ALERT_SINKS includes webhook but ALERT_WEBHOOK_URL is not set")),
+                "telegram" => match (&config.telegram_bot_token, &config.telegram_chat_id) {
+                    (Some(bot_token), Some(chat_id)) => Ok(Sink::Telegram {
+                        bot_token: bot_token.clone(),
+                        chat_id: chat_id.clone(),
+                    }),
+                    _ => Err(anyhow::anyhow!(
+                        "ALERT_SINKS includes telegram but TELEGRAM_BOT_TOKEN or TELEGRAM_CHAT_ID is not set"
+                    )),
+                },
+                "slack" => config
+                    .slack_webhook_url
+                    .clone()
+                    .map(|url| Sink::Slack { name: name.clone(), url })
+                    .ok_or_else(|| anyhow::anyhow!("ALERT_SINKS includes slack but SLACK_WEBHOOK_URL is not set")),
+                "discord" => config
+                    .discord_webhook_url
+                    .clone()
+                    .map(|url| Sink::Discord { name: name.clone(), url })
+                    .ok_or_else(|| anyhow::anyhow!("ALERT_SINKS includes discord but DISCORD_WEBHOOK_URL is not set")),
+                "email" => email_sink(config),
+                other => match other.split_once(':') {
+                    Some(("slack", route)) => find_route(&config.slack_routes, route)
+                        .map(|url| Sink::Slack { name: name.clone(), url })
+                        .ok_or_else(|| anyhow::anyhow!("ALERT_SINKS includes {} but SLACK_ROUTES has no {}", other, route)),
+                    Some(("discord", route)) => find_route(&config.discord_routes, route)
+                        .map(|url| Sink::Discord { name: name.clone(), url })
+                        .ok_or_else(|| anyhow::anyhow!("ALERT_SINKS includes {} but DISCORD_ROUTES has no {}", other, route)),
+                    _ => Err(anyhow::anyhow!("Unknown alert sink: {}", other)),
+                },
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for (var, channels) in [
+            ("ALERT_MIN_VALUE_CHANNELS", &config.alert_min_value_channels),
+            ("SANCTIONS_ALERT_CHANNELS", &config.sanctions_alert_channels),
+            ("ANOMALY_ALERT_CHANNELS", &config.anomaly_alert_channels),
+            ("LAG_ALERT_CHANNELS", &config.lag_alert_channels),
+        ] {
+            if let Some(channel) = channels
+                .iter()
+                .find(|channel| !sinks.iter().any(|sink| sink.name() == channel.as_str()))
+            {
+                return Err(anyhow::anyhow!("{} includes {} but ALERT_SINKS does not", var, channel));
+            }
+        }
+
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(SINK_TIMEOUT_SECS))
+            .build()?;
+
+        Ok(Self {
+            sinks,
+            http,
+            digest: None,
+        })
+    }
+
+    /// Holds alerts for a daily `EMAIL_MODE=daily` digest in `database`. Without this, email
+    /// goes out immediately whatever the mode, as `alert test --send` and `sinks backfill` want.
+    pub fn with_digest(mut self, database: Database) -> Self {
+        self.digest = Some(database);
+        self
+    }
+
+    /// Sends one digest email per finished UTC day held in `email_digest`, then forgets it.
+    pub async fn flush_digest(&self) -> Result<()> {
+        let Some(database) = &self.digest else {
+            return Ok(());
+        };
+        let Some(sink) = self.sinks.iter().find(|sink| matches!(sink, Sink::Email { daily: true, .. })) else {
+            return Ok(());
+        };
+
+        let today = database.now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let entries = database.get_digest_entries(today).await?;
+        let mut days: Vec<(NaiveDate, Vec<&DigestEntry>)> = Vec::new();
+        for entry in &entries {
+            match days.last_mut() {
+                Some((day, day_entries)) if *day == entry.at.date_naive() => day_entries.push(entry),
+                _ => days.push((entry.at.date_naive(), vec![entry])),
+            }
+        }
+
+        for (day, day_entries) in days {
+            let noun = if day_entries.len() == 1 { "alert" } else { "alerts" };
+            let subject = format!("Alert digest for {}: {} {}", day, day_entries.len(), noun);
+            let body: String = day_entries
+                .iter()
+                .map(|entry| format!("{} {}\n", entry.at.format("%H:%M:%S"), entry.message))
+                .collect();
+            self.send_email(sink, &subject, body).await?;
+
+            let ids: Vec<i64> = day_entries.iter().map(|entry| entry.id).collect();
+            database.delete_digest_entries(&ids).await?;
+            info!(%day, alerts = ids.len(), "Sent the email digest");
+        }
+        Ok(())
+    }
+
+    /// Delivers `alert` to every sink. A failing sink is logged and does not stop the others.
+    pub async fn notify(&self, alert: &Alert, at: DateTime<Utc>) {
+        self.notify_via(alert, at, &[]).await
+    }
+
+    /// Delivers `alert` to the configured sinks named in `channels`, or to all of them when
+    /// `channels` is empty.
+    pub async fn notify_via(&self, alert: &Alert, at: DateTime<Utc>, channels: &[String]) {
+        for channel in channels {
+            if !self.sinks.iter().any(|sink| sink.name() == channel) {
+                warn!(%channel, "Alert channel is not configured in ALERT_SINKS");
+            }
+        }
+
+        let selected = self
+            .sinks
+            .iter()
+            .filter(|sink| channels.is_empty() || channels.iter().any(|c| c == sink.name()));
+        for sink in selected {
+            if let Err(e) = self.send(sink, alert, at).await {
+                warn!(sink = sink.name(), error = %e, "Failed to deliver alert");
+            }
+        }
+    }
+
+    /// Replays stored transfers in `[from_block, to_block]` through `rules` and, when
+    /// `large_transfers` is set, `ALERT_MIN_VALUE`, delivering each alert to the `target` sink
+    /// only. Stops at the first failed delivery so the run can be resumed from that block.
+    /// Returns the number of alerts delivered.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn backfill(
+        &self,
+        database: &Database,
+        config: &Config,
+        target: &str,
+        rules: &[AlertRule],
+        large_transfers: bool,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<u64> {
+        let sink = self
+            .sinks
+            .iter()
+            .find(|sink| sink.name() == target)
+            .ok_or_else(|| anyhow::anyhow!("Sink {} is not configured in ALERT_SINKS", target))?;
+
+        let monitor = RuleMonitor::default();
+        let mut delivered = 0;
+        let mut start = from_block;
+        while start <= to_block {
+            let end = std::cmp::min(start.saturating_add(BACKFILL_BATCH_BLOCKS - 1), to_block);
+
+            let mut filter = TransferFilter::new().block_range(Some(start), Some(end)).include_archived(true);
+            if rules.is_empty() {
+                filter = filter.value_range(config.alert_min_value.map(|v| v.to_string()), None);
+            }
+            let mut transfers = Vec::new();
+            database
+                .for_each_transfer(&filter, |transfer| {
+                    transfers.push(transfer);
+                    Ok(())
+                })
+                .await?;
+
+            let mut alerts: Vec<(i64, FiredAlert)> = Vec::new();
+            if large_transfers {
+                for transfer in &transfers {
+                    if let Some(alert) = Alert::for_transfer(transfer, config) {
+                        alerts.push((transfer.block_number, FiredAlert { at: transfer.timestamp, alert }));
+                    }
+                }
+            }
+            for (_, fired) in monitor.evaluate(database, config, rules, &transfers).await? {
+                if let Alert::RuleMatched { block_number, .. } = fired.alert {
+                    alerts.push((block_number, fired));
+                }
+            }
+            alerts.sort_by_key(|(block_number, _)| *block_number);
+
+            for (block_number, fired) in alerts {
+                self.send(sink, &fired.alert, fired.at).await.map_err(|e| {
+                    anyhow::anyhow!("Failed to deliver alert for block {} to {} sink: {}", block_number, target, e)
+                })?;
+                delivered += 1;
+            }
+
+            if end == to_block {
+                break;
+            }
+            start = end + 1;
+        }
+        Ok(delivered)
+    }
+
+    async fn send_email(&self, sink: &Sink, subject: &str, body: String) -> Result<()> {
+        let Sink::Email { transport, from, to, .. } = sink else {
+            return Ok(());
+        };
+        let mut message = Message::builder().from(from.clone()).subject(subject);
+        for recipient in to {
+            message = message.to(recipient.clone());
+        }
+        transport.send(message.body(body)?).await?;
+        Ok(())
+    }
+
+    async fn send(&self, sink: &Sink, alert: &Alert, at: DateTime<Utc>) -> Result<()> {
+        match sink {
+            Sink::Log => warn!("{}", alert.message()),
+            Sink::Console => {
+                let stdout = std::io::stdout();
+                writeln!(stdout.lock(), "{}", highlight(&alert.message(), stdout.is_terminal()))?;
+            }
+            Sink::Webhook { url } => {
+                let notification = Notification {
+                    at,
+                    message: alert.message(),
+                    alert,
+                };
+                // Webhook URLs often embed a secret, so errors are reported without them.
+                self.http
+                    .post(url)
+                    .json(&notification)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| e.without_url())?;
+            }
+            Sink::Telegram { bot_token, chat_id } => {
+                let body = serde_json::json!({
+                    "chat_id": chat_id,
+                    "text": telegram_text(alert),
+                    "parse_mode": "HTML",
+                    "disable_web_page_preview": true,
+                });
+                // The request URL carries the bot token; keep it out of logs.
+                self.http
+                    .post(format!("https://api.telegram.org/bot{}/sendMessage", bot_token))
+                    .json(&body)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| e.without_url())?;
+            }
+            Sink::Slack { url, .. } => {
+                let body = serde_json::json!({ "text": slack_text(alert) });
+                // Slack and Discord webhook URLs are bearer secrets as well.
+                self.http
+                    .post(url)
+                    .json(&body)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| e.without_url())?;
+            }
+            Sink::Email { daily: true, .. } if self.digest.is_some() => {
+                if let Some(database) = &self.digest {
+                    database.add_digest_entry(at, &alert.message()).await?;
+                }
+            }
+            Sink::Email { .. } => {
+                let body = format!("{}\n\n{}\n", alert.message(), serde_json::to_string_pretty(alert)?);
+                self.send_email(sink, &alert.summary(), body).await?;
+            }
+            Sink::Discord { url, .. } => {
+                let body = serde_json::json!({
+                    "content": discord_text(alert),
+                    "allowed_mentions": { "parse": [] },
+                });
+                self.http
+                    .post(url)
+                    .json(&body)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| e.without_url())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct QueuedAlert {
+    alert: Alert,
+    at: DateTime<Utc>,
+    channels: Vec<String>,
+}
+
+/// Hands alerts to a background task that delivers them through a `Notifier`, so a slow or
+/// unreachable sink never holds up the caller.
+pub struct AlertQueue {
+    sender: mpsc::Sender<QueuedAlert>,
+}
+
+impl AlertQueue {
+    /// Starts the delivery task. Must be called inside a Tokio runtime.
+    pub fn spawn(notifier: Notifier) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<QueuedAlert>(ALERT_QUEUE_CAPACITY);
+        tokio::spawn(async move {
+            let mut digest_check = tokio::time::interval(Duration::from_secs(DIGEST_CHECK_SECS));
+            loop {
+                tokio::select! {
+                    queued = receiver.recv() => match queued {
+                        Some(queued) => notifier.notify_via(&queued.alert, queued.at, &queued.channels).await,
+                        None => break,
+                    },
+                    _ = digest_check.tick() => {
+                        if let Err(e) = notifier.flush_digest().await {
+                            warn!(error = %e, "Failed to send the email digest");
+                        }
+                    }
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    /// Queues `alert` for the sinks named in `channels` (all when empty). Never waits: when
+    /// `ALERT_QUEUE_CAPACITY` alerts are already pending, this one is dropped with a warning.
+    pub fn push(&self, alert: Alert, at: DateTime<Utc>, channels: Vec<String>) {
+        match self.sender.try_send(QueuedAlert { alert, at, channels }) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(queued)) => {
+                warn!(alert = %queued.alert.message(), "Alert queue is full, dropping alert")
+            }
+            Err(mpsc::error::TrySendError::Closed(queued)) => {
+                warn!(alert = %queued.alert.message(), "Alert delivery has stopped, dropping alert")
+            }
+        }
+    }
+}
+
+/// Bold summary with the transaction link underneath, in Telegram's HTML subset.
+fn telegram_text(alert: &Alert) -> String {
+    let mut text = format!("<b>{}</b>", escape_html(&alert.summary()));
+    if let Some(url) = alert.tx_url() {
+        text.push_str(&format!("\n<a href=\"{}\">View transaction</a>", escape_html(url)));
+    }
+    text
+}
+
+/// Bold summary with the transaction link underneath, in Slack's mrkdwn.
+fn slack_text(alert: &Alert) -> String {
+    let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let mut text = format!("*{}*", escape(&alert.summary()));
+    if let Some(url) = alert.tx_url() {
+        text.push_str(&format!("\n<{}|View transaction>", escape(url)));
+    }
+    text
+}
+
+/// Bold summary with the transaction link underneath, in Discord markdown.
+fn discord_text(alert: &Alert) -> String {
+    let mut summary = String::new();
+    for c in alert.summary().chars() {
+        if matches!(c, '*' | '_' | '~' | '`' | '|' | '\\' | '[' | ']') {
+            summary.push('\\');
+        }
+        summary.push(c);
+    }
+    let mut text = format!("**{}**", summary);
+    if let Some(url) = alert.tx_url() {
+        text.push_str(&format!("\n[View transaction](<{}>)", url));
+    }
+    text
+}
+
+/// Builds the `email` sink from the `SMTP_*` and `EMAIL_*` settings.
+fn email_sink(config: &Config) -> Result<Sink> {
+    let host = config
+        .smtp_host
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("ALERT_SINKS includes email but SMTP_HOST is not set"))?;
+    let from = config
+        .email_from
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("ALERT_SINKS includes email but EMAIL_FROM is not set"))?
+        .parse::<Mailbox>()
+        .map_err(|e| anyhow::anyhow!("Invalid EMAIL_FROM: {}", e))?;
+    if config.email_to.is_empty() {
+        return Err(anyhow::anyhow!("ALERT_SINKS includes email but EMAIL_TO is not set"));
+    }
+    let to = config
+        .email_to
+        .iter()
+        .map(|address| address.parse::<Mailbox>().map_err(|e| anyhow::anyhow!("Invalid EMAIL_TO address {}: {}", address, e)))
+        .collect::<Result<Vec<_>>>()?;
+    let daily = match config.email_mode.as_str() {
+        "immediate" => false,
+        "daily" => true,
+        other => return Err(anyhow::anyhow!("Invalid EMAIL_MODE: {} (expected immediate or daily)", other)),
+    };
+
+    let mut builder = match config.smtp_tls.as_str() {
+        "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+        "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        other => return Err(anyhow::anyhow!("Invalid SMTP_TLS: {} (expected starttls, tls or none)", other)),
+    };
+    builder = builder.port(config.smtp_port).timeout(Some(Duration::from_secs(SINK_TIMEOUT_SECS)));
+    if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    Ok(Sink::Email {
+        transport: Box::new(builder.build()),
+        from,
+        to,
+        daily,
+    })
+}
+
+fn find_route(routes: &[(String, String)], route: &str) -> Option<String> {
+    routes.iter().find(|(name, _)| name == route).map(|(_, url)| url.clone())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,523 @@
+use anyhow::Result;
+use ethers_core::types::U256;
+use serde::Serialize;
+use std::{io::Write, marker::PhantomData};
+
+use crate::{
+    bench::BenchResult,
+    config::Config,
+    models::{
+        AddressLabel, AlertRule, ApiKey, BlockActivity, Counterparty, DailyVolume, DirectedTransfer, Erc1155Transfer, HolderBucket, HolderSnapshot, NetFlow, PendingTransfer, PeriodActivity, PricedRow, TokenCheckpoint,
+        TransferEvent, WatchedAddress,
+    },
+    price::parse_cents,
+    units::format_amount,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Jsonl,
+    Csv,
+    #[default]
+    Table,
+}
+
+/// Streaming formats for `export`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    #[default]
+    Jsonl,
+}
+
+/// How raw token values are shown in the CSV and table formats. JSON output always carries the
+/// raw integer string.
+#[derive(Debug, Clone)]
+pub struct AmountFormat {
+    pub decimals: u8,
+    pub symbol: String,
+    /// Print the unscaled integer instead of token units.
+    pub raw: bool,
+}
+
+impl AmountFormat {
+    pub fn new(config: &Config, raw: bool) -> Self {
+        Self {
+            decimals: config.token_decimals,
+            symbol: config.token_symbol.clone(),
+            raw,
+        }
+    }
+
+    pub fn format(&self, value: &str) -> String {
+        match U256::from_dec_str(value) {
+            Ok(amount) if !self.raw => format!("{} {}", format_amount(amount, self.decimals), self.symbol),
+            _ => value.to_string(),
+        }
+    }
+
+    /// Formats a raw amount that may carry a leading `-`, such as a net flow.
+    pub fn format_signed(&self, value: &str) -> String {
+        match value.strip_prefix('-') {
+            Some(magnitude) => format!("-{}", self.format(magnitude)),
+            None => self.format(value),
+        }
+    }
+}
+
+/// Rows that can be rendered as columns for the CSV and table formats.
+pub trait Tabular {
+    fn headers() -> Vec<&'static str>;
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String>;
+}
+
+impl Tabular for TransferEvent {
+    fn headers() -> Vec<&'static str> {
+        vec!["block", "log", "transaction_hash", "from", "to", "value", "timestamp"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.block_number.to_string(),
+            self.log_index.to_string(),
+            self.transaction_hash.clone(),
+            with_names(&self.from_address, &self.from_label, &self.from_ens),
+            with_names(&self.to_address, &self.to_label, &self.to_ens),
+            amounts.format(&self.value),
+            self.timestamp.to_rfc3339(),
+        ]
+    }
+}
+
+impl Tabular for DirectedTransfer {
+    fn headers() -> Vec<&'static str> {
+        let mut headers = TransferEvent::headers();
+        headers.push("direction");
+        headers
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        let mut cells = self.event.cells(amounts);
+        cells.push(self.direction.to_string());
+        cells
+    }
+}
+
+impl Tabular for PendingTransfer {
+    fn headers() -> Vec<&'static str> {
+        let mut headers = TransferEvent::headers();
+        headers.push("confirmed");
+        headers
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        let mut cells = self.event.cells(amounts);
+        cells.push(self.confirmed.to_string());
+        cells
+    }
+}
+
+impl<T: Tabular> Tabular for PricedRow<T> {
+    fn headers() -> Vec<&'static str> {
+        let mut headers = T::headers();
+        headers.push("usd_value");
+        headers
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        let mut cells = self.row.cells(amounts);
+        cells.push(self.usd_value.as_deref().map(format_usd).unwrap_or_default());
+        cells
+    }
+}
+
+/// `$1,500.34` for a stored `usd_value` of `1500.34`.
+pub fn format_usd(usd: &str) -> String {
+    match parse_cents(usd) {
+        Ok(cents) => format!("${}", format_amount(cents, 2)),
+        Err(_) => usd.to_string(),
+    }
+}
+
+/// `address (label, name.eth)`, leaving out whichever names are missing.
+fn with_names(address: &str, label: &Option<String>, ens: &Option<String>) -> String {
+    let names: Vec<&str> = [label, ens].into_iter().flatten().map(String::as_str).collect();
+    if names.is_empty() {
+        address.to_string()
+    } else {
+        format!("{} ({})", address, names.join(", "))
+    }
+}
+
+impl Tabular for AddressLabel {
+    fn headers() -> Vec<&'static str> {
+        vec!["address", "name", "category", "created_at"]
+    }
+
+    fn cells(&self, _amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.address.clone(),
+            self.name.clone(),
+            self.category.clone().unwrap_or_default(),
+            self.created_at.to_rfc3339(),
+        ]
+    }
+}
+
+impl Tabular for DailyVolume {
+    fn headers() -> Vec<&'static str> {
+        vec!["day", "transfers", "volume"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        vec![self.day.clone(), self.transfers.to_string(), amounts.format(&self.volume)]
+    }
+}
+
+impl Tabular for PeriodActivity {
+    fn headers() -> Vec<&'static str> {
+        vec!["period", "transfers", "volume", "active_addresses", "supply", "velocity"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.period.clone(),
+            self.transfers.to_string(),
+            amounts.format(&self.volume),
+            self.active_addresses.to_string(),
+            self.supply.as_deref().map(|supply| amounts.format(supply)).unwrap_or_default(),
+            self.velocity.map(|velocity| format!("{:.4}", velocity)).unwrap_or_default(),
+        ]
+    }
+}
+
+impl Tabular for Counterparty {
+    fn headers() -> Vec<&'static str> {
+        vec!["counterparty", "label", "sent_transfers", "sent", "received_transfers", "received"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.address.clone(),
+            self.label.clone().unwrap_or_default(),
+            self.sent_transfers.to_string(),
+            amounts.format(&self.sent_volume),
+            self.received_transfers.to_string(),
+            amounts.format(&self.received_volume),
+        ]
+    }
+}
+
+impl Tabular for HolderSnapshot {
+    fn headers() -> Vec<&'static str> {
+        vec!["block", "timestamp", "holders", "supply"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.block_number.to_string(),
+            self.timestamp.to_rfc3339(),
+            self.holders.to_string(),
+            amounts.format(&self.supply),
+        ]
+    }
+}
+
+impl Tabular for HolderBucket {
+    fn headers() -> Vec<&'static str> {
+        vec!["balance", "holders", "total", "share"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        let range = match (self.min.as_str(), &self.max) {
+            ("0", Some(max)) => format!("< {}", amounts.format(max)),
+            (min, Some(max)) => format!("{} - {}", amounts.format(min), amounts.format(max)),
+            (min, None) => format!(">= {}", amounts.format(min)),
+        };
+        vec![
+            range,
+            self.holders.to_string(),
+            amounts.format(&self.balance),
+            format!("{:.2}%", self.share),
+        ]
+    }
+}
+
+impl Tabular for BenchResult {
+    fn headers() -> Vec<&'static str> {
+        vec!["batch_blocks", "blocks", "events", "secs", "events_per_sec", "blocks_per_sec"]
+    }
+
+    fn cells(&self, _amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.batch_blocks.to_string(),
+            self.blocks.to_string(),
+            self.events.to_string(),
+            format!("{:.3}", self.secs),
+            format!("{:.0}", self.events_per_sec),
+            format!("{:.0}", self.blocks_per_sec),
+        ]
+    }
+}
+
+impl Tabular for NetFlow {
+    fn headers() -> Vec<&'static str> {
+        vec!["period", "inflow_transfers", "inflow", "outflow_transfers", "outflow", "net"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.period.clone(),
+            self.inflow_transfers.to_string(),
+            amounts.format(&self.inflow),
+            self.outflow_transfers.to_string(),
+            amounts.format(&self.outflow),
+            amounts.format_signed(&self.net),
+        ]
+    }
+}
+
+impl Tabular for BlockActivity {
+    fn headers() -> Vec<&'static str> {
+        vec!["block", "timestamp", "base_fee_gwei", "gas_used", "fee_recipient", "transfers", "volume"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        let base_fee = self
+            .base_fee_per_gas
+            .as_deref()
+            .and_then(|fee| U256::from_dec_str(fee).ok())
+            .map(|fee| format_amount(fee, 9))
+            .unwrap_or_default();
+        let gas_used = match (self.gas_used, self.gas_limit) {
+            (Some(used), Some(limit)) if limit > 0 => format!("{:.1}%", used as f64 * 100.0 / limit as f64),
+            _ => String::new(),
+        };
+        vec![
+            self.block_number.to_string(),
+            self.timestamp.to_rfc3339(),
+            base_fee,
+            gas_used,
+            self.fee_recipient.clone().unwrap_or_default(),
+            self.transfers.to_string(),
+            amounts.format(&self.volume),
+        ]
+    }
+}
+
+impl Tabular for Erc1155Transfer {
+    fn headers() -> Vec<&'static str> {
+        vec!["block", "log", "batch", "contract", "operator", "from", "to", "token_id", "value", "timestamp"]
+    }
+
+    fn cells(&self, _amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.block_number.to_string(),
+            self.log_index.to_string(),
+            self.batch_index.to_string(),
+            self.contract_address.clone(),
+            self.operator.clone(),
+            self.from_address.clone(),
+            self.to_address.clone(),
+            self.token_id.clone(),
+            self.value.clone(),
+            self.timestamp.to_rfc3339(),
+        ]
+    }
+}
+
+impl Tabular for TokenCheckpoint {
+    fn headers() -> Vec<&'static str> {
+        vec!["chain_id", "token", "symbol", "standard", "start_block", "next_block", "updated_at"]
+    }
+
+    fn cells(&self, _amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.chain_id.to_string(),
+            self.token_address.clone(),
+            self.symbol.clone().unwrap_or_default(),
+            self.standard.clone(),
+            self.start_block.to_string(),
+            self.next_block.to_string(),
+            self.updated_at.to_rfc3339(),
+        ]
+    }
+}
+
+impl Tabular for WatchedAddress {
+    fn headers() -> Vec<&'static str> {
+        vec!["address", "label", "created_at"]
+    }
+
+    fn cells(&self, _amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.address.clone(),
+            self.label.clone().unwrap_or_default(),
+            self.created_at.to_rfc3339(),
+        ]
+    }
+}
+
+impl Tabular for ApiKey {
+    fn headers() -> Vec<&'static str> {
+        vec!["name", "scope", "created_at", "last_used_at"]
+    }
+
+    fn cells(&self, _amounts: &AmountFormat) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.scope.as_str().to_string(),
+            self.created_at.to_rfc3339(),
+            self.last_used_at.map(|time| time.to_rfc3339()).unwrap_or_default(),
+        ]
+    }
+}
+
+impl Tabular for AlertRule {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "name", "address", "direction", "min_value", "max_value", "counterparty", "frequency", "channels"]
+    }
+
+    fn cells(&self, amounts: &AmountFormat) -> Vec<String> {
+        let value = |value: &Option<String>| value.as_deref().map(|v| amounts.format(v)).unwrap_or_default();
+        let frequency = match (self.min_count, self.window_secs) {
+            (Some(count), Some(secs)) => format!("{} in {}s", count, secs),
+            _ => String::new(),
+        };
+        let channels = if self.channels.is_empty() {
+            "all".to_string()
+        } else {
+            self.channels.join(",")
+        };
+
+        vec![
+            self.id.to_string(),
+            self.name.clone(),
+            self.address.clone().unwrap_or_default(),
+            format!("{:?}", self.direction).to_lowercase(),
+            value(&self.min_value),
+            value(&self.max_value),
+            self.counterparty_label.clone().unwrap_or_default(),
+            frequency,
+            channels,
+        ]
+    }
+}
+
+/// `watch` output: one line per transfer, highlighted when `color` is set.
+pub fn alert_line(transfer: &TransferEvent, amounts: &AmountFormat, color: bool) -> String {
+    let line = format!(
+        "ALERT {} {} -> {} tx {} block {} at {}",
+        amounts.format(&transfer.value),
+        with_names(&transfer.from_address, &transfer.from_label, &transfer.from_ens),
+        with_names(&transfer.to_address, &transfer.to_label, &transfer.to_ens),
+        transfer.transaction_hash,
+        transfer.block_number,
+        transfer.timestamp.to_rfc3339(),
+    );
+    highlight(&line, color)
+}
+
+/// Bold yellow when `color` is set.
+pub fn highlight(line: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[1;33m{}\x1b[0m", line)
+    } else {
+        line.to_string()
+    }
+}
+
+/// Writes `rows` to `out` in the requested format.
+pub fn write_rows<W: Write, T: Serialize + Tabular>(
+    mut out: W,
+    format: OutputFormat,
+    amounts: &AmountFormat,
+    rows: &[T],
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, rows)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Jsonl => {
+            for row in rows {
+                serde_json::to_writer(&mut out, row)?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer.write_record(T::headers())?;
+            for row in rows {
+                writer.write_record(row.cells(amounts))?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Table => {
+            let headers = T::headers();
+            let cells: Vec<Vec<String>> = rows.iter().map(|row| row.cells(amounts)).collect();
+
+            let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+            for row in &cells {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+
+            let line = |values: Vec<&str>| {
+                values
+                    .iter()
+                    .zip(&widths)
+                    .map(|(value, width)| format!("{:<width$}", value, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            };
+
+            writeln!(out, "{}", line(headers.clone()))?;
+            writeln!(out, "{}", widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("  "))?;
+            for row in &cells {
+                writeln!(out, "{}", line(row.iter().map(String::as_str).collect()))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes rows one at a time, so exports never hold more than a single row in memory.
+/// CSV columns follow the serialized field names, so exports carry every field.
+pub enum RowWriter<W: Write, T> {
+    Csv(Box<csv::Writer<W>>, PhantomData<T>),
+    Jsonl(W),
+}
+
+impl<W: Write, T: Serialize> RowWriter<W, T> {
+    pub fn new(out: W, format: ExportFormat) -> Self {
+        match format {
+            ExportFormat::Csv => Self::Csv(Box::new(csv::Writer::from_writer(out)), PhantomData),
+            ExportFormat::Jsonl => Self::Jsonl(out),
+        }
+    }
+
+    pub fn write(&mut self, row: &T) -> Result<()> {
+        match self {
+            Self::Csv(writer, _) => writer.serialize(row)?,
+            Self::Jsonl(out) => {
+                serde_json::to_writer(&mut *out, row)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Self::Csv(mut writer, _) => writer.flush()?,
+            Self::Jsonl(mut out) => out.flush()?,
+        }
+        Ok(())
+    }
+}
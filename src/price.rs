@@ -0,0 +1,72 @@
+use anyhow::Result;
+use ethers_core::types::U256;
+
+use crate::models::BlockPrice;
+
+/// The block whose price is used for transfers in `block_number`: the start of its
+/// `PRICE_BLOCK_INTERVAL`, so one feed read covers the whole interval.
+pub fn price_block(block_number: u64, interval: u64) -> u64 {
+    block_number - block_number % interval.max(1)
+}
+
+/// Dollar value of `value` raw token units at `price`, rounded down to the cent.
+pub fn usd_value(value: &str, token_decimals: u8, price: &BlockPrice) -> Result<String> {
+    let value = U256::from_dec_str(value)?;
+    let answer = U256::from_dec_str(&price.answer)?;
+    let scale = U256::exp10(token_decimals as usize + price.decimals as usize);
+    let cents = value
+        .checked_mul(answer)
+        .and_then(|v| v.checked_mul(U256::from(100)))
+        .ok_or_else(|| anyhow::anyhow!("USD value of {} overflows", value))?
+        / scale;
+    Ok(format_cents(cents))
+}
+
+/// Formats a cent amount as `1234.56`.
+pub fn format_cents(cents: U256) -> String {
+    let (whole, rest) = cents.div_mod(U256::from(100));
+    format!("{}.{:02}", whole, rest.as_u32())
+}
+
+/// Parses a `usd_value` back into cents.
+pub fn parse_cents(usd: &str) -> Result<U256> {
+    let (whole, cents) = usd
+        .split_once('.')
+        .filter(|(_, cents)| cents.len() == 2)
+        .ok_or_else(|| anyhow::anyhow!("Invalid USD value: {}", usd))?;
+    Ok(U256::from_dec_str(whole)? * U256::from(100) + U256::from_dec_str(cents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn price(answer: &str, decimals: u8) -> BlockPrice {
+        BlockPrice {
+            feed: "0x8fFfFfd4AfB6115b954Bd326cbe7B4BA576818f6".to_string(),
+            block_number: 100,
+            answer: answer.to_string(),
+            decimals,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn values_transfers_at_the_feed_price() {
+        // 1,500.5 USDC at $0.9999.
+        assert_eq!(usd_value("1500500000", 6, &price("99990000", 8)).unwrap(), "1500.34");
+        // 2 WETH at $3,012.45.
+        assert_eq!(usd_value("2000000000000000000", 18, &price("301245000000", 8)).unwrap(), "6024.90");
+        assert_eq!(usd_value("1", 6, &price("100000000", 8)).unwrap(), "0.00");
+        assert_eq!(parse_cents("6024.90").unwrap(), U256::from(602490));
+    }
+
+    #[test]
+    fn prices_each_interval_at_its_first_block() {
+        assert_eq!(price_block(12_345, 100), 12_300);
+        assert_eq!(price_block(12_300, 100), 12_300);
+        assert_eq!(price_block(12_345, 1), 12_345);
+        assert_eq!(price_block(12_345, 0), 12_345);
+    }
+}
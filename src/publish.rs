@@ -0,0 +1,200 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{database::Database, models::TransferEvent, query::TransferFilter};
+
+/// Blocks of stored transfers read per outbox batch.
+const OUTBOX_BATCH_BLOCKS: u64 = 1_000;
+
+/// One record on an outbound transfer stream.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StreamMessage<'a> {
+    /// A transfer committed to the database.
+    Transfer(&'a TransferEvent),
+    /// Stored transfers from `from_block` on were rolled back by a reorg. Their replacements
+    /// follow as ordinary transfer messages.
+    Rollback { from_block: u64 },
+}
+
+/// Committed transfers and rollback markers not yet delivered to a stream.
+pub struct OutboxBatch {
+    pub rollbacks: Vec<u64>,
+    pub transfers: Vec<TransferEvent>,
+    reorg_id: i64,
+    through_block: u64,
+}
+
+impl OutboxBatch {
+    /// Rollback markers first, then transfers in block and log order.
+    pub fn messages(&self) -> Vec<StreamMessage<'_>> {
+        self.rollbacks
+            .iter()
+            .map(|&from_block| StreamMessage::Rollback { from_block })
+            .chain(self.transfers.iter().map(StreamMessage::Transfer))
+            .collect()
+    }
+}
+
+/// Delivery progress of one outbound stream, kept in `meta` so a failed send or a restart
+/// resumes where it stopped. Delivery is at-least-once: a batch that fails part way is sent
+/// again in full.
+pub struct Outbox {
+    block_key: String,
+    reorg_key: String,
+}
+
+impl Outbox {
+    pub fn new(name: &str) -> Self {
+        Self {
+            block_key: format!("{}_published_block", name),
+            reorg_key: format!("{}_published_reorg", name),
+        }
+    }
+
+    /// Starts a stream that has never published just before `start_block`, so enabling it does
+    /// not replay history. Streams with recorded progress keep it.
+    pub async fn start(&self, database: &Database, start_block: u64) -> Result<()> {
+        if database.get_meta(&self.block_key).await?.is_none() {
+            let reorg_id = database.get_reorg_forks_after(0).await?.last().map_or(0, |(id, _)| *id);
+            database.set_meta(&self.reorg_key, &reorg_id.to_string()).await?;
+            database
+                .set_meta(&self.block_key, &start_block.saturating_sub(1).to_string())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Delivers again from `from_block`, for a consumer whose copy from there on was dropped.
+    pub async fn rewind(&self, database: &Database, from_block: u64) -> Result<()> {
+        let reorg_id = database.get_reorg_forks_after(0).await?.last().map_or(0, |(id, _)| *id);
+        database.set_meta(&self.reorg_key, &reorg_id.to_string()).await?;
+        database
+            .set_meta(&self.block_key, &from_block.saturating_sub(1).to_string())
+            .await
+    }
+
+    /// The next undelivered batch up to `through_block`, or `None` once the stream is caught up.
+    pub async fn next_batch(&self, database: &Database, through_block: u64) -> Result<Option<OutboxBatch>> {
+        let mut published_block = self.read(database, &self.block_key).await? as u64;
+        let mut reorg_id = self.read(database, &self.reorg_key).await?;
+
+        // Reorgs of blocks the stream has not reached yet (including the unfinalized buffer)
+        // never reached consumers and need no marker.
+        let mut rollbacks = Vec::new();
+        for (id, fork_block) in database.get_reorg_forks_after(reorg_id).await? {
+            reorg_id = id;
+            if fork_block <= published_block {
+                rollbacks.push(fork_block);
+                published_block = fork_block.saturating_sub(1);
+            }
+        }
+
+        if rollbacks.is_empty() && published_block >= through_block {
+            return Ok(None);
+        }
+
+        let end = std::cmp::min(published_block.saturating_add(OUTBOX_BATCH_BLOCKS), through_block);
+        let mut transfers = Vec::new();
+        if published_block < end {
+            let filter = TransferFilter::new().block_range(Some(published_block + 1), Some(end));
+            database
+                .for_each_transfer(&filter, |transfer| {
+                    transfers.push(transfer);
+                    Ok(())
+                })
+                .await?;
+            database.mark_sanctioned(&mut transfers).await?;
+        }
+
+        Ok(Some(OutboxBatch {
+            rollbacks,
+            transfers,
+            reorg_id,
+            through_block: std::cmp::max(end, published_block),
+        }))
+    }
+
+    /// Records `batch` as delivered.
+    pub async fn commit(&self, database: &Database, batch: &OutboxBatch) -> Result<()> {
+        database.set_meta(&self.reorg_key, &batch.reorg_id.to_string()).await?;
+        database.set_meta(&self.block_key, &batch.through_block.to_string()).await
+    }
+
+    async fn read(&self, database: &Database, key: &str) -> Result<i64> {
+        match database.get_meta(key).await? {
+            Some(value) => value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid {} in meta: {}", key, value)),
+            None => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::{database, TransferBuilder};
+
+    async fn insert(database: &Database, block_number: i64) {
+        let transfer = TransferBuilder::new(block_number).build();
+        database.insert_transfer_event(&transfer).await.unwrap();
+    }
+
+    async fn drain(outbox: &Outbox, database: &Database, through_block: u64) -> Vec<String> {
+        let mut sent = Vec::new();
+        while let Some(batch) = outbox.next_batch(database, through_block).await.unwrap() {
+            for message in batch.messages() {
+                sent.push(match message {
+                    StreamMessage::Transfer(t) => format!("transfer {}", t.block_number),
+                    StreamMessage::Rollback { from_block } => format!("rollback {}", from_block),
+                });
+            }
+            outbox.commit(database, &batch).await.unwrap();
+        }
+        sent
+    }
+
+    #[tokio::test]
+    async fn starts_at_the_first_indexed_block() {
+        let database = database().await;
+        insert(&database, 90).await;
+        insert(&database, 100).await;
+
+        let outbox = Outbox::new("test");
+        outbox.start(&database, 100).await.unwrap();
+        assert_eq!(drain(&outbox, &database, 100).await, ["transfer 100"]);
+        assert!(drain(&outbox, &database, 100).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn uncommitted_batches_are_sent_again() {
+        let database = database().await;
+        insert(&database, 100).await;
+
+        let outbox = Outbox::new("test");
+        outbox.start(&database, 100).await.unwrap();
+        let batch = outbox.next_batch(&database, 100).await.unwrap().unwrap();
+        assert_eq!(batch.transfers.len(), 1);
+
+        assert_eq!(drain(&outbox, &database, 100).await, ["transfer 100"]);
+    }
+
+    #[tokio::test]
+    async fn reorgs_of_published_blocks_send_a_rollback_then_the_replacements() {
+        let database = database().await;
+        insert(&database, 100).await;
+        insert(&database, 101).await;
+
+        let outbox = Outbox::new("test");
+        outbox.start(&database, 100).await.unwrap();
+        drain(&outbox, &database, 101).await;
+
+        database.record_reorg(101, 105, 5, 12).await.unwrap();
+        // Unpublished blocks never reached consumers.
+        database.record_reorg(110, 112, 3, 12).await.unwrap();
+
+        assert_eq!(drain(&outbox, &database, 101).await, ["rollback 101", "transfer 101"]);
+        assert!(drain(&outbox, &database, 101).await.is_empty());
+    }
+}
@@ -0,0 +1,504 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use sqlx::{QueryBuilder, Sqlite};
+use utoipa::ToSchema;
+
+use crate::models::TransferEvent;
+
+/// Which side of a transfer an address filter applies to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+    #[default]
+    Both,
+}
+
+/// How counterparties are ranked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CounterpartyRank {
+    /// Most transfers first.
+    #[default]
+    Count,
+    /// Most value moved first.
+    Volume,
+}
+
+/// Time buckets for `netflow`, all in UTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowInterval {
+    Hour,
+    #[default]
+    Day,
+    /// Weeks starting on Monday.
+    Week,
+    Month,
+}
+
+impl FlowInterval {
+    /// SQLite expression naming the bucket of a row's `timestamp`.
+    pub fn sql_bucket(self) -> &'static str {
+        match self {
+            FlowInterval::Hour => "strftime('%Y-%m-%d %H:00', timestamp)",
+            FlowInterval::Day => "DATE(timestamp)",
+            FlowInterval::Week => "DATE(timestamp, 'weekday 0', '-6 days')",
+            FlowInterval::Month => "strftime('%Y-%m', timestamp)",
+        }
+    }
+
+    /// The bucket of `time`, matching `sql_bucket`.
+    pub fn bucket(self, time: DateTime<Utc>) -> String {
+        match self {
+            FlowInterval::Hour => time.format("%Y-%m-%d %H:00").to_string(),
+            FlowInterval::Day => time.format("%Y-%m-%d").to_string(),
+            FlowInterval::Week => {
+                let monday = time.date_naive() - Duration::days(time.weekday().num_days_from_monday() as i64);
+                monday.format("%Y-%m-%d").to_string()
+            }
+            FlowInterval::Month => time.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// What an address is, as recorded in `address_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressKind {
+    /// An externally owned account.
+    Eoa,
+    Contract,
+}
+
+/// Keyset position in the `block_number DESC, log_index ASC` ordering used by transfer queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub block_number: i64,
+    pub log_index: i64,
+}
+
+impl Cursor {
+    pub fn after(event: &TransferEvent) -> Self {
+        Self {
+            block_number: event.block_number,
+            log_index: event.log_index,
+        }
+    }
+
+    /// Opaque token handed to clients; only `decode` should interpret it.
+    pub fn encode(&self) -> String {
+        hex::encode(format!("{}:{}", self.block_number, self.log_index))
+    }
+
+    pub fn decode(token: &str) -> Result<Self> {
+        let raw = String::from_utf8(hex::decode(token)?)?;
+        let (block_number, log_index) = raw
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Invalid cursor"))?;
+
+        Ok(Self {
+            block_number: block_number.parse()?,
+            log_index: log_index.parse()?,
+        })
+    }
+}
+
+/// Filter set shared by every query over `transfer_events`-shaped tables.
+///
+/// All values are bound as parameters; nothing user-supplied is formatted into the SQL.
+#[derive(Debug, Clone, Default)]
+pub struct TransferFilter {
+    pub address: Option<String>,
+    pub direction: Direction,
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub min_value: Option<String>,
+    pub max_value: Option<String>,
+    pub from_time: Option<DateTime<Utc>>,
+    pub to_time: Option<DateTime<Utc>>,
+    pub from_kind: Option<AddressKind>,
+    pub to_kind: Option<AddressKind>,
+    pub sanctioned: bool,
+    pub cursor: Option<Cursor>,
+    pub include_archived: bool,
+}
+
+impl TransferFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn address(mut self, address: Option<String>) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn block_range(mut self, from_block: Option<u64>, to_block: Option<u64>) -> Self {
+        self.from_block = from_block;
+        self.to_block = to_block;
+        self
+    }
+
+    /// Raw integer bounds (inclusive), as decimal strings without leading zeros.
+    pub fn value_range(mut self, min_value: Option<String>, max_value: Option<String>) -> Self {
+        self.min_value = min_value;
+        self.max_value = max_value;
+        self
+    }
+
+    pub fn time_range(mut self, from_time: Option<DateTime<Utc>>, to_time: Option<DateTime<Utc>>) -> Self {
+        self.from_time = from_time;
+        self.to_time = to_time;
+        self
+    }
+
+    /// Only return rows whose sender and recipient were classified as these kinds. Addresses not
+    /// classified yet match neither kind.
+    pub fn address_kinds(mut self, from_kind: Option<AddressKind>, to_kind: Option<AddressKind>) -> Self {
+        self.from_kind = from_kind;
+        self.to_kind = to_kind;
+        self
+    }
+
+    /// Only return rows touching an address on the sanctions list.
+    pub fn sanctioned(mut self, sanctioned: bool) -> Self {
+        self.sanctioned = sanctioned;
+        self
+    }
+
+    /// Whether the filter joins `address_info` or `sanctioned_addresses`, which only live beside
+    /// unsharded, live rows.
+    pub fn uses_lookup_tables(&self) -> bool {
+        self.from_kind.is_some() || self.to_kind.is_some() || self.sanctioned
+    }
+
+    /// Only return rows that sort after `cursor`.
+    pub fn cursor(mut self, cursor: Option<Cursor>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    /// Also search compressed `archived_transfers` batches once live rows run out.
+    pub fn include_archived(mut self, include_archived: bool) -> Self {
+        self.include_archived = include_archived;
+        self
+    }
+
+    /// In-memory equivalent of `push_where`, used for rows decoded from archive batches. Address
+    /// kinds and sanctions are not checked; archived queries reject them.
+    pub fn matches(&self, event: &TransferEvent) -> bool {
+        if let Some(address) = &self.address {
+            let matched = match self.direction {
+                Direction::Sent => event.from_address.eq_ignore_ascii_case(address),
+                Direction::Received => event.to_address.eq_ignore_ascii_case(address),
+                Direction::Both => {
+                    event.from_address.eq_ignore_ascii_case(address) || event.to_address.eq_ignore_ascii_case(address)
+                }
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        let block = event.block_number as u64;
+        if self.from_block.is_some_and(|from| block < from) || self.to_block.is_some_and(|to| block > to) {
+            return false;
+        }
+
+        if let Some(min) = &self.min_value {
+            if compare_values(&event.value, min) == Ordering::Less {
+                return false;
+            }
+        }
+        if let Some(max) = &self.max_value {
+            if compare_values(&event.value, max) == Ordering::Greater {
+                return false;
+            }
+        }
+
+        if self.from_time.is_some_and(|from| event.timestamp < from) || self.to_time.is_some_and(|to| event.timestamp > to) {
+            return false;
+        }
+
+        if let Some(cursor) = self.cursor {
+            let after = event.block_number < cursor.block_number
+                || (event.block_number == cursor.block_number && event.log_index > cursor.log_index);
+            if !after {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Appends ` WHERE ...` (or nothing) to `builder` with every condition bound as a parameter.
+    pub(crate) fn push_where(&self, builder: &mut QueryBuilder<'_, Sqlite>) {
+        let mut has_condition = false;
+        let mut next = |builder: &mut QueryBuilder<'_, Sqlite>| {
+            builder.push(if has_condition { " AND " } else { " WHERE " });
+            has_condition = true;
+        };
+
+        if let Some(address) = &self.address {
+            next(builder);
+            match self.direction {
+                Direction::Sent => {
+                    builder.push("from_address = ").push_bind(address.clone()).push(" COLLATE NOCASE");
+                }
+                Direction::Received => {
+                    builder.push("to_address = ").push_bind(address.clone()).push(" COLLATE NOCASE");
+                }
+                Direction::Both => {
+                    builder
+                        .push("(from_address = ")
+                        .push_bind(address.clone())
+                        .push(" COLLATE NOCASE OR to_address = ")
+                        .push_bind(address.clone())
+                        .push(" COLLATE NOCASE)");
+                }
+            }
+        }
+
+        if let Some(from) = self.from_block {
+            next(builder);
+            builder.push("block_number >= ").push_bind(from as i64);
+        }
+
+        if let Some(to) = self.to_block {
+            next(builder);
+            builder.push("block_number <= ").push_bind(to as i64);
+        }
+
+        // Values are unpadded decimal strings, so compare by length first and then lexically.
+        if let Some(min) = &self.min_value {
+            next(builder);
+            builder
+                .push("(LENGTH(value) > ")
+                .push_bind(min.len() as i64)
+                .push(" OR (LENGTH(value) = ")
+                .push_bind(min.len() as i64)
+                .push(" AND value >= ")
+                .push_bind(min.clone())
+                .push("))");
+        }
+
+        if let Some(max) = &self.max_value {
+            next(builder);
+            builder
+                .push("(LENGTH(value) < ")
+                .push_bind(max.len() as i64)
+                .push(" OR (LENGTH(value) = ")
+                .push_bind(max.len() as i64)
+                .push(" AND value <= ")
+                .push_bind(max.clone())
+                .push("))");
+        }
+
+        if let Some(from) = self.from_time {
+            next(builder);
+            builder.push("timestamp >= ").push_bind(from);
+        }
+
+        if let Some(to) = self.to_time {
+            next(builder);
+            builder.push("timestamp <= ").push_bind(to);
+        }
+
+        for (column, kind) in [("from_address", self.from_kind), ("to_address", self.to_kind)] {
+            if let Some(kind) = kind {
+                next(builder);
+                builder
+                    .push(column)
+                    .push(" COLLATE NOCASE IN (SELECT address FROM address_info WHERE is_contract = ")
+                    .push_bind(kind == AddressKind::Contract)
+                    .push(")");
+            }
+        }
+
+        if self.sanctioned {
+            next(builder);
+            builder.push(
+                "(from_address COLLATE NOCASE IN (SELECT address FROM sanctioned_addresses) \
+                 OR to_address COLLATE NOCASE IN (SELECT address FROM sanctioned_addresses))",
+            );
+        }
+
+        if let Some(cursor) = self.cursor {
+            next(builder);
+            builder
+                .push("(block_number < ")
+                .push_bind(cursor.block_number)
+                .push(" OR (block_number = ")
+                .push_bind(cursor.block_number)
+                .push(" AND log_index > ")
+                .push_bind(cursor.log_index)
+                .push("))");
+        }
+    }
+}
+
+/// Orders unpadded decimal strings numerically: by digit count, then lexically.
+fn compare_values(a: &str, b: &str) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// Parses an RFC3339 timestamp, or a relative age such as `30m`, `24h` or `7d` counted back from `now`.
+pub fn parse_time(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let age = parse_duration(input)
+        .map_err(|_| anyhow::anyhow!("Invalid time: {} (expected RFC3339 or e.g. 24h)", input.trim()))?;
+    Ok(now - age)
+}
+
+/// Parses a span like `90s`, `15m`, `24h`, `7d` or `2w`.
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    let split = input.char_indices().last().map_or(0, |(i, _)| i);
+    let (amount, unit) = input.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration: {} (expected e.g. 24h)", input))?;
+
+    match unit {
+        "s" => Ok(Duration::seconds(amount)),
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(anyhow::anyhow!("Invalid time unit in {} (use s, m, h, d or w)", input)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::TransferBuilder;
+
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const BOB: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    fn transfer(block_number: i64, log_index: i64, value: &str) -> TransferEvent {
+        TransferBuilder::new(block_number)
+            .log_index(log_index)
+            .from(ALICE)
+            .to(BOB)
+            .value(value)
+            .at(at("2026-01-01T12:00:00Z"))
+            .build()
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        for cursor in [
+            Cursor { block_number: 0, log_index: 0 },
+            Cursor { block_number: 19_000_000, log_index: 417 },
+            Cursor { block_number: i64::MAX, log_index: i64::MAX },
+        ] {
+            assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        }
+    }
+
+    #[test]
+    fn cursor_rejects_tampered_tokens() {
+        let token = Cursor { block_number: 100, log_index: 2 }.encode();
+        let truncated = &token[..token.len() - 1];
+        let not_hex = token.replacen(|c: char| c.is_ascii_digit(), "z", 1);
+        for tampered in [
+            truncated.to_string(),
+            not_hex,
+            hex::encode("100"),
+            hex::encode("100:x"),
+            hex::encode(":2"),
+            hex::encode([0xff, 0xfe]),
+            String::new(),
+        ] {
+            assert!(Cursor::decode(&tampered).is_err(), "{:?} should not decode", tampered);
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::seconds(90));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::minutes(15));
+        assert_eq!(parse_duration(" 24h ").unwrap(), Duration::hours(24));
+        assert_eq!(parse_duration("7d").unwrap(), Duration::days(7));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::weeks(2));
+        for input in ["", "h", "24", "24x", "1.5h", "h24"] {
+            assert!(parse_duration(input).is_err(), "{:?} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn parses_absolute_and_relative_times() {
+        let now = at("2026-01-02T00:00:00Z");
+        assert_eq!(parse_time("2025-12-31T06:00:00+02:00", now).unwrap(), at("2025-12-31T04:00:00Z"));
+        assert_eq!(parse_time("24h", now).unwrap(), at("2026-01-01T00:00:00Z"));
+        assert!(parse_time("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn matches_address_by_direction_ignoring_case() {
+        let event = transfer(100, 0, "1");
+        let lower = ALICE.to_lowercase();
+
+        assert!(TransferFilter::new().address(Some(lower.clone())).matches(&event));
+        assert!(TransferFilter::new().address(Some(lower.clone())).direction(Direction::Sent).matches(&event));
+        assert!(!TransferFilter::new().address(Some(lower)).direction(Direction::Received).matches(&event));
+        assert!(TransferFilter::new().address(Some(BOB.to_string())).direction(Direction::Received).matches(&event));
+        assert!(!TransferFilter::new().address(Some(BOB.to_string())).direction(Direction::Sent).matches(&event));
+    }
+
+    #[test]
+    fn matches_block_range_inclusively() {
+        let filter = TransferFilter::new().block_range(Some(100), Some(200));
+        assert!(filter.matches(&transfer(100, 0, "1")));
+        assert!(filter.matches(&transfer(200, 0, "1")));
+        assert!(!filter.matches(&transfer(99, 0, "1")));
+        assert!(!filter.matches(&transfer(201, 0, "1")));
+    }
+
+    #[test]
+    fn matches_values_numerically() {
+        let filter = TransferFilter::new().value_range(Some("100".to_string()), Some("1000".to_string()));
+        assert!(filter.matches(&transfer(1, 0, "100")));
+        assert!(filter.matches(&transfer(1, 0, "999")));
+        assert!(filter.matches(&transfer(1, 0, "1000")));
+        // As text "99" sorts after "100", so digit counts have to be compared first.
+        assert!(!filter.matches(&transfer(1, 0, "99")));
+        assert!(!filter.matches(&transfer(1, 0, "1001")));
+        assert!(!filter.matches(&transfer(1, 0, "2000")));
+    }
+
+    #[test]
+    fn matches_time_range_inclusively() {
+        let event = transfer(1, 0, "1");
+        assert!(TransferFilter::new().time_range(Some(event.timestamp), Some(event.timestamp)).matches(&event));
+        assert!(!TransferFilter::new().time_range(Some(at("2026-01-01T12:00:01Z")), None).matches(&event));
+        assert!(!TransferFilter::new().time_range(None, Some(at("2026-01-01T11:59:59Z"))).matches(&event));
+    }
+
+    #[test]
+    fn matches_only_rows_after_the_cursor() {
+        let filter = TransferFilter::new().cursor(Some(Cursor { block_number: 100, log_index: 2 }));
+        assert!(filter.matches(&transfer(99, 9, "1")));
+        assert!(filter.matches(&transfer(100, 3, "1")));
+        assert!(!filter.matches(&transfer(100, 2, "1")));
+        assert!(!filter.matches(&transfer(100, 1, "1")));
+        assert!(!filter.matches(&transfer(101, 0, "1")));
+    }
+}
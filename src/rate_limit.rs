@@ -0,0 +1,65 @@
+use anyhow::Result;
+use governor::{clock::Clock, DefaultKeyedRateLimiter, Quota};
+use std::{
+    net::IpAddr,
+    num::NonZeroU32,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Checks between sweeps of callers that are back to a full burst, which bounds the memory one
+/// limiter keeps for clients that came and went.
+const SWEEP_EVERY: u64 = 4_096;
+
+/// Who a request is metered as: the API key it authenticated with, or else its client's address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateKey {
+    Token(String),
+    Ip(IpAddr),
+}
+
+/// Token buckets per `RateKey`, refilling `per_minute` requests a minute up to `burst`.
+pub struct RateLimiter {
+    limiter: DefaultKeyedRateLimiter<RateKey>,
+    checks: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32, burst: u32) -> Result<Self> {
+        let per_minute = NonZeroU32::new(per_minute).ok_or_else(|| anyhow::anyhow!("Rate limit must be positive"))?;
+        let burst = NonZeroU32::new(burst).ok_or_else(|| anyhow::anyhow!("Rate limit burst must be positive"))?;
+        Ok(Self {
+            limiter: DefaultKeyedRateLimiter::keyed(Quota::per_minute(per_minute).allow_burst(burst)),
+            checks: AtomicU64::new(0),
+        })
+    }
+
+    /// Takes one request from `key`'s bucket, or says how long until it holds one again.
+    pub fn check(&self, key: &RateKey) -> std::result::Result<(), Duration> {
+        if self.checks.fetch_add(1, Ordering::Relaxed) % SWEEP_EVERY == SWEEP_EVERY - 1 {
+            self.limiter.retain_recent();
+            self.limiter.shrink_to_fit();
+        }
+        self.limiter
+            .check_key(key)
+            .map_err(|not_until| not_until.wait_time_from(self.limiter.clock().now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_key_gets_its_own_burst() {
+        let limiter = RateLimiter::new(60, 2).unwrap();
+        let alice = RateKey::Token("alice".to_string());
+        assert!(limiter.check(&alice).is_ok());
+        assert!(limiter.check(&alice).is_ok());
+        let wait = limiter.check(&alice).unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(1));
+
+        assert!(limiter.check(&RateKey::Ip([127, 0, 0, 1].into())).is_ok());
+        assert!(RateLimiter::new(0, 1).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+
+use crate::{
+    config::Config,
+    database::Database,
+    publish::{Outbox, StreamMessage},
+};
+
+/// Appends committed transfers and reorg rollback markers to a Redis Stream, and optionally
+/// publishes them on a pub/sub channel.
+pub struct RedisPublisher {
+    client: redis::Client,
+    stream: String,
+    /// Approximate cap on stream length; 0 keeps every entry.
+    max_len: usize,
+    channel: Option<String>,
+    outbox: Outbox,
+}
+
+impl RedisPublisher {
+    /// `None` when `REDIS_URL` is unset.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(url) = &config.redis_url else {
+            return Ok(None);
+        };
+
+        let client = redis::Client::open(url.as_str()).map_err(|e| anyhow::anyhow!("Invalid REDIS_URL: {}", e))?;
+
+        Ok(Some(Self {
+            client,
+            stream: config.redis_stream.clone(),
+            max_len: config.redis_stream_max_len,
+            channel: config.redis_channel.clone(),
+            outbox: Outbox::new("redis"),
+        }))
+    }
+
+    pub async fn start(&self, database: &Database, start_block: u64) -> Result<()> {
+        self.outbox.start(database, start_block).await
+    }
+
+    /// Sends everything committed through `through_block` that Redis has not accepted yet.
+    pub async fn publish(&self, database: &Database, through_block: u64) -> Result<u64> {
+        let mut connection = None;
+        let mut published = 0;
+        while let Some(batch) = self.outbox.next_batch(database, through_block).await? {
+            let messages = batch.messages();
+            let mut pipe = redis::pipe();
+            for message in &messages {
+                let kind = match message {
+                    StreamMessage::Transfer(_) => "transfer",
+                    StreamMessage::Rollback { .. } => "rollback",
+                };
+                let data = serde_json::to_string(message)?;
+
+                let mut xadd = redis::cmd("XADD");
+                xadd.arg(&self.stream);
+                if self.max_len > 0 {
+                    xadd.arg("MAXLEN").arg("~").arg(self.max_len);
+                }
+                xadd.arg("*").arg("kind").arg(kind).arg("data").arg(&data);
+                pipe.add_command(xadd).ignore();
+
+                if let Some(channel) = &self.channel {
+                    pipe.cmd("PUBLISH").arg(channel).arg(&data).ignore();
+                }
+            }
+
+            let connection = match connection.as_mut() {
+                Some(connection) => connection,
+                None => connection.insert(self.client.get_multiplexed_async_connection().await?),
+            };
+            pipe.query_async::<()>(connection).await?;
+
+            self.outbox.commit(database, &batch).await?;
+            published += messages.len() as u64;
+        }
+        Ok(published)
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+
+use crate::{
+    database::Database,
+    ethereum::{decode_erc1155_logs, decode_transfer_logs},
+    models::ReplaySummary,
+};
+
+/// Blocks decoded and rewritten per batch.
+const REPLAY_BATCH_BLOCKS: u64 = 1_000;
+
+/// Rebuilds `transfer_events` and `erc1155_transfers` in `from_block..=to_block` from `raw_logs`,
+/// without the RPC, then recomputes the address rollups. Blocks indexed without `RAW_LOGS` are left as they are.
+pub async fn replay(database: &Database, from_block: u64, to_block: u64) -> Result<ReplaySummary> {
+    // Archived rows would survive the rewrite and be stored twice.
+    if let Some(archived) = database.latest_archived_block().await? {
+        if from_block <= archived {
+            return Err(anyhow::anyhow!(
+                "Blocks up to {} are archived; replay from {} or later",
+                archived,
+                archived + 1
+            ));
+        }
+    }
+
+    let mut summary = ReplaySummary::default();
+    let mut start = from_block;
+    while start <= to_block {
+        let end = std::cmp::min(start.saturating_add(REPLAY_BATCH_BLOCKS - 1), to_block);
+
+        let (covered, uncovered): (Vec<_>, Vec<_>) = database
+            .get_raw_log_coverage(start, end)
+            .await?
+            .into_iter()
+            .partition(|(_, raw_logs)| *raw_logs);
+        summary.skipped_blocks += uncovered.len() as u64;
+
+        let blocks: Vec<u64> = covered.into_iter().map(|(block, _)| block).collect();
+        if !blocks.is_empty() {
+            let logs: Vec<_> = database
+                .get_raw_logs(start, end)
+                .await?
+                .into_iter()
+                .filter(|log| blocks.binary_search(&log.block_number).is_ok())
+                .collect();
+            let events = decode_transfer_logs(&logs, database.now())?;
+            database.replace_block_transfers(&blocks, &events).await?;
+            let erc1155_transfers = decode_erc1155_logs(&logs, database.now())?;
+            database.replace_block_erc1155_transfers(&blocks, &erc1155_transfers).await?;
+
+            summary.blocks += blocks.len() as u64;
+            summary.logs += logs.len() as u64;
+            summary.transfers += events.len() as u64;
+            summary.erc1155_transfers += erc1155_transfers.len() as u64;
+        }
+
+        start = end + 1;
+    }
+
+    if summary.blocks > 0 {
+        database.rebuild_address_stats().await?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::testing::database,
+        dedup::EventSource,
+        models::{ProcessedBlock, RawLog},
+        query::TransferFilter,
+    };
+    use chrono::Utc;
+
+    fn raw_log(block_number: u64, value: u64) -> RawLog {
+        RawLog {
+            block_number,
+            block_hash: format!("0x{:064x}", block_number),
+            block_timestamp: Utc::now(),
+            transaction_hash: format!("0x{:064x}", block_number),
+            transaction_index: Some(0),
+            log_index: 0,
+            address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string(),
+            topics: vec![
+                "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef".to_string(),
+                format!("0x{:064x}", 1),
+                format!("0x{:064x}", 2),
+            ],
+            data: format!("0x{:064x}", value),
+        }
+    }
+
+    fn block(block_number: u64) -> ProcessedBlock {
+        ProcessedBlock {
+            block_number,
+            block_hash: format!("0x{:064x}", block_number),
+            timestamp: Utc::now(),
+            base_fee_per_gas: Some("1000000000".to_string()),
+            gas_used: 15_000_000,
+            gas_limit: 30_000_000,
+            fee_recipient: format!("0x{:040x}", 3),
+        }
+    }
+
+    async fn values(database: &Database) -> Vec<(i64, String)> {
+        let mut values = Vec::new();
+        database
+            .for_each_transfer(&TransferFilter::new(), |t| {
+                values.push((t.block_number, t.value));
+                Ok(())
+            })
+            .await
+            .unwrap();
+        values
+    }
+
+    #[tokio::test]
+    async fn rebuilds_transfers_only_in_blocks_with_raw_logs() {
+        let database = database().await;
+
+        let archived = [raw_log(100, 5)];
+        let events = decode_transfer_logs(&archived, Utc::now()).unwrap();
+        database
+            .commit_block_range(&events, &[block(100)], Some(&archived), EventSource::Rpc, 0)
+            .await
+            .unwrap();
+        let events = decode_transfer_logs(&[raw_log(101, 7)], Utc::now()).unwrap();
+        database
+            .commit_block_range(&events, &[block(101)], None, EventSource::Rpc, 0)
+            .await
+            .unwrap();
+
+        // As if a decoding bug had dropped block 100's transfer.
+        database.replace_block_transfers(&[100], &[]).await.unwrap();
+        assert_eq!(values(&database).await, [(101, "7".to_string())]);
+
+        let summary = replay(&database, 0, 101).await.unwrap();
+        assert_eq!((summary.blocks, summary.skipped_blocks, summary.transfers), (1, 1, 1));
+        assert_eq!(values(&database).await, [(100, "5".to_string()), (101, "7".to_string())]);
+    }
+}
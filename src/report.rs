@@ -0,0 +1,378 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers_core::types::U256;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{database::Database, models::TransferEvent, output::AmountFormat, query::TransferFilter, units::net_flow};
+
+/// Everything stored about one address, summarized. Amounts are raw token units.
+#[derive(Debug, Serialize)]
+pub struct AddressReport {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub first_seen: Option<Sighting>,
+    pub last_seen: Option<Sighting>,
+    pub transfers_sent: u64,
+    pub transfers_received: u64,
+    pub total_sent: String,
+    pub total_received: String,
+    /// Received minus sent, negative when the address sent more.
+    pub net_flow: String,
+    /// Highest combined volume first.
+    pub top_counterparties: Vec<CounterpartyActivity>,
+    /// Largest first.
+    pub largest_transfers: Vec<TransferEvent>,
+    /// Oldest first.
+    pub by_month: Vec<MonthActivity>,
+}
+
+/// A transfer that bounds an address's activity.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sighting {
+    pub block_number: i64,
+    pub timestamp: DateTime<Utc>,
+    pub transaction_hash: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CounterpartyActivity {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub transfers: u64,
+    /// Sent by the reported address to this counterparty.
+    pub sent: String,
+    pub received: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MonthActivity {
+    /// `YYYY-MM`.
+    pub month: String,
+    pub transfers: u64,
+    pub sent: String,
+    pub received: String,
+    pub net_flow: String,
+}
+
+#[derive(Default)]
+struct Flow {
+    transfers: u64,
+    sent: U256,
+    received: U256,
+}
+
+impl Flow {
+    fn add(&mut self, sent: bool, received: bool, value: U256) {
+        self.transfers += 1;
+        if sent {
+            self.sent += value;
+        }
+        if received {
+            self.received += value;
+        }
+    }
+}
+
+/// Accumulates an [`AddressReport`] one transfer at a time, so it can be fed straight from
+/// `for_each_transfer`.
+pub struct ReportBuilder {
+    address: String,
+    top: usize,
+    first: Option<(i64, i64, Sighting)>,
+    last: Option<(i64, i64, Sighting)>,
+    transfers_sent: u64,
+    transfers_received: u64,
+    totals: Flow,
+    counterparties: HashMap<String, Flow>,
+    largest: Vec<(U256, TransferEvent)>,
+    months: BTreeMap<String, Flow>,
+}
+
+impl ReportBuilder {
+    /// `top` caps the counterparty and largest transfer lists.
+    pub fn new(address: &str, top: usize) -> Self {
+        Self {
+            address: address.to_string(),
+            top,
+            first: None,
+            last: None,
+            transfers_sent: 0,
+            transfers_received: 0,
+            totals: Flow::default(),
+            counterparties: HashMap::new(),
+            largest: Vec::new(),
+            months: BTreeMap::new(),
+        }
+    }
+
+    /// Adds one of the address's transfers. Transfers may arrive in any order.
+    pub fn add(&mut self, transfer: TransferEvent) -> Result<()> {
+        let value = U256::from_dec_str(&transfer.value)
+            .map_err(|_| anyhow::anyhow!("Invalid value of {}: {}", transfer.id, transfer.value))?;
+        let sent = transfer.from_address.eq_ignore_ascii_case(&self.address);
+        let received = transfer.to_address.eq_ignore_ascii_case(&self.address);
+        if !sent && !received {
+            return Ok(());
+        }
+
+        self.transfers_sent += sent as u64;
+        self.transfers_received += received as u64;
+        self.totals.add(sent, received, value);
+        let counterparty = if sent { &transfer.to_address } else { &transfer.from_address };
+        self.counterparties.entry(counterparty.clone()).or_default().add(sent, received, value);
+        self.months
+            .entry(transfer.timestamp.format("%Y-%m").to_string())
+            .or_default()
+            .add(sent, received, value);
+
+        let position = (transfer.block_number, transfer.log_index);
+        let sighting = || Sighting {
+            block_number: transfer.block_number,
+            timestamp: transfer.timestamp,
+            transaction_hash: transfer.transaction_hash.clone(),
+        };
+        if self.first.as_ref().is_none_or(|(b, l, _)| position < (*b, *l)) {
+            self.first = Some((position.0, position.1, sighting()));
+        }
+        if self.last.as_ref().is_none_or(|(b, l, _)| position > (*b, *l)) {
+            self.last = Some((position.0, position.1, sighting()));
+        }
+
+        self.largest.push((value, transfer));
+        if self.largest.len() >= self.top.max(1) * 2 {
+            self.trim_largest();
+        }
+        Ok(())
+    }
+
+    fn trim_largest(&mut self) {
+        self.largest.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| (b.1.block_number, b.1.log_index).cmp(&(a.1.block_number, a.1.log_index)))
+        });
+        self.largest.truncate(self.top);
+    }
+
+    /// The finished report, with names from `labels` attached.
+    pub fn finish(mut self, labels: &HashMap<String, String>) -> AddressReport {
+        self.trim_largest();
+
+        let mut counterparties: Vec<(String, Flow)> = self.counterparties.into_iter().collect();
+        counterparties.sort_by(|a, b| {
+            (b.1.sent + b.1.received)
+                .cmp(&(a.1.sent + a.1.received))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        counterparties.truncate(self.top);
+
+        AddressReport {
+            label: labels.get(&self.address).cloned(),
+            address: self.address,
+            first_seen: self.first.map(|(_, _, sighting)| sighting),
+            last_seen: self.last.map(|(_, _, sighting)| sighting),
+            transfers_sent: self.transfers_sent,
+            transfers_received: self.transfers_received,
+            total_sent: self.totals.sent.to_string(),
+            total_received: self.totals.received.to_string(),
+            net_flow: net_flow(self.totals.received, self.totals.sent),
+            top_counterparties: counterparties
+                .into_iter()
+                .map(|(address, flow)| CounterpartyActivity {
+                    label: labels.get(&address).cloned(),
+                    address,
+                    transfers: flow.transfers,
+                    sent: flow.sent.to_string(),
+                    received: flow.received.to_string(),
+                })
+                .collect(),
+            largest_transfers: self
+                .largest
+                .into_iter()
+                .map(|(_, mut transfer)| {
+                    transfer.from_label = labels.get(&transfer.from_address).cloned();
+                    transfer.to_label = labels.get(&transfer.to_address).cloned();
+                    transfer
+                })
+                .collect(),
+            by_month: self
+                .months
+                .into_iter()
+                .map(|(month, flow)| MonthActivity {
+                    month,
+                    transfers: flow.transfers,
+                    sent: flow.sent.to_string(),
+                    received: flow.received.to_string(),
+                    net_flow: net_flow(flow.received, flow.sent),
+                })
+                .collect(),
+        }
+    }
+
+    /// Addresses whose labels `finish` shows.
+    pub fn addresses(&self) -> Vec<String> {
+        let mut addresses: HashSet<String> = self.counterparties.keys().cloned().collect();
+        addresses.insert(self.address.clone());
+        for (_, transfer) in &self.largest {
+            addresses.insert(transfer.from_address.clone());
+            addresses.insert(transfer.to_address.clone());
+        }
+        addresses.into_iter().collect()
+    }
+}
+
+fn with_label(address: &str, label: &Option<String>) -> String {
+    match label {
+        Some(label) => format!("{} ({})", address, label),
+        None => address.to_string(),
+    }
+}
+
+impl AddressReport {
+    /// The report as aligned plain text.
+    pub fn render(&self, amounts: &AmountFormat) -> String {
+        let mut out = String::new();
+        let seen = |sighting: &Option<Sighting>| match sighting {
+            Some(s) => format!("{} block {} tx {}", s.timestamp.to_rfc3339(), s.block_number, s.transaction_hash),
+            None => "never".to_string(),
+        };
+        let _ = writeln!(out, "Address     {}", with_label(&self.address, &self.label));
+        let _ = writeln!(out, "First seen  {}", seen(&self.first_seen));
+        let _ = writeln!(out, "Last seen   {}", seen(&self.last_seen));
+        let _ = writeln!(out, "Sent        {} in {} transfers", amounts.format(&self.total_sent), self.transfers_sent);
+        let _ = writeln!(
+            out,
+            "Received    {} in {} transfers",
+            amounts.format(&self.total_received),
+            self.transfers_received
+        );
+        let _ = writeln!(out, "Net flow    {}", amounts.format_signed(&self.net_flow));
+
+        if !self.top_counterparties.is_empty() {
+            let _ = writeln!(out, "\nTop counterparties");
+            for c in &self.top_counterparties {
+                let _ = writeln!(
+                    out,
+                    "  {}  sent {}  received {}  {} transfers",
+                    with_label(&c.address, &c.label),
+                    amounts.format(&c.sent),
+                    amounts.format(&c.received),
+                    c.transfers
+                );
+            }
+        }
+
+        if !self.largest_transfers.is_empty() {
+            let _ = writeln!(out, "\nLargest transfers");
+            for t in &self.largest_transfers {
+                let (direction, counterparty, label) = if t.from_address.eq_ignore_ascii_case(&self.address) {
+                    ("to", &t.to_address, &t.to_label)
+                } else {
+                    ("from", &t.from_address, &t.from_label)
+                };
+                let _ = writeln!(
+                    out,
+                    "  {}  {} {} {}  block {} tx {}",
+                    t.timestamp.format("%Y-%m-%d"),
+                    amounts.format(&t.value),
+                    direction,
+                    with_label(counterparty, label),
+                    t.block_number,
+                    t.transaction_hash
+                );
+            }
+        }
+
+        if !self.by_month.is_empty() {
+            let _ = writeln!(out, "\nBy month");
+            for m in &self.by_month {
+                let _ = writeln!(
+                    out,
+                    "  {}  {:>6} transfers  sent {}  received {}  net {}",
+                    m.month,
+                    m.transfers,
+                    amounts.format(&m.sent),
+                    amounts.format(&m.received),
+                    amounts.format_signed(&m.net_flow)
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Builds the report for `address` from every stored transfer touching it.
+pub async fn address_report(database: &Database, address: &str, top: usize) -> Result<AddressReport> {
+    let mut builder = ReportBuilder::new(address, top);
+    database
+        .for_each_transfer(&TransferFilter::new().address(Some(address.to_string())), |transfer| {
+            builder.add(transfer)
+        })
+        .await?;
+    let labels = database.get_labels(&builder.addresses()).await?;
+    Ok(builder.finish(&labels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::TransferBuilder;
+    use chrono::TimeZone;
+
+    const TREASURY: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const EXCHANGE: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+    const VENDOR: &str = "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB";
+
+    fn transfer(block_number: i64, month: u32, from: &str, to: &str, value: &str) -> TransferEvent {
+        TransferBuilder::new(block_number)
+            .from(from)
+            .to(to)
+            .value(value)
+            .at(Utc.with_ymd_and_hms(2026, month, 1, 0, 0, 0).unwrap())
+            .build()
+    }
+
+    #[test]
+    fn summarizes_flows_counterparties_and_months() {
+        let mut builder = ReportBuilder::new(TREASURY, 2);
+        // Out of order, as shards and archives return them.
+        for t in [
+            transfer(300, 2, TREASURY, VENDOR, "40"),
+            transfer(100, 1, EXCHANGE, TREASURY, "500"),
+            transfer(200, 1, TREASURY, VENDOR, "30"),
+            transfer(400, 2, TREASURY, EXCHANGE, "600"),
+        ] {
+            builder.add(t).unwrap();
+        }
+        let labels: HashMap<_, _> = [(EXCHANGE.to_string(), "Exchange".to_string())].into_iter().collect();
+        let report = builder.finish(&labels);
+
+        assert_eq!(report.first_seen.unwrap().block_number, 100);
+        assert_eq!(report.last_seen.unwrap().block_number, 400);
+        assert_eq!((report.transfers_sent, report.transfers_received), (3, 1));
+        assert_eq!((report.total_sent.as_str(), report.total_received.as_str()), ("670", "500"));
+        assert_eq!(report.net_flow, "-170");
+
+        let counterparties: Vec<_> = report
+            .top_counterparties
+            .iter()
+            .map(|c| (c.address.as_str(), c.label.as_deref(), c.sent.as_str(), c.received.as_str()))
+            .collect();
+        assert_eq!(counterparties, [(EXCHANGE, Some("Exchange"), "600", "500"), (VENDOR, None, "70", "0")]);
+
+        let largest: Vec<_> = report.largest_transfers.iter().map(|t| t.block_number).collect();
+        assert_eq!(largest, [400, 100]);
+
+        let months: Vec<_> = report
+            .by_month
+            .iter()
+            .map(|m| (m.month.as_str(), m.transfers, m.net_flow.as_str()))
+            .collect();
+        assert_eq!(months, [("2026-01", 2, "470"), ("2026-02", 2, "-640")]);
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::Result;
+use ethers_core::types::U256;
+use std::collections::HashMap;
+
+use crate::models::TransferEvent;
+
+/// Change to one address's `address_stats` row contributed by a set of transfers.
+#[derive(Debug, Default, Clone)]
+pub struct AddressDelta {
+    pub total_in: U256,
+    pub total_out: U256,
+    pub tx_count: i64,
+    pub last_block: i64,
+}
+
+/// Folds transfers into per-address deltas. A self-transfer counts once but moves value both ways.
+pub fn aggregate(events: &[TransferEvent]) -> Result<HashMap<String, AddressDelta>> {
+    let mut deltas: HashMap<String, AddressDelta> = HashMap::new();
+
+    for event in events {
+        let value = U256::from_dec_str(&event.value)?;
+
+        let sender = deltas.entry(event.from_address.clone()).or_default();
+        sender.total_out += value;
+        sender.tx_count += 1;
+        sender.last_block = sender.last_block.max(event.block_number);
+
+        let recipient = deltas.entry(event.to_address.clone()).or_default();
+        recipient.total_in += value;
+        if event.to_address != event.from_address {
+            recipient.tx_count += 1;
+        }
+        recipient.last_block = recipient.last_block.max(event.block_number);
+    }
+
+    Ok(deltas)
+}
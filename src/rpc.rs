@@ -0,0 +1,462 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+use ethers_core::{
+    types::{Block, Log, U256},
+    utils::keccak256,
+};
+use ethers_providers::{Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use futures::{future::BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    ethereum::{raw_log, TRANSFER_EVENT_SIGNATURE},
+    models::RawLog,
+};
+
+/// How an [`EthereumClient`](crate::EthereumClient) reaches the chain.
+#[derive(Debug)]
+pub enum Transport {
+    Http(Http),
+    /// Forwards to another transport and appends every exchange to a fixture file.
+    Record(Arc<Recorder>),
+    /// Answers from a fixture file, without a network.
+    Replay(Arc<Replay>),
+    Mock(MockEthereumClient),
+}
+
+impl Transport {
+    fn call<'a>(&'a self, method: &'a str, params: Value) -> BoxFuture<'a, Result<Value, TransportError>> {
+        async move {
+            match self {
+                Transport::Http(http) => Ok(http.request(method, params).await?),
+                Transport::Record(recorder) => recorder.call(method, params).await,
+                Transport::Replay(replay) => replay.answer(method, &params),
+                Transport::Mock(mock) => mock.answer(method, &params),
+            }
+        }
+        .boxed()
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for Transport {
+    type Error = TransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, TransportError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if let Transport::Http(http) = self {
+            return Ok(http.request(method, params).await?);
+        }
+        // Requests without parameters serialize as `null`; nodes expect an empty list.
+        let params = match serde_json::to_value(params)? {
+            Value::Null => json!([]),
+            params => params,
+        };
+        Ok(serde_json::from_value(self.call(method, params).await?)?)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+    /// An error response, replayed or simulated.
+    #[error(transparent)]
+    JsonRpc(#[from] JsonRpcError),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("{0}")]
+    Fixture(String),
+}
+
+impl RpcError for TransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            TransportError::Http(e) => e.as_error_response(),
+            TransportError::JsonRpc(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            TransportError::Http(e) => e.as_serde_error(),
+            TransportError::Serde(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<TransportError> for ProviderError {
+    fn from(e: TransportError) -> Self {
+        match e {
+            TransportError::Http(e) => e.into(),
+            e => ProviderError::JsonRpcClientError(Box::new(e)),
+        }
+    }
+}
+
+/// One line of a fixture file: a request and the node's result or error response.
+#[derive(Debug, Serialize, Deserialize)]
+struct Exchange {
+    method: String,
+    params: Value,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Result(Value),
+    Error(RecordedError),
+}
+
+/// `JsonRpcError`, which cannot be serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedError {
+    code: i64,
+    message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl Outcome {
+    fn into_result(self) -> Result<Value, TransportError> {
+        match self {
+            Outcome::Result(value) => Ok(value),
+            Outcome::Error(e) => Err(JsonRpcError {
+                code: e.code,
+                message: e.message,
+                data: e.data,
+            }
+            .into()),
+        }
+    }
+}
+
+/// Appends every request made through it, with the answer, to a JSON-lines fixture file. Error
+/// responses are recorded too; failures to reach the node are not.
+#[derive(Debug)]
+pub struct Recorder {
+    inner: Transport,
+    fixture: Mutex<File>,
+}
+
+impl Recorder {
+    /// Appends to `path`, creating it if missing.
+    pub fn create(inner: Transport, path: &Path) -> anyhow::Result<Self> {
+        let fixture = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open RPC fixture {}: {}", path.display(), e))?;
+        Ok(Self {
+            inner,
+            fixture: Mutex::new(fixture),
+        })
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, TransportError> {
+        let outcome = match self.inner.call(method, params.clone()).await {
+            Ok(result) => Outcome::Result(result),
+            Err(e) => match e.as_error_response() {
+                Some(error) => Outcome::Error(RecordedError {
+                    code: error.code,
+                    message: error.message.clone(),
+                    data: error.data.clone(),
+                }),
+                None => return Err(e),
+            },
+        };
+        let exchange = Exchange {
+            method: method.to_string(),
+            params,
+            outcome,
+        };
+        let line = serde_json::to_string(&exchange)?;
+        writeln!(self.fixture.lock().unwrap(), "{}", line)
+            .map_err(|e| TransportError::Fixture(format!("Failed to record {}: {}", method, e)))?;
+        exchange.outcome.into_result()
+    }
+}
+
+/// Answers requests from a fixture file written by [`Recorder`]. A request recorded several
+/// times, such as `eth_blockNumber` while the chain advanced, gets the answers in recorded order,
+/// then the last one again.
+#[derive(Debug)]
+pub struct Replay {
+    answers: Mutex<HashMap<String, VecDeque<Outcome>>>,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut answers: HashMap<String, VecDeque<Outcome>> = HashMap::new();
+        for exchange in read_fixture(path)? {
+            answers
+                .entry(request_key(&exchange.method, &exchange.params))
+                .or_default()
+                .push_back(exchange.outcome);
+        }
+        Ok(Self {
+            answers: Mutex::new(answers),
+        })
+    }
+
+    fn answer(&self, method: &str, params: &Value) -> Result<Value, TransportError> {
+        let key = request_key(method, params);
+        let mut answers = self.answers.lock().unwrap();
+        let queue = answers
+            .get_mut(&key)
+            .ok_or_else(|| TransportError::Fixture(format!("No recorded answer to {}", key)))?;
+        let outcome = match queue.len() {
+            1 => queue[0].clone(),
+            _ => queue.pop_front().expect("recorded requests have an answer"),
+        };
+        outcome.into_result()
+    }
+}
+
+fn request_key(method: &str, params: &Value) -> String {
+    format!("{} {}", method, params)
+}
+
+fn read_fixture(path: &Path) -> anyhow::Result<Vec<Exchange>> {
+    let file = File::open(path).map_err(|e| anyhow::anyhow!("Failed to open RPC fixture {}: {}", path.display(), e))?;
+    let mut exchanges = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        exchanges.push(
+            serde_json::from_str(&line)
+                .map_err(|e| anyhow::anyhow!("Invalid RPC fixture {} line {}: {}", path.display(), number + 1, e))?,
+        );
+    }
+    Ok(exchanges)
+}
+
+/// Every log answered to `eth_getLogs` in a fixture file, in log order, timestamped from the
+/// recorded `eth_getBlockByNumber` answers.
+pub fn recorded_logs(path: &Path) -> anyhow::Result<Vec<RawLog>> {
+    let mut logs: Vec<Log> = Vec::new();
+    let mut timestamps = HashMap::new();
+    for exchange in read_fixture(path)? {
+        let Outcome::Result(result) = exchange.outcome else {
+            continue;
+        };
+        match exchange.method.as_str() {
+            "eth_getLogs" => logs.extend(serde_json::from_value::<Vec<Log>>(result)?),
+            "eth_getBlockByNumber" if !result.is_null() => {
+                let block: Block<Value> = serde_json::from_value(result)?;
+                if let Some(number) = block.number {
+                    timestamps.insert(number.as_u64(), block.timestamp.as_u64() as i64);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut raw_logs = logs
+        .iter()
+        .map(|log| {
+            let block_number = log.block_number.map(|n| n.as_u64()).unwrap_or_default();
+            let timestamp = timestamps
+                .get(&block_number)
+                .and_then(|secs| DateTime::from_timestamp(*secs, 0))
+                .ok_or_else(|| anyhow::anyhow!("{} has no recorded header for block {}", path.display(), block_number))?;
+            raw_log(log, timestamp)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    raw_logs.sort_by_key(|log| (log.block_number, log.log_index));
+    raw_logs.dedup_by_key(|log| (log.block_number, log.log_index));
+    Ok(raw_logs)
+}
+
+/// A simulated chain that answers what [`EthereumClient`](crate::EthereumClient) asks about one
+/// token, for deterministic tests of [`Indexer`](crate::Indexer): blocks up to a head that only
+/// moves with [`MockEthereumClient::mine`], 12 seconds apart, the `Transfer` logs added with
+/// [`MockEthereumClient::transfer`], and reorgs that replace every block from a fork point. Other
+/// methods, such as `eth_call`, answer "method not found". Clones share the chain.
+#[derive(Debug, Clone)]
+pub struct MockEthereumClient {
+    chain: Arc<Mutex<MockChain>>,
+}
+
+#[derive(Debug)]
+struct MockChain {
+    chain_id: u64,
+    token: String,
+    head: u64,
+    /// `(from, to, value)` by block, in log order.
+    transfers: BTreeMap<u64, Vec<(String, String, U256)>>,
+    /// Fork blocks of past reorgs; each changes the hash of every block from it on.
+    reorgs: Vec<u64>,
+}
+
+impl MockEthereumClient {
+    /// A chain of `token` at block 0.
+    pub fn new(chain_id: u64, token: &str) -> Self {
+        Self {
+            chain: Arc::new(Mutex::new(MockChain {
+                chain_id,
+                token: token.to_lowercase(),
+                head: 0,
+                transfers: BTreeMap::new(),
+                reorgs: Vec::new(),
+            })),
+        }
+    }
+
+    /// Advances the head by `blocks`.
+    pub fn mine(&self, blocks: u64) {
+        self.chain.lock().unwrap().head += blocks;
+    }
+
+    /// Adds a `Transfer` log of the token to `block_number`, after those already there.
+    pub fn transfer(&self, block_number: u64, from: &str, to: &str, value: u64) {
+        self.chain
+            .lock()
+            .unwrap()
+            .transfers
+            .entry(block_number)
+            .or_default()
+            .push((from.to_lowercase(), to.to_lowercase(), U256::from(value)));
+    }
+
+    /// Replaces `fork_block` and every block after it with empty blocks under new hashes; add
+    /// transfers to build the new branch.
+    pub fn reorg(&self, fork_block: u64) {
+        let mut chain = self.chain.lock().unwrap();
+        chain.transfers.retain(|&block_number, _| block_number < fork_block);
+        chain.reorgs.push(fork_block);
+    }
+
+    /// The hash `eth_getBlockByNumber` returns for `block_number` on the current branch.
+    pub fn block_hash(&self, block_number: u64) -> String {
+        self.chain.lock().unwrap().block_hash(block_number)
+    }
+
+    fn answer(&self, method: &str, params: &Value) -> Result<Value, TransportError> {
+        let chain = self.chain.lock().unwrap();
+        match method {
+            "eth_chainId" => Ok(json!(format!("{:#x}", chain.chain_id))),
+            "eth_blockNumber" => Ok(json!(format!("{:#x}", chain.head))),
+            "eth_getBlockByNumber" => Ok(match &params[0] {
+                Value::String(tag) if tag == "latest" => chain.block(chain.head),
+                tag => match parse_quantity(tag) {
+                    Some(number) if number <= chain.head => chain.block(number),
+                    _ => Value::Null,
+                },
+            }),
+            "eth_getLogs" => Ok(Value::Array(chain.logs(&params[0]))),
+            _ => Err(JsonRpcError {
+                code: -32601,
+                message: format!("the method {} does not exist/is not available", method),
+                data: None,
+            }
+            .into()),
+        }
+    }
+}
+
+impl MockChain {
+    fn block_hash(&self, block_number: u64) -> String {
+        let branch = self.reorgs.iter().filter(|&&fork| fork <= block_number).count();
+        format!("0x{}", hex::encode(keccak256(format!("block {} branch {}", block_number, branch))))
+    }
+
+    fn block(&self, number: u64) -> Value {
+        let zero = |bytes: usize| format!("0x{}", "00".repeat(bytes));
+        json!({
+            "number": format!("{:#x}", number),
+            "hash": self.block_hash(number),
+            "parentHash": if number == 0 { zero(32) } else { self.block_hash(number - 1) },
+            "timestamp": format!("{:#x}", 1_700_000_000 + 12 * number),
+            "gasUsed": "0x0",
+            "gasLimit": "0x1c9c380",
+            "baseFeePerGas": "0x1",
+            "miner": zero(20),
+            "nonce": zero(8),
+            "sha3Uncles": zero(32),
+            "logsBloom": zero(256),
+            "transactionsRoot": zero(32),
+            "stateRoot": zero(32),
+            "receiptsRoot": zero(32),
+            "mixHash": zero(32),
+            "difficulty": "0x0",
+            "totalDifficulty": "0x0",
+            "extraData": "0x",
+            "size": "0x0",
+            "transactions": [],
+            "uncles": [],
+        })
+    }
+
+    /// Transfer logs matching an `eth_getLogs` filter on the token, its `Transfer` topic and
+    /// optionally the `from` and `to` topics.
+    fn logs(&self, filter: &Value) -> Vec<Value> {
+        let from_block = parse_quantity(&filter["fromBlock"]).unwrap_or(0);
+        let to_block = parse_quantity(&filter["toBlock"]).unwrap_or(self.head).min(self.head);
+        let topics = |index: usize| filter["topics"].get(index).cloned().unwrap_or(Value::Null);
+        if !matches(&filter["address"], &self.token) || !matches(&topics(0), TRANSFER_EVENT_SIGNATURE) {
+            return Vec::new();
+        }
+
+        let mut logs = Vec::new();
+        for (&block_number, transfers) in self.transfers.range(from_block..=to_block) {
+            for (log_index, (from, to, value)) in transfers.iter().enumerate() {
+                let (from, to) = (address_topic(from), address_topic(to));
+                if !matches(&topics(1), &from) || !matches(&topics(2), &to) {
+                    continue;
+                }
+                let mut data = [0u8; 32];
+                value.to_big_endian(&mut data);
+                let transaction = format!("tx {} {} {}", self.block_hash(block_number), block_number, log_index);
+                logs.push(json!({
+                    "address": self.token,
+                    "topics": [TRANSFER_EVENT_SIGNATURE, from, to],
+                    "data": format!("0x{}", hex::encode(data)),
+                    "blockNumber": format!("{:#x}", block_number),
+                    "blockHash": self.block_hash(block_number),
+                    "transactionHash": format!("0x{}", hex::encode(keccak256(transaction))),
+                    "transactionIndex": format!("{:#x}", log_index),
+                    "logIndex": format!("{:#x}", log_index),
+                    "removed": false,
+                }));
+            }
+        }
+        logs
+    }
+}
+
+/// Whether a filter field (absent, one value, or a list of alternatives) accepts `value`.
+fn matches(filter: &Value, value: &str) -> bool {
+    match filter {
+        Value::Null => true,
+        Value::String(expected) => expected.eq_ignore_ascii_case(value),
+        Value::Array(alternatives) => alternatives.iter().any(|alternative| matches(alternative, value)),
+        _ => false,
+    }
+}
+
+fn address_topic(address: &str) -> String {
+    format!("0x{:0>64}", address.trim_start_matches("0x"))
+}
+
+fn parse_quantity(value: &Value) -> Option<u64> {
+    u64::from_str_radix(value.as_str()?.trim_start_matches("0x"), 16).ok()
+}
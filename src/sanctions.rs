@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use ethers_core::types::H160;
+use std::{collections::BTreeSet, str::FromStr};
+
+use crate::{address::checksum, database::Database};
+
+/// Meta key holding when the sanctions list was last loaded.
+const REFRESHED_AT_KEY: &str = "sanctions_refreshed_at";
+
+/// Every `0x`-prefixed 40-digit hex token in `text`, checksummed and deduplicated. Plain
+/// one-per-line lists, CSV and JSON exports all load without a dedicated parser.
+pub fn parse_sanctions_list(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|token| token.len() == 42 && (token.starts_with("0x") || token.starts_with("0X")))
+        .filter_map(|token| H160::from_str(&token[2..]).ok())
+        .map(|address| checksum(&address))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Reads the list at `source`, an `http(s)://` URL or a file path.
+pub async fn fetch_sanctions_list(source: &str) -> Result<Vec<String>> {
+    let text = if source.starts_with("http://") || source.starts_with("https://") {
+        reqwest::get(source)
+            .await?
+            .error_for_status()?
+            .text()
+            .await
+            .with_context(|| format!("Failed to download {}", source))?
+    } else {
+        std::fs::read_to_string(source).with_context(|| format!("Failed to read {}", source))?
+    };
+
+    let addresses = parse_sanctions_list(&text);
+    if addresses.is_empty() {
+        return Err(anyhow::anyhow!("No addresses found in {}", source));
+    }
+    Ok(addresses)
+}
+
+/// Replaces the stored list with the one at `source` and returns its size.
+pub async fn refresh_sanctions(database: &Database, source: &str) -> Result<usize> {
+    let addresses = fetch_sanctions_list(source).await?;
+    database.replace_sanctioned_addresses(source, &addresses).await?;
+    database.set_meta(REFRESHED_AT_KEY, &database.now().to_rfc3339()).await?;
+    Ok(addresses.len())
+}
+
+/// Refreshes from `source` when the stored list is older than `max_age`. Returns the new size
+/// when it did.
+pub async fn refresh_sanctions_if_due(database: &Database, source: &str, max_age: Duration) -> Result<Option<usize>> {
+    let refreshed_at = database
+        .get_meta(REFRESHED_AT_KEY)
+        .await?
+        .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| at.with_timezone(&Utc));
+    if refreshed_at.is_some_and(|at| database.now() - at < max_age) {
+        return Ok(None);
+    }
+    refresh_sanctions(database, source).await.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_addresses_in_any_list_format() {
+        let text = r#"
+            ["0x8589427373D6D84E98730D7795D8f6f8731FDA16", "0x722122df12d4e14e13ac3b6895a86e84145b6967"]
+            address,name
+            0x722122dF12D4e14e13Ac3b6895a86e84145b6967,Tornado Cash
+            0x1234 not-an-address 0x8589427373D6D84E98730D7795D8f6f8731FDA16extra
+        "#;
+        assert_eq!(
+            parse_sanctions_list(text),
+            ["0x722122dF12D4e14e13Ac3b6895a86e84145b6967", "0x8589427373D6D84E98730D7795D8f6f8731FDA16"]
+        );
+    }
+}
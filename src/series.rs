@@ -0,0 +1,251 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers_core::types::U256;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{database::Database, query::TransferFilter};
+
+/// Volume is summed into this many slots per requested point before downsampling, so LTTB and
+/// the bucket methods have detail to choose from.
+const OVERSAMPLE: i64 = 4;
+
+/// Volume slot widths, so slots line up with minutes, hours and days; past the last, whole days.
+const SLOT_SECS: [i64; 9] = [60, 300, 900, 1_800, 3_600, 7_200, 21_600, 43_200, 86_400];
+
+/// How a series is reduced to the requested number of points.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Downsample {
+    /// Largest-Triangle-Three-Buckets: keeps the points that preserve the visual shape.
+    #[default]
+    Lttb,
+    /// Largest value per bucket.
+    Max,
+    /// Mean value per bucket.
+    Avg,
+    /// Total per bucket; only meaningful for volume.
+    Sum,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SeriesPoint {
+    pub timestamp: DateTime<Utc>,
+    /// Token units, as a float for charting.
+    pub value: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Series {
+    pub method: Downsample,
+    /// Width of the slots volume was summed over before downsampling; `None` for supply, which
+    /// is sampled at holder snapshots.
+    pub resolution_secs: Option<i64>,
+    pub points: Vec<SeriesPoint>,
+}
+
+/// Volume of transfers matching `filter` between `from` and `to`, reduced to at most `points`
+/// points. Quiet slots count as zero.
+pub async fn volume_series(
+    database: &Database,
+    filter: &TransferFilter,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    points: usize,
+    method: Downsample,
+    decimals: u8,
+) -> Result<Series> {
+    let points = points.max(2);
+    let (start, end) = (from.timestamp(), to.timestamp().max(from.timestamp()));
+    let slot_secs = slot_width((end - start) / (points as i64 * OVERSAMPLE));
+    let filter = filter.clone().time_range(Some(from), Some(to));
+    let mut slots = database.volume_slots(&filter, slot_secs).await?.into_iter().peekable();
+
+    let first_slot = start.div_euclid(slot_secs) * slot_secs;
+    let mut series = Vec::new();
+    for slot in (first_slot..=end).step_by(slot_secs as usize) {
+        let volume = slots.next_if(|(start, _)| *start == slot).map(|(_, volume)| volume).unwrap_or_default();
+        series.push(SeriesPoint {
+            timestamp: DateTime::from_timestamp(slot, 0).unwrap_or(from),
+            value: to_units(volume, decimals),
+        });
+    }
+
+    Ok(Series {
+        method,
+        resolution_secs: Some(slot_secs),
+        points: downsample(&series, points, method),
+    })
+}
+
+/// Tracked supply at each holder snapshot between `from` and `to`, reduced to at most `points`.
+pub async fn supply_series(
+    database: &Database,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    points: usize,
+    method: Downsample,
+    decimals: u8,
+) -> Result<Series> {
+    if method == Downsample::Sum {
+        return Err(anyhow::anyhow!("Supply cannot be summed; use lttb, max or avg"));
+    }
+    let series: Vec<SeriesPoint> = database
+        .get_holder_snapshots(Some(from), Some(to))
+        .await?
+        .into_iter()
+        .map(|snapshot| {
+            Ok(SeriesPoint {
+                timestamp: snapshot.timestamp,
+                value: to_units(U256::from_dec_str(&snapshot.supply)?, decimals),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Series {
+        method,
+        resolution_secs: None,
+        points: downsample(&series, points.max(2), method),
+    })
+}
+
+/// At most `target` points standing in for `points`, which must be in time order. Short series
+/// are returned unchanged.
+pub fn downsample(points: &[SeriesPoint], target: usize, method: Downsample) -> Vec<SeriesPoint> {
+    if points.len() <= target || target < 2 {
+        return points.to_vec();
+    }
+    match method {
+        Downsample::Lttb => lttb(points, target),
+        Downsample::Max | Downsample::Avg | Downsample::Sum => (0..target)
+            .map(|i| {
+                // Contiguous, near-equal runs of points, each shown at its first point's time.
+                let bucket = &points[i * points.len() / target..(i + 1) * points.len() / target];
+                let values = bucket.iter().map(|p| p.value);
+                let value = match method {
+                    Downsample::Max => values.fold(f64::MIN, f64::max),
+                    Downsample::Avg => values.sum::<f64>() / bucket.len() as f64,
+                    _ => values.sum(),
+                };
+                SeriesPoint {
+                    timestamp: bucket[0].timestamp,
+                    value,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Keeps the first and last points and, from each of `target - 2` buckets in between, the point
+/// forming the largest triangle with the last kept point and the next bucket's average.
+fn lttb(points: &[SeriesPoint], target: usize) -> Vec<SeriesPoint> {
+    let x = |p: &SeriesPoint| p.timestamp.timestamp() as f64;
+    let every = (points.len() - 2) as f64 / (target - 2) as f64;
+    let mut sampled = vec![points[0]];
+    let mut kept = 0;
+
+    for i in 0..target - 2 {
+        let start = (i as f64 * every) as usize + 1;
+        let end = ((i + 1) as f64 * every) as usize + 1;
+        let next = &points[end..(((i + 2) as f64 * every) as usize + 1).min(points.len())];
+        let (next_x, next_y) = if next.is_empty() {
+            (x(&points[points.len() - 1]), points[points.len() - 1].value)
+        } else {
+            let n = next.len() as f64;
+            (next.iter().map(x).sum::<f64>() / n, next.iter().map(|p| p.value).sum::<f64>() / n)
+        };
+
+        let (kept_x, kept_y) = (x(&points[kept]), points[kept].value);
+        let area = |p: &SeriesPoint| ((kept_x - next_x) * (p.value - kept_y) - (kept_x - x(p)) * (next_y - kept_y)).abs();
+        kept = (start..end)
+            .max_by(|&a, &b| area(&points[a]).total_cmp(&area(&points[b])))
+            .unwrap_or(start);
+        sampled.push(points[kept]);
+    }
+    sampled.push(points[points.len() - 1]);
+    sampled
+}
+
+/// The narrowest of `SLOT_SECS`, or whole days, at least `secs` wide.
+fn slot_width(secs: i64) -> i64 {
+    const DAY: i64 = 86_400;
+    match SLOT_SECS.iter().find(|&&width| width >= secs) {
+        Some(&width) => width,
+        None => (secs + DAY - 1) / DAY * DAY,
+    }
+}
+
+fn to_units(value: U256, decimals: u8) -> f64 {
+    value.to_string().parse::<f64>().unwrap_or(f64::MAX) / 10f64.powi(decimals as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::{database, TransferBuilder};
+
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const BOB: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    fn series(values: &[f64]) -> Vec<SeriesPoint> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| SeriesPoint {
+                timestamp: DateTime::from_timestamp(i as i64 * 60, 0).unwrap(),
+                value,
+            })
+            .collect()
+    }
+
+    fn values(points: &[SeriesPoint]) -> Vec<f64> {
+        points.iter().map(|p| p.value).collect()
+    }
+
+    #[test]
+    fn lttb_keeps_ends_and_peaks() {
+        let points = series(&[0.0, 1.0, 0.0, 0.0, 9.0, 0.0, 0.0, -5.0, 0.0, 1.0]);
+        let sampled = downsample(&points, 4, Downsample::Lttb);
+        assert_eq!(values(&sampled), [0.0, 9.0, -5.0, 1.0]);
+        assert_eq!(downsample(&points, 20, Downsample::Lttb).len(), 10);
+    }
+
+    #[test]
+    fn buckets_reduce_runs_of_points() {
+        let points = series(&[1.0, 3.0, 2.0, 2.0, 6.0, 4.0]);
+        assert_eq!(values(&downsample(&points, 3, Downsample::Max)), [3.0, 2.0, 6.0]);
+        assert_eq!(values(&downsample(&points, 3, Downsample::Avg)), [2.0, 2.0, 5.0]);
+        let summed = downsample(&points, 2, Downsample::Sum);
+        assert_eq!(values(&summed), [6.0, 12.0]);
+        assert_eq!(summed[1].timestamp, points[3].timestamp);
+    }
+
+    #[tokio::test]
+    async fn volume_is_summed_per_slot_with_quiet_slots_as_zero() {
+        let database = database().await;
+        for (block_number, time, value) in [
+            (1, "2026-01-01T00:10:00Z", "1000000"),
+            (2, "2026-01-01T00:50:00Z", "2000000"),
+            (3, "2026-01-01T03:20:00Z", "5000000"),
+        ] {
+            let transfer = TransferBuilder::new(block_number)
+                .from(ALICE)
+                .to(BOB)
+                .value(value)
+                .at(time.parse().unwrap())
+                .build();
+            database.insert_transfer_event(&transfer).await.unwrap();
+        }
+
+        let from = "2026-01-01T00:00:00Z".parse().unwrap();
+        let to = "2026-01-01T03:59:59Z".parse().unwrap();
+        let filter = TransferFilter::new();
+        let hourly = volume_series(&database, &filter, from, to, 4, Downsample::Sum, 6).await.unwrap();
+        assert_eq!(hourly.resolution_secs, Some(900));
+        assert_eq!(values(&hourly.points), [3.0, 0.0, 0.0, 5.0]);
+
+        let peaks = volume_series(&database, &filter, from, to, 2, Downsample::Max, 6).await.unwrap();
+        assert_eq!(values(&peaks.points), [2.0, 5.0]);
+        assert!(supply_series(&database, from, to, 10, Downsample::Sum, 6).await.is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::RwLock;
+
+use crate::database::{create_transfer_events_table, is_in_memory, PoolSettings};
+
+/// A set of SQLite files that each hold `transfer_events` for one fixed block range.
+///
+/// Shard `i` covers blocks `[i * shard_size, (i + 1) * shard_size)` and lives next to the
+/// main database as `<stem>.shard<i>.db`. Shards are discovered on open and created on
+/// first write.
+pub struct ShardSet {
+    dir: PathBuf,
+    stem: String,
+    shard_size: u64,
+    settings: PoolSettings,
+    pools: RwLock<BTreeMap<u64, SqlitePool>>,
+}
+
+impl ShardSet {
+    pub async fn open(database_url: &str, shard_size: u64, settings: PoolSettings) -> Result<Self> {
+        if is_in_memory(database_url) {
+            return Err(anyhow::anyhow!("SHARD_SIZE_BLOCKS needs a database file; unset it for an in-memory database"));
+        }
+        let path = Path::new(sqlite_path(database_url));
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Cannot derive shard names from {}", database_url))?
+            .to_string();
+
+        let shards = Self {
+            dir,
+            stem,
+            shard_size,
+            settings,
+            pools: RwLock::new(BTreeMap::new()),
+        };
+
+        let prefix = format!("{}.shard", shards.stem);
+        let mut pools = BTreeMap::new();
+        for entry in std::fs::read_dir(&shards.dir)? {
+            let name = entry?.file_name();
+            let Some(index) = name
+                .to_str()
+                .and_then(|n| n.strip_prefix(&prefix))
+                .and_then(|n| n.strip_suffix(".db"))
+                .and_then(|n| n.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            pools.insert(index, shards.connect(index).await?);
+        }
+        *shards.pools.write().await = pools;
+
+        Ok(shards)
+    }
+
+    pub fn shard_index(&self, block_number: u64) -> u64 {
+        block_number / self.shard_size
+    }
+
+    /// Inclusive block range covered by shard `index`.
+    pub fn block_range(&self, index: u64) -> (u64, u64) {
+        let start = index * self.shard_size;
+        (start, start + self.shard_size - 1)
+    }
+
+    pub fn path(&self, index: u64) -> PathBuf {
+        self.dir.join(shard_file_name(&self.stem, index))
+    }
+
+    /// Returns the pool for the shard holding `block_number`, creating the file if needed.
+    pub async fn pool_for_block(&self, block_number: u64) -> Result<SqlitePool> {
+        let index = self.shard_index(block_number);
+        if let Some(pool) = self.pools.read().await.get(&index) {
+            return Ok(pool.clone());
+        }
+
+        let mut pools = self.pools.write().await;
+        if let Some(pool) = pools.get(&index) {
+            return Ok(pool.clone());
+        }
+        let pool = self.connect(index).await?;
+        pools.insert(index, pool.clone());
+        Ok(pool)
+    }
+
+    /// Existing shards, newest block range first.
+    pub async fn pools_desc(&self) -> Vec<(u64, SqlitePool)> {
+        self.pools
+            .read()
+            .await
+            .iter()
+            .rev()
+            .map(|(index, pool)| (*index, pool.clone()))
+            .collect()
+    }
+
+    async fn connect(&self, index: u64) -> Result<SqlitePool> {
+        let url = format!("sqlite:{}?mode=rwc", self.path(index).display());
+        let pool = self.settings.connect(&url).await?;
+        if !self.settings.read_only {
+            create_transfer_events_table(&pool).await?;
+        }
+        Ok(pool)
+    }
+}
+
+/// File name of shard `index` for a main database named `<stem>.db`.
+pub fn shard_file_name(stem: &str, index: u64) -> String {
+    format!("{}.shard{:05}.db", stem, index)
+}
+
+fn sqlite_path(database_url: &str) -> &str {
+    let path = database_url.strip_prefix("sqlite:").unwrap_or(database_url);
+    let path = path.strip_prefix("//").unwrap_or(path);
+    path.split('?').next().unwrap_or(path)
+}
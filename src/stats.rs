@@ -0,0 +1,248 @@
+use anyhow::Result;
+use chrono::Duration;
+use ethers_core::types::U256;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use utoipa::ToSchema;
+
+use crate::{
+    address::ZERO_ADDRESS,
+    database::Database,
+    models::{ActivityRange, DatabaseStats},
+    output::AmountFormat,
+    query::{Direction, TransferFilter},
+    units::net_flow,
+};
+
+/// What `stats` prints: database totals, recent throughput, supply changes and indexing lag.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StatsReport {
+    #[serde(flatten)]
+    pub database: DatabaseStats,
+    /// Raw token units moved by every stored transfer.
+    pub total_volume: String,
+    pub transfers_last_7d: i64,
+    pub transfers_per_day_7d: f64,
+    pub transfers_last_30d: i64,
+    pub transfers_per_day_30d: f64,
+    /// Raw token units sent from the zero address.
+    pub minted: String,
+    /// Raw token units sent to the zero address.
+    pub burned: String,
+    /// Size of the main database and every shard file.
+    pub database_bytes: u64,
+    pub last_processed_block: Option<i64>,
+    /// `None` when the node could not be reached.
+    pub chain_head: Option<u64>,
+    pub lag_blocks: Option<u64>,
+    /// Seconds between the last processed block's timestamp and now.
+    pub lag_secs: Option<i64>,
+}
+
+/// Gathers the report; `chain_head` is the node's latest block, if it answered.
+pub async fn collect_stats(database: &Database, chain_head: Option<u64>) -> Result<StatsReport> {
+    let now = database.now();
+    let all = database.aggregate_transfers(&TransferFilter::new()).await?;
+    let recent = |days: i64| TransferFilter::new().time_range(Some(now - Duration::days(days)), None);
+    let last_7d = database.aggregate_transfers(&recent(7)).await?.count;
+    let last_30d = database.aggregate_transfers(&recent(30)).await?.count;
+    let zero = TransferFilter::new().address(Some(ZERO_ADDRESS.to_string()));
+    let minted = database.aggregate_transfers(&zero.clone().direction(Direction::Sent)).await?;
+    let burned = database.aggregate_transfers(&zero.direction(Direction::Received)).await?;
+    let last_block = database.query_block_activity(None, None, 1).await?.into_iter().next();
+
+    Ok(StatsReport {
+        database: database.get_stats().await?,
+        total_volume: all.total_value,
+        transfers_last_7d: last_7d,
+        transfers_per_day_7d: last_7d as f64 / 7.0,
+        transfers_last_30d: last_30d,
+        transfers_per_day_30d: last_30d as f64 / 30.0,
+        minted: minted.total_value,
+        burned: burned.total_value,
+        database_bytes: database.size_bytes().await?,
+        last_processed_block: last_block.as_ref().map(|block| block.block_number),
+        chain_head,
+        lag_blocks: chain_head
+            .zip(last_block.as_ref())
+            .map(|(head, block)| head.saturating_sub(block.block_number as u64)),
+        lag_secs: last_block.map(|block| (now - block.timestamp).num_seconds().max(0)),
+    })
+}
+
+impl StatsReport {
+    pub fn render(&self, amounts: &AmountFormat) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+        let mut out = String::new();
+        let _ = writeln!(out, "Database Statistics:");
+        let _ = writeln!(out, "Total transfers: {}", self.database.total_transfers);
+        let _ = writeln!(out, "Unique addresses: {}", self.database.unique_addresses);
+        let _ = writeln!(out, "Latest block: {}", self.database.latest_block.unwrap_or(0));
+        let _ = writeln!(out, "Earliest block: {}", self.database.earliest_block.unwrap_or(0));
+        let _ = writeln!(out, "Total volume: {}", amounts.format(&self.total_volume));
+        let _ = writeln!(
+            out,
+            "Transfers, last 7 days: {} ({:.1}/day)",
+            self.transfers_last_7d, self.transfers_per_day_7d
+        );
+        let _ = writeln!(
+            out,
+            "Transfers, last 30 days: {} ({:.1}/day)",
+            self.transfers_last_30d, self.transfers_per_day_30d
+        );
+        let _ = writeln!(out, "Minted: {}", amounts.format(&self.minted));
+        let _ = writeln!(out, "Burned: {}", amounts.format(&self.burned));
+        let _ = writeln!(out, "Database size: {:.1} MiB", self.database_bytes as f64 / (1024.0 * 1024.0));
+        let _ = writeln!(out, "Last processed block: {}", optional(self.last_processed_block.map(|b| b.to_string())));
+        let _ = writeln!(out, "Chain head: {}", optional(self.chain_head.map(|b| b.to_string())));
+        let _ = writeln!(
+            out,
+            "Lag: {} blocks, {} seconds",
+            optional(self.lag_blocks.map(|b| b.to_string())),
+            optional(self.lag_secs.map(|s| s.to_string()))
+        );
+        out
+    }
+}
+
+/// What `stats --address` prints, read from the `address_stats` rollups.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AddressStatsReport {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub transfers: i64,
+    /// Raw token units.
+    pub total_in: String,
+    pub total_out: String,
+    /// `total_in - total_out`, with a leading `-` when negative.
+    pub balance: String,
+    #[serde(flatten)]
+    pub activity: Option<ActivityRange>,
+    /// 1 for the largest balance; `None` when the address holds nothing.
+    pub holder_rank: Option<usize>,
+    pub holders: usize,
+}
+
+/// Counts, totals, first and last activity, and balance rank of `address`. Fails when the
+/// rollups have no activity for it.
+pub async fn address_stats_report(database: &Database, address: &str) -> Result<AddressStatsReport> {
+    let Some(stats) = database.get_address_stats(address).await? else {
+        return Err(anyhow::anyhow!("No activity recorded for {}", address));
+    };
+    let total_in = U256::from_dec_str(&stats.total_in)?;
+    let total_out = U256::from_dec_str(&stats.total_out)?;
+
+    let balances = database.holder_balances().await?;
+    let holder_rank = (total_in > total_out && stats.address != ZERO_ADDRESS).then(|| {
+        let balance = total_in - total_out;
+        1 + balances.iter().filter(|(_, other)| *other > balance).count()
+    });
+    let label = database.get_labels(std::slice::from_ref(&stats.address)).await?.remove(&stats.address);
+
+    Ok(AddressStatsReport {
+        label,
+        transfers: stats.tx_count,
+        balance: net_flow(total_in, total_out),
+        total_in: stats.total_in,
+        total_out: stats.total_out,
+        activity: database.address_activity_range(&stats.address).await?,
+        holder_rank,
+        holders: balances.len(),
+        address: stats.address,
+    })
+}
+
+impl AddressStatsReport {
+    pub fn render(&self, amounts: &AmountFormat) -> String {
+        let mut out = String::new();
+        match &self.label {
+            Some(label) => {
+                let _ = writeln!(out, "Address: {} ({})", self.address, label);
+            }
+            None => {
+                let _ = writeln!(out, "Address: {}", self.address);
+            }
+        }
+        let _ = writeln!(out, "Transfers: {}", self.transfers);
+        let _ = writeln!(out, "Received: {}", amounts.format(&self.total_in));
+        let _ = writeln!(out, "Sent: {}", amounts.format(&self.total_out));
+        let _ = writeln!(out, "Balance: {}", amounts.format_signed(&self.balance));
+        if let Some(activity) = &self.activity {
+            let _ = writeln!(out, "First activity: block {} at {}", activity.first_block, activity.first_seen.to_rfc3339());
+            let _ = writeln!(out, "Last activity: block {} at {}", activity.last_block, activity.last_seen.to_rfc3339());
+        }
+        match self.holder_rank {
+            Some(rank) => {
+                let _ = writeln!(out, "Holder rank: {} of {}", rank, self.holders);
+            }
+            None => {
+                let _ = writeln!(out, "Holder rank: not a holder ({} holders)", self.holders);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::{database, TransferBuilder};
+    use chrono::Utc;
+
+    const HOLDER: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    async fn insert(database: &Database, block_number: i64, age_days: i64, from: &str, to: &str, value: &str) {
+        let timestamp = Utc::now() - Duration::days(age_days);
+        let transfer = TransferBuilder::new(block_number)
+            .from(from)
+            .to(to)
+            .value(value)
+            .at(timestamp)
+            .build();
+        database.insert_transfer_event(&transfer).await.unwrap();
+        database
+            .insert_processed_block(block_number as u64, &transfer.block_hash, timestamp)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn stats_cover_volume_supply_changes_and_lag() {
+        let database = database().await;
+        insert(&database, 100, 40, ZERO_ADDRESS, HOLDER, "1000").await;
+        insert(&database, 101, 10, HOLDER, ZERO_ADDRESS, "300").await;
+        insert(&database, 102, 1, HOLDER, ZERO_ADDRESS, "200").await;
+
+        let stats = collect_stats(&database, Some(110)).await.unwrap();
+        assert_eq!(stats.total_volume, "1500");
+        assert_eq!((stats.transfers_last_7d, stats.transfers_last_30d), (1, 2));
+        assert_eq!((stats.minted.as_str(), stats.burned.as_str()), ("1000", "500"));
+        assert_eq!((stats.last_processed_block, stats.lag_blocks), (Some(102), Some(8)));
+        assert!(stats.database_bytes > 0);
+
+        let stats = collect_stats(&database, None).await.unwrap();
+        assert!(stats.lag_blocks.is_none());
+        assert!(stats.lag_secs.is_some_and(|secs| secs >= 86_400));
+    }
+
+    #[tokio::test]
+    async fn address_stats_rank_the_address_among_holders() {
+        let database = database().await;
+        let other = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+        insert(&database, 100, 40, ZERO_ADDRESS, HOLDER, "1000").await;
+        insert(&database, 101, 10, HOLDER, other, "600").await;
+        insert(&database, 102, 1, HOLDER, ZERO_ADDRESS, "100").await;
+        database.refresh_address_stats(102).await.unwrap();
+
+        let report = address_stats_report(&database, HOLDER).await.unwrap();
+        assert_eq!((report.transfers, report.balance.as_str()), (3, "300"));
+        assert_eq!((report.holder_rank, report.holders), (Some(2), 2));
+        let activity = report.activity.unwrap();
+        assert_eq!((activity.first_block, activity.last_block), (100, 102));
+
+        assert_eq!(address_stats_report(&database, other).await.unwrap().holder_rank, Some(1));
+        assert_eq!(address_stats_report(&database, ZERO_ADDRESS).await.unwrap().holder_rank, None);
+        assert!(address_stats_report(&database, "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB").await.is_err());
+    }
+}
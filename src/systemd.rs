@@ -0,0 +1,23 @@
+use sd_notify::NotifyState;
+
+/// Tells systemd that startup is done, for units with `Type=notify`. Like every function here, this
+/// does nothing unless the process was started by systemd with `NOTIFY_SOCKET` set.
+pub fn notify_ready() {
+    notify(&[NotifyState::Ready]);
+}
+
+/// Pings the watchdog of units with `WatchdogSec=`, and shows `status` in `systemctl status`.
+pub fn notify_alive(status: &str) {
+    notify(&[NotifyState::Watchdog, NotifyState::Status(status)]);
+}
+
+/// Tells systemd the service is shutting down on its own, so the stop is not mistaken for a crash.
+pub fn notify_stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        tracing::warn!(error = %e, "Failed to notify systemd");
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use serde::Deserialize;
+use std::io::IsTerminal;
+use tracing::Level;
+use tracing_appender::non_blocking::{NonBlockingBuilder, WorkerGuard};
+use tracing_subscriber::{
+    filter::{EnvFilter, Targets},
+    fmt::MakeWriter,
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    Layer, Registry,
+};
+
+use crate::config::Config;
+
+/// Target of this crate's own spans, the only ones exported: those of sqlx, hyper or the exporter
+/// itself would bury the block ranges and RPC calls worth looking at.
+const TRACED_TARGET: &str = "ethereum_erc20_indexer";
+
+/// How events are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// One human-readable line per event, colored on a terminal.
+    #[default]
+    Text,
+    /// One JSON object per line: `timestamp`, `level`, `target`, `message` and the event's fields
+    /// (`block_number`, counts, `error`, ...) at the top level, plus the innermost `span` with its
+    /// fields, for log pipelines such as Loki or ELK.
+    Json,
+}
+
+/// When `LOG_FILE` is rotated, besides on reaching `LOG_FILE_MAX_MB`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    #[default]
+    Daily,
+}
+
+/// Keeps the span exporter and the log file writer running. Dropping it flushes the spans still
+/// queued for the collector and the lines still queued for the file.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+    _log_file: Option<WorkerGuard>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!(error = %e, "Failed to flush traces");
+            }
+        }
+    }
+}
+
+/// Installs the global `tracing` subscriber: events on stderr in `format`, filtered by `RUST_LOG`
+/// (errors only by default), plus `log` records of dependencies; the same events in `LOG_FILE`,
+/// rotated as configured, when set; and with `OTEL_EXPORTER_OTLP_ENDPOINT` set, this
+/// crate's spans at info level and above exported to that collector over OTLP/gRPC, whatever
+/// `RUST_LOG` says. Must be called within a Tokio runtime, which the exporter runs on.
+pub fn init(config: &Config, format: LogFormat) -> Result<Telemetry> {
+    let stderr = format_layer(format, std::io::stderr, std::io::stderr().is_terminal());
+    let mut layers = vec![stderr.with_filter(EnvFilter::from_default_env()).boxed()];
+
+    let log_file = match &config.log_file {
+        Some(path) => {
+            let condition = match config.log_file_rotation {
+                LogRotation::Never => RollingConditionBasic::new(),
+                LogRotation::Hourly => RollingConditionBasic::new().hourly(),
+                LogRotation::Daily => RollingConditionBasic::new().daily(),
+            };
+            let condition = match config.log_file_max_mb {
+                0 => condition,
+                mb => condition.max_size(mb * 1024 * 1024),
+            };
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            let appender = BasicRollingFileAppender::new(path, condition, config.log_file_keep.max(1))
+                .with_context(|| format!("Failed to open log file {}", path.display()))?;
+            // Written from a background thread; lossless, so a slow disk delays events rather than
+            // dropping them.
+            let (writer, guard) = NonBlockingBuilder::default().lossy(false).finish(appender);
+            layers.push(format_layer(format, writer, false).with_filter(EnvFilter::from_default_env()).boxed());
+            Some(guard)
+        }
+        None => None,
+    };
+
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = SpanExporter::builder().with_tonic().with_endpoint(endpoint).build()?;
+            let provider = SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(config.otel_service_name.clone()).build())
+                .build();
+            let otlp = tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+                .with_filter(Targets::new().with_target(TRACED_TARGET, Level::INFO));
+            layers.push(otlp.boxed());
+            Some(provider)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry().with(layers).try_init()?;
+    Ok(Telemetry {
+        provider,
+        _log_file: log_file,
+    })
+}
+
+/// Writes every event it sees to `writer` in `format`, colored only with `ansi` and text.
+fn format_layer<W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Text => layer.with_ansi(ansi).boxed(),
+        LogFormat::Json => layer.json().flatten_event(true).with_span_list(false).boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn json_lines_carry_event_and_span_fields() {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let writer = {
+            let buffer = buffer.clone();
+            move || Capture(buffer.clone())
+        };
+        let subscriber = tracing_subscriber::registry().with(format_layer(LogFormat::Json, writer, false));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("commit_range", start_block = 100u64, end_block = 199u64);
+            let _entered = span.enter();
+            tracing::error!(block_number = 150u64, error = %"timeout", "Event handler failed on block");
+        });
+
+        let lines = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(lines.trim()).unwrap();
+        assert_eq!(line["level"], "ERROR");
+        assert_eq!(line["message"], "Event handler failed on block");
+        assert_eq!((line["block_number"].as_u64(), line["error"].as_str()), (Some(150), Some("timeout")));
+        assert_eq!((line["span"]["name"].as_str(), line["span"]["start_block"].as_u64()), (Some("commit_range"), Some(100)));
+        assert!(line["timestamp"].is_string());
+    }
+
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
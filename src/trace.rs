@@ -0,0 +1,270 @@
+use anyhow::Result;
+use ethers_core::types::U256;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{database::Database, models::TransferEvent, output::AmountFormat, query::TransferFilter};
+
+/// Addresses expanded per hop; past this the search is cut short and marked `truncated`.
+const MAX_FRONTIER: usize = 5_000;
+
+/// Paths collected before ranking, so a dense neighborhood cannot run the search forever.
+const MAX_PATHS_EXPLORED: usize = 10_000;
+
+/// One way value can have moved between two addresses: a chain of transfers, each sent by the
+/// previous one's recipient after it.
+#[derive(Debug, Serialize)]
+pub struct FlowPath {
+    pub hops: Vec<TransferEvent>,
+    /// The smallest hop, an upper bound on what passed along the whole path.
+    pub bottleneck: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlowTrace {
+    pub from: String,
+    pub to: String,
+    pub max_hops: usize,
+    /// Addresses whose outgoing transfers were searched.
+    pub addresses_searched: usize,
+    /// Some hop reached more than `MAX_FRONTIER` addresses and was not searched in full, so
+    /// paths may be missing.
+    pub truncated: bool,
+    /// Fewest hops first, then the largest bottleneck.
+    pub paths: Vec<FlowPath>,
+}
+
+/// Searches stored transfers matching `filter` for paths of at most `max_hops` transfers from
+/// `from` to `to`, returning the best `limit` of them.
+pub async fn trace_flow(
+    database: &Database,
+    from: &str,
+    to: &str,
+    max_hops: usize,
+    filter: &TransferFilter,
+    limit: usize,
+) -> Result<FlowTrace> {
+    let target = to.to_lowercase();
+    let mut by_sender: HashMap<String, Vec<TransferEvent>> = HashMap::new();
+    let mut searched: HashSet<String> = HashSet::new();
+    let mut truncated = false;
+
+    // Breadth first, one query per hop for every newly reached address.
+    let mut frontier = vec![from.to_string()];
+    for hop in 1..=max_hops {
+        frontier.retain(|address| searched.insert(address.to_lowercase()));
+        if frontier.is_empty() {
+            break;
+        }
+        if frontier.len() > MAX_FRONTIER {
+            truncated = true;
+            frontier.truncate(MAX_FRONTIER);
+        }
+
+        let mut next = Vec::new();
+        for transfer in database.transfers_sent_by(&frontier, filter).await? {
+            let recipient = transfer.to_address.to_lowercase();
+            if recipient != target {
+                // The last hop has to land on the target.
+                if hop == max_hops {
+                    continue;
+                }
+                if !searched.contains(&recipient) {
+                    next.push(transfer.to_address.clone());
+                }
+            }
+            by_sender.entry(transfer.from_address.to_lowercase()).or_default().push(transfer);
+        }
+        next.sort();
+        next.dedup();
+        frontier = next;
+    }
+
+    for transfers in by_sender.values_mut() {
+        transfers.sort_by_key(|t| (t.block_number, t.log_index));
+    }
+    let mut paths = find_paths(&by_sender, from, to, max_hops);
+    paths.sort_by(|a, b| a.0.len().cmp(&b.0.len()).then_with(|| b.1.cmp(&a.1)));
+    paths.truncate(limit);
+
+    let addresses: Vec<String> = paths
+        .iter()
+        .flat_map(|(hops, _)| hops.iter().flat_map(|t| [t.from_address.clone(), t.to_address.clone()]))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let labels = database.get_labels(&addresses).await?;
+
+    Ok(FlowTrace {
+        from: from.to_string(),
+        to: to.to_string(),
+        max_hops,
+        addresses_searched: searched.len(),
+        truncated,
+        paths: paths
+            .into_iter()
+            .map(|(hops, bottleneck)| FlowPath {
+                hops: hops
+                    .into_iter()
+                    .map(|mut t| {
+                        t.from_label = labels.get(&t.from_address).cloned();
+                        t.to_label = labels.get(&t.to_address).cloned();
+                        t
+                    })
+                    .collect(),
+                bottleneck: bottleneck.to_string(),
+            })
+            .collect(),
+    })
+}
+
+/// Every time-ordered path from `from` to `to` over `by_sender` (keyed by lowercased sender,
+/// oldest first) that visits no address twice, with its bottleneck.
+fn find_paths(
+    by_sender: &HashMap<String, Vec<TransferEvent>>,
+    from: &str,
+    to: &str,
+    max_hops: usize,
+) -> Vec<(Vec<TransferEvent>, U256)> {
+    fn walk<'a>(
+        by_sender: &'a HashMap<String, Vec<TransferEvent>>,
+        current: &str,
+        target: &str,
+        max_hops: usize,
+        path: &mut Vec<&'a TransferEvent>,
+        visited: &mut HashSet<String>,
+        found: &mut Vec<(Vec<TransferEvent>, U256)>,
+    ) {
+        for transfer in by_sender.get(current).into_iter().flatten() {
+            if found.len() >= MAX_PATHS_EXPLORED {
+                return;
+            }
+            if path
+                .last()
+                .is_some_and(|last| (transfer.block_number, transfer.log_index) <= (last.block_number, last.log_index))
+            {
+                continue;
+            }
+            let recipient = transfer.to_address.to_lowercase();
+            if recipient == target {
+                let hops: Vec<TransferEvent> = path.iter().copied().chain([transfer]).cloned().collect();
+                let bottleneck = hops
+                    .iter()
+                    .filter_map(|t| U256::from_dec_str(&t.value).ok())
+                    .min()
+                    .unwrap_or_default();
+                found.push((hops, bottleneck));
+                continue;
+            }
+            if path.len() + 1 >= max_hops || !visited.insert(recipient.clone()) {
+                continue;
+            }
+            path.push(transfer);
+            walk(by_sender, &recipient, target, max_hops, path, visited, found);
+            path.pop();
+            visited.remove(&recipient);
+        }
+    }
+
+    let mut found = Vec::new();
+    let from = from.to_lowercase();
+    let mut visited: HashSet<String> = [from.clone()].into_iter().collect();
+    if max_hops > 0 {
+        walk(by_sender, &from, &to.to_lowercase(), max_hops, &mut Vec::new(), &mut visited, &mut found);
+    }
+    found
+}
+
+impl FlowTrace {
+    /// The paths as plain text, one line per hop.
+    pub fn render(&self, amounts: &AmountFormat) -> String {
+        let name = |address: &str, label: &Option<String>| match label {
+            Some(label) => format!("{} ({})", address, label),
+            None => address.to_string(),
+        };
+        let mut out = String::new();
+        if self.paths.is_empty() {
+            let _ = writeln!(
+                out,
+                "No path from {} to {} within {} hops ({} addresses searched)",
+                self.from, self.to, self.max_hops, self.addresses_searched
+            );
+        }
+        for (i, path) in self.paths.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "Path {}: {} {}, at most {}",
+                i + 1,
+                path.hops.len(),
+                if path.hops.len() == 1 { "hop" } else { "hops" },
+                amounts.format(&path.bottleneck)
+            );
+            for t in &path.hops {
+                let _ = writeln!(
+                    out,
+                    "  {}  {} -> {}  {}  block {} tx {}",
+                    t.timestamp.to_rfc3339(),
+                    name(&t.from_address, &t.from_label),
+                    name(&t.to_address, &t.to_label),
+                    amounts.format(&t.value),
+                    t.block_number,
+                    t.transaction_hash
+                );
+            }
+        }
+        if self.truncated {
+            let _ = writeln!(out, "Search cut short at {} addresses per hop; some paths may be missing", MAX_FRONTIER);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::testing::{database, TransferBuilder};
+
+    const A: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const B: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+    const C: &str = "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB";
+    const D: &str = "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb";
+
+    async fn insert(database: &Database, block_number: i64, from: &str, to: &str, value: &str) {
+        let transfer = TransferBuilder::new(block_number).from(from).to(to).value(value).build();
+        database.insert_transfer_event(&transfer).await.unwrap();
+    }
+
+    fn blocks(trace: &FlowTrace) -> Vec<Vec<i64>> {
+        trace
+            .paths
+            .iter()
+            .map(|path| path.hops.iter().map(|t| t.block_number).collect())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn finds_time_ordered_paths() {
+        let database = database().await;
+        insert(&database, 10, C, D, "900").await; // Before C received anything.
+        insert(&database, 11, A, B, "100").await;
+        insert(&database, 12, B, D, "60").await;
+        insert(&database, 13, A, C, "500").await;
+        insert(&database, 14, C, B, "400").await;
+        insert(&database, 15, B, D, "300").await;
+        insert(&database, 16, D, A, "50").await;
+
+        let filter = TransferFilter::new();
+        let trace = trace_flow(&database, A, D, 3, &filter, 10).await.unwrap();
+        assert_eq!(blocks(&trace), [vec![11, 15], vec![11, 12], vec![13, 14, 15]]);
+        assert_eq!(trace.paths[2].bottleneck, "300");
+
+        let trace = trace_flow(&database, A, D, 2, &filter, 1).await.unwrap();
+        assert_eq!(blocks(&trace), [vec![11, 15]]);
+
+        let trace = trace_flow(&database, A, D, 3, &filter.block_range(Some(13), None), 10).await.unwrap();
+        assert_eq!(blocks(&trace), [vec![13, 14, 15]]);
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use ethers_core::types::U256;
+
+/// Parses a user-supplied amount into raw token units.
+///
+/// Plain numbers are token units and are scaled by `decimals` (`1000`, `1,000.50`);
+/// a `raw` suffix takes the integer as already scaled (`1000000000raw`).
+pub fn parse_amount(input: &str, decimals: u8) -> Result<U256> {
+    let cleaned: String = input.trim().chars().filter(|c| *c != ',' && *c != '_').collect();
+
+    let invalid = || anyhow::anyhow!("Invalid amount: {}", input);
+
+    if let Some(raw) = cleaned.strip_suffix("raw") {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return Err(invalid());
+        }
+        return U256::from_dec_str(raw).map_err(|_| invalid());
+    }
+
+    let (whole, fraction) = cleaned.split_once('.').unwrap_or((&cleaned, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    if fraction.len() > decimals as usize {
+        return Err(anyhow::anyhow!("{} has more than {} decimal places", input, decimals));
+    }
+
+    let digits = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    U256::from_dec_str(&digits).map_err(|_| invalid())
+}
+
+/// `received - sent` as a signed decimal string, with a leading `-` when more was sent.
+pub fn net_flow(received: U256, sent: U256) -> String {
+    if sent > received {
+        format!("-{}", sent - received)
+    } else {
+        (received - sent).to_string()
+    }
+}
+
+/// Formats raw token units for display, e.g. `1250000500000` with 6 decimals as `1,250,000.50`.
+///
+/// Trailing fractional zeros are dropped, keeping at least two decimal places.
+pub fn format_amount(raw: U256, decimals: u8) -> String {
+    let digits = format!("{:0>width$}", raw.to_string(), width = decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+
+    let mut grouped = String::with_capacity(whole.len() + whole.len() / 3);
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+
+    let keep = fraction.trim_end_matches('0').len().max(2.min(fraction.len()));
+    if keep == 0 {
+        grouped
+    } else {
+        format!("{}.{}", grouped, &fraction[..keep])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(value: u64) -> U256 {
+        U256::from(value)
+    }
+
+    #[test]
+    fn parses_whole_and_fractional_amounts() {
+        assert_eq!(parse_amount("1000", 6).unwrap(), raw(1_000_000_000));
+        assert_eq!(parse_amount("0.5", 6).unwrap(), raw(500_000));
+        assert_eq!(parse_amount("1.000001", 6).unwrap(), raw(1_000_001));
+        assert_eq!(parse_amount(".25", 6).unwrap(), raw(250_000));
+        assert_eq!(parse_amount("7.", 6).unwrap(), raw(7_000_000));
+    }
+
+    #[test]
+    fn ignores_digit_grouping() {
+        assert_eq!(parse_amount("1,000,000", 6).unwrap(), raw(1_000_000_000_000));
+        assert_eq!(parse_amount("1_000.5", 6).unwrap(), raw(1_000_500_000));
+        assert_eq!(parse_amount(" 42 ", 6).unwrap(), raw(42_000_000));
+    }
+
+    #[test]
+    fn raw_suffix_skips_scaling() {
+        assert_eq!(parse_amount("1000000raw", 6).unwrap(), raw(1_000_000));
+        assert_eq!(parse_amount("1,000 raw", 6).unwrap(), raw(1_000));
+        assert!(parse_amount("1.5raw", 6).is_err());
+        assert!(parse_amount("raw", 6).is_err());
+    }
+
+    #[test]
+    fn zero_decimals_accepts_whole_amounts_only() {
+        assert_eq!(parse_amount("12", 0).unwrap(), raw(12));
+        assert!(parse_amount("12.5", 0).is_err());
+    }
+
+    #[test]
+    fn rejects_too_many_fraction_digits() {
+        let err = parse_amount("1.0000001", 6).unwrap_err();
+        assert!(err.to_string().contains("more than 6 decimal places"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for input in ["", ".", "abc", "-1", "1.2.3", "1e6"] {
+            assert!(parse_amount(input, 6).is_err(), "{:?} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn formats_with_grouping_and_trimmed_fraction() {
+        assert_eq!(format_amount(raw(1_250_000_500_000), 6), "1,250,000.50");
+        assert_eq!(format_amount(raw(1_000_001), 6), "1.000001");
+        assert_eq!(format_amount(raw(123_456_789_000_000), 6), "123,456,789.00");
+        assert_eq!(format_amount(raw(999_000), 6), "0.999");
+        assert_eq!(format_amount(raw(1), 6), "0.000001");
+        assert_eq!(format_amount(U256::zero(), 6), "0.00");
+    }
+
+    #[test]
+    fn formats_zero_decimal_tokens_without_a_fraction() {
+        assert_eq!(format_amount(raw(1_234_567), 0), "1,234,567");
+        assert_eq!(format_amount(U256::zero(), 0), "0");
+    }
+
+    #[test]
+    fn format_round_trips_through_parse() {
+        for value in [0, 1, 999_999, 1_000_000, 1_234_567_890_123] {
+            let formatted = format_amount(raw(value), 6);
+            assert_eq!(parse_amount(&formatted, 6).unwrap(), raw(value), "{}", formatted);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethers_core::types::U256;
+
+use crate::{database::Database, models::PeriodActivity, query::FlowInterval};
+
+/// Activity per `interval` bucket within the time range, with the volume as a multiple of the
+/// tracked supply: the latest holder snapshot taken in or before the bucket, or the current supply
+/// for buckets older than every snapshot.
+pub async fn velocity(
+    database: &Database,
+    interval: FlowInterval,
+    from_time: Option<DateTime<Utc>>,
+    to_time: Option<DateTime<Utc>>,
+) -> Result<Vec<PeriodActivity>> {
+    let mut periods = database.period_activity(interval, from_time, to_time).await?;
+    let snapshots = database.get_holder_snapshots(None, to_time).await?;
+    let current = database.holder_snapshot().await?.map(|snapshot| snapshot.supply);
+
+    for activity in &mut periods {
+        let supply = snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| interval.bucket(snapshot.timestamp) <= activity.period)
+            .map(|snapshot| snapshot.supply.clone())
+            .or_else(|| current.clone())
+            .filter(|supply| U256::from_dec_str(supply).is_ok_and(|supply| !supply.is_zero()));
+        activity.velocity = supply
+            .as_deref()
+            .map(|supply| to_f64(&activity.volume) / to_f64(supply));
+        activity.supply = supply;
+    }
+    Ok(periods)
+}
+
+fn to_f64(value: &str) -> f64 {
+    value.parse().unwrap_or(f64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        address::ZERO_ADDRESS,
+        database::testing::{database, TransferBuilder},
+        models::HolderSnapshot,
+    };
+
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const BOB: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+    const CAROL: &str = "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB";
+
+    async fn insert(database: &Database, block_number: i64, time: &str, from: &str, to: &str, value: &str) {
+        let transfer = TransferBuilder::new(block_number)
+            .from(from)
+            .to(to)
+            .value(value)
+            .at(time.parse().unwrap())
+            .build();
+        database.insert_transfer_event(&transfer).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn velocity_divides_volume_by_the_supply_of_the_period() {
+        let database = database().await;
+        insert(&database, 1, "2026-01-01T08:00:00Z", ZERO_ADDRESS, ALICE, "1000").await;
+        insert(&database, 2, "2026-01-01T09:00:00Z", ALICE, BOB, "500").await;
+        insert(&database, 3, "2026-01-02T09:00:00Z", &BOB.to_lowercase(), CAROL, "200").await;
+        insert(&database, 4, "2026-01-02T10:00:00Z", CAROL, ALICE, "200").await;
+        for (block_number, time, supply) in [(1, "2026-01-01T08:00:00Z", "1000"), (3, "2026-01-02T09:00:00Z", "2000")] {
+            let snapshot = HolderSnapshot {
+                block_number,
+                timestamp: time.parse().unwrap(),
+                holders: 1,
+                supply: supply.to_string(),
+            };
+            database.insert_holder_snapshot(&snapshot).await.unwrap();
+        }
+
+        let periods = velocity(&database, FlowInterval::Day, None, None).await.unwrap();
+        let rows: Vec<_> = periods
+            .iter()
+            .map(|p| (p.period.as_str(), p.transfers, p.volume.as_str(), p.active_addresses, p.velocity))
+            .collect();
+        assert_eq!(
+            rows,
+            [("2026-01-01", 2, "1500", 2, Some(1.5)), ("2026-01-02", 2, "400", 3, Some(0.2))]
+        );
+
+        let from = "2026-01-02T00:00:00Z".parse().unwrap();
+        let periods = velocity(&database, FlowInterval::Month, Some(from), None).await.unwrap();
+        assert_eq!((periods.len(), periods[0].transfers, periods[0].active_addresses), (1, 2, 3));
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{
+    database::Database, dedup::payload_mismatches, ethereum::EthereumClient, models::TransferEvent,
+    query::TransferFilter,
+};
+
+/// A stored transfer whose payload differs from the chain's copy.
+#[derive(Debug, Serialize)]
+pub struct Mismatch {
+    pub stored: TransferEvent,
+    pub chain: TransferEvent,
+    pub fields: Vec<&'static str>,
+}
+
+/// Differences between stored transfers and logs re-fetched from the chain.
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub blocks_checked: u64,
+    pub transfers_checked: u64,
+    /// On chain but not stored.
+    pub missing: Vec<TransferEvent>,
+    /// Stored but not on chain.
+    pub extra: Vec<TransferEvent>,
+    pub mismatched: Vec<Mismatch>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.mismatched.is_empty()
+    }
+
+    /// Matches both sides on `(transaction_hash, log_index)`.
+    fn compare(&mut self, chain: Vec<TransferEvent>, stored: Vec<TransferEvent>) {
+        self.transfers_checked += chain.len() as u64;
+
+        let mut stored: HashMap<(String, i64), TransferEvent> = stored
+            .into_iter()
+            .map(|event| ((event.transaction_hash.clone(), event.log_index), event))
+            .collect();
+
+        for event in chain {
+            match stored.remove(&(event.transaction_hash.clone(), event.log_index)) {
+                None => self.missing.push(event),
+                Some(existing) => {
+                    let fields = payload_mismatches(&existing, &event);
+                    if !fields.is_empty() {
+                        self.mismatched.push(Mismatch {
+                            stored: existing,
+                            chain: event,
+                            fields,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut extra: Vec<TransferEvent> = stored.into_values().collect();
+        extra.sort_by_key(|event| (event.block_number, event.log_index));
+        self.extra.extend(extra);
+    }
+}
+
+/// Re-fetches logs for `from_block..=to_block` and compares them with stored and archived rows.
+/// With a `watchlist`, only transfers touching those addresses are expected to be stored.
+pub async fn verify_range(
+    client: &EthereumClient,
+    database: &Database,
+    from_block: u64,
+    to_block: u64,
+    watchlist: Option<&[String]>,
+    report: &mut VerifyReport,
+) -> Result<()> {
+    let chain = match watchlist {
+        Some([]) => Vec::new(),
+        Some(addresses) => client.get_transfer_events_for(from_block, to_block, addresses).await?,
+        None => client.get_transfer_events(from_block, to_block).await?,
+    };
+
+    let filter = TransferFilter::new()
+        .block_range(Some(from_block), Some(to_block))
+        .include_archived(true);
+    let mut stored = Vec::new();
+    database
+        .for_each_transfer(&filter, |event| {
+            stored.push(event);
+            Ok(())
+        })
+        .await?;
+
+    report.blocks_checked += to_block - from_block + 1;
+    report.compare(chain, stored);
+    Ok(())
+}
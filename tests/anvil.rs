@@ -0,0 +1,173 @@
+//! End-to-end tests of `index` against a local anvil node: a minimal token is deployed, transfers
+//! are sent, and reorgs are forced with `evm_snapshot`/`evm_revert`. Needs foundry's `anvil` on
+//! `PATH`:
+//!
+//! ```bash
+//! cargo test --features anvil --test anvil
+//! ```
+#![cfg(feature = "anvil")]
+
+use ethereum_erc20_indexer::{database::PoolSettings, query::TransferFilter, Config, Database, Indexer};
+use ethers::{
+    abi::{encode, Token},
+    prelude::*,
+    utils::{Anvil, AnvilInstance},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+/// Init code of a token whose `transfer(address,uint256)` only emits
+/// `Transfer(msg.sender, to, value)`, which is all the indexer reads.
+const TOKEN_INIT_CODE: &str = concat!(
+    // CODECOPY the 49-byte runtime that follows this 11-byte prefix and return it.
+    "603180600b6000396000f3",
+    // MSTORE(0, CALLDATALOAD(36)); LOG3(0, 32, Transfer, CALLER, CALLDATALOAD(4)); STOP
+    "602435600052600435337f",
+    "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+    "60206000a300",
+);
+const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+struct Chain {
+    anvil: AnvilInstance,
+    provider: Provider<Http>,
+    sender: Address,
+    recipient: Address,
+    token: Address,
+}
+
+impl Chain {
+    /// A fresh anvil, mining a block per transaction, with the token deployed in block 1.
+    async fn start() -> Self {
+        let anvil = Anvil::new().spawn();
+        let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+        let (sender, recipient) = (anvil.addresses()[0], anvil.addresses()[1]);
+        let deploy = TransactionRequest::new()
+            .from(sender)
+            .data(Bytes::from(hex::decode(TOKEN_INIT_CODE).unwrap()));
+        let receipt = provider.send_transaction(deploy, None).await.unwrap().await.unwrap().unwrap();
+        Self {
+            token: receipt.contract_address.unwrap(),
+            anvil,
+            provider,
+            sender,
+            recipient,
+        }
+    }
+
+    /// Sends `value` to the recipient in a block of its own.
+    async fn transfer(&self, value: u64) {
+        let mut data = TRANSFER_SELECTOR.to_vec();
+        data.extend(encode(&[Token::Address(self.recipient), Token::Uint(value.into())]));
+        let tx = TransactionRequest::new().from(self.sender).to(self.token).data(data);
+        self.provider.send_transaction(tx, None).await.unwrap().await.unwrap().unwrap();
+    }
+
+    async fn mine(&self, blocks: usize) {
+        let () = self.provider.request("anvil_mine", [U256::from(blocks)]).await.unwrap();
+    }
+
+    async fn snapshot(&self) -> U256 {
+        self.provider.request("evm_snapshot", ()).await.unwrap()
+    }
+
+    /// Drops every block mined since `snapshot`, so the next ones form a competing branch.
+    async fn revert(&self, snapshot: U256) {
+        let reverted: bool = self.provider.request("evm_revert", [snapshot]).await.unwrap();
+        assert!(reverted);
+    }
+
+    /// `index` with 2-block finality and small ranges, polling every second, in the background.
+    async fn index(&self) -> (Arc<Indexer>, Database, JoinHandle<anyhow::Result<()>>) {
+        let config = Config {
+            ethereum_rpc_url: self.anvil.endpoint(),
+            usdc_contract_address: format!("{:#x}", self.token),
+            chain_id: Some(self.anvil.chain_id()),
+            finality_blocks: 2,
+            blocks_per_request: 5,
+            poll_interval_secs: 1,
+            pending_blocks: 0,
+            ..Config::from_env().unwrap()
+        };
+        let database = Database::new("sqlite::memory:", PoolSettings::default()).await.unwrap();
+        let indexer = Arc::new(Indexer::new(config, database.clone()).await.unwrap());
+        let indexing = tokio::spawn({
+            let indexer = indexer.clone();
+            async move { indexer.start_indexing(Some(0)).await }
+        });
+        (indexer, database, indexing)
+    }
+}
+
+/// `(block, value)` of every stored transfer, oldest first.
+async fn transfers(database: &Database) -> Vec<(i64, String)> {
+    let page = database.query_transfers_page(&TransferFilter::new(), 100).await.unwrap();
+    let mut transfers: Vec<_> = page.transfers.into_iter().map(|t| (t.block_number, t.value)).collect();
+    transfers.sort();
+    transfers
+}
+
+/// Waits up to 30 seconds for the indexer to have processed through `block` and stored `expected`.
+async fn wait_for(database: &Database, block: u64, expected: &[(i64, &str)]) {
+    let expected: Vec<(i64, String)> = expected.iter().map(|(b, v)| (*b, v.to_string())).collect();
+    for _ in 0..300 {
+        if database.get_latest_processed_block().await.unwrap() == Some(block) && transfers(database).await == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!(
+        "expected {:?} through block {}, found {:?} through {:?}",
+        expected,
+        block,
+        transfers(database).await,
+        database.get_latest_processed_block().await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn indexes_only_finalized_transfers() {
+    let chain = Chain::start().await;
+    chain.transfer(100).await;
+    chain.transfer(40).await;
+    chain.mine(3).await;
+    chain.transfer(7).await;
+
+    // Head 7: block 6's transfer is not final yet.
+    let (indexer, database, indexing) = chain.index().await;
+    wait_for(&database, 5, &[(2, "100"), (3, "40")]).await;
+
+    chain.mine(2).await;
+    wait_for(&database, 7, &[(2, "100"), (3, "40"), (6, "7")]).await;
+
+    indexer.stop();
+    indexing.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn rolls_back_and_reindexes_a_reorg() {
+    let chain = Chain::start().await;
+    chain.transfer(100).await;
+    let fork = chain.snapshot().await;
+    chain.transfer(40).await;
+    chain.transfer(7).await;
+    chain.mine(5).await;
+
+    let (indexer, database, indexing) = chain.index().await;
+    wait_for(&database, 7, &[(2, "100"), (3, "40"), (4, "7")]).await;
+
+    // A longer branch from block 3 without either transfer.
+    chain.revert(fork).await;
+    chain.transfer(9).await;
+    chain.mine(7).await;
+    wait_for(&database, 8, &[(2, "100"), (3, "9")]).await;
+
+    let reorgs = database
+        .get_reorg_events(chrono::DateTime::<chrono::Utc>::MIN_UTC, chrono::Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(reorgs.iter().map(|reorg| reorg.fork_block).collect::<Vec<_>>(), vec![3]);
+
+    indexer.stop();
+    indexing.await.unwrap().unwrap();
+}